@@ -0,0 +1,170 @@
+//! Throughput of `Generator::generate` with the 4 cylinder `example6.esc` and the bundled presets, run with `cargo bench`.
+//! `example6_silent` measures the same engine after running for a second and turning its cylinders and intake noise off for 10 seconds,
+//! which is slow if the decaying filter states and waveguide samples become subnormal.
+//! `gui_contention` measures how long the audio side waits for a buffer while a thread edits the engine like the GUI does
+//! every frame, once locking a shared generator and once through a `GeneratorHandle`.
+//! `warmup` compares the 3 second warmup of the headless mode with `Generator::skip` to generating it into a
+//! buffer which is allocated and discarded.
+//! `loop_buffer` compares delaying a buffer one sample at a time with `LoopBuffer::pop`/`push` to the SIMD copies of
+//! `pop_bulk`/`push_bulk`.
+//! Compare implementations with `cargo bench -- --save-baseline old` and `cargo bench -- --baseline old`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use enginesound::generator_thread::GeneratorThread;
+use enginesound::presets::{load_preset, PRESETS};
+use enginesound::{fix_engine, BiquadFilter, Engine, Generator, LoopBuffer, DC_OFFSET_LP_FREQ};
+use parking_lot::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const SAMPLE_RATE: u32 = 48000;
+const BUFFER_SIZE: usize = 4800;
+/// frames of the buffers of the audio thread, see `audio::GENERATOR_BUFFER_SIZE`
+const AUDIO_BUFFER_SIZE: usize = 256;
+
+fn generator(mut engine: Engine) -> Generator {
+    engine.noise_seed = Some(7);
+    engine.seed_noise();
+
+    Generator::new(
+        SAMPLE_RATE,
+        engine,
+        BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, SAMPLE_RATE),
+    )
+}
+
+fn generate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("generate");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+
+    let mut buf = vec![0.0; BUFFER_SIZE];
+
+    let mut engine: Engine = ron::de::from_str(include_str!("../example6.esc"))
+        .expect("failed to deserialize example6.esc");
+    fix_engine(&mut engine, SAMPLE_RATE).expect("example6.esc is invalid");
+    let mut example6 = generator(engine.clone());
+    group.bench_function("example6", |b| b.iter(|| example6.generate(&mut buf)));
+
+    let mut silent = generator(engine);
+    silent.skip(SAMPLE_RATE as usize);
+    silent.engine.intake_noise_factor = 0.0;
+    for cylinder in silent.engine.cylinders.iter_mut() {
+        cylinder.active = false;
+    }
+    silent.skip(SAMPLE_RATE as usize * 10);
+    group.bench_function("example6_silent", |b| b.iter(|| silent.generate(&mut buf)));
+
+    for (name, _) in PRESETS {
+        let mut preset = generator(load_preset(name, SAMPLE_RATE).expect("invalid preset"));
+        group.bench_function(*name, |b| b.iter(|| preset.generate(&mut buf)));
+    }
+
+    group.finish();
+}
+
+fn gui_contention(c: &mut Criterion) {
+    let mut group = c.benchmark_group("gui_contention");
+    group.throughput(Throughput::Elements(AUDIO_BUFFER_SIZE as u64));
+
+    let engine = load_preset("v8", SAMPLE_RATE).expect("invalid preset");
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // copies the engine and applies it again, like a GUI frame
+    let shared = Arc::new(RwLock::new(generator(engine.clone())));
+    let gui = std::thread::spawn({
+        let shared = shared.clone();
+        let stop = stop.clone();
+        move || {
+            while !stop.load(Ordering::Relaxed) {
+                let engine = shared.read().engine.clone();
+                shared.write().engine.apply_parameters(engine);
+            }
+        }
+    });
+    let mut buf = vec![0.0; AUDIO_BUFFER_SIZE * 2];
+    group.bench_function("rwlock", |b| {
+        b.iter(|| shared.write().generate_stereo(&mut buf))
+    });
+    stop.store(true, Ordering::Relaxed);
+    gui.join().unwrap();
+
+    stop.store(false, Ordering::Relaxed);
+    let (generator_thread, handle) = GeneratorThread::new(generator(engine));
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    let audio = std::thread::spawn(move || generator_thread.run(AUDIO_BUFFER_SIZE, sender, &[]));
+    let gui = std::thread::spawn({
+        let stop = stop.clone();
+        move || {
+            while !stop.load(Ordering::Relaxed) {
+                if let Some(snapshot) = handle.snapshot() {
+                    handle
+                        .apply(move |generator| generator.engine.apply_parameters(snapshot.engine));
+                }
+            }
+        }
+    });
+    group.bench_function("handle", |b| b.iter(|| receiver.recv().unwrap()));
+    stop.store(true, Ordering::Relaxed);
+    gui.join().unwrap();
+    // disconnects the output, which stops the generator thread
+    drop(receiver);
+    audio.join().unwrap();
+
+    group.finish();
+}
+
+fn warmup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("warmup");
+    let warmup_samples = SAMPLE_RATE as usize * 3;
+    group.throughput(Throughput::Elements(warmup_samples as u64));
+    group.sample_size(10);
+
+    let engine = load_preset("i4", SAMPLE_RATE).expect("invalid preset");
+
+    let mut skipped = generator(engine.clone());
+    group.bench_function("skip", |b| b.iter(|| skipped.skip(warmup_samples)));
+
+    let mut generated = generator(engine);
+    group.bench_function("generate", |b| {
+        b.iter(|| generated.generate(&mut vec![0.0; warmup_samples]))
+    });
+
+    group.finish();
+}
+
+fn loop_buffer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("loop_buffer");
+    group.throughput(Throughput::Elements(BUFFER_SIZE as u64));
+
+    // a 20 ms delay line, processed in blocks shorter than its delay
+    let delay = SAMPLE_RATE as usize / 50;
+    let block_len = delay - 1;
+    let input = (0..BUFFER_SIZE).map(|i| i as f32).collect::<Vec<f32>>();
+    let mut output = vec![0.0; BUFFER_SIZE];
+
+    let mut single = LoopBuffer::new(delay, SAMPLE_RATE);
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            for (input, output) in input.iter().zip(output.iter_mut()) {
+                *output = single.pop();
+                single.push(*input);
+                single.advance();
+            }
+        })
+    });
+
+    let mut bulk = LoopBuffer::new(delay, SAMPLE_RATE);
+    group.bench_function("bulk", |b| {
+        b.iter(|| {
+            for (input, output) in input.chunks(block_len).zip(output.chunks_mut(block_len)) {
+                bulk.pop_bulk(output);
+                bulk.push_bulk(input);
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, generate, gui_contention, warmup, loop_buffer);
+criterion_main!(benches);
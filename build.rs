@@ -0,0 +1,24 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    ffi::build();
+}
+
+/// generates the C header of the `ffi` module into `OUT_DIR`, `tests/ffi.rs` checks that `include/enginesound.h` matches it
+#[cfg(feature = "ffi")]
+mod ffi {
+    use std::path::PathBuf;
+
+    pub fn build() {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+
+        let crate_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+        let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
+
+        let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+            .expect("Failed to read cbindgen.toml");
+        cbindgen::generate_with_config(&crate_dir, config)
+            .expect("Failed to generate the C header")
+            .write_to_file(out_dir.join("enginesound.h"));
+    }
+}
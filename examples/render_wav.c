@@ -0,0 +1,119 @@
+/*
+ * Renders one second of an engine at 3000 rpm into a 32 bit float mono WAV file with the C API.
+ *
+ *   cargo build --release --no-default-features --features capi
+ *   cc examples/render_wav.c -Iinclude -Ltarget/release -lenginesound -o render_wav
+ *   LD_LIBRARY_PATH=target/release ./render_wav src/default.esc engine.wav
+ */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "enginesound.h"
+
+#define SAMPLE_RATE 48000
+/* frames per enginesound_generate call, like the buffer of an audio callback */
+#define BLOCK_FRAMES 512
+
+static char *read_file(const char *path) {
+    FILE *file = fopen(path, "rb");
+    char *data = NULL;
+    long len;
+
+    if (file == NULL) {
+        return NULL;
+    }
+    if (fseek(file, 0, SEEK_END) == 0 && (len = ftell(file)) >= 0 && fseek(file, 0, SEEK_SET) == 0) {
+        data = malloc((size_t)len + 1);
+        if (data != NULL && fread(data, 1, (size_t)len, file) == (size_t)len) {
+            data[len] = '\0';
+        } else {
+            free(data);
+            data = NULL;
+        }
+    }
+    fclose(file);
+    return data;
+}
+
+static void write_u32(FILE *file, uint32_t value) {
+    uint8_t bytes[4] = {value, value >> 8, value >> 16, value >> 24};
+    fwrite(bytes, 1, 4, file);
+}
+
+static void write_u16(FILE *file, uint16_t value) {
+    uint8_t bytes[2] = {value, value >> 8};
+    fwrite(bytes, 1, 2, file);
+}
+
+/* header of a WAV file with `frames` mono float samples */
+static void write_wav_header(FILE *file, uint32_t frames) {
+    uint32_t data_len = frames * 4;
+
+    fwrite("RIFF", 1, 4, file);
+    write_u32(file, 36 + data_len);
+    fwrite("WAVEfmt ", 1, 8, file);
+    write_u32(file, 16);
+    write_u16(file, 3); /* IEEE float */
+    write_u16(file, 1);
+    write_u32(file, SAMPLE_RATE);
+    write_u32(file, SAMPLE_RATE * 4);
+    write_u16(file, 4);
+    write_u16(file, 32);
+    fwrite("data", 1, 4, file);
+    write_u32(file, data_len);
+}
+
+int main(int argc, char **argv) {
+    float block[BLOCK_FRAMES];
+    OpaqueGenerator *gen;
+    FILE *out;
+    char *config;
+
+    if (argc != 3) {
+        fprintf(stderr, "usage: %s <config.esc> <output.wav>\n", argv[0]);
+        return 1;
+    }
+
+    config = read_file(argv[1]);
+    if (config == NULL) {
+        fprintf(stderr, "failed to read \"%s\"\n", argv[1]);
+        return 1;
+    }
+
+    /* the config is only read during creation */
+    gen = enginesound_create(config, SAMPLE_RATE);
+    free(config);
+    if (gen == NULL) {
+        return 1;
+    }
+
+    out = fopen(argv[2], "wb");
+    if (out == NULL) {
+        fprintf(stderr, "failed to create \"%s\"\n", argv[2]);
+        enginesound_destroy(gen);
+        return 1;
+    }
+
+    enginesound_set_rpm(gen, 3000.0f);
+    enginesound_set_throttle(gen, 0.8f);
+
+    write_wav_header(out, SAMPLE_RATE);
+    for (size_t frames = 0; frames < SAMPLE_RATE; frames += BLOCK_FRAMES) {
+        size_t len = SAMPLE_RATE - frames < BLOCK_FRAMES ? SAMPLE_RATE - frames : BLOCK_FRAMES;
+
+        if (enginesound_generate(gen, block, len) != ENGINESOUND_STATUS_OK) {
+            fprintf(stderr, "failed to generate samples\n");
+            fclose(out);
+            enginesound_destroy(gen);
+            return 1;
+        }
+        /* assumes a little endian machine, like the WAV format */
+        fwrite(block, sizeof(float), len, out);
+    }
+
+    fclose(out);
+    enginesound_destroy(gen);
+    return 0;
+}
@@ -0,0 +1,93 @@
+#ifndef ENGINESOUND_H
+#define ENGINESOUND_H
+
+/* Generated by cbindgen from src/ffi.rs with the "ffi" feature, do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ result of the functions which take a generator
+ */
+typedef enum EnginesoundStatus {
+  ENGINESOUND_STATUS_OK = 0,
+  /*
+   the generator or the buffer was null
+   */
+  ENGINESOUND_STATUS_NULL_POINTER = 1,
+  /*
+   the generator panicked, its state may be inconsistent
+   */
+  ENGINESOUND_STATUS_PANIC = 2,
+} EnginesoundStatus;
+
+/*
+ generator handle owned by the C side, freed with `enginesound_destroy`
+ */
+typedef struct OpaqueGenerator OpaqueGenerator;
+
+/*
+ creates a generator from a NUL-terminated RON engine config, returns null if the config is invalid
+
+ # Safety
+
+ `config_ron` must be null or point to a NUL-terminated string
+ */
+struct OpaqueGenerator *enginesound_create(const char *config_ron, uint32_t sample_rate);
+
+/*
+ fills `out` with `frames` mono samples, without allocating
+
+ # Safety
+
+ `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet,
+ `out` must be null or valid for writing `frames` floats
+ */
+enum EnginesoundStatus enginesound_generate(struct OpaqueGenerator *gen, float *out, size_t frames);
+
+/*
+ sets the rpm of the engine instantly
+
+ # Safety
+
+ `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+ */
+enum EnginesoundStatus enginesound_set_rpm(struct OpaqueGenerator *gen, float rpm);
+
+/*
+ sets the engine load 0.0 (closed) - 1.0 (wide open), which the engine follows smoothly
+
+ # Safety
+
+ `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+ */
+enum EnginesoundStatus enginesound_set_throttle(struct OpaqueGenerator *gen, float throttle);
+
+/*
+ clears the sound in the pipes and the state of all filters, e.g. before playing the sound again
+
+ # Safety
+
+ `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+ */
+enum EnginesoundStatus enginesound_reset(struct OpaqueGenerator *gen);
+
+/*
+ frees a generator, null is ignored
+
+ # Safety
+
+ `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+ */
+void enginesound_destroy(struct OpaqueGenerator *gen);
+
+/*
+ same as `enginesound_destroy`
+
+ # Safety
+
+ `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+ */
+void enginesound_free(struct OpaqueGenerator *gen);
+
+#endif /* ENGINESOUND_H */
@@ -0,0 +1,280 @@
+//! ## Analysis module ##
+//!
+//! Theoretical resonances of the pipes of an engine compared with the harmonics of its firing frequency, see `--analyze`
+//!
+
+use crate::gen::{Engine, MufflerElement, WaveGuide};
+use crate::utils::SPEED_OF_SOUND;
+use std::fmt::{self, Write};
+
+/// resonances reported per pipe, the fundamental and its first overtones
+pub const REPORTED_RESONANCES: usize = 4;
+/// firing harmonics a resonance is compared with, the higher ones are too weak and too dense to stand out
+pub const FIRING_HARMONICS: usize = 8;
+/// a resonance coincides with a firing harmonic if their frequencies differ by less than this fraction of the firing frequency
+pub const COINCIDENCE_TOLERANCE: f32 = 0.05;
+
+/// Resonances of every pipe of an engine, see `analyze`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResonanceReport {
+    pub rpm: f32,
+    /// number of active cylinders
+    pub cylinders: usize,
+    /// ignitions per second in hz, `rpm / 120 * cylinders`
+    pub firing_frequency: f32,
+    pub pipes: Vec<PipeResonances>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PipeResonances {
+    /// path of the pipe in the config, e.g. `cylinders[0].intake_waveguide`
+    pub name: String,
+    /// length in meters, `None` for Helmholtz resonators which are no pipes
+    pub length: Option<f32>,
+    /// lowest first
+    pub resonances: Vec<Resonance>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resonance {
+    /// in hz
+    pub freq: f32,
+    /// multiple of the firing frequency up to `FIRING_HARMONICS` this resonance coincides with, see `COINCIDENCE_TOLERANCE`
+    pub firing_harmonic: Option<usize>,
+}
+
+/// analyzes every waveguide of the engine at its current rpm, the EGR pipe only if it is in use
+///
+/// A pulse travels through a pipe and back in twice its delay. If both ends reflect with the same sign, the pipe resonates
+/// at the multiples of `1 / (2 * delay)`. If one end inverts the pulse (e.g. an open end, negative reflectivity), it takes
+/// two round trips to repeat, and only the odd multiples of `1 / (4 * delay)` resonate. The cylinder side of the intake
+/// and exhaust waveguides is taken to be closed, which their valves are for most of the cycle.
+pub fn analyze(engine: &Engine) -> ResonanceReport {
+    let cylinders = engine
+        .cylinders
+        .iter()
+        .filter(|cylinder| cylinder.active)
+        .count();
+    let firing_frequency = engine.rpm / 120.0 * cylinders as f32;
+
+    let mut pipes = Vec::new();
+
+    for (i, cylinder) in engine.cylinders.iter().enumerate() {
+        for (side, waveguide, alpha) in IntoIterator::into_iter([
+            (
+                "intake",
+                &cylinder.intake_waveguide,
+                cylinder.intake_closed_refl,
+            ),
+            (
+                "exhaust",
+                &cylinder.exhaust_waveguide,
+                cylinder.exhaust_closed_refl,
+            ),
+            (
+                "extractor",
+                &cylinder.extractor_waveguide,
+                cylinder.extractor_waveguide.alpha,
+            ),
+        ]) {
+            pipes.push(pipe(
+                format!("cylinders[{}].{}_waveguide", i, side),
+                waveguide,
+                alpha,
+                firing_frequency,
+            ));
+        }
+    }
+
+    let straight_pipe = &engine.muffler.straight_pipe;
+    pipes.push(pipe(
+        "muffler.straight_pipe".to_string(),
+        straight_pipe,
+        straight_pipe.alpha,
+        firing_frequency,
+    ));
+
+    for (i, element) in engine.muffler.muffler_elements.iter().enumerate() {
+        let name = format!("muffler.muffler_elements[{}]", i);
+
+        match element {
+            MufflerElement::StraightPipe(waveguide) => {
+                pipes.push(pipe(name, waveguide, waveguide.alpha, firing_frequency))
+            }
+            MufflerElement::HelmholtzResonator { .. } => pipes.push(PipeResonances {
+                name,
+                length: None,
+                resonances: element
+                    .resonant_frequency()
+                    .map(|freq| resonance(freq, firing_frequency))
+                    .into_iter()
+                    .collect(),
+            }),
+        }
+    }
+
+    if engine.egr_factor > 0.0 {
+        pipes.push(pipe(
+            "egr_delay".to_string(),
+            &engine.egr_delay,
+            engine.egr_delay.alpha,
+            firing_frequency,
+        ));
+    }
+
+    ResonanceReport {
+        rpm: engine.rpm,
+        cylinders,
+        firing_frequency,
+        pipes,
+    }
+}
+
+/// `alpha` is the reflectivity of the alpha end, which differs from the waveguide's for the valve-modulated ones
+fn pipe(name: String, waveguide: &WaveGuide, alpha: f32, firing_frequency: f32) -> PipeResonances {
+    let delay = waveguide.chamber0.samples.delay;
+    let inverting = alpha * waveguide.beta < 0.0;
+
+    let resonances = (1..=REPORTED_RESONANCES)
+        .map(|k| {
+            if inverting {
+                (2 * k - 1) as f32 / (4.0 * delay)
+            } else {
+                k as f32 / (2.0 * delay)
+            }
+        })
+        .map(|freq| resonance(freq, firing_frequency))
+        .collect();
+
+    PipeResonances {
+        name,
+        length: Some(delay * SPEED_OF_SOUND),
+        resonances,
+    }
+}
+
+fn resonance(freq: f32, firing_frequency: f32) -> Resonance {
+    let harmonic = (freq / firing_frequency).round();
+
+    Resonance {
+        freq,
+        firing_harmonic: if (1.0..=FIRING_HARMONICS as f32).contains(&harmonic)
+            && (freq - harmonic * firing_frequency).abs() < COINCIDENCE_TOLERANCE * firing_frequency
+        {
+            Some(harmonic as usize)
+        } else {
+            None
+        },
+    }
+}
+
+impl ResonanceReport {
+    /// one row per resonance: `pipe,length_m,resonance,freq_hz,firing_harmonic`, the length and harmonic may be empty
+    pub fn to_csv(&self) -> String {
+        let mut csv = "pipe,length_m,resonance,freq_hz,firing_harmonic\n".to_string();
+
+        for pipe in self.pipes.iter() {
+            for (i, resonance) in pipe.resonances.iter().enumerate() {
+                let _ = writeln!(
+                    csv,
+                    "{},{},{},{},{}",
+                    pipe.name,
+                    pipe.length
+                        .map(|length| length.to_string())
+                        .unwrap_or_default(),
+                    i + 1,
+                    resonance.freq,
+                    resonance
+                        .firing_harmonic
+                        .map(|harmonic| harmonic.to_string())
+                        .unwrap_or_default()
+                );
+            }
+        }
+
+        csv
+    }
+}
+
+/// table of the resonances, the ones which coincide with a firing harmonic are marked and listed below it
+impl fmt::Display for ResonanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Firing frequency at {:.0} rpm with {} active cylinders: {:.1} Hz",
+            self.rpm, self.cylinders, self.firing_frequency
+        )?;
+
+        let name_width = self
+            .pipes
+            .iter()
+            .map(|pipe| pipe.name.len())
+            .max()
+            .unwrap_or(0)
+            .max("Pipe".len());
+
+        write!(f, "{:<width$}  {:>8}", "Pipe", "Length", width = name_width)?;
+        for i in 1..=REPORTED_RESONANCES {
+            write!(f, "  {:>11}", format!("f{}", i))?;
+        }
+        writeln!(f)?;
+
+        for pipe in self.pipes.iter() {
+            let length = match pipe.length {
+                Some(length) => format!("{:.3} m", length),
+                None => "-".to_string(),
+            };
+            write!(
+                f,
+                "{:<width$}  {:>8}",
+                pipe.name,
+                length,
+                width = name_width
+            )?;
+
+            for resonance in pipe.resonances.iter() {
+                let marker = if resonance.firing_harmonic.is_some() {
+                    "*"
+                } else {
+                    " "
+                };
+                write!(f, "  {:>9.1}{}", resonance.freq, marker)?;
+            }
+            writeln!(f)?;
+        }
+
+        let coincidences = self
+            .pipes
+            .iter()
+            .flat_map(|pipe| {
+                pipe.resonances
+                    .iter()
+                    .enumerate()
+                    .filter_map(move |(i, resonance)| {
+                        resonance
+                            .firing_harmonic
+                            .map(|harmonic| (pipe, i, resonance.freq, harmonic))
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        if coincidences.is_empty() {
+            writeln!(f, "No resonance coincides with a firing harmonic")?;
+        } else {
+            writeln!(f, "* coincides with a firing harmonic:")?;
+            for (pipe, i, freq, harmonic) in coincidences {
+                writeln!(
+                    f,
+                    "    {} f{} {:.1} Hz ~ {}x firing frequency ({:.1} Hz)",
+                    pipe.name,
+                    i + 1,
+                    freq,
+                    harmonic,
+                    harmonic as f32 * self.firing_frequency
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,33 +1,200 @@
 use crate::exactstreamer::ExactStreamer;
-use crate::gen::Generator;
+use crate::resampler::Resampler;
+#[cfg(feature = "jack")]
+use crate::samplering::{sample_ring, RingConsumer};
 use cpal::traits::HostTrait;
 use cpal::traits::{DeviceTrait, StreamTrait};
-use cpal::{BufferSize, Host, SampleRate, StreamConfig};
-use parking_lot::RwLock;
+use cpal::{BufferSize, Device, Host, SampleRate, Stream, StreamConfig};
+use enginesound::generator_thread::{GeneratorThread, MonitorBuffer};
+use parking_lot::Mutex;
+#[cfg(feature = "jack")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 
 pub const GENERATOR_BUFFER_SIZE: usize = 256;
 pub const GENERATOR_CHANNEL_SIZE: usize = 6;
+/// buffers which weren't received by the GUI yet are dropped instead of delaying the generator,
+/// this holds a few frames of the GUI so the oscilloscope gets continuous samples
+pub const OSCILLOSCOPE_CHANNEL_SIZE: usize = 16;
 
-pub struct Audio;
+/// time between attempts to rebuild a failed stream
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(1000);
 
-/// starts audio streaming to an audio device and also steps the generator with a fixed buffer of size `GENERATOR_BUFFER_SIZE`
+/// keeps audio playing back until it is dropped
+pub struct Audio {
+    /// stops the thread owning the stream when dropped
+    _stop: Option<crossbeam_channel::Sender<()>>,
+    /// `None` if the backend can't switch devices, e.g. JACK
+    device_switch: Option<DeviceSwitch>,
+    /// deactivates the JACK client when dropped
+    #[cfg(feature = "jack")]
+    _jack: Option<jack::AsyncClient<(), JackProcess>>,
+}
+
+impl Audio {
+    /// switches the output device while playing back, see `DeviceSwitch`
+    // only the GUI switches devices
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub fn device_switch(&self) -> Option<DeviceSwitch> {
+        self.device_switch.clone()
+    }
+}
+
+/// Rebuilds the stream of an `Audio` on another output device, the generator thread keeps running so
+/// its state carries over to the new device without interruption
+#[derive(Clone)]
+pub struct DeviceSwitch(crossbeam_channel::Sender<Option<String>>);
+
+// only the GUI switches devices
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+impl DeviceSwitch {
+    /// plays back on the first output device whose name contains `device_name`, or on the default output device if it is `None`
+    pub fn switch(&self, device_name: Option<String>) {
+        let _ = self.0.send(device_name);
+    }
+}
+
+/// receive the mono downmix of every generated buffer
+// only the GUI displays them
+#[cfg_attr(not(feature = "gui"), allow(dead_code))]
+pub struct AudioReceivers {
+    /// feeds the waterfall's fft
+    pub fft: crossbeam_channel::Receiver<MonitorBuffer>,
+    pub oscilloscope: crossbeam_channel::Receiver<MonitorBuffer>,
+}
+
+/// names of all output devices of the default audio host, empty if they can't be enumerated
+pub fn list_devices() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            eprintln!("Failed to enumerate audio output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// prints the names of all output devices of the default audio host
+pub fn print_devices() -> Result<(), String> {
+    let host: Host = cpal::default_host();
+    let default_name = host
+        .default_output_device()
+        .and_then(|device| device.name().ok());
+
+    println!("Audio output devices of {:?}:", host.id());
+
+    for device in host
+        .output_devices()
+        .map_err(|e| format!("Failed to enumerate audio output devices: {}", e))?
+    {
+        let name = device.name().unwrap_or_else(|_| "<unknown>".to_string());
+        let default = if Some(&name) == default_name.as_ref() {
+            " (default)"
+        } else {
+            ""
+        };
+
+        println!("    {}{}", name, default);
+    }
+
+    Ok(())
+}
+
+/// returns the output device named `device_name`, or the first output device whose name contains it, or the default output device
+fn select_device(host: &Host, device_name: Option<String>) -> Result<Device, String> {
+    if let Some(device_name) = device_name {
+        let mut devices = host
+            .output_devices()
+            .map_err(|e| format!("Failed to enumerate audio output devices: {}", e))?
+            .filter_map(|device| Some((device.name().ok()?, device)))
+            .collect::<Vec<_>>();
+        let index = devices
+            .iter()
+            .position(|(name, _)| *name == device_name)
+            .or_else(|| {
+                devices
+                    .iter()
+                    .position(|(name, _)| name.contains(device_name.as_str()))
+            });
+
+        match index {
+            Some(index) => return Ok(devices.swap_remove(index).1),
+            None => eprintln!(
+                "No audio output device matching \"{}\" found, using the default output device",
+                device_name
+            ),
+        }
+    }
+
+    host.default_output_device()
+        .ok_or_else(|| "Failed to get default audio output device".to_string())
+}
+
+/// returns the stereo sample rate supported by `device` which is the closest to `sample_rate`,
+/// or `sample_rate` if the supported configs can't be queried
+fn nearest_supported_sample_rate(device: &Device, sample_rate: u32) -> u32 {
+    device
+        .supported_output_configs()
+        .ok()
+        .and_then(|configs| {
+            configs
+                .filter(|config| config.channels() == 2)
+                .map(|config| {
+                    sample_rate.clamp(config.min_sample_rate().0, config.max_sample_rate().0)
+                })
+                .min_by_key(|supported| (*supported as i64 - sample_rate as i64).abs())
+        })
+        .unwrap_or(sample_rate)
+}
+
+/// prints the stream configs supported by `device`
+fn print_supported_configs(device: &Device) {
+    match device.supported_output_configs() {
+        Ok(configs) => {
+            eprintln!("Supported output configs:");
+            for config in configs {
+                eprintln!(
+                    "    {} channels, {} - {} Hz, {:?}",
+                    config.channels(),
+                    config.min_sample_rate().0,
+                    config.max_sample_rate().0,
+                    config.sample_format()
+                );
+            }
+        }
+        Err(e) => eprintln!("Failed to query supported output configs: {}", e),
+    }
+}
+
+/// starts audio streaming to an audio device and also runs the generator thread with a fixed buffer of size `GENERATOR_BUFFER_SIZE`
+///
+/// plays back on the first output device whose name contains `device_name` if set, otherwise on the default output device.
+/// If the stream fails, e.g. because the device was unplugged, it is rebuilt on the device matching `device_name` or the new default device
 pub fn init(
-    gen: Arc<RwLock<Generator>>,
+    generator_thread: GeneratorThread,
     sample_rate: u32,
-) -> Result<(Audio, crossbeam_channel::Receiver<Vec<f32>>), String> {
-    // spawn a new thread to not conflict with winit's COM
+    device_name: Option<String>,
+) -> Result<(Audio, AudioReceivers), String> {
+    let (generator_sender, device_receiver) = crossbeam_channel::bounded(GENERATOR_CHANNEL_SIZE);
+    let (generator_fft_sender, fft_receiver) = crossbeam_channel::bounded(GENERATOR_CHANNEL_SIZE);
+    let (oscilloscope_sender, oscilloscope_receiver) =
+        crossbeam_channel::bounded(OSCILLOSCOPE_CHANNEL_SIZE);
+    let (stop_sender, stop_receiver) = crossbeam_channel::bounded::<()>(0);
+    let (started_sender, started_receiver) = crossbeam_channel::bounded(1);
+    let (switch_sender, switch_receiver) = crossbeam_channel::unbounded();
 
-    std::thread::spawn(move || {
-        let (generator_sender, device_receiver) =
-            crossbeam_channel::bounded(GENERATOR_CHANNEL_SIZE);
-        let (generator_fft_sender, fft_receiver) =
-            crossbeam_channel::bounded(GENERATOR_CHANNEL_SIZE);
+    // the generator sends interleaved stereo data, shared by the streams of all reconnects
+    let stream = Arc::new(Mutex::new(Resampler::new(
+        ExactStreamer::new(GENERATOR_BUFFER_SIZE * 2, device_receiver),
+        sample_rate,
+        sample_rate,
+    )));
 
+    // streams can't be sent between threads, so the stream is owned by its own thread which also doesn't conflict with winit's COM
+    std::thread::spawn(move || {
         let host: Host = cpal::default_host();
-        let speaker = host
-            .default_output_device()
-            .ok_or_else(|| "Failed to get default audio output device".to_string())?;
+        let mut device_name = device_name;
 
         println!(
             "Audio driver: {:?}\nSamplerate: {} Hz",
@@ -35,69 +202,325 @@ pub fn init(
             sample_rate
         );
 
-        println!("Audio output device: {}", speaker.name().unwrap());
+        let (error_sender, error_receiver) = crossbeam_channel::unbounded();
 
-        let stream_config = StreamConfig {
-            sample_rate: SampleRate(sample_rate),
-            channels: 2,
-            buffer_size: BufferSize::Default,
-        };
+        let mut speaker_stream =
+            match build_stream(&host, &device_name, sample_rate, &stream, &error_sender) {
+                Ok(speaker_stream) => {
+                    let _ = started_sender.send(Ok(()));
+                    speaker_stream
+                }
+                Err(e) => {
+                    let _ = started_sender.send(Err(e));
+                    return;
+                }
+            };
 
-        println!("Audio output format: {:?}", stream_config);
+        loop {
+            crossbeam_channel::select! {
+                // the `Audio` has been dropped
+                recv(stop_receiver) -> _ => return,
+                recv(error_receiver) -> _ => {}
+                recv(switch_receiver) -> switch => match switch {
+                    Ok(new_device_name) => {
+                        println!("Switching the audio output device..");
+                        device_name = new_device_name;
+                    }
+                    Err(_) => return,
+                },
+            }
+
+            std::mem::drop(speaker_stream);
+
+            // errors of the old stream have been handled by rebuilding it
+            while error_receiver.try_recv().is_ok() {}
+
+            let mut attempt = 1;
+            speaker_stream = loop {
+                println!("Reconnecting audio output (attempt {})..", attempt);
 
-        let speaker_stream = speaker
-            .build_output_stream::<f32, _, _>(
-                &stream_config,
+                match build_stream(&host, &device_name, sample_rate, &stream, &error_sender) {
+                    Ok(speaker_stream) => break speaker_stream,
+                    Err(e) => eprintln!("Failed to reconnect audio output: {}", e),
+                }
+
+                attempt += 1;
+                if stop_receiver.recv_timeout(RECONNECT_INTERVAL)
+                    != Err(crossbeam_channel::RecvTimeoutError::Timeout)
                 {
-                    let mut stream = ExactStreamer::new(GENERATOR_BUFFER_SIZE, device_receiver);
+                    return;
+                }
+            };
+        }
+    });
 
-                    move |data, _info| {
-                        let len_2 = data.len() / 2;
-                        let _ = stream.fill(&mut data[len_2..]);
+    started_receiver
+        .recv()
+        .map_err(|_| "Audio playback thread stopped unexpectedly".to_string())??;
 
-                        // interleave mono data to stereo
+    // the fft and the oscilloscope only use the mono downmix
+    std::thread::spawn(move || {
+        generator_thread.run(
+            GENERATOR_BUFFER_SIZE,
+            generator_sender,
+            &[oscilloscope_sender, generator_fft_sender],
+        )
+    });
 
-                        let mut i = 0;
-                        while i < len_2 {
-                            let lr = data[i + len_2];
-                            data[i * 2] = lr;
-                            data[i * 2 + 1] = lr;
-                            i += 1;
-                        }
-                    }
-                },
+    Ok((
+        Audio {
+            _stop: Some(stop_sender),
+            device_switch: Some(DeviceSwitch(switch_sender)),
+            #[cfg(feature = "jack")]
+            _jack: None,
+        },
+        AudioReceivers {
+            fft: fft_receiver,
+            oscilloscope: oscilloscope_receiver,
+        },
+    ))
+}
+
+/// builds and plays a stream on the output device matching `device_name` which is filled by `stream`,
+/// errors of the stream are sent to `errors`
+fn build_stream(
+    host: &Host,
+    device_name: &Option<String>,
+    sample_rate: u32,
+    stream: &Arc<Mutex<Resampler>>,
+    errors: &crossbeam_channel::Sender<cpal::StreamError>,
+) -> Result<Stream, String> {
+    let speaker = select_device(host, device_name.clone())?;
+
+    println!(
+        "Audio output device: {}",
+        speaker.name().unwrap_or_else(|_| "<unknown>".to_string())
+    );
+
+    // the generator keeps running at its sample rate, its output is resampled if the device doesn't support it
+    let device_sample_rate = nearest_supported_sample_rate(&speaker, sample_rate);
+    if device_sample_rate != sample_rate {
+        println!(
+            "The audio output device does not support {} Hz, resampling to {} Hz",
+            sample_rate, device_sample_rate
+        );
+    }
+    stream.lock().set_output_sample_rate(device_sample_rate);
+
+    let stream_config = StreamConfig {
+        sample_rate: SampleRate(device_sample_rate),
+        channels: 2,
+        buffer_size: BufferSize::Default,
+    };
+
+    println!("Audio output format: {:?}", stream_config);
+
+    let speaker_stream = speaker
+        .build_output_stream::<f32, _, _>(
+            &stream_config,
+            {
+                let stream = stream.clone();
+                move |data, _info| {
+                    let _ = stream.lock().fill(data);
+                }
+            },
+            {
+                let errors = errors.clone();
                 move |e| {
                     println!("== An error occurred during audio playback: {:?}", e);
-                },
-            )
-            .expect("Failed to build audio output stream");
+                    let _ = errors.send(e);
+                }
+            },
+        )
+        .map_err(|e| {
+            print_supported_configs(&speaker);
+            format!("Failed to build audio output stream: {}", e)
+        })?;
 
-        speaker_stream.play().expect("Failed to play stream");
+    speaker_stream
+        .play()
+        .map_err(|e| format!("Failed to play stream: {}", e))?;
 
-        std::thread::spawn({
-            move || {
-                let mut buf = [0.0f32; GENERATOR_BUFFER_SIZE];
+    Ok(speaker_stream)
+}
 
-                loop {
-                    // contains lock guard
-                    {
-                        gen.write().generate(&mut buf);
-                    }
+/// longest JACK buffer in samples which can be played back without underruns
+#[cfg(feature = "jack")]
+const JACK_RING_SIZE: usize = 16384;
+/// JACK buffers which are kept generated in advance
+#[cfg(feature = "jack")]
+const JACK_BUFFERS_AHEAD: usize = 2;
+/// time between checks whether the JACK process callback needs more samples
+#[cfg(feature = "jack")]
+const JACK_FEED_INTERVAL: Duration = Duration::from_millis(1);
 
-                    let _ = generator_fft_sender.try_send(buf.to_vec());
+/// JACK process callback, plays back the samples of `ring` and silence if it runs empty
+#[cfg(feature = "jack")]
+pub struct JackProcess {
+    output_port: jack::Port<jack::AudioOut>,
+    ring: RingConsumer,
+    /// current JACK buffer size, read by the thread feeding the ring
+    buffer_size: Arc<AtomicUsize>,
+}
 
-                    if generator_sender.send(buf.to_vec()).is_err() {
+#[cfg(feature = "jack")]
+impl jack::ProcessHandler for JackProcess {
+    fn process(&mut self, _: &jack::Client, ps: &jack::ProcessScope) -> jack::Control {
+        for out in self.output_port.as_mut_slice(ps) {
+            *out = self.ring.try_recv().unwrap_or(0.0);
+        }
+
+        jack::Control::Continue
+    }
+
+    fn buffer_size(&mut self, _: &jack::Client, size: jack::Frames) -> jack::Control {
+        self.buffer_size.store(size as usize, Ordering::Relaxed);
+        jack::Control::Continue
+    }
+}
+
+/// starts a JACK client with a single mono output port which plays back the downmix of the generator thread's buffers
+///
+/// the buffer size is dictated by the JACK server and may change at runtime. The process callback neither blocks
+/// nor allocates, a separate thread downmixes the generator thread's buffers into a lock-free ring buffer which the
+/// callback reads from.
+#[cfg(feature = "jack")]
+pub fn init_jack(
+    generator_thread: GeneratorThread,
+    sample_rate: u32,
+) -> Result<(Audio, AudioReceivers), String> {
+    let (generator_sender, jack_receiver) =
+        crossbeam_channel::bounded::<Vec<f32>>(GENERATOR_CHANNEL_SIZE);
+    let (generator_fft_sender, fft_receiver) = crossbeam_channel::bounded(GENERATOR_CHANNEL_SIZE);
+    let (oscilloscope_sender, oscilloscope_receiver) =
+        crossbeam_channel::bounded(OSCILLOSCOPE_CHANNEL_SIZE);
+
+    let (client, _status) = jack::Client::new("enginesound", jack::ClientOptions::NO_START_SERVER)
+        .map_err(|e| format!("Failed to create JACK client: {}", e))?;
+
+    println!(
+        "Audio driver: JACK\nSamplerate: {} Hz\nBuffer size: {} samples",
+        client.sample_rate(),
+        client.buffer_size()
+    );
+
+    if client.sample_rate() != sample_rate as usize {
+        eprintln!(
+            "JACK server samplerate ({} Hz) differs from the generator samplerate ({} Hz), playback will be pitched",
+            client.sample_rate(),
+            sample_rate
+        );
+    }
+
+    let output_port = client
+        .register_port("output", jack::AudioOut)
+        .map_err(|e| format!("Failed to register JACK output port: {}", e))?;
+
+    let output_port_name = output_port
+        .name()
+        .map_err(|e| format!("Failed to get JACK output port name: {}", e))?;
+
+    let (mut producer, consumer) = sample_ring(JACK_RING_SIZE);
+    let buffer_size = Arc::new(AtomicUsize::new(client.buffer_size() as usize));
+
+    let active_client = client
+        .activate_async(
+            (),
+            JackProcess {
+                output_port,
+                ring: consumer,
+                buffer_size: buffer_size.clone(),
+            },
+        )
+        .map_err(|e| format!("Failed to activate JACK client: {}", e))?;
+
+    // connect to the first two physical playback ports (usually the speakers), the port can also be patched manually
+    for playback_port in active_client
+        .as_client()
+        .ports(
+            None,
+            Some(jack::jack_sys::FLOAT_MONO_AUDIO),
+            jack::PortFlags::IS_INPUT | jack::PortFlags::IS_PHYSICAL,
+        )
+        .iter()
+        .take(2)
+    {
+        if let Err(e) = active_client
+            .as_client()
+            .connect_ports_by_name(&output_port_name, playback_port)
+        {
+            eprintln!(
+                "Failed to connect to JACK port \"{}\": {}",
+                playback_port, e
+            );
+        }
+    }
+
+    // keeps the ring filled with a few JACK buffers, the generator's buffers are freed on this thread.
+    // Stops when the client was dropped with the `Audio`, which also stops the generator thread
+    std::thread::spawn(move || {
+        let mut mono = Vec::with_capacity(GENERATOR_BUFFER_SIZE);
+
+        for stereo in jack_receiver {
+            mono.clear();
+            mono.extend(
+                stereo
+                    .chunks_exact(2)
+                    .map(|lr: &[f32]| (lr[0] + lr[1]) * 0.5),
+            );
+
+            let mut pushed = 0;
+            loop {
+                let ahead = (buffer_size.load(Ordering::Relaxed) * JACK_BUFFERS_AHEAD)
+                    .min(JACK_RING_SIZE - GENERATOR_BUFFER_SIZE);
+                if producer.len() < ahead {
+                    pushed += producer.push_slice(&mono[pushed..]);
+                    if pushed == mono.len() {
                         break;
                     }
                 }
+
+                if producer.is_abandoned() {
+                    return;
+                }
+                std::thread::sleep(JACK_FEED_INTERVAL);
             }
-        });
+        }
+    });
+
+    std::thread::spawn(move || {
+        generator_thread.run(
+            GENERATOR_BUFFER_SIZE,
+            generator_sender,
+            &[oscilloscope_sender, generator_fft_sender],
+        )
+    });
+
+    Ok((
+        Audio {
+            _stop: None,
+            device_switch: None,
+            _jack: Some(active_client),
+        },
+        AudioReceivers {
+            fft: fft_receiver,
+            oscilloscope: oscilloscope_receiver,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        // let's just forget about (this/the stream so it stays open)
-        std::mem::forget(speaker_stream);
+    #[test]
+    fn listed_devices_can_be_selected_by_name() {
+        let host = cpal::default_host();
 
-        Ok((Audio, fft_receiver))
-    })
-    .join()
-    .unwrap()
+        // machines without audio hardware, e.g. CI runners, don't list any devices
+        for name in list_devices() {
+            let device = select_device(&host, Some(name.clone())).unwrap();
+            assert_eq!(device.name().unwrap(), name);
+        }
+    }
 }
@@ -0,0 +1,201 @@
+//! ## Automation module ##
+//!
+//! Time-stamped keyframes of the rpm, throttle and master volume for scripted recordings,
+//! loaded from a RON list of `Keyframe`s or a CSV file with a `time,rpm,throttle,volume,hold` header.
+//! Every parameter follows the keyframes which set it, keyframes which leave it unset are skipped.
+//!
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// time in seconds after the warmup
+    pub time: f32,
+    #[serde(default)]
+    pub rpm: Option<f32>,
+    /// 0.0 (closed) - 1.0 (wide open)
+    #[serde(default)]
+    pub throttle: Option<f32>,
+    /// master volume
+    #[serde(default)]
+    pub volume: Option<f32>,
+    /// keeps the values of this keyframe until the next keyframe instead of interpolating linearly towards it
+    #[serde(default)]
+    pub hold: bool,
+}
+
+/// the automated parameters at a point in time, `None` if no keyframe sets the parameter
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AutomationValues {
+    pub rpm: Option<f32>,
+    pub throttle: Option<f32>,
+    pub volume: Option<f32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Automation {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Automation {
+    /// returns `None` if there are no keyframes, the keyframes are sorted by their time
+    pub fn new(mut keyframes: Vec<Keyframe>) -> Option<Automation> {
+        if keyframes.is_empty() {
+            None
+        } else {
+            keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+            Some(Automation { keyframes })
+        }
+    }
+
+    /// loads a CSV automation if the path ends with `csv`, otherwise a RON automation
+    pub fn load(path: &str) -> Result<Automation, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read automation \"{}\": {}", path, e))?;
+
+        let keyframes = if path.ends_with("csv") {
+            parse_csv(&text)
+        } else {
+            ron::de::from_str::<Vec<Keyframe>>(&text).map_err(|e| e.to_string())
+        }
+        .map_err(|e| format!("Failed to load automation \"{}\": {}", path, e))?;
+
+        if let Some(keyframe) = keyframes.iter().find(|keyframe| !keyframe.time.is_finite()) {
+            return Err(format!(
+                "Failed to load automation \"{}\": invalid keyframe time {}",
+                path, keyframe.time
+            ));
+        }
+
+        Automation::new(keyframes)
+            .ok_or_else(|| format!("Failed to load automation \"{}\": no keyframes", path))
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    /// returns the time of the last keyframe in seconds
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().unwrap().time
+    }
+
+    /// returns the parameters at `time` seconds, the values of the first/last keyframe setting a parameter are held before/after them
+    pub fn sample_at(&self, time: f32) -> AutomationValues {
+        AutomationValues {
+            rpm: self.sample_parameter(time, |keyframe| keyframe.rpm),
+            throttle: self.sample_parameter(time, |keyframe| keyframe.throttle),
+            volume: self.sample_parameter(time, |keyframe| keyframe.volume),
+        }
+    }
+
+    fn sample_parameter(
+        &self,
+        time: f32,
+        parameter: impl Fn(&Keyframe) -> Option<f32>,
+    ) -> Option<f32> {
+        let mut last = None;
+
+        for keyframe in self.keyframes.iter() {
+            let value = match parameter(keyframe) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            if keyframe.time > time {
+                return Some(match last {
+                    None => value,
+                    Some((_, start_value, true)) => start_value,
+                    Some((start_time, start_value, false)) => {
+                        start_value
+                            + (value - start_value) * (time - start_time)
+                                / (keyframe.time - start_time)
+                    }
+                });
+            }
+
+            last = Some((keyframe.time, value, keyframe.hold));
+        }
+
+        last.map(|(_, value, _)| value)
+    }
+}
+
+/// parses lines of comma separated values below a header naming the columns, e.g. `time,rpm,throttle,volume,hold`,
+/// values may be left empty, empty lines and lines starting with `#` are skipped
+fn parse_csv(text: &str) -> Result<Vec<Keyframe>, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let columns = match lines.next() {
+        Some((_, header)) => header
+            .split(',')
+            .map(|column| match column.trim() {
+                column @ ("time" | "rpm" | "throttle" | "volume" | "hold") => Ok(column),
+                column => Err(format!("unknown column \"{}\"", column)),
+            })
+            .collect::<Result<Vec<&str>, String>>()?,
+        None => return Ok(Vec::new()),
+    };
+
+    let time_column = columns
+        .iter()
+        .position(|column| *column == "time")
+        .ok_or_else(|| "missing \"time\" column".to_string())?;
+
+    lines
+        .map(|(line_number, line)| {
+            let mut keyframe = Keyframe {
+                time: 0.0,
+                rpm: None,
+                throttle: None,
+                volume: None,
+                hold: false,
+            };
+
+            let values = line.split(',').map(str::trim).collect::<Vec<&str>>();
+            if values.len() > columns.len() {
+                return Err(format!("line {}: more values than columns", line_number));
+            }
+            if values.get(time_column).is_none_or(|time| time.is_empty()) {
+                return Err(format!("line {}: missing time", line_number));
+            }
+
+            for (column, value) in columns.iter().zip(values) {
+                if value.is_empty() {
+                    continue;
+                }
+
+                let number = || {
+                    value.parse::<f32>().map_err(|_| {
+                        format!("line {}: invalid {} \"{}\"", line_number, column, value)
+                    })
+                };
+
+                match *column {
+                    "time" => keyframe.time = number()?,
+                    "rpm" => keyframe.rpm = Some(number()?),
+                    "throttle" => keyframe.throttle = Some(number()?),
+                    "volume" => keyframe.volume = Some(number()?),
+                    _ => {
+                        keyframe.hold = match value {
+                            "true" | "1" => true,
+                            "false" | "0" => false,
+                            _ => {
+                                return Err(format!(
+                                    "line {}: invalid hold \"{}\", expected true or false",
+                                    line_number, value
+                                ))
+                            }
+                        }
+                    }
+                }
+            }
+
+            Ok(keyframe)
+        })
+        .collect()
+}
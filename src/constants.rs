@@ -1,3 +1,2 @@
-// these two are only used for reserving Ids for the sliders in the gui
+/// upper end of the cylinder count slider of the gui, configs with more cylinders can still be loaded
 pub const MAX_CYLINDERS: usize = 16;
-pub const MUFFLER_ELEMENT_COUNT: usize = 4;
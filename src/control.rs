@@ -0,0 +1,77 @@
+//! ## Control module ##
+//!
+//! Receives parameter updates over UDP while playing back without a GUI.
+//! Datagrams are either text of the form `rpm 4500.0` (or just `4500.0`) or a little-endian binary `f32` rpm.
+//!
+
+use enginesound::generator_thread::GeneratorHandle;
+use std::io::ErrorKind;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+/// interval at which the rpm is moved towards the last received rpm
+const SMOOTHING_INTERVAL: Duration = Duration::from_millis(5);
+/// time constant of the rpm smoothing in seconds
+const SMOOTHING_TIME: f32 = 0.08;
+
+/// binds a UDP socket to `port` on all interfaces and spawns a thread which writes received rpms into the generator
+pub fn listen(port: u16, generator: GeneratorHandle) -> Result<(), String> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))
+        .map_err(|e| format!("Failed to bind UDP socket to port {}: {}", port, e))?;
+    socket
+        .set_read_timeout(Some(SMOOTHING_INTERVAL))
+        .map_err(|e| format!("Failed to set UDP socket timeout: {}", e))?;
+
+    println!("Listening for RPM control datagrams on UDP port {}", port);
+
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 64];
+        let mut target_rpm = None;
+        let mut last_time = Instant::now();
+
+        loop {
+            match socket.recv_from(&mut buf) {
+                Ok((len, source)) => match parse_rpm(&buf[..len]) {
+                    Some(rpm) => target_rpm = Some(rpm.max(0.0)),
+                    None => eprintln!("Ignoring malformed control datagram from {}", source),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => eprintln!("Failed to receive control datagram: {}", e),
+            }
+
+            let elapsed = last_time.elapsed().as_secs_f32();
+            last_time = Instant::now();
+
+            if let Some(target_rpm) = target_rpm {
+                // exponential smoothing so step changes don't jump in pitch
+                let fac = 1.0 - (-elapsed / SMOOTHING_TIME).exp();
+
+                // the smoothed rpm is slewed by the generator if the engine's rpm slew rate is limited
+                generator.apply(move |generator| {
+                    let rpm = generator.target_rpm().unwrap_or(generator.engine.rpm);
+                    generator.set_target_rpm(rpm + (target_rpm - rpm) * fac);
+                });
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn parse_rpm(datagram: &[u8]) -> Option<f32> {
+    if let Ok(text) = std::str::from_utf8(datagram) {
+        let text = text.trim();
+        let value = text.strip_prefix("rpm").unwrap_or(text).trim();
+
+        if let Ok(rpm) = value.parse::<f32>() {
+            return Some(rpm).filter(|rpm| rpm.is_finite());
+        }
+    }
+
+    if datagram.len() == 4 {
+        let rpm = f32::from_le_bytes([datagram[0], datagram[1], datagram[2], datagram[3]]);
+        return Some(rpm).filter(|rpm| rpm.is_finite());
+    }
+
+    None
+}
@@ -0,0 +1,281 @@
+//! ## Control pipe module ##
+//!
+//! Reads newline-terminated text commands from a named pipe (`mkfifo`, Unix only) or from stdin (`-`) during playback,
+//! e.g. to drive the engine from a game engine or a script. Windows named pipes (`\\.\pipe\<name>`) would have to be
+//! created by this program as their server, which isn't supported, so commands are piped into stdin there.
+//!
+//! Every line is a command followed by its argument, separated by whitespace:
+//!
+//! * `rpm <rpm>` moves the rpm towards this value (slewed like the RPM slider)
+//! * `volume <volume>` sets the master volume
+//! * `throttle <0..1>` sets the throttle
+//! * `<parameter> <value>` sets an engine parameter, see `PARAMETERS`
+//! * `save [path]` saves the current engine config, as JSON if the path ends with `.json`,
+//!   to `enginesound_<time>.esc` if no path is given
+//! * `quit` stops the program
+//!
+//! Empty lines and lines starting with `#` are ignored, invalid commands are reported and skipped.
+//! A named pipe is opened again once its writer closes it, so several scripts can write to it one after another.
+//!
+
+use enginesound::gen::Engine;
+use enginesound::generator_thread::{GeneratorCommand, GeneratorHandle};
+use enginesound::utils::{config_name, save_engine};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+type SetParameter = fn(&mut Engine, f32);
+
+/// engine parameters which can be set by name
+pub const PARAMETERS: &[(&str, SetParameter)] = &[
+    ("intake_volume", |engine, value| {
+        engine.intake_volume = value.max(0.0)
+    }),
+    ("exhaust_volume", |engine, value| {
+        engine.exhaust_volume = value.max(0.0)
+    }),
+    ("engine_vibrations_volume", |engine, value| {
+        engine.engine_vibrations_volume = value.max(0.0)
+    }),
+    ("intake_valve_shift", |engine, value| {
+        engine.intake_valve_shift = value
+    }),
+    ("exhaust_valve_shift", |engine, value| {
+        engine.exhaust_valve_shift = value
+    }),
+    ("intake_noise_factor", |engine, value| {
+        engine.intake_noise_factor = value.max(0.0)
+    }),
+    ("crankshaft_fluctuation", |engine, value| {
+        engine.crankshaft_fluctuation = value.max(0.0)
+    }),
+];
+
+/// a parsed line of the control pipe
+#[derive(Debug, PartialEq)]
+pub enum ControlCommand {
+    Rpm(f32),
+    Volume(f32),
+    Throttle(f32),
+    /// index into `PARAMETERS` and the value
+    Parameter(usize, f32),
+    Save(Option<PathBuf>),
+    Quit,
+}
+
+impl ControlCommand {
+    /// parses a line, returns `Ok(None)` for empty lines and comments
+    pub fn parse(line: &str) -> Result<Option<ControlCommand>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (name, argument) = match line.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (line, None),
+        };
+
+        let value = || match argument.map(str::parse::<f32>) {
+            Some(Ok(value)) if value.is_finite() => Ok(value),
+            _ => Err(format!("\"{}\" expects a number", name)),
+        };
+
+        let command = match name {
+            "rpm" => ControlCommand::Rpm(value()?.max(0.0)),
+            "volume" => ControlCommand::Volume(value()?.max(0.0)),
+            "throttle" => ControlCommand::Throttle(value()?.clamp(0.0, 1.0)),
+            "save" => ControlCommand::Save(argument.map(PathBuf::from)),
+            "quit" => ControlCommand::Quit,
+            _ => match PARAMETERS
+                .iter()
+                .position(|(parameter, _)| *parameter == name)
+            {
+                Some(index) => ControlCommand::Parameter(index, value()?),
+                None => return Err(format!("Unknown command \"{}\"", name)),
+            },
+        };
+
+        Ok(Some(command))
+    }
+}
+
+pub struct ControlServer {
+    /// path of the named pipe, `-` reads from stdin
+    pub path: PathBuf,
+}
+
+impl ControlServer {
+    pub fn new(path: impl Into<PathBuf>) -> ControlServer {
+        ControlServer { path: path.into() }
+    }
+
+    /// applies the commands read from the pipe to the generator on the current thread until `quit` was read,
+    /// stdin or a file which isn't a pipe has ended or the pipe can't be opened, returns whether `quit` was read
+    pub fn serve(&self, generator: GeneratorHandle) -> Result<bool, String> {
+        if self.path == Path::new("-") {
+            let stdin = std::io::stdin();
+            return serve_lines(stdin.lock(), &generator);
+        }
+
+        // opening a Windows named pipe connects to its server, which doesn't exist
+        if cfg!(not(unix)) && self.path.to_string_lossy().starts_with(r"\\.\pipe\") {
+            return Err(format!(
+                "Named control pipes like \"{}\" are only supported on Unix, use \"-\" to read commands from stdin",
+                self.path.display()
+            ));
+        }
+
+        loop {
+            let file = File::open(&self.path).map_err(|e| {
+                format!(
+                    "Failed to open control pipe \"{}\": {}",
+                    self.path.display(),
+                    e
+                )
+            })?;
+
+            let quit = serve_lines(BufReader::new(file), &generator)?;
+            if quit || !is_pipe(&self.path) {
+                return Ok(quit);
+            }
+        }
+    }
+}
+
+/// returns whether `quit` was read
+fn serve_lines(reader: impl BufRead, generator: &GeneratorHandle) -> Result<bool, String> {
+    for line in reader.lines() {
+        let line = line.map_err(|e| format!("Failed to read control pipe: {}", e))?;
+
+        match ControlCommand::parse(&line) {
+            Ok(Some(ControlCommand::Quit)) => return Ok(true),
+            Ok(Some(command)) => apply(command, generator),
+            Ok(None) => {}
+            Err(e) => eprintln!("Ignoring control command \"{}\": {}", line.trim(), e),
+        }
+    }
+
+    Ok(false)
+}
+
+fn apply(command: ControlCommand, generator: &GeneratorHandle) {
+    match command {
+        ControlCommand::Rpm(rpm) => generator.send(GeneratorCommand::SetRpm(rpm)),
+        ControlCommand::Volume(volume) => generator.send(GeneratorCommand::SetVolume(volume)),
+        ControlCommand::Throttle(throttle) => {
+            generator.apply(move |generator| generator.engine.throttle = throttle)
+        }
+        ControlCommand::Parameter(index, value) => {
+            let set = PARAMETERS[index].1;
+            generator.apply(move |generator| set(&mut generator.engine, value))
+        }
+        ControlCommand::Save(path) => {
+            let path = path.unwrap_or_else(|| PathBuf::from(config_name()));
+
+            if let Some(snapshot) = generator.snapshot() {
+                match save_engine(&path, &snapshot.engine) {
+                    Ok(()) => println!("Saved engine config \"{}\"", path.display()),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+        }
+        ControlCommand::Quit => {}
+    }
+}
+
+#[cfg(unix)]
+fn is_pipe(path: &Path) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    std::fs::metadata(path)
+        .map(|metadata| metadata.file_type().is_fifo())
+        .unwrap_or(false)
+}
+
+/// named pipes are only read on Unix, other files are read once
+#[cfg(not(unix))]
+fn is_pipe(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use enginesound::gen::{BiquadFilter, Generator, DC_OFFSET_LP_FREQ};
+    use enginesound::generator_thread::GeneratorThread;
+    use enginesound::presets::load_preset;
+    use std::io::Write;
+    use std::process::Command;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(
+            ControlCommand::parse(" rpm 3500 "),
+            Ok(Some(ControlCommand::Rpm(3500.0)))
+        );
+        assert_eq!(
+            ControlCommand::parse("exhaust_valve_shift -0.1"),
+            Ok(Some(ControlCommand::Parameter(4, -0.1)))
+        );
+        assert_eq!(
+            ControlCommand::parse("save my engine.esc"),
+            Ok(Some(ControlCommand::Save(Some(PathBuf::from(
+                "my engine.esc"
+            )))))
+        );
+        assert_eq!(ControlCommand::parse("# comment"), Ok(None));
+        assert!(ControlCommand::parse("rpm fast").is_err());
+        assert!(ControlCommand::parse("turbo 1").is_err());
+    }
+
+    #[test]
+    fn commands_written_to_the_pipe_change_the_generator() {
+        let path = std::env::temp_dir().join(format!("enginesound_pipe_{}", std::process::id()));
+        let status = Command::new("mkfifo").arg(&path).status().unwrap();
+        assert!(status.success());
+
+        let mut engine = load_preset("i4", SAMPLE_RATE).unwrap();
+        engine.rpm = 1000.0;
+        engine.rpm_slew_rate = None;
+        let generator = Generator::new(
+            SAMPLE_RATE,
+            engine,
+            BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, SAMPLE_RATE),
+        );
+        let (generator_thread, generator) = GeneratorThread::new(generator);
+
+        let (output, buffers) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || generator_thread.run(256, output, &[]));
+        std::thread::spawn(move || buffers.iter().for_each(drop));
+
+        let server = {
+            let path = path.clone();
+            let generator = generator.clone();
+            std::thread::spawn(move || ControlServer::new(path).serve(generator))
+        };
+
+        // the server opens the pipe again for every writer
+        for commands in [
+            "rpm 3500\nintake_volume 0.4\n",
+            "exhaust_valve_shift 0.1\nquit\n",
+        ] {
+            File::create(&path)
+                .unwrap()
+                .write_all(commands.as_bytes())
+                .unwrap();
+        }
+        assert!(server.join().unwrap().unwrap());
+
+        let snapshot = generator.snapshot().unwrap();
+        // the rpm is reached while generating the next buffer
+        assert_eq!(snapshot.target_rpm.unwrap_or(snapshot.engine.rpm), 3500.0);
+        assert_eq!(snapshot.engine.intake_volume, 0.4);
+        assert_eq!(snapshot.engine.exhaust_valve_shift, 0.1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -1,17 +1,19 @@
-pub struct ExactStreamer<T> {
+/// Reads buffers of any length of type `B` from a channel into slices of a fixed length
+pub struct ExactStreamer<T, B = Vec<T>> {
     remainder: Vec<T>,
     remainder_len: usize,
-    receiver: crossbeam_channel::Receiver<Vec<T>>,
+    receiver: crossbeam_channel::Receiver<B>,
 }
 
-impl<T> ExactStreamer<T>
+impl<T, B> ExactStreamer<T, B>
 where
     T: Copy + Default,
+    B: AsRef<[T]>,
 {
     pub fn new(
         remainder_buffer_size: usize,
-        receiver: crossbeam_channel::Receiver<Vec<T>>,
-    ) -> ExactStreamer<T> {
+        receiver: crossbeam_channel::Receiver<B>,
+    ) -> ExactStreamer<T, B> {
         ExactStreamer {
             remainder: vec![T::default(); remainder_buffer_size],
             remainder_len: 0,
@@ -30,6 +32,7 @@ where
 
         while i < out.len() {
             let generated = self.receiver.recv()?;
+            let generated = generated.as_ref();
 
             if generated.len() > out.len() - i {
                 let left = out.len() - i;
@@ -39,14 +42,16 @@ where
 
                 let vec_len = self.remainder.len();
                 if vec_len < self.remainder_len {
-                    self.remainder
-                        .extend(std::iter::repeat(T::default()).take(self.remainder_len - vec_len));
+                    self.remainder.extend(std::iter::repeat_n(
+                        T::default(),
+                        self.remainder_len - vec_len,
+                    ));
                 }
 
                 self.remainder[..self.remainder_len].copy_from_slice(&generated[left..]);
                 break;
             } else {
-                out[i..(i + generated.len())].copy_from_slice(&generated);
+                out[i..(i + generated.len())].copy_from_slice(generated);
                 i += generated.len();
             }
         }
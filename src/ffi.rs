@@ -0,0 +1,196 @@
+//! ## FFI module ##
+//!
+//! C-compatible API around the generator, the header is generated into `include/enginesound.h` by the build script.
+//! `examples/render_wav.c` renders a second of audio with it.
+//!
+//! No function unwinds into the caller: panics are caught and reported as `EnginesoundStatus::Panic`, after which the
+//! generator should be destroyed. `enginesound_create` reports errors as null pointers and prints them to stderr.
+//! `enginesound_generate` doesn't allocate, so it can be called from an audio callback.
+//!
+
+use crate::gen::{BiquadFilter, Engine, Generator, DC_OFFSET_LP_FREQ};
+use crate::utils::fix_engine;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+/// frames generated at once by `enginesound_generate`, its stereo buffer is allocated for this many frames on creation
+const CHUNK_FRAMES: usize = 1024;
+
+/// generator handle owned by the C side, freed with `enginesound_destroy`
+pub struct OpaqueGenerator {
+    generator: Generator,
+    /// stereo output of the generator which is downmixed into the caller's buffer
+    stereo: Vec<f32>,
+}
+
+/// result of the functions which take a generator
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnginesoundStatus {
+    Ok = 0,
+    /// the generator or the buffer was null
+    NullPointer = 1,
+    /// the generator panicked, its state may be inconsistent
+    Panic = 2,
+}
+
+/// runs `f` with the generator behind `gen` and catches its panics
+unsafe fn with_generator(
+    gen: *mut OpaqueGenerator,
+    f: impl FnOnce(&mut OpaqueGenerator),
+) -> EnginesoundStatus {
+    let gen = match gen.as_mut() {
+        Some(gen) => gen,
+        None => return EnginesoundStatus::NullPointer,
+    };
+
+    match catch_unwind(AssertUnwindSafe(|| f(gen))) {
+        Ok(()) => EnginesoundStatus::Ok,
+        Err(_) => EnginesoundStatus::Panic,
+    }
+}
+
+/// creates a generator from a NUL-terminated RON engine config, returns null if the config is invalid
+///
+/// # Safety
+///
+/// `config_ron` must be null or point to a NUL-terminated string
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_create(
+    config_ron: *const c_char,
+    sample_rate: u32,
+) -> *mut OpaqueGenerator {
+    if config_ron.is_null() || sample_rate == 0 {
+        return std::ptr::null_mut();
+    }
+
+    catch_unwind(|| create(CStr::from_ptr(config_ron), sample_rate))
+        .unwrap_or_else(|_| {
+            eprintln!("Failed to create the generator: panicked");
+            None
+        })
+        .map_or(std::ptr::null_mut(), |gen| Box::into_raw(Box::new(gen)))
+}
+
+fn create(config_ron: &CStr, sample_rate: u32) -> Option<OpaqueGenerator> {
+    let config = match config_ron.to_str() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load RON config: {}", e);
+            return None;
+        }
+    };
+
+    let mut engine = match ron::de::from_str::<Engine>(config) {
+        Ok(engine) => engine,
+        Err(e) => {
+            eprintln!("Failed to load RON config: {}", e);
+            return None;
+        }
+    };
+
+    if let Err(e) = fix_engine(&mut engine, sample_rate) {
+        eprintln!("Invalid RON config: {}", e);
+        return None;
+    }
+
+    Some(OpaqueGenerator {
+        generator: Generator::new(
+            sample_rate,
+            engine,
+            BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, sample_rate),
+        ),
+        stereo: vec![0.0; CHUNK_FRAMES * 2],
+    })
+}
+
+/// fills `out` with `frames` mono samples, without allocating
+///
+/// # Safety
+///
+/// `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet,
+/// `out` must be null or valid for writing `frames` floats
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_generate(
+    gen: *mut OpaqueGenerator,
+    out: *mut f32,
+    frames: usize,
+) -> EnginesoundStatus {
+    if out.is_null() {
+        return EnginesoundStatus::NullPointer;
+    }
+    let out = std::slice::from_raw_parts_mut(out, frames);
+
+    with_generator(gen, |gen| {
+        for chunk in out.chunks_mut(CHUNK_FRAMES) {
+            let stereo = &mut gen.stereo[..chunk.len() * 2];
+            gen.generator.generate_stereo(stereo);
+
+            chunk
+                .iter_mut()
+                .zip(stereo.chunks_exact(2))
+                .for_each(|(sample, lr)| *sample = (lr[0] + lr[1]) * 0.5);
+        }
+    })
+}
+
+/// sets the rpm of the engine instantly
+///
+/// # Safety
+///
+/// `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_set_rpm(
+    gen: *mut OpaqueGenerator,
+    rpm: f32,
+) -> EnginesoundStatus {
+    with_generator(gen, |gen| gen.generator.set_rpm(rpm.max(0.0)))
+}
+
+/// sets the engine load 0.0 (closed) - 1.0 (wide open), which the engine follows smoothly
+///
+/// # Safety
+///
+/// `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_set_throttle(
+    gen: *mut OpaqueGenerator,
+    throttle: f32,
+) -> EnginesoundStatus {
+    with_generator(gen, |gen| gen.generator.set_throttle(throttle))
+}
+
+/// clears the sound in the pipes and the state of all filters, e.g. before playing the sound again
+///
+/// # Safety
+///
+/// `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_reset(gen: *mut OpaqueGenerator) -> EnginesoundStatus {
+    with_generator(gen, |gen| gen.generator.reset())
+}
+
+/// frees a generator, null is ignored
+///
+/// # Safety
+///
+/// `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_destroy(gen: *mut OpaqueGenerator) {
+    if !gen.is_null() {
+        let gen = Box::from_raw(gen);
+        // a panicking destructor leaks the rest of the generator instead of unwinding into the caller
+        let _ = catch_unwind(AssertUnwindSafe(|| drop(gen)));
+    }
+}
+
+/// same as `enginesound_destroy`
+///
+/// # Safety
+///
+/// `gen` must be null or a pointer returned by `enginesound_create` which was not destroyed yet
+#[no_mangle]
+pub unsafe extern "C" fn enginesound_free(gen: *mut OpaqueGenerator) {
+    enginesound_destroy(gen)
+}
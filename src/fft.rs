@@ -1,34 +1,174 @@
 use crate::exactstreamer::ExactStreamer;
+use enginesound::generator_thread::MonitorBuffer;
 use num_complex::Complex32;
 use num_traits::identities::Zero;
 use rustfft::FFT;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
+/// smallest FFT size of `--fft-size`
+pub const MIN_FFT_SIZE: usize = 256;
+/// largest FFT size of `--fft-size`
+pub const MAX_FFT_SIZE: usize = 8192;
+/// windows are scaled to the coherent gain (mean) of the Hamming window, so the waterfall is about as bright with every window
+const HAMMING_COHERENT_GAIN: f32 = 0.54;
+/// windows are scaled as if they had this many samples, so the peak of a sine is as high with every FFT size
+const REFERENCE_FFT_SIZE: usize = 1024;
+
+/// Window function applied to the samples before the FFT, see `window_value`
+///
+/// Every window trades frequency resolution for amplitude accuracy and leakage:
+/// * `Rectangular` has the narrowest peaks, but a sine between two bins loses up to 3.9 dB and leaks into the whole spectrum
+/// * `Hann` has narrow peaks (4 bins wide) and its leakage falls off fast, which resolves close frequencies best
+/// * `Hamming` has lower side lobes next to the peak than `Hann` (-43 dB instead of -31 dB), but its leakage falls off slowly
+/// * `Blackman` has wider peaks (6 bins) and less leakage, which shows quiet frequencies next to loud ones
+/// * `FlatTop` has the widest peaks (10 bins), but measures the amplitude of a sine within 0.02 dB wherever it lies between two bins
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WindowFunction {
+    Hann,
+    Hamming,
+    Blackman,
+    FlatTop,
+    Rectangular,
+}
+
+impl WindowFunction {
+    pub const ALL: [WindowFunction; 5] = [
+        WindowFunction::Hann,
+        WindowFunction::Hamming,
+        WindowFunction::Blackman,
+        WindowFunction::FlatTop,
+        WindowFunction::Rectangular,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WindowFunction::Hann => "Hann",
+            WindowFunction::Hamming => "Hamming",
+            WindowFunction::Blackman => "Blackman",
+            WindowFunction::FlatTop => "Flat-top",
+            WindowFunction::Rectangular => "Rectangular",
+        }
+    }
+
+    /// value of `--fft-window`
+    pub fn arg(self) -> &'static str {
+        match self {
+            WindowFunction::Hann => "hann",
+            WindowFunction::Hamming => "hamming",
+            WindowFunction::Blackman => "blackman",
+            WindowFunction::FlatTop => "flat-top",
+            WindowFunction::Rectangular => "rectangular",
+        }
+    }
+
+    /// parses the value of `--fft-window`
+    pub fn parse(arg: &str) -> Option<WindowFunction> {
+        WindowFunction::ALL
+            .iter()
+            .copied()
+            .find(|window| window.arg() == arg.to_ascii_lowercase())
+    }
+}
+
+/// value of the periodic window `function` of length `n` at sample `i`, a sum of cosines `a0 - a1 * cos(x) + a2 * cos(2x) - ..`
+/// with `x = 2πi / n`
+pub fn window_value(function: WindowFunction, i: usize, n: usize) -> f32 {
+    let coefficients: &[f32] = match function {
+        WindowFunction::Hann => &[0.5, 0.5],
+        WindowFunction::Hamming => &[0.54, 0.46],
+        WindowFunction::Blackman => &[0.42, 0.5, 0.08],
+        WindowFunction::FlatTop => &[
+            0.215_578_95,
+            0.416_631_58,
+            0.277_263_16,
+            0.083_578_95,
+            0.006_947_368,
+        ],
+        WindowFunction::Rectangular => &[1.0],
+    };
+    let x = std::f32::consts::PI * 2.0 * i as f32 / n as f32;
+
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            sign * a * (k as f32 * x).cos()
+        })
+        .sum()
+}
+
+/// `window_value`s of a window of length `n`, scaled to the sum of a Hamming window of `REFERENCE_FFT_SIZE` samples
+fn window(function: WindowFunction, n: usize) -> Vec<f32> {
+    let window = (0..n)
+        .map(|i| window_value(function, i, n))
+        .collect::<Vec<f32>>();
+    let gain = HAMMING_COHERENT_GAIN * REFERENCE_FFT_SIZE as f32 / window.iter().sum::<f32>();
+
+    window.into_iter().map(|x| x * gain).collect()
+}
+
+/// magnitudes of the FFT of the `samples` multiplied by the `window`, `fft` has to have the length of both
+fn windowed_magnitudes(samples: &[f32], window: &[f32], fft: &dyn FFT<f32>) -> Vec<f32> {
+    let mut input = samples
+        .iter()
+        .zip(window)
+        .map(|(sample, weight)| Complex32::new(sample * weight, 0.0))
+        .collect::<Vec<_>>();
+    let mut output = vec![Complex32::zero(); samples.len()];
+
+    fft.process(&mut input, &mut output);
+
+    output.iter().map(|complex| complex.norm()).collect()
+}
+
+/// One frame of the spectrum sent by the `FFTStreamer`
+pub struct Spectrum {
+    /// magnitudes of the linearly spaced bins, starting at 0 hz, which decay slowly from their peaks
+    pub values: Vec<f32>,
+    /// frequency difference of two neighboring bins in hz
+    pub bin_width: f32,
+}
+
 pub struct FFTStreamer {
     size: usize,
-    stream: ExactStreamer<f32>,
-    sender: crossbeam_channel::Sender<Vec<f32>>,
+    sample_rate: u32,
+    /// shared with the GUI which can change it while running, see `window_function`
+    window_function: Arc<Mutex<WindowFunction>>,
+    stream: ExactStreamer<f32, MonitorBuffer>,
+    sender: crossbeam_channel::Sender<Spectrum>,
 }
 
 impl FFTStreamer {
+    /// `size` has to be a power of two
     pub fn new(
         size: usize,
-        stream: ExactStreamer<f32>,
-        sender: crossbeam_channel::Sender<Vec<f32>>,
+        sample_rate: u32,
+        window_function: WindowFunction,
+        stream: ExactStreamer<f32, MonitorBuffer>,
+        sender: crossbeam_channel::Sender<Spectrum>,
     ) -> Self {
         FFTStreamer {
             size,
+            sample_rate,
+            window_function: Arc::new(Mutex::new(window_function)),
             stream,
             sender,
         }
     }
 
+    /// the window function applied from the next spectrum on when changed
+    pub fn window_function(&self) -> Arc<Mutex<WindowFunction>> {
+        self.window_function.clone()
+    }
+
     pub fn run(&mut self) {
         let mut buf = vec![0.0f32; self.size];
-        let mut complex_buf = vec![Complex32::zero(); self.size];
-        let mut complex_buf2 = vec![Complex32::zero(); self.size];
 
-        let mut frequencies = vec![0.0; self.size];
+        let mut window_function = *self.window_function.lock().unwrap();
+        let mut weights = window(window_function, self.size);
+
         let mut last_frequencies = vec![0.0; self.size];
         let mut last_time = Instant::now();
 
@@ -39,18 +179,13 @@ impl FFTStreamer {
                 break;
             }
 
-            let window_fac = std::f32::consts::PI * 2.0 / self.size as f32;
-            complex_buf.clear();
-            complex_buf.extend(buf.iter().enumerate().map(|(i, sample)| {
-                Complex32::new(*sample * (0.54 - 0.46 * (i as f32 * window_fac).cos()), 0.0)
-            }));
-
-            fft.process(&mut complex_buf, &mut complex_buf2);
+            let selected = *self.window_function.lock().unwrap();
+            if selected != window_function {
+                window_function = selected;
+                weights = window(window_function, self.size);
+            }
 
-            frequencies
-                .iter_mut()
-                .zip(complex_buf2.iter().map(|complex| complex.norm()))
-                .for_each(|(old, new)| *old = new);
+            let frequencies = windowed_magnitudes(&buf, &weights, &fft);
 
             let fac = 0.00005f32.powf(last_time.elapsed().as_secs_f32());
             last_time = Instant::now();
@@ -65,12 +200,10 @@ impl FFTStreamer {
 
             if self
                 .sender
-                .send(
-                    last_frequencies
-                        .iter()
-                        .map(|x| (((x * 0.008).exp() - 1.0) * 0.7).powf(0.5) * 2.0)
-                        .collect::<Vec<f32>>(),
-                )
+                .send(Spectrum {
+                    values: last_frequencies.clone(),
+                    bin_width: self.sample_rate as f32 / self.size as f32,
+                })
                 .is_err()
             {
                 break;
@@ -78,3 +211,79 @@ impl FFTStreamer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// magnitudes up to the nyquist frequency of a sine exactly at `bin` or between two bins
+    fn sine_spectrum(window_function: WindowFunction, bin: f32) -> Vec<f32> {
+        const SIZE: usize = 1024;
+        let samples = (0..SIZE)
+            .map(|i| (std::f32::consts::PI * 2.0 * bin * i as f32 / SIZE as f32).sin())
+            .collect::<Vec<f32>>();
+        let fft = rustfft::algorithm::Radix4::new(SIZE, false);
+
+        let mut magnitudes = windowed_magnitudes(&samples, &window(window_function, SIZE), &fft);
+        magnitudes.truncate(SIZE / 2);
+        magnitudes
+    }
+
+    /// asserts that the largest magnitude is at `bin` and that every bin further than `main_lobe` bins away is
+    /// at least 40 dB below it
+    fn assert_single_peak(magnitudes: &[f32], bin: usize, main_lobe: usize) {
+        let (peak_bin, &peak) = magnitudes
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.partial_cmp(b.1).unwrap())
+            .unwrap();
+        assert_eq!(peak_bin, bin);
+
+        for (i, &magnitude) in magnitudes.iter().enumerate() {
+            if (i as isize - bin as isize).unsigned_abs() > main_lobe {
+                assert!(
+                    magnitude < peak * 0.01,
+                    "bin {}: {} of {}",
+                    i,
+                    magnitude,
+                    peak
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sine_has_a_single_dominant_bin() {
+        assert_single_peak(&sine_spectrum(WindowFunction::Hann, 100.0), 100, 2);
+        assert_single_peak(&sine_spectrum(WindowFunction::FlatTop, 100.0), 100, 5);
+        // leakage of a sine between two bins
+        assert_single_peak(&sine_spectrum(WindowFunction::Hann, 100.3), 100, 4);
+        assert_single_peak(&sine_spectrum(WindowFunction::FlatTop, 100.3), 100, 5);
+    }
+
+    #[test]
+    fn flat_top_measures_the_amplitude_between_bins() {
+        let peak = |window_function, bin| {
+            sine_spectrum(window_function, bin)
+                .into_iter()
+                .fold(0.0, f32::max)
+        };
+
+        let flat_top = peak(WindowFunction::FlatTop, 100.5) / peak(WindowFunction::FlatTop, 100.0);
+        assert!((flat_top - 1.0).abs() < 0.01, "{}", flat_top);
+        // the hann window loses about 1.4 dB
+        let hann = peak(WindowFunction::Hann, 100.5) / peak(WindowFunction::Hann, 100.0);
+        assert!(hann < 0.9, "{}", hann);
+    }
+
+    #[test]
+    fn window_arguments_round_trip() {
+        for &window_function in WindowFunction::ALL.iter() {
+            assert_eq!(
+                WindowFunction::parse(window_function.arg()),
+                Some(window_function)
+            );
+        }
+        assert_eq!(WindowFunction::parse("kaiser"), None);
+    }
+}
@@ -4,86 +4,1117 @@
 //! Every sample-output generating object (Cylinder, WaveGuide, DelayLine, ..) has to be first `pop`ped,
 //! it's output worked upon and then new input samples are `push`ed.
 //!
+//! The model follows Baldan et al., "Physically informed car engine sound synthesis for virtual and augmented
+//! environments" (2015, linked below the constants). Every cylinder is a pressure source driven by the piston motion and
+//! the combustion, coupled to the intake and exhaust through digital waveguides (bidirectional delay lines, see `WaveGuide`)
+//! whose reflection factor at the cylinder follows the valve openings. One `Generator::gen` step is one sample:
+//!
+//! ```text
+//! intake noise -> LP -> (resonator)
+//!        |
+//!        v              one per cylinder, see `Cylinder`
+//! (Intake collector) <==|IV|> (Cylinder) <|EV|==> (Exhaust) <====> (Exhaust collector) <====> (Straight pipe) <====> (Muffler)
+//!     |      ^                    |                                        |                                           |
+//!     |      `---------------- EGR pipe (optional) <-----------------------'                                           |
+//!     v                           v                                                                                    v
+//!   intake               engine vibrations                                                                          exhaust
+//! ```
+//!
+//! The intake, engine vibrations and exhaust signals are mixed with their volumes (linear gains) into the output.
+//! Positions within the engine cycle are given in cycles of 0.0 - 1.0, one cycle being the two crankshaft revolutions
+//! (720°) of the four strokes: intake 0.0 - 0.25, compression 0.25 - 0.5, power 0.5 - 0.75 and exhaust 0.75 - 1.0.
+//! Lengths of pipes are given as the delay in seconds of a pressure wave travelling through them at `SPEED_OF_SOUND`.
+//!
 
 use crate::recorder::Recorder;
+use crate::state::{GeneratorState, StateRestorer, StateVisitor};
+use crate::utils::{Resampler, SPEED_OF_SOUND};
 
 use rand_core::{RngCore, SeedableRng};
 use rand_xorshift::XorShiftRng;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::SystemTime;
 
 pub const PI2F: f32 = 2.0 * std::f32::consts::PI;
 pub const PI4F: f32 = 4.0 * std::f32::consts::PI;
 pub const WAVEGUIDE_MAX_AMP: f32 = 20.0; // at this amplitude, a damping function is applied to fight feedback loops
+/// with `Engine::waveguide_adaptive_damping`, the gain of a waveguide is reduced while its running maximum amplitude exceeds this
+pub const WAVEGUIDE_DAMPEN_THRESHOLD: f32 = 10.0;
+/// samples the running maximum amplitude of a waveguide has to exceed `WAVEGUIDE_DAMPEN_THRESHOLD` before its gain is reduced,
+/// so single peaks don't dampen the waveguide
+pub const MIN_STABLE_SAMPLES: usize = 256;
+/// per sample decay of the running maximum amplitude of a waveguide (about 20 ms at 48 kHz)
+const WAVEGUIDE_RUNNING_MAX_DECAY: f32 = 0.999;
+/// per sample factor of the gain of an unstable waveguide (halves in about 30 ms at 48 kHz)
+const WAVEGUIDE_DAMPING_GAIN_REDUCTION: f32 = 0.9995;
+/// per sample increase of the gain of a stable waveguide (recovers from 0 in about 2 seconds at 48 kHz)
+const WAVEGUIDE_DAMPING_ATTACK: f32 = 0.00001;
+/// lowest gain of an adaptively dampened waveguide
+const WAVEGUIDE_DAMPING_MIN_GAIN: f32 = 0.05;
+/// time constant in seconds by which the throttle follows `Engine::throttle`
+pub const THROTTLE_SMOOTHING_TIME: f32 = 0.02;
+/// engines below this rpm barely produce any sound, `Engine::validate` warns about them
+pub const MIN_RECOMMENDED_RPM: f32 = 100.0;
+/// `Engine::validate` warns about intake, exhaust and engine vibrations volumes whose sum differs more than this from 1
+pub const VOLUME_SUM_TOLERANCE: f32 = 0.1;
+/// duration in seconds of the crossfade when the engine is replaced with `Generator::swap_engine`, short enough to sound instant
+pub const ENGINE_SWAP_TIME: f32 = 0.05;
+/// fraction of the ignition volume at closed throttle
+pub const CLOSED_THROTTLE_IGNITION: f32 = 0.15;
+/// fraction of the intake noise flowing through the intake valves at closed throttle
+pub const CLOSED_THROTTLE_INTAKE_NOISE: f32 = 0.3;
+/// volume of the intake noise hissing past the closed throttle plate into the intake
+pub const THROTTLE_VACUUM_HISS: f32 = 0.5;
+/// below this throttle the fuel is cut off gradually (engine braking), the ignition fades out towards closed throttle
+pub const ENGINE_BRAKING_THROTTLE: f32 = 0.2;
+/// additional vacuum hiss at closed throttle while engine braking, relative to `THROTTLE_VACUUM_HISS`
+pub const ENGINE_BRAKING_VACUUM_HISS: f32 = 0.5;
+/// additional crankshaft fluctuation at closed throttle while engine braking, relative to `Engine::crankshaft_fluctuation`
+pub const ENGINE_BRAKING_CRANKSHAFT_FLUCTUATION: f32 = 1.0;
+/// crank offsets closer than this (in cycles) fire at the same time, see `Engine::validate_firing_order`
+pub const FIRING_OFFSET_TOLERANCE: f32 = 0.001;
+/// `Engine::validate_firing_order` warns about gaps between two ignitions which are longer than this many even intervals
+pub const UNEVEN_FIRING_GAP: f32 = 1.5;
+/// cutoff frequency in hz of the high pass which removes the dc offset of the generator's output, see `Generator::new`
+///
+/// the first order low pass this replaced was at 0.5 hz, so the default removes more of the lowest frequencies than it
+pub const DC_OFFSET_LP_FREQ: f32 = 4.0;
+/// delay in seconds of the EGR pipe of configs which don't have one (about 0.5 m)
+pub const DEFAULT_EGR_DELAY: f32 = 0.0015;
+/// reflection factor of both ends of the EGR pipe of configs which don't have one
+pub const DEFAULT_EGR_REFLECTIVITY: f32 = 0.1;
+/// filter states and waveguide samples below this magnitude (-400 dB) are flushed to zero, because decaying signals
+/// otherwise end up as subnormal floats which are very slow to compute with on x86
+pub const DENORMAL_THRESHOLD: f32 = 1e-20;
 
 // https://www.researchgate.net/profile/Stefano_Delle_Monache/publication/280086598_Physically_informed_car_engine_sound_synthesis_for_virtual_and_augmented_environments/links/55a791bc08aea2222c746724/Physically-informed-car-engine-sound-synthesis-for-virtual-and-augmented-environments.pdf?origin=publication_detail
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Muffler {
     pub straight_pipe: WaveGuide,
-    pub muffler_elements: Vec<WaveGuide>,
+    pub muffler_elements: Vec<MufflerElement>,
+}
+
+/// quality factor of the notch of a `MufflerElement::HelmholtzResonator`
+pub const HELMHOLTZ_Q: f32 = 4.0;
+
+/// One of the parallel elements of the muffler which are fed by the end of the straight pipe
+///
+/// The variants are untagged, so the plain waveguides of configs from before the resonators were added are straight pipes
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
+pub enum MufflerElement {
+    /// a closed pipe whose beta side is the exhaust output
+    StraightPipe(WaveGuide),
+    /// a cavity connected to the muffler through a narrow neck, which is modeled as a lumped element:
+    /// a shunt band stop (notch) filter at its resonant frequency, see `helmholtz_frequency`
+    HelmholtzResonator {
+        /// in meters
+        neck_length: f32,
+        /// in meters
+        neck_radius: f32,
+        /// in cubic meters
+        cavity_volume: f32,
+        /// reflectivity of the cancelled band back into the straight pipe
+        alpha: f32,
+        // running values
+        #[serde(skip, default = "default_helmholtz_notch")]
+        notch: EqBand,
+        /// last input of the notch
+        #[serde(skip)]
+        input: f32,
+        /// last output of the notch
+        #[serde(skip)]
+        output: f32,
+    },
+}
+
+/// fields of both `MufflerElement` variants, serde's untagged enums can't read integers from RON into floats
+#[derive(Deserialize)]
+struct MufflerElementConfig {
+    #[serde(default, deserialize_with = "present")]
+    chamber0: Option<DelayLine>,
+    #[serde(default, deserialize_with = "present")]
+    chamber1: Option<DelayLine>,
+    alpha: f32,
+    #[serde(default, deserialize_with = "present")]
+    beta: Option<f32>,
+    #[serde(default, deserialize_with = "present")]
+    neck_length: Option<f32>,
+    #[serde(default, deserialize_with = "present")]
+    neck_radius: Option<f32>,
+    #[serde(default, deserialize_with = "present")]
+    cavity_volume: Option<f32>,
+}
+
+/// reads a field which is `None` if it is missing without requiring `Some(..)` around it
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+impl<'de> Deserialize<'de> for MufflerElement {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        let config = MufflerElementConfig::deserialize(deserializer)?;
+
+        match config {
+            MufflerElementConfig {
+                chamber0: Some(chamber0),
+                chamber1: Some(chamber1),
+                alpha,
+                beta: Some(beta),
+                ..
+            } => Ok(MufflerElement::StraightPipe(WaveGuide {
+                chamber0,
+                chamber1,
+                alpha,
+                beta,
+                fractional_delay: 0.0,
+                c1_out: 0.0,
+                c0_out: 0.0,
+                running_max: 0.0,
+                samples_over_threshold: 0,
+                gain: default_gain(),
+                dampened: false,
+            })),
+            MufflerElementConfig {
+                alpha,
+                neck_length: Some(neck_length),
+                neck_radius: Some(neck_radius),
+                cavity_volume: Some(cavity_volume),
+                ..
+            } => Ok(MufflerElement::helmholtz_resonator(
+                neck_length,
+                neck_radius,
+                cavity_volume,
+                alpha,
+            )),
+            _ => Err(D::Error::custom(
+                "a muffler element needs either chamber0, chamber1 and beta or neck_length, neck_radius and cavity_volume",
+            )),
+        }
+    }
+}
+
+fn default_helmholtz_notch() -> EqBand {
+    // the frequency is set from the resonator's geometry while generating
+    EqBand::new(EqBandKind::Notch, 1000.0, 0.0, HELMHOLTZ_Q)
+}
+
+/// resonant frequency in hz of a Helmholtz resonator with a cylindrical neck, `f = c / 2pi * sqrt(A / (L * V))`
+pub fn helmholtz_frequency(neck_length: f32, neck_radius: f32, cavity_volume: f32) -> f32 {
+    let neck_area = std::f32::consts::PI * neck_radius * neck_radius;
+
+    SPEED_OF_SOUND / PI2F * (neck_area / (neck_length * cavity_volume)).sqrt()
+}
+
+impl MufflerElement {
+    pub fn helmholtz_resonator(
+        neck_length: f32,
+        neck_radius: f32,
+        cavity_volume: f32,
+        alpha: f32,
+    ) -> MufflerElement {
+        MufflerElement::HelmholtzResonator {
+            neck_length,
+            neck_radius,
+            cavity_volume,
+            alpha,
+            notch: default_helmholtz_notch(),
+            input: 0.0,
+            output: 0.0,
+        }
+    }
+
+    pub fn waveguide(&self) -> Option<&WaveGuide> {
+        match self {
+            MufflerElement::StraightPipe(waveguide) => Some(waveguide),
+            MufflerElement::HelmholtzResonator { .. } => None,
+        }
+    }
+
+    pub fn waveguide_mut(&mut self) -> Option<&mut WaveGuide> {
+        match self {
+            MufflerElement::StraightPipe(waveguide) => Some(waveguide),
+            MufflerElement::HelmholtzResonator { .. } => None,
+        }
+    }
+
+    /// resonant frequency in hz of a Helmholtz resonator
+    pub fn resonant_frequency(&self) -> Option<f32> {
+        match self {
+            MufflerElement::StraightPipe(_) => None,
+            MufflerElement::HelmholtzResonator {
+                neck_length,
+                neck_radius,
+                cavity_volume,
+                ..
+            } => Some(helmholtz_frequency(
+                *neck_length,
+                *neck_radius,
+                *cavity_volume,
+            )),
+        }
+    }
+
+    /// keeps the running values if the variant and, for straight pipes, the length did not change
+    fn apply_parameters(&mut self, from: MufflerElement) {
+        match (self, from) {
+            (MufflerElement::StraightPipe(waveguide), MufflerElement::StraightPipe(from)) => {
+                waveguide.apply_parameters(from)
+            }
+            (
+                MufflerElement::HelmholtzResonator {
+                    neck_length,
+                    neck_radius,
+                    cavity_volume,
+                    alpha,
+                    ..
+                },
+                MufflerElement::HelmholtzResonator {
+                    neck_length: from_neck_length,
+                    neck_radius: from_neck_radius,
+                    cavity_volume: from_cavity_volume,
+                    alpha: from_alpha,
+                    ..
+                },
+            ) => {
+                *neck_length = from_neck_length;
+                *neck_radius = from_neck_radius;
+                *cavity_volume = from_cavity_volume;
+                *alpha = from_alpha;
+            }
+            (element, from) => *element = from,
+        }
+    }
+
+    /// returns the outputs towards the straight pipe and towards the exhaust
+    fn pop(&mut self, adaptive_damping: bool) -> (f32, f32) {
+        match self {
+            MufflerElement::StraightPipe(waveguide) => {
+                let (alpha_out, beta_out, _) = waveguide.pop(adaptive_damping);
+                (alpha_out, beta_out)
+            }
+            MufflerElement::HelmholtzResonator {
+                alpha,
+                input,
+                output,
+                ..
+            } => (*alpha * (*input - *output), *output),
+        }
+    }
+
+    /// takes in the pressure at the end of the straight pipe
+    fn push(&mut self, x: f32, samples_per_second: u32) {
+        match self {
+            MufflerElement::StraightPipe(waveguide) => waveguide.push(x, 0.0),
+            MufflerElement::HelmholtzResonator {
+                neck_length,
+                neck_radius,
+                cavity_volume,
+                notch,
+                input,
+                output,
+                ..
+            } => {
+                notch.freq = helmholtz_frequency(*neck_length, *neck_radius, *cavity_volume);
+                notch.update(samples_per_second);
+
+                *input = x;
+                *output = flush_denormal(notch.filter(x, 0));
+            }
+        }
+    }
+
+    /// silences the element
+    fn reset(&mut self) {
+        match self {
+            MufflerElement::StraightPipe(waveguide) => {
+                waveguide.chamber0.samples.data.fill(0.0);
+                waveguide.chamber1.samples.data.fill(0.0);
+            }
+            MufflerElement::HelmholtzResonator {
+                notch,
+                input,
+                output,
+                ..
+            } => {
+                notch.state = [[0.0; 4]; 2];
+                *input = 0.0;
+                *output = 0.0;
+            }
+        }
+    }
+
+    /// straight pipes are visited with the other waveguides, see `Engine::waveguides`
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        match self {
+            MufflerElement::StraightPipe(_) => {}
+            MufflerElement::HelmholtzResonator {
+                notch,
+                input,
+                output,
+                ..
+            } => {
+                notch.state[0].iter_mut().for_each(|x| visitor.value(x));
+                visitor.value(input);
+                visitor.value(output);
+            }
+        }
+    }
+
+    /// magnitude response from the straight pipe to the exhaust at `num_bins` linearly spaced frequencies
+    /// from 0 to `sample_rate / 2`, see `WaveGuide::frequency_response`
+    pub fn frequency_response(&self, sample_rate: u32, num_bins: usize) -> Vec<f32> {
+        match self {
+            MufflerElement::StraightPipe(waveguide) => {
+                waveguide.frequency_response(sample_rate, num_bins)
+            }
+            MufflerElement::HelmholtzResonator { .. } => {
+                let mut notch = default_helmholtz_notch();
+                notch.freq = self.resonant_frequency().unwrap();
+                notch.update(sample_rate);
+                let bin_width = sample_rate as f32 * 0.5 / num_bins.saturating_sub(1).max(1) as f32;
+
+                (0..num_bins)
+                    .map(|i| notch.magnitude(i as f32 * bin_width, sample_rate))
+                    .collect()
+            }
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+/// Configuration and running state of the whole engine model, see the module documentation for the signal flow
+///
+/// The volumes are linear gains of the three output signals which usually add up to about 1.0
+/// (see `VOLUME_SUM_TOLERANCE`), reflection factors range from -1.0 to 1.0
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Engine {
+    /// name of the engine, shown in the window title and written to recorded WAV files like `author` and `description`
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// crankshaft revolutions per minute, the engine cycle advances by `rpm / 120` cycles per second
     pub rpm: f32,
+    /// gain of the intake collector signal 0.0 - 1.0
     pub intake_volume: f32,
+    /// gain of the muffler output 0.0 - 1.0
     pub exhaust_volume: f32,
+    /// gain of the low passed sum of the cylinders' pressure (piston motion and ignition) 0.0 - 1.0
     pub engine_vibrations_volume: f32,
+    /// engine load 0.0 (closed) - 1.0 (wide open), scales the combustion intensity and the intake noise
+    #[serde(default = "default_throttle")]
+    pub throttle: f32,
+    /// maximum rate in rpm per second at which the rpm follows `Generator::set_target_rpm` to simulate the flywheel's inertia,
+    /// unlimited if unset
+    #[serde(default)]
+    pub rpm_slew_rate: Option<f32>,
 
     pub cylinders: Vec<Cylinder>,
+    /// name of a firing preset (see `utils::FIRING_PRESETS`) which sets the cylinder count and crank offsets while fixing the engine,
+    /// unset when offsets are edited manually
+    #[serde(default)]
+    pub firing_preset: Option<String>,
     #[serde(skip)]
     pub intake_noise: Noise,
+    /// gain of the turbulent air noise which is fed into the intake waveguides while the intake valves are open
     pub intake_noise_factor: f32,
+    /// shapes the white intake noise, usually a few khz
     pub intake_noise_lp: LowPassFilter,
+    /// optional airbox resonance which the intake noise passes through before it reaches the valves
+    #[serde(default)]
+    pub intake_resonator: Option<Resonator>,
+    /// low pass of the engine vibrations, the engine block only radiates low frequencies
     pub engine_vibration_filter: LowPassFilter,
     pub muffler: Muffler,
-    /// valve timing -0.5 - 0.5
+    /// optionally applied to the exhaust output of the muffler
+    #[serde(default)]
+    pub exhaust_highpass_filter: Option<HighPassFilter>,
+    /// valve timing -0.5 - 0.5 in cycles, added to the crank position at which `intake_valve` is evaluated,
+    /// so positive values open the valve earlier
     pub intake_valve_shift: f32,
-    /// valve timing -0.5 - 0.5
+    /// valve timing -0.5 - 0.5 in cycles, see `intake_valve_shift`
     pub exhaust_valve_shift: f32,
+    /// amount in cycles by which low passed noise jitters the crank position of the cylinders, 0.0 runs perfectly smooth
     pub crankshaft_fluctuation: f32,
+    /// shapes the crankshaft fluctuation noise, a low cutoff makes the fluctuation slow
     pub crankshaft_fluctuation_lp: LowPassFilter,
     #[serde(skip)]
     pub crankshaft_noise: Noise,
+    /// decides which cycles of the cylinders misfire, see `Cylinder::misfire_probability`
+    #[serde(skip)]
+    pub misfire_noise: Noise,
+    /// optional turbocharger whine and blow-off which is added to the intake
+    #[serde(default)]
+    pub turbo: Option<Turbo>,
+    /// optional pops in the exhaust while engine braking at high rpm
+    #[serde(default)]
+    pub overrun_crackle: Option<OverrunCrackle>,
+    /// optional equalizer of the master output, does not apply to stems and multichannel tracks
+    #[serde(default)]
+    pub eq: Option<ParametricEq>,
+    /// optionally applied to the master output after the dc offset removal to cut sub-bass rumble,
+    /// does not apply to stems and multichannel tracks
+    #[serde(default)]
+    pub output_highpass_filter: Option<HighPassFilter>,
+    /// seeds the noise sources deterministically if set, otherwise they are seeded from the system time
+    #[serde(default)]
+    pub noise_seed: Option<u64>,
+    /// fraction 0.0 - 1.0 of the exhaust collector which is fed back into the intake collector through `egr_delay`
+    /// to simulate exhaust gas recirculation, the feedback path is left out at 0.0
+    #[serde(default)]
+    pub egr_factor: f32,
+    /// EGR pipe, the alpha end is at the exhaust collector and the beta end at the intake collector,
+    /// its reflection factors keep the feedback loop stable
+    #[serde(default = "default_egr_delay")]
+    pub egr_delay: WaveGuide,
+    /// reduces the gain of waveguides whose amplitude stays high instead of only soft clipping them at `WAVEGUIDE_MAX_AMP`,
+    /// see `WaveGuide::pop`
+    #[serde(default)]
+    pub waveguide_adaptive_damping: bool,
     // running values
     /// crankshaft position, 0.0-1.0
+    #[doc(hidden)]
     #[serde(skip)]
     pub crankshaft_pos: f32,
+    #[doc(hidden)]
     #[serde(skip)]
     pub exhaust_collector: f32,
+    #[doc(hidden)]
     #[serde(skip)]
     pub intake_collector: f32,
+    /// follows `throttle` smoothly to prevent zipper noise
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub current_throttle: f32,
+}
+
+fn default_throttle() -> f32 {
+    1.0
+}
+
+/// has to be fixed to the sample rate with `utils::fix_engine` like every loaded waveguide
+fn default_egr_delay() -> WaveGuide {
+    const SAMPLE_RATE: u32 = 48000;
+
+    WaveGuide::new(
+        DEFAULT_EGR_DELAY * SAMPLE_RATE as f32,
+        DEFAULT_EGR_REFLECTIVITY,
+        DEFAULT_EGR_REFLECTIVITY,
+        SAMPLE_RATE,
+    )
+}
+
+fn default_active() -> bool {
+    true
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+/// Problem of an engine config found by `Engine::validate`
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineValidationError {
+    /// the rpm is negative or not finite
+    RpmOutOfRange(f32),
+    /// the rpm is below `MIN_RECOMMENDED_RPM` (warning)
+    RpmVeryLow(f32),
+    CylinderCountZero,
+    /// a waveguide of a cylinder has no length, `side` is "intake", "exhaust" or "extractor"
+    WaveguideDelayZero {
+        cylinder: usize,
+        side: &'static str,
+    },
+    /// the straight pipe (`None`) or a muffler element has no length
+    MufflerDelayZero {
+        element: Option<usize>,
+    },
+    /// the EGR pipe has no length
+    EgrDelayZero,
+    /// the neck or cavity of a Helmholtz resonator muffler element has no size
+    HelmholtzResonatorSizeInvalid {
+        element: usize,
+    },
+    /// the cutoff frequency of a low pass filter is not positive and finite
+    LowPassFrequencyInvalid {
+        filter: &'static str,
+        freq: f32,
+    },
+    /// the cutoff frequency of a high pass filter is not positive and finite
+    HighPassFrequencyInvalid {
+        filter: &'static str,
+        freq: f32,
+    },
+    /// the cutoff frequency of a filter is above the nyquist frequency of the sample rate, so it has no effect (warning)
+    FilterFrequencyAboveNyquist {
+        filter: &'static str,
+        freq: f32,
+        nyquist: f32,
+    },
+    /// a mixing volume is outside of 0.0 - 1.0
+    VolumeOutOfRange {
+        field: &'static str,
+        volume: f32,
+    },
+    /// the intake, exhaust and engine vibrations volumes don't sum up to about 1, see `VOLUME_SUM_TOLERANCE` (warning)
+    VolumeSumUnusual(f32),
+    /// a reflection factor is outside of -1.0 - 1.0, which amplifies the waves on every reflection
+    ReflectivityOutOfRange {
+        field: String,
+        value: f32,
+    },
+}
+
+impl EngineValidationError {
+    /// warnings are reported, but the engine can be used anyway
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            EngineValidationError::RpmVeryLow(_)
+                | EngineValidationError::FilterFrequencyAboveNyquist { .. }
+                | EngineValidationError::VolumeSumUnusual(_)
+        )
+    }
+
+    /// path of the offending field in the config, e.g. `cylinders[2].intake_waveguide`
+    pub fn field(&self) -> String {
+        match self {
+            EngineValidationError::RpmOutOfRange(_) | EngineValidationError::RpmVeryLow(_) => {
+                "rpm".to_string()
+            }
+            EngineValidationError::CylinderCountZero => "cylinders".to_string(),
+            EngineValidationError::WaveguideDelayZero { cylinder, side } => {
+                format!("cylinders[{}].{}_waveguide", cylinder, side)
+            }
+            EngineValidationError::MufflerDelayZero { element: None } => {
+                "muffler.straight_pipe".to_string()
+            }
+            EngineValidationError::MufflerDelayZero {
+                element: Some(element),
+            }
+            | EngineValidationError::HelmholtzResonatorSizeInvalid { element } => {
+                format!("muffler.muffler_elements[{}]", element)
+            }
+            EngineValidationError::EgrDelayZero => "egr_delay".to_string(),
+            EngineValidationError::LowPassFrequencyInvalid { filter, .. }
+            | EngineValidationError::HighPassFrequencyInvalid { filter, .. }
+            | EngineValidationError::FilterFrequencyAboveNyquist { filter, .. } => {
+                filter.to_string()
+            }
+            EngineValidationError::VolumeOutOfRange { field, .. } => field.to_string(),
+            EngineValidationError::VolumeSumUnusual(_) => {
+                "intake_volume + exhaust_volume + engine_vibrations_volume".to_string()
+            }
+            EngineValidationError::ReflectivityOutOfRange { field, .. } => field.clone(),
+        }
+    }
+}
+
+impl fmt::Display for EngineValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineValidationError::RpmOutOfRange(rpm) => write!(f, "invalid rpm {}", rpm),
+            EngineValidationError::RpmVeryLow(rpm) => {
+                write!(f, "very low rpm {} (below {})", rpm, MIN_RECOMMENDED_RPM)
+            }
+            EngineValidationError::CylinderCountZero => write!(f, "the engine has no cylinders"),
+            EngineValidationError::WaveguideDelayZero { cylinder, side } => write!(
+                f,
+                "the {} pipe of cylinder {} has no length",
+                side,
+                cylinder + 1
+            ),
+            EngineValidationError::MufflerDelayZero { element: None } => {
+                write!(f, "the straight pipe has no length")
+            }
+            EngineValidationError::MufflerDelayZero {
+                element: Some(element),
+            } => write!(f, "muffler element {} has no length", element + 1),
+            EngineValidationError::EgrDelayZero => write!(f, "the EGR pipe has no length"),
+            EngineValidationError::HelmholtzResonatorSizeInvalid { element } => write!(
+                f,
+                "the neck or cavity of muffler element {} has no size",
+                element + 1
+            ),
+            EngineValidationError::LowPassFrequencyInvalid { freq, .. } => {
+                write!(f, "invalid low pass cutoff frequency {} Hz", freq)
+            }
+            EngineValidationError::HighPassFrequencyInvalid { freq, .. } => {
+                write!(f, "invalid high pass cutoff frequency {} Hz", freq)
+            }
+            EngineValidationError::FilterFrequencyAboveNyquist { freq, nyquist, .. } => write!(
+                f,
+                "cutoff frequency {} Hz is above the nyquist frequency {} Hz",
+                freq, nyquist
+            ),
+            EngineValidationError::VolumeOutOfRange { volume, .. } => {
+                write!(f, "volume {} is outside of 0 - 1", volume)
+            }
+            EngineValidationError::VolumeSumUnusual(sum) => {
+                write!(f, "the volumes sum up to {} instead of about 1", sum)
+            }
+            EngineValidationError::ReflectivityOutOfRange { value, .. } => {
+                write!(f, "reflectivity {} is outside of -1 - 1", value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineValidationError {}
+
+/// Irregularity of the firing order found by `Engine::validate_firing_order`, cylinder indices start at 0
+#[derive(Clone, Debug, PartialEq)]
+pub enum FiringOrderWarning {
+    /// two cylinders have the same crank offset and fire at the same time
+    SimultaneousFiring { first: usize, second: usize },
+    /// the gap in cycles from the ignition of cylinder `after` to the next ignition is longer than `UNEVEN_FIRING_GAP`
+    /// even intervals
+    UnevenFiring { after: usize, gap: f32 },
+    /// the crank offset is not a multiple of the even interval `1 / cylinder count`
+    OffsetOffGrid { cylinder: usize, offset: f32 },
+}
+
+impl fmt::Display for FiringOrderWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FiringOrderWarning::SimultaneousFiring { first, second } => write!(
+                f,
+                "cylinders {} and {} fire at the same time",
+                first + 1,
+                second + 1
+            ),
+            FiringOrderWarning::UnevenFiring { after, gap } => write!(
+                f,
+                "uneven firing, {:.3} cycles pass after cylinder {} fires",
+                gap,
+                after + 1
+            ),
+            FiringOrderWarning::OffsetOffGrid { cylinder, offset } => write!(
+                f,
+                "crank offset {} of cylinder {} is not a multiple of the even firing interval",
+                offset,
+                cylinder + 1
+            ),
+        }
+    }
+}
+
+impl Engine {
+    /// checks the deserialized parameters, see `EngineValidationError::is_warning`
+    pub fn validate(&self, sample_rate: u32) -> Vec<EngineValidationError> {
+        // delays and frequencies are also invalid if they are not finite
+        fn positive(value: f32) -> bool {
+            value > 0.0 && value.is_finite()
+        }
+
+        let mut errors = Vec::new();
+
+        if !self.rpm.is_finite() || self.rpm < 0.0 {
+            errors.push(EngineValidationError::RpmOutOfRange(self.rpm));
+        } else if self.rpm < MIN_RECOMMENDED_RPM {
+            errors.push(EngineValidationError::RpmVeryLow(self.rpm));
+        }
+
+        let volumes = [
+            ("intake_volume", self.intake_volume),
+            ("exhaust_volume", self.exhaust_volume),
+            ("engine_vibrations_volume", self.engine_vibrations_volume),
+        ];
+        for &(field, volume) in volumes.iter() {
+            if !(0.0..=1.0).contains(&volume) {
+                errors.push(EngineValidationError::VolumeOutOfRange { field, volume });
+            }
+        }
+        let volume_sum = volumes.iter().map(|(_, volume)| volume).sum::<f32>();
+        // the sum is meaningless if a volume is out of range
+        if volumes
+            .iter()
+            .all(|(_, volume)| (0.0..=1.0).contains(volume))
+            && (volume_sum - 1.0).abs() > VOLUME_SUM_TOLERANCE
+        {
+            errors.push(EngineValidationError::VolumeSumUnusual(volume_sum));
+        }
+
+        if self.cylinders.is_empty() {
+            errors.push(EngineValidationError::CylinderCountZero);
+        }
+
+        let mut reflectivities = Vec::new();
+
+        for (i, cylinder) in self.cylinders.iter().enumerate() {
+            for (side, waveguide) in [
+                ("intake", &cylinder.intake_waveguide),
+                ("exhaust", &cylinder.exhaust_waveguide),
+                ("extractor", &cylinder.extractor_waveguide),
+            ] {
+                if !positive(waveguide.chamber0.samples.delay) {
+                    errors.push(EngineValidationError::WaveguideDelayZero { cylinder: i, side });
+                }
+                reflectivities.push((format!("cylinders[{}].{}_waveguide", i, side), waveguide));
+            }
+
+            for (field, value) in [
+                ("intake_open_refl", cylinder.intake_open_refl),
+                ("intake_closed_refl", cylinder.intake_closed_refl),
+                ("exhaust_open_refl", cylinder.exhaust_open_refl),
+                ("exhaust_closed_refl", cylinder.exhaust_closed_refl),
+            ] {
+                if !(-1.0..=1.0).contains(&value) {
+                    errors.push(EngineValidationError::ReflectivityOutOfRange {
+                        field: format!("cylinders[{}].{}", i, field),
+                        value,
+                    });
+                }
+            }
+        }
+
+        for (element, waveguide) in std::iter::once((None, &self.muffler.straight_pipe)).chain(
+            self.muffler
+                .muffler_elements
+                .iter()
+                .enumerate()
+                .filter_map(|(i, element)| Some((Some(i), element.waveguide()?))),
+        ) {
+            if !positive(waveguide.chamber0.samples.delay) {
+                errors.push(EngineValidationError::MufflerDelayZero { element });
+            }
+            reflectivities.push((
+                match element {
+                    Some(element) => format!("muffler.muffler_elements[{}]", element),
+                    None => "muffler.straight_pipe".to_string(),
+                },
+                waveguide,
+            ));
+        }
+
+        if !positive(self.egr_delay.chamber0.samples.delay) {
+            errors.push(EngineValidationError::EgrDelayZero);
+        }
+        reflectivities.push(("egr_delay".to_string(), &self.egr_delay));
+
+        for (path, waveguide) in reflectivities {
+            for (end, value) in [("alpha", waveguide.alpha), ("beta", waveguide.beta)] {
+                if !(-1.0..=1.0).contains(&value) {
+                    errors.push(EngineValidationError::ReflectivityOutOfRange {
+                        field: format!("{}.{}", path, end),
+                        value,
+                    });
+                }
+            }
+        }
+
+        for (element, muffler_element) in self.muffler.muffler_elements.iter().enumerate() {
+            if let MufflerElement::HelmholtzResonator {
+                neck_length,
+                neck_radius,
+                cavity_volume,
+                ..
+            } = muffler_element
+            {
+                if !(positive(*neck_length) && positive(*neck_radius) && positive(*cavity_volume)) {
+                    errors.push(EngineValidationError::HelmholtzResonatorSizeInvalid { element });
+                }
+            }
+        }
+
+        let nyquist = sample_rate as f32 * 0.5;
+
+        for (filter, lpf) in IntoIterator::into_iter([
+            ("intake_noise_lp", Some(&self.intake_noise_lp)),
+            (
+                "engine_vibration_filter",
+                Some(&self.engine_vibration_filter),
+            ),
+            (
+                "crankshaft_fluctuation_lp",
+                Some(&self.crankshaft_fluctuation_lp),
+            ),
+            (
+                "turbo.spool_lp",
+                self.turbo.as_ref().map(|turbo| &turbo.spool_lp),
+            ),
+            (
+                "turbo.blowoff_lp",
+                self.turbo.as_ref().map(|turbo| &turbo.blowoff_lp),
+            ),
+        ])
+        .filter_map(|(filter, lpf)| Some((filter, lpf?)))
+        {
+            let freq = lpf.get_freq();
+            if !positive(freq) {
+                errors.push(EngineValidationError::LowPassFrequencyInvalid { filter, freq });
+            } else if freq > nyquist {
+                errors.push(EngineValidationError::FilterFrequencyAboveNyquist {
+                    filter,
+                    freq,
+                    nyquist,
+                });
+            }
+        }
+
+        for (filter, hpf) in IntoIterator::into_iter([
+            (
+                "exhaust_highpass_filter",
+                self.exhaust_highpass_filter.as_ref(),
+            ),
+            (
+                "output_highpass_filter",
+                self.output_highpass_filter.as_ref(),
+            ),
+        ])
+        .filter_map(|(filter, hpf)| Some((filter, hpf?)))
+        {
+            let freq = hpf.get_freq();
+            if !positive(freq) {
+                errors.push(EngineValidationError::HighPassFrequencyInvalid { filter, freq });
+            } else if freq > nyquist {
+                errors.push(EngineValidationError::FilterFrequencyAboveNyquist {
+                    filter,
+                    freq,
+                    nyquist,
+                });
+            }
+        }
+
+        errors
+    }
+
+    /// indices of the cylinders sorted by their crank offset (in cycles 0.0 - 1.0) in the order in which they fire,
+    /// cylinders with the same offset are sorted by their index
+    pub fn cylinder_firing_order(&self) -> Vec<usize> {
+        let mut order = (0..self.cylinders.len()).collect::<Vec<usize>>();
+        order.sort_by(|&a, &b| {
+            let offset_a = self.cylinders[a].crank_offset.rem_euclid(1.0);
+            let offset_b = self.cylinders[b].crank_offset.rem_euclid(1.0);
+            offset_a
+                .partial_cmp(&offset_b)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        order
+    }
+
+    /// checks whether the cylinders fire evenly, uneven firing orders (like the one of a V-twin) are valid but sound rough
+    pub fn validate_firing_order(&self) -> Vec<FiringOrderWarning> {
+        let mut warnings = Vec::new();
+        let num_cylinders = self.cylinders.len();
+        let interval = 1.0 / num_cylinders.max(1) as f32;
+        let offset = |cylinder: usize| self.cylinders[cylinder].crank_offset.rem_euclid(1.0);
+
+        let order = self.cylinder_firing_order();
+        for (position, &cylinder) in order.iter().enumerate() {
+            // the last cylinder is followed by the first one of the next cycle
+            let next = order[(position + 1) % num_cylinders];
+            let gap = offset(next) - offset(cylinder)
+                + if position + 1 == num_cylinders {
+                    1.0
+                } else {
+                    0.0
+                };
+
+            if gap < FIRING_OFFSET_TOLERANCE {
+                warnings.push(FiringOrderWarning::SimultaneousFiring {
+                    first: cylinder.min(next),
+                    second: cylinder.max(next),
+                });
+            } else if gap > interval * UNEVEN_FIRING_GAP {
+                warnings.push(FiringOrderWarning::UnevenFiring {
+                    after: cylinder,
+                    gap,
+                });
+            }
+        }
+
+        for cylinder in 0..num_cylinders {
+            let intervals = offset(cylinder) / interval;
+            if (intervals - intervals.round()).abs() * interval > FIRING_OFFSET_TOLERANCE {
+                warnings.push(FiringOrderWarning::OffsetOffGrid {
+                    cylinder,
+                    offset: self.cylinders[cylinder].crank_offset,
+                });
+            }
+        }
+
+        warnings
+    }
+
+    /// reseeds the noise sources from `noise_seed`, does nothing if it is not set
+    pub fn seed_noise(&mut self) {
+        if let Some(seed) = self.noise_seed {
+            self.intake_noise = Noise::from_seed(seed);
+            // the crankshaft noise must not be correlated with the intake noise
+            self.crankshaft_noise = Noise::from_seed(seed.wrapping_add(1));
+            self.misfire_noise = Noise::from_seed(seed.wrapping_add(3));
+
+            if let Some(turbo) = &mut self.turbo {
+                turbo.blowoff_noise = Noise::from_seed(seed.wrapping_add(2));
+            }
+
+            if let Some(overrun_crackle) = &mut self.overrun_crackle {
+                overrun_crackle.noise = Noise::from_seed(seed.wrapping_add(4));
+            }
+        }
+    }
+
+    /// copies all parameters of `from` into this engine while keeping its running values (waveguide contents, filter states, noise, crankshaft position)
+    ///
+    /// waveguides, cylinders and muffler elements are only taken over from `from` if their length or count differs
+    pub fn apply_parameters(&mut self, from: Engine) {
+        self.name = from.name;
+        self.author = from.author;
+        self.description = from.description;
+        self.rpm = from.rpm;
+        self.intake_volume = from.intake_volume;
+        self.exhaust_volume = from.exhaust_volume;
+        self.engine_vibrations_volume = from.engine_vibrations_volume;
+        self.throttle = from.throttle;
+        self.rpm_slew_rate = from.rpm_slew_rate;
+        self.intake_noise_factor = from.intake_noise_factor;
+        self.intake_noise_lp.apply_parameters(&from.intake_noise_lp);
+        match (&mut self.intake_resonator, from.intake_resonator) {
+            (Some(resonator), Some(from)) => resonator.apply_parameters(&from),
+            (resonator, from) => *resonator = from,
+        }
+        self.engine_vibration_filter
+            .apply_parameters(&from.engine_vibration_filter);
+        self.intake_valve_shift = from.intake_valve_shift;
+        self.exhaust_valve_shift = from.exhaust_valve_shift;
+        self.crankshaft_fluctuation = from.crankshaft_fluctuation;
+        self.crankshaft_fluctuation_lp
+            .apply_parameters(&from.crankshaft_fluctuation_lp);
+        self.noise_seed = from.noise_seed;
+        self.firing_preset = from.firing_preset;
+        self.waveguide_adaptive_damping = from.waveguide_adaptive_damping;
+        self.egr_factor = from.egr_factor;
+        self.egr_delay.apply_parameters(from.egr_delay);
+
+        match (
+            &mut self.exhaust_highpass_filter,
+            from.exhaust_highpass_filter,
+        ) {
+            (Some(hpf), Some(from)) => hpf.apply_parameters(&from),
+            (hpf, from) => *hpf = from,
+        }
+
+        match (
+            &mut self.output_highpass_filter,
+            from.output_highpass_filter,
+        ) {
+            (Some(hpf), Some(from)) => hpf.apply_parameters(&from),
+            (hpf, from) => *hpf = from,
+        }
+
+        match (&mut self.turbo, from.turbo) {
+            (Some(turbo), Some(from)) => turbo.apply_parameters(&from),
+            (turbo, from) => *turbo = from,
+        }
+
+        match (&mut self.overrun_crackle, from.overrun_crackle) {
+            (Some(overrun_crackle), Some(from)) => overrun_crackle.apply_parameters(&from),
+            (overrun_crackle, from) => *overrun_crackle = from,
+        }
+
+        match (&mut self.eq, from.eq) {
+            (Some(eq), Some(from)) if eq.bands.len() == from.bands.len() => {
+                for (band, from) in eq.bands.iter_mut().zip(from.bands) {
+                    band.apply_parameters(&from);
+                }
+            }
+            (eq, from) => *eq = from,
+        }
+
+        if self.cylinders.len() == from.cylinders.len() {
+            for (cyl, from) in self.cylinders.iter_mut().zip(from.cylinders) {
+                cyl.apply_parameters(from);
+            }
+        } else {
+            self.cylinders = from.cylinders;
+        }
+
+        self.muffler
+            .straight_pipe
+            .apply_parameters(from.muffler.straight_pipe);
+
+        if self.muffler.muffler_elements.len() == from.muffler.muffler_elements.len() {
+            for (muffler_element, from) in self
+                .muffler
+                .muffler_elements
+                .iter_mut()
+                .zip(from.muffler.muffler_elements)
+            {
+                muffler_element.apply_parameters(from);
+            }
+        } else {
+            self.muffler.muffler_elements = from.muffler.muffler_elements;
+        }
+    }
 }
 
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct Noise {
     inner: XorShiftRng,
 }
 
 impl Default for Noise {
+    #[cfg(not(target_arch = "wasm32"))]
     fn default() -> Self {
         Noise {
-            inner: XorShiftRng::from_seed(unsafe {
-                std::mem::transmute::<u128, [u8; 16]>(
-                    SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_nanos(),
-                )
-            }),
+            inner: XorShiftRng::from_seed(
+                SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap()
+                    .as_nanos()
+                    .to_ne_bytes(),
+            ),
         }
     }
+
+    /// there is no system time on wasm, the noise sources are seeded by `JsGenerator` instead
+    #[cfg(target_arch = "wasm32")]
+    fn default() -> Self {
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        // every noise source still gets a different seed
+        static NEXT_SEED: AtomicU64 = AtomicU64::new(0);
+
+        Noise::from_seed(NEXT_SEED.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 impl Noise {
+    pub fn from_seed(seed: u64) -> Self {
+        Noise {
+            inner: XorShiftRng::seed_from_u64(seed),
+        }
+    }
+
     pub fn step(&mut self) -> f32 {
-        self.inner.next_u32() as f32 / (std::u32::MAX as f32 / 2.0) - 1.0
+        self.inner.next_u32() as f32 / (u32::MAX as f32 / 2.0) - 1.0
     }
 }
 
 /// Represents one audio cylinder
 /// It has two `WaveGuide`s each connected from the cylinder to the exhaust or intake collector
-/// ```
+///
+/// The cylinder itself is a pressure source (the paper's cylinder model): `piston_motion` times `piston_motion_factor`
+/// plus `fuel_ignition` times `ignition_factor`. Half of it is pushed into the intake and the exhaust waveguide each,
+/// scaled by the transmission `1 - |alpha|` of their cylinder side. The alpha (reflection factor) of these sides is
+/// interpolated between the closed and the open reflectivity by the valve openings `intake_valve` and `exhaust_valve`,
+/// so a closed valve reflects the pressure back into the cylinder and an open one lets it through.
+/// ```text
 /// Labels:                                                     \/ Extractor
 ///                    b      a            a      b           a    b
 /// (Intake Collector) <==|IV|> (Cylinder) <|EV|==> (Exhaust) <====> (Exhaust collector)
@@ -97,7 +1128,7 @@ impl Noise {
 /// ```
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Cylinder {
-    /// offset of this cylinder's piston crank
+    /// offset of this cylinder's piston crank in cycles 0.0 - 1.0 (720°), sets the firing order
     pub crank_offset: f32,
     /// waveguide from the cylinder to the exhaust
     pub exhaust_waveguide: WaveGuide,
@@ -106,69 +1137,182 @@ pub struct Cylinder {
     /// waveguide from the other end of the exhaust WG to the exhaust collector
     pub extractor_waveguide: WaveGuide,
     // waveguide alpha values for when the valves are closed or opened
+    /// reflection factor -1.0 - 1.0 of the intake waveguide's cylinder side at a fully open intake valve, usually near 0.0
     pub intake_open_refl: f32,
+    /// reflection factor -1.0 - 1.0 of the intake waveguide's cylinder side at a closed intake valve, usually near 1.0
     pub intake_closed_refl: f32,
+    /// see `intake_open_refl`
     pub exhaust_open_refl: f32,
+    /// see `intake_closed_refl`
     pub exhaust_closed_refl: f32,
 
+    /// gain of the pressure caused by the moving piston, see `piston_motion`
     pub piston_motion_factor: f32,
+    /// gain of the combustion pressure, see `fuel_ignition`, scaled down at closed throttle
     pub ignition_factor: f32,
-    /// the time it takes for the fuel to ignite in crank cycles (0.0 - 1.0)
+    /// the time it takes for the fuel to ignite in crank cycles (0.0 - 1.0), the combustion pressure pulse lasts
+    /// half of it, see `fuel_ignition`
     pub ignition_time: f32,
+    /// chance of a cycle without ignition (0.0 - 1.0), the piston keeps moving
+    #[serde(default)]
+    pub misfire_probability: f32,
+    /// deactivated cylinders neither move air nor ignite, their waveguides only drain
+    #[serde(default = "default_active")]
+    pub active: bool,
+    /// volume of the burst of pressure which is released into the exhaust when the exhaust valve opens
+    #[serde(default)]
+    pub pressure_release_factor: f32,
 
     // running values
+    #[doc(hidden)]
     #[serde(skip)]
     pub cyl_sound: f32,
+    /// impulse of the opening exhaust valve which is pushed into the exhaust waveguide, only non-zero at the
+    /// first sample of a cycle at which the valve is open
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub pressure_release: f32,
+    /// exhaust valve opening of the last `pop` to detect the valve opening
+    #[serde(skip)]
+    last_exhaust_valve: f32,
+    #[doc(hidden)]
     #[serde(skip)]
     pub extractor_exhaust: f32,
+    /// crank position of the last `pop` to detect the start of a new cycle
+    #[serde(skip)]
+    last_crank: f32,
+    /// set if the current cycle misfires
+    #[serde(skip)]
+    misfiring: bool,
 }
 
 impl Cylinder {
     /// takes in the current exhaust collector pressure
-    /// returns (intake, exhaust, piston + ignition, waveguide dampened)
+    /// returns (intake, exhaust, piston + ignition)
     #[inline]
+    #[allow(clippy::too_many_arguments)]
     pub(in crate::gen) fn pop(
         &mut self,
         crank_pos: f32,
         exhaust_collector: f32,
         intake_valve_shift: f32,
         exhaust_valve_shift: f32,
-    ) -> (f32, f32, f32, bool) {
+        throttle: f32,
+        misfire_noise: &mut Noise,
+        adaptive_damping: bool,
+    ) -> (f32, f32, f32) {
+        if !self.active {
+            self.drain(adaptive_damping);
+            return (0.0, 0.0, 0.0);
+        }
+
         let crank = (crank_pos + self.crank_offset).fract();
 
+        // the misfire is decided once per cycle, a backwards jump of more than half a cycle is a new cycle
+        // instead of crankshaft fluctuation
+        if crank < self.last_crank - 0.5 {
+            self.misfiring = self.misfire_probability > 0.0
+                && (misfire_noise.step() + 1.0) * 0.5 < self.misfire_probability;
+        }
+        self.last_crank = crank;
+
+        let ignition_factor = if self.misfiring {
+            0.0
+        } else {
+            self.ignition_factor
+                * (CLOSED_THROTTLE_IGNITION + (1.0 - CLOSED_THROTTLE_IGNITION) * throttle)
+                * (throttle / ENGINE_BRAKING_THROTTLE).min(1.0)
+        };
+
         self.cyl_sound = piston_motion(crank) * self.piston_motion_factor
-            + fuel_ignition(crank, self.ignition_time) * self.ignition_factor;
+            + fuel_ignition(crank, self.ignition_time) * ignition_factor;
 
         let ex_valve = exhaust_valve((crank + exhaust_valve_shift).fract());
         let in_valve = intake_valve((crank + intake_valve_shift).fract());
 
+        // a misfired cycle has no combustion pressure to release
+        self.pressure_release =
+            if self.last_exhaust_valve == 0.0 && ex_valve != 0.0 && !self.misfiring {
+                self.pressure_release_factor * (ex_valve - self.last_exhaust_valve)
+            } else {
+                0.0
+            };
+        self.last_exhaust_valve = ex_valve;
+
         self.exhaust_waveguide.alpha = self.exhaust_closed_refl
             + (self.exhaust_open_refl - self.exhaust_closed_refl) * ex_valve;
         self.intake_waveguide.alpha =
             self.intake_closed_refl + (self.intake_open_refl - self.intake_closed_refl) * in_valve;
 
         // the first return value in the tuple is the cylinder-side valve-modulated side of the waveguide (alpha side)
-        let ex_wg_ret = self.exhaust_waveguide.pop();
-        let in_wg_ret = self.intake_waveguide.pop();
+        let ex_wg_ret = self.exhaust_waveguide.pop(adaptive_damping);
+        let in_wg_ret = self.intake_waveguide.pop(adaptive_damping);
 
-        let extractor_wg_ret = self.extractor_waveguide.pop();
+        let extractor_wg_ret = self.extractor_waveguide.pop(adaptive_damping);
         self.extractor_exhaust = extractor_wg_ret.0;
         self.extractor_waveguide
             .push(ex_wg_ret.1, exhaust_collector);
 
         //self.cyl_sound += ex_wg_ret.0 + in_wg_ret.0;
 
-        (
-            in_wg_ret.1,
-            extractor_wg_ret.1,
-            self.cyl_sound,
-            ex_wg_ret.2 | in_wg_ret.2 | extractor_wg_ret.2,
-        )
+        (in_wg_ret.1, extractor_wg_ret.1, self.cyl_sound)
+    }
+
+    /// advances the waveguides of a deactivated cylinder without any input so the remaining pressure decays,
+    /// `push` does nothing for deactivated cylinders
+    fn drain(&mut self, adaptive_damping: bool) {
+        self.cyl_sound = 0.0;
+        self.pressure_release = 0.0;
+        self.extractor_exhaust = 0.0;
+
+        for waveguide in [
+            &mut self.exhaust_waveguide,
+            &mut self.intake_waveguide,
+            &mut self.extractor_waveguide,
+        ] {
+            waveguide.pop(adaptive_damping);
+            waveguide.push(0.0, 0.0);
+        }
+    }
+
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        visitor.value(&mut self.cyl_sound);
+        visitor.value(&mut self.pressure_release);
+        visitor.value(&mut self.last_exhaust_valve);
+        visitor.value(&mut self.extractor_exhaust);
+        visitor.value(&mut self.last_crank);
+        visitor.flag(&mut self.misfiring);
+    }
+
+    fn apply_parameters(&mut self, from: Cylinder) {
+        self.crank_offset = from.crank_offset;
+        self.exhaust_waveguide
+            .apply_parameters(from.exhaust_waveguide);
+        self.intake_waveguide
+            .apply_parameters(from.intake_waveguide);
+        self.extractor_waveguide
+            .apply_parameters(from.extractor_waveguide);
+        self.intake_open_refl = from.intake_open_refl;
+        self.intake_closed_refl = from.intake_closed_refl;
+        self.exhaust_open_refl = from.exhaust_open_refl;
+        self.exhaust_closed_refl = from.exhaust_closed_refl;
+        self.piston_motion_factor = from.piston_motion_factor;
+        self.ignition_factor = from.ignition_factor;
+        self.ignition_time = from.ignition_time;
+        self.misfire_probability = from.misfire_probability;
+        self.active = from.active;
+        self.pressure_release_factor = from.pressure_release_factor;
     }
 
-    /// called after pop
-    pub(in crate::gen) fn push(&mut self, intake: f32) {
-        let ex_in = (1.0 - self.exhaust_waveguide.alpha.abs()) * self.cyl_sound * 0.5;
+    /// called after pop, `exhaust_pop` is added to the exhaust behind the valve
+    pub(in crate::gen) fn push(&mut self, intake: f32, exhaust_pop: f32) {
+        if !self.active {
+            return;
+        }
+
+        let ex_in = (1.0 - self.exhaust_waveguide.alpha.abs()) * self.cyl_sound * 0.5
+            + exhaust_pop
+            + self.pressure_release;
         self.exhaust_waveguide.push(ex_in, self.extractor_exhaust);
         let in_in = (1.0 - self.intake_waveguide.alpha.abs()) * self.cyl_sound * 0.5;
         self.intake_waveguide.push(in_in, intake);
@@ -176,67 +1320,591 @@ impl Cylinder {
 }
 
 pub struct Generator {
-    pub(crate) recorder: Option<Recorder>,
+    pub recorder: Option<Recorder>,
+    /// frames which are generated before the `recorder` receives samples, counted down while generating,
+    /// so a recording starts exactly this many frames after it was armed
+    pub recorder_preroll: usize,
     pub volume: f32,
+    /// panning of the intake (left) and exhaust (right) channels for stereo output, 0.0 = mono, 1.0 = full separation
+    pub stereo_width: f32,
     pub samples_per_second: u32,
     pub engine: Engine,
-    /// `LowPassFilter` which is subtracted from the sample while playing back to reduce dc offset and thus clipping
-    dc_lp: LowPassFilter,
-    /// set to true by any waveguide if it is dampening it's output to prevent feedback loops
-    pub waveguides_dampened: bool,
+    /// high pass `BiquadFilter`s for the left and right channel which remove the dc offset and thus reduce clipping
+    dc_hp: [BiquadFilter; 2],
+    /// states of `engine.output_highpass_filter` for the left and right channel
+    output_hpf: [HighPassFilter; 2],
+    /// number of waveguides which dampened their output to prevent feedback loops while generating the last buffer,
+    /// see `WaveGuide::dampened`
+    pub dampened_waveguides: usize,
     /// set to true if the amplitude of the recording is greater than 1
     pub recording_currently_clipping: bool,
+    /// when set, `engine.rpm` follows this envelope while generating, see `set_rpm_envelope`
+    pub rpm_envelope: Option<RpmEnvelope>,
+    /// samples generated since the rpm envelope was set
+    rpm_envelope_pos: usize,
+    /// when set, `engine.throttle` follows this envelope (of throttles instead of rpms) while generating, see `set_throttle_envelope`
+    pub throttle_envelope: Option<RpmEnvelope>,
+    /// samples generated since the throttle envelope was set
+    throttle_envelope_pos: usize,
+    /// rpm which `engine.rpm` moves towards while generating, see `set_target_rpm`
+    target_rpm: Option<f32>,
+    /// called with (frames generated, total frames) every `samples_per_second` frames while generating, see `set_progress_callback`
+    pub progress_callback: Option<ProgressCallback>,
+    /// frames generated since the progress callback was set
+    progress_pos: usize,
+    /// frames after which the progress callback is called for the last time
+    progress_total: usize,
+    /// when set, the intake, engine vibrations and exhaust signals are additionally collected separately, see `start_stems`
+    stems: Option<Stems>,
+    /// dc offset filters of the intake, engine vibrations and exhaust tracks while recording with a multichannel recorder
+    track_dc_hp: Option<[BiquadFilter; 3]>,
+    /// running morph to another engine, see `morph_to`
+    morph: Option<MorphState>,
+    /// incremented every time the engine is swapped by the generator itself (at the end of a morph)
+    engine_revision: usize,
+    /// state of `generate_resampled`, kept between calls so consecutive buffers are continuous
+    resampler: Option<Resampler>,
+    /// stereo output which `generate` downmixes, grows to the longest buffer generated so far
+    stereo_scratch: Vec<f32>,
+    /// output of `generate` which `generate_resampled` resamples, grows like `stereo_scratch`
+    resample_scratch: Vec<f32>,
+}
+
+/// frames generated at once by `Generator::skip`
+const SKIP_CHUNK_SIZE: usize = 1024;
+
+pub type ProgressCallback = Box<dyn Fn(usize, usize) + Send + Sync>;
+
+/// separately generated mono intake, engine vibrations and exhaust signals which sum up to the mono mix
+pub struct Stems {
+    pub intake: Vec<f32>,
+    pub engine_vibrations: Vec<f32>,
+    pub exhaust: Vec<f32>,
+    /// dc offset filters of the intake, engine vibrations and exhaust stem
+    dc_hp: [BiquadFilter; 3],
 }
 
 impl Generator {
-    pub fn new(samples_per_second: u32, engine: Engine, dc_lp: LowPassFilter) -> Generator {
+    /// `dc_hp` removes the dc offset of the output, usually `BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, samples_per_second)`
+    pub fn new(samples_per_second: u32, engine: Engine, dc_hp: BiquadFilter) -> Generator {
         Generator {
             recorder: None,
+            recorder_preroll: 0,
             volume: 0.1_f32,
+            stereo_width: 0.0,
             samples_per_second,
             engine,
-            dc_lp,
-            waveguides_dampened: false,
+            dc_hp: [dc_hp.clone(), dc_hp],
+            output_hpf: Default::default(),
+            dampened_waveguides: 0,
             recording_currently_clipping: false,
+            rpm_envelope: None,
+            rpm_envelope_pos: 0,
+            throttle_envelope: None,
+            throttle_envelope_pos: 0,
+            target_rpm: None,
+            progress_callback: None,
+            progress_pos: 0,
+            progress_total: 0,
+            stems: None,
+            track_dc_hp: None,
+            morph: None,
+            engine_revision: 0,
+            resampler: None,
+            stereo_scratch: Vec::new(),
+            resample_scratch: Vec::new(),
         }
     }
 
-    pub fn generate(&mut self, buf: &mut [f32]) {
-        let samples_per_second = self.samples_per_second as f32 * 120.0;
+    /// smoothly changes the engine to `target` over `duration_samples` while generating,
+    /// the target has to be fixed for the sample rate of this generator (see `utils::fix_engine`)
+    ///
+    /// the scalar parameters (rpm, volumes, valve shifts, ..) of both engines are linearly interpolated
+    /// while the output of the current engine is crossfaded to the output of the target, which replaces the current engine when the morph is done
+    pub fn morph_to(&mut self, target: Engine, duration_samples: usize) {
+        self.morph = Some(MorphState::new(&self.engine, target, duration_samples));
+    }
 
-        self.recording_currently_clipping = false;
-        self.waveguides_dampened = false;
+    /// replaces the engine without a click: the target runs in parallel to the current engine and their outputs are crossfaded
+    /// over `crossfade_samples`, the target has to be fixed for the sample rate of this generator (see `utils::fix_engine`)
+    ///
+    /// the rpm and throttle of the current engine are kept, a running morph or swap is replaced
+    pub fn swap_engine(&mut self, target: Engine, crossfade_samples: usize) {
+        self.morph = Some(MorphState::swap(&self.engine, target, crossfade_samples));
+    }
 
-        let inc = self.engine.rpm / samples_per_second;
+    /// crossfades to a copy of the engine with silent waveguides over `crossfade_samples`, see `swap_engine` and `reset`
+    pub fn soft_reset(&mut self, crossfade_samples: usize) {
+        let mut target = self.engine.clone();
+        target.reset();
+        self.swap_engine(target, crossfade_samples);
+    }
 
-        buf.iter_mut().for_each(|sample| {
-            self.engine.crankshaft_pos = (self.engine.crankshaft_pos + inc).fract();
+    pub fn morph(&self) -> Option<&MorphState> {
+        self.morph.as_ref()
+    }
 
-            let channels = self.gen();
-            let mixed = (channels.0 * self.engine.intake_volume
-                + channels.1 * self.engine.engine_vibrations_volume
-                + channels.2 * self.engine.exhaust_volume)
-                * self.volume;
-            self.waveguides_dampened |= channels.3;
+    /// changes every time the generator replaces its engine by itself, e.g. when a morph is done
+    pub fn engine_revision(&self) -> usize {
+        self.engine_revision
+    }
 
-            // reduces dc offset
-            *sample = mixed - self.dc_lp.filter(mixed);
+    /// starts collecting the intake, engine vibrations and exhaust signals separately, overwriting previously collected stems
+    pub fn start_stems(&mut self) {
+        self.stems = Some(Stems {
+            intake: Vec::new(),
+            engine_vibrations: Vec::new(),
+            exhaust: Vec::new(),
+            dc_hp: self.split_dc_hp(),
         });
+    }
 
-        if let Some(recorder) = &mut self.recorder {
-            let bufvec = buf.to_vec();
-            let mut recording_currently_clipping = false;
-            bufvec
-                .iter()
-                .for_each(|sample| recording_currently_clipping |= sample.abs() > 1.0);
-            self.recording_currently_clipping = recording_currently_clipping;
+    /// dc offset filters for the intake, engine vibrations and exhaust signals
+    fn split_dc_hp(&self) -> [BiquadFilter; 3] {
+        let mut dc_hp = [
+            self.dc_hp[0].clone(),
+            self.dc_hp[0].clone(),
+            self.dc_hp[0].clone(),
+        ];
+
+        // the states of the filters have to add up to the state of the mono mix's filter so the signals sum up to the mix
+        dc_hp[0].z1 = (self.dc_hp[0].z1 + self.dc_hp[1].z1) * 0.5;
+        dc_hp[0].z2 = (self.dc_hp[0].z2 + self.dc_hp[1].z2) * 0.5;
+        dc_hp[1].reset();
+        dc_hp[2].reset();
+
+        dc_hp
+    }
 
-            recorder.record(bufvec);
+    /// copies the cutoff frequency of `engine.output_highpass_filter` to the filters of both channels, keeping their states
+    fn update_output_hpf(&mut self) {
+        if let Some(hpf) = &self.engine.output_highpass_filter {
+            self.output_hpf
+                .iter_mut()
+                .for_each(|channel| channel.apply_parameters(hpf));
+        }
+    }
+
+    /// stops collecting stems and returns the stems collected since `start_stems`
+    pub fn take_stems(&mut self) -> Option<Stems> {
+        self.stems.take()
+    }
+
+    /// sets the rpm envelope and restarts it from its beginning
+    pub fn set_rpm_envelope(&mut self, rpm_envelope: Option<RpmEnvelope>) {
+        self.rpm_envelope = rpm_envelope;
+        self.rpm_envelope_pos = 0;
+    }
+
+    /// sets the progress callback, which is called for the last time once `total_frames` have been generated
+    pub fn set_progress_callback(
+        &mut self,
+        progress_callback: Option<ProgressCallback>,
+        total_frames: usize,
+    ) {
+        self.progress_callback = progress_callback;
+        self.progress_pos = 0;
+        self.progress_total = total_frames;
+    }
+
+    /// sets the throttle envelope and restarts it from its beginning
+    pub fn set_throttle_envelope(&mut self, throttle_envelope: Option<RpmEnvelope>) {
+        self.throttle_envelope = throttle_envelope;
+        self.throttle_envelope_pos = 0;
+    }
+
+    /// sets the engine load 0.0 (closed) - 1.0 (wide open), which the engine follows smoothly,
+    /// engine braking (fuel cut-off) sets in below `ENGINE_BRAKING_THROTTLE`
+    pub fn set_throttle(&mut self, throttle: f32) {
+        self.engine.throttle = throttle.clamp(0.0, 1.0);
+    }
+
+    /// sets the rpm instantly, cancels moving towards a target rpm
+    pub fn set_rpm(&mut self, rpm: f32) {
+        self.engine.rpm = rpm;
+        self.target_rpm = None;
+    }
+
+    /// activates or deactivates the cylinder at `index` without replacing the cylinders, does nothing if it doesn't exist
+    pub fn set_cylinder_active(&mut self, index: usize, active: bool) {
+        if let Some(cylinder) = self.engine.cylinders.get_mut(index) {
+            cylinder.active = active;
+        }
+    }
+
+    /// moves the rpm towards `rpm` at no more than `engine.rpm_slew_rate` while generating, instantly if it is not set
+    pub fn set_target_rpm(&mut self, rpm: f32) {
+        self.target_rpm = Some(rpm);
+    }
+
+    /// the rpm set by `set_target_rpm` until it has been reached
+    pub fn target_rpm(&self) -> Option<f32> {
+        self.target_rpm
+    }
+
+    /// advances the engine by `num_samples` frames exactly like generating them, but without recording them,
+    /// collecting stems or allocating an output buffer
+    pub fn skip(&mut self, num_samples: usize) {
+        let recorder = self.recorder.take();
+        let stems = self.stems.take();
+
+        let mut buf = [0.0; SKIP_CHUNK_SIZE * 2];
+        let mut remaining = num_samples;
+        while remaining > 0 {
+            let frames = remaining.min(SKIP_CHUNK_SIZE);
+            self.generate_stereo(&mut buf[..(frames * 2)]);
+            remaining -= frames;
+        }
+
+        self.recorder = recorder;
+        self.stems = stems;
+    }
+
+    /// generates mono audio by downmixing the output of `generate_stereo`
+    ///
+    /// `buf` receives one sample per frame at `samples_per_second`, the crankshaft advances by `rpm / 120 / samples_per_second`
+    /// cycles per sample and the output is the sum of the intake, engine vibrations and exhaust signals weighted with their
+    /// volumes and `volume`, minus the dc offset
+    ///
+    /// Only allocates if `buf` is longer than every buffer before, so it can be called from audio callbacks.
+    pub fn generate(&mut self, buf: &mut [f32]) {
+        let mut stereo = std::mem::take(&mut self.stereo_scratch);
+        if stereo.len() < buf.len() * 2 {
+            stereo.resize(buf.len() * 2, 0.0);
+        }
+        self.generate_stereo(&mut stereo[..buf.len() * 2]);
+
+        buf.iter_mut()
+            .zip(stereo.chunks_exact(2))
+            .for_each(|(sample, lr)| *sample = (lr[0] + lr[1]) * 0.5);
+        self.stereo_scratch = stereo;
+    }
+
+    /// generates mono audio like `generate` and linearly resamples it from `samples_per_second` to `output_sample_rate`,
+    /// recordings are still written at `samples_per_second`
+    pub fn generate_resampled(&mut self, buf: &mut [f32], output_sample_rate: u32) {
+        let samples_per_second = self.samples_per_second;
+        let resampler = self
+            .resampler
+            .get_or_insert_with(|| Resampler::new(samples_per_second, output_sample_rate));
+        // the output sample rate may change between calls without discontinuities
+        resampler.ratio = samples_per_second as f64 / output_sample_rate as f64;
+
+        let input_len = resampler.input_len(buf.len());
+
+        let mut input = std::mem::take(&mut self.resample_scratch);
+        if input.len() < input_len {
+            input.resize(input_len, 0.0);
+        }
+        self.generate(&mut input[..input_len]);
+
+        if let Some(resampler) = self.resampler.as_mut() {
+            resampler.resample_linear(&input[..input_len], buf);
+        }
+        self.resample_scratch = input;
+    }
+
+    /// generates interleaved L/R audio, the intake is panned to the left and the exhaust to the right by `stereo_width`
+    pub fn generate_stereo(&mut self, buf: &mut [f32]) {
+        let samples_per_second = self.samples_per_second as f32 * 120.0;
+
+        self.recording_currently_clipping = false;
+        for engine in std::iter::once(&mut self.engine)
+            .chain(self.morph.as_mut().map(|morph| &mut morph.target))
+        {
+            engine
+                .waveguides_mut()
+                .for_each(|waveguide| waveguide.dampened = false);
+        }
+
+        let mut inc = self.engine.rpm / samples_per_second;
+
+        // the pan factors for both sides add up to 2 so the downmix is equal to the mono mix
+        let (near, far) = (1.0 + self.stereo_width, 1.0 - self.stereo_width);
+
+        if let Some(eq) = &mut self.engine.eq {
+            eq.update(self.samples_per_second);
+        }
+        self.update_output_hpf();
+
+        // intake, engine vibrations and exhaust for multichannel recorders
+        let mut tracks = if self
+            .recorder
+            .as_ref()
+            .is_some_and(Recorder::is_multichannel)
+        {
+            if self.track_dc_hp.is_none() {
+                self.track_dc_hp = Some(self.split_dc_hp());
+            }
+            Some(
+                (0..3)
+                    .map(|_| Vec::with_capacity(buf.len() / 2))
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            self.track_dc_hp = None;
+            None
+        };
+
+        // maximum rpm change per sample
+        let max_rpm_step = self
+            .engine
+            .rpm_slew_rate
+            .map_or(f32::INFINITY, |rate| rate / self.samples_per_second as f32);
+
+        buf.chunks_exact_mut(2).for_each(|frame| {
+            if let Some(target_rpm) = self.target_rpm {
+                let dif = target_rpm - self.engine.rpm;
+                if dif.abs() <= max_rpm_step {
+                    self.engine.rpm = target_rpm;
+                    self.target_rpm = None;
+                } else {
+                    self.engine.rpm += max_rpm_step.copysign(dif);
+                }
+                inc = self.engine.rpm / samples_per_second;
+            }
+
+            if let Some(rpm_envelope) = &self.rpm_envelope {
+                self.engine.rpm = rpm_envelope
+                    .sample_at(self.rpm_envelope_pos as f32 / self.samples_per_second as f32);
+                self.rpm_envelope_pos += 1;
+                inc = self.engine.rpm / samples_per_second;
+            }
+
+            if let Some(throttle_envelope) = &self.throttle_envelope {
+                self.engine.throttle = throttle_envelope
+                    .sample_at(self.throttle_envelope_pos as f32 / self.samples_per_second as f32)
+                    .clamp(0.0, 1.0);
+                self.throttle_envelope_pos += 1;
+            }
+
+            if let Some(morph) = &mut self.morph {
+                morph.step(&mut self.engine);
+                inc = self.engine.rpm / samples_per_second;
+            }
+
+            self.engine.crankshaft_pos = (self.engine.crankshaft_pos + inc).fract();
+
+            let channels = match &mut self.morph {
+                Some(morph) => morph.gen(&mut self.engine, self.samples_per_second),
+                None => self.engine.gen(self.samples_per_second),
+            };
+
+            if self.morph.as_ref().is_some_and(MorphState::is_done) {
+                self.engine = self.morph.take().unwrap().target;
+                self.engine_revision += 1;
+                if let Some(eq) = &mut self.engine.eq {
+                    eq.update(self.samples_per_second);
+                }
+                self.update_output_hpf();
+            }
+            let intake = channels.0 * self.engine.intake_volume;
+            let engine_vibrations = channels.1 * self.engine.engine_vibrations_volume;
+            let exhaust = channels.2 * self.engine.exhaust_volume;
+
+            let left = (intake * near + engine_vibrations + exhaust * far) * self.volume;
+            let right = (intake * far + engine_vibrations + exhaust * near) * self.volume;
+
+            let (left, right) = match &mut self.engine.eq {
+                Some(eq) => (eq.filter(left, 0), eq.filter(right, 1)),
+                None => (left, right),
+            };
+
+            // reduces dc offset
+            frame[0] = self.dc_hp[0].filter(left);
+            frame[1] = self.dc_hp[1].filter(right);
+
+            if self.engine.output_highpass_filter.is_some() {
+                frame[0] = self.output_hpf[0].filter(frame[0]);
+                frame[1] = self.output_hpf[1].filter(frame[1]);
+            }
+
+            if let Some(stems) = &mut self.stems {
+                let intake = intake * self.volume;
+                let engine_vibrations = engine_vibrations * self.volume;
+                let exhaust = exhaust * self.volume;
+
+                stems.intake.push(stems.dc_hp[0].filter(intake));
+                stems
+                    .engine_vibrations
+                    .push(stems.dc_hp[1].filter(engine_vibrations));
+                stems.exhaust.push(stems.dc_hp[2].filter(exhaust));
+            }
+
+            if let (Some(tracks), Some(dc_hp)) = (&mut tracks, &mut self.track_dc_hp) {
+                for (i, track) in [intake, engine_vibrations, exhaust].iter().enumerate() {
+                    let track = track * self.volume;
+                    tracks[i].push(dc_hp[i].filter(track));
+                }
+            }
+
+            if let Some(progress_callback) = &self.progress_callback {
+                if self.progress_pos < self.progress_total {
+                    self.progress_pos += 1;
+
+                    if self
+                        .progress_pos
+                        .is_multiple_of(self.samples_per_second as usize)
+                        || self.progress_pos == self.progress_total
+                    {
+                        progress_callback(self.progress_pos, self.progress_total);
+                    }
+                }
+            }
+        });
+
+        self.dampened_waveguides = std::iter::once(&self.engine)
+            .chain(self.morph.as_ref().map(|morph| &morph.target))
+            .flat_map(Engine::waveguides)
+            .filter(|waveguide| waveguide.dampened)
+            .count();
+
+        // the frames of the preroll are left out
+        let frames = buf.len() / 2;
+        let skip = if self.recorder.is_some() {
+            let skip = self.recorder_preroll.min(frames);
+            self.recorder_preroll -= skip;
+            skip
+        } else {
+            0
+        };
+        if skip == frames {
+            return;
+        }
+
+        if let (Some(recorder), Some(mut tracks)) = (&mut self.recorder, tracks) {
+            tracks.iter_mut().for_each(|track| {
+                track.drain(..skip);
+            });
+            self.recording_currently_clipping =
+                tracks.iter().flatten().any(|sample| sample.abs() > 1.0);
+
+            recorder.record_multichannel(tracks);
+        } else if let Some(recorder) = &mut self.recorder {
+            let buf = &buf[skip * 2..];
+            let bufvec = if recorder.channels() == 1 {
+                buf.chunks_exact(2)
+                    .map(|lr| (lr[0] + lr[1]) * 0.5)
+                    .collect::<Vec<f32>>()
+            } else {
+                buf.to_vec()
+            };
+            let mut recording_currently_clipping = false;
+            bufvec
+                .iter()
+                .for_each(|sample| recording_currently_clipping |= sample.abs() > 1.0);
+            self.recording_currently_clipping = recording_currently_clipping;
+
+            recorder.record(bufvec);
+        }
+    }
+
+    /// silences all waveguides and clears the state of all filters of the generator and the engine
+    pub fn reset(&mut self) {
+        self.engine.reset();
+        self.dc_hp.iter_mut().for_each(BiquadFilter::reset);
+        self.output_hpf.iter_mut().for_each(HighPassFilter::reset);
+    }
+
+    /// copies the running state of the generator, generating after restoring it with `set_state` produces the same samples
+    pub fn get_state(&self) -> GeneratorState {
+        let mut state = GeneratorState {
+            crankshaft_pos: self.engine.crankshaft_pos,
+            target_rpm: self.target_rpm,
+            envelope_pos: [self.rpm_envelope_pos, self.throttle_envelope_pos],
+            dc_hp: [
+                [self.dc_hp[0].z1, self.dc_hp[0].z2],
+                [self.dc_hp[1].z1, self.dc_hp[1].z2],
+            ],
+            output_hpf: [self.output_hpf[0].last, self.output_hpf[1].last],
+            ..GeneratorState::default()
+        };
+        self.engine.clone().visit_state(&mut state);
+
+        state
+    }
+
+    /// restores a state returned by `get_state` of a generator with the same engine layout,
+    /// otherwise returns an error and leaves the generator unchanged
+    pub fn set_state(&mut self, state: GeneratorState) -> Result<(), String> {
+        let mut engine = self.engine.clone();
+        engine.crankshaft_pos = state.crankshaft_pos;
+        let (target_rpm, envelope_pos, dc_hp, output_hpf) = (
+            state.target_rpm,
+            state.envelope_pos,
+            state.dc_hp,
+            state.output_hpf,
+        );
+
+        let mut restorer = StateRestorer::new(state);
+        engine.visit_state(&mut restorer);
+        restorer.finish()?;
+
+        self.engine = engine;
+        self.target_rpm = target_rpm;
+        self.rpm_envelope_pos = envelope_pos[0];
+        self.throttle_envelope_pos = envelope_pos[1];
+        for (filter, [z1, z2]) in self.dc_hp.iter_mut().zip(dc_hp) {
+            filter.z1 = z1;
+            filter.z2 = z2;
+        }
+        self.output_hpf[0].last = output_hpf[0];
+        self.output_hpf[1].last = output_hpf[1];
+
+        Ok(())
+    }
+}
+
+/// `futures_core::Stream` of mono chunks of a `Generator` for async runtimes like tokio, e.g. to stream audio over HTTP
+///
+/// Every chunk is generated synchronously in `poll_next`, so the stream is always ready and never ends. It blocks the
+/// executor while generating and has no timing of its own, which makes it unsuitable for real-time playback,
+/// use `audio::init` of the binary instead.
+#[cfg(feature = "tokio")]
+pub struct GeneratorStream {
+    pub generator: Generator,
+    chunk_size: usize,
+}
+
+#[cfg(feature = "tokio")]
+impl GeneratorStream {
+    /// every chunk holds `chunk_size` mono samples at the generator's sample rate
+    pub fn new(generator: Generator, chunk_size: usize) -> Self {
+        GeneratorStream {
+            generator,
+            chunk_size,
         }
     }
 
+    pub fn into_inner(self) -> Generator {
+        self.generator
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl futures_core::Stream for GeneratorStream {
+    type Item = Vec<f32>;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Vec<f32>>> {
+        let stream = self.get_mut();
+        let mut chunk = vec![0.0; stream.chunk_size];
+        stream.generator.generate(&mut chunk);
+
+        std::task::Poll::Ready(Some(chunk))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+impl Engine {
+    /// silences all waveguides and clears the state of the filters, e.g. to stop feedback loops
     pub fn reset(&mut self) {
-        for cyl in self.engine.cylinders.iter_mut() {
+        for cyl in self.cylinders.iter_mut() {
             [
                 &mut cyl.exhaust_waveguide,
                 &mut cyl.intake_waveguide,
@@ -250,304 +1918,1785 @@ impl Generator {
             cyl.cyl_sound = 0.0;
         }
 
-        std::iter::once(&mut self.engine.muffler.straight_pipe)
+        IntoIterator::into_iter([&mut self.muffler.straight_pipe, &mut self.egr_delay])
             .flat_map(|x| vec![&mut x.chamber0, &mut x.chamber1])
             .for_each(|chamber| chamber.samples.data.iter_mut().for_each(|x| *x = 0.0));
 
-        for muffler_element in self.engine.muffler.muffler_elements.iter_mut() {
-            muffler_element
-                .chamber0
-                .samples
-                .data
-                .iter_mut()
-                .for_each(|sample| *sample = 0.0);
-            muffler_element
-                .chamber1
-                .samples
-                .data
+        self.muffler
+            .muffler_elements
+            .iter_mut()
+            .for_each(MufflerElement::reset);
+
+        self.exhaust_collector = 0.0;
+        self.intake_collector = 0.0;
+
+        self.intake_noise_lp.reset();
+        self.engine_vibration_filter.reset();
+        self.crankshaft_fluctuation_lp.reset();
+        if let Some(hpf) = &mut self.exhaust_highpass_filter {
+            hpf.reset();
+        }
+
+        self.waveguides_mut().for_each(WaveGuide::reset_damping);
+    }
+
+    /// visits the running values of the engine except for the crankshaft position, see `GeneratorState`,
+    /// the rpm and throttle are included because envelopes change them while generating
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        visitor.value(&mut self.rpm);
+        visitor.value(&mut self.throttle);
+        visitor.value(&mut self.current_throttle);
+        visitor.value(&mut self.exhaust_collector);
+        visitor.value(&mut self.intake_collector);
+        visitor.noise(&mut self.intake_noise);
+        visitor.noise(&mut self.crankshaft_noise);
+        visitor.noise(&mut self.misfire_noise);
+        visitor.value(&mut self.intake_noise_lp.last);
+        visitor.value(&mut self.engine_vibration_filter.last);
+        visitor.value(&mut self.crankshaft_fluctuation_lp.last);
+
+        if let Some(resonator) = &mut self.intake_resonator {
+            resonator.state.iter_mut().for_each(|x| visitor.value(x));
+        }
+        if let Some(hpf) = &mut self.exhaust_highpass_filter {
+            visitor.value(&mut hpf.last);
+        }
+        if let Some(eq) = &mut self.eq {
+            for band in eq.bands.iter_mut() {
+                band.state
+                    .iter_mut()
+                    .flatten()
+                    .for_each(|x| visitor.value(x));
+            }
+        }
+        if let Some(turbo) = &mut self.turbo {
+            turbo.visit_state(visitor);
+        }
+        if let Some(overrun_crackle) = &mut self.overrun_crackle {
+            overrun_crackle.visit_state(visitor);
+        }
+
+        self.cylinders
+            .iter_mut()
+            .for_each(|cylinder| cylinder.visit_state(visitor));
+        self.waveguides_mut()
+            .for_each(|waveguide| waveguide.visit_state(visitor));
+        self.muffler
+            .muffler_elements
+            .iter_mut()
+            .for_each(|muffler_element| muffler_element.visit_state(visitor));
+        self.egr_delay.visit_state(visitor);
+    }
+
+    /// the waveguides of all cylinders (exhaust, intake and extractor), the straight pipe, the muffler elements and the EGR pipe
+    pub fn waveguides(&self) -> impl Iterator<Item = &WaveGuide> {
+        self.cylinders
+            .iter()
+            .flat_map(|cylinder| {
+                [
+                    &cylinder.exhaust_waveguide,
+                    &cylinder.intake_waveguide,
+                    &cylinder.extractor_waveguide,
+                ]
+            })
+            .chain(std::iter::once(&self.muffler.straight_pipe))
+            .chain(
+                self.muffler
+                    .muffler_elements
+                    .iter()
+                    .filter_map(MufflerElement::waveguide),
+            )
+            .chain(std::iter::once(&self.egr_delay))
+    }
+
+    fn waveguides_mut(&mut self) -> impl Iterator<Item = &mut WaveGuide> {
+        let Muffler {
+            straight_pipe,
+            muffler_elements,
+        } = &mut self.muffler;
+        let egr_delay = &mut self.egr_delay;
+
+        self.cylinders
+            .iter_mut()
+            .flat_map(|cylinder| {
+                [
+                    &mut cylinder.exhaust_waveguide,
+                    &mut cylinder.intake_waveguide,
+                    &mut cylinder.extractor_waveguide,
+                ]
+            })
+            .chain(std::iter::once(straight_pipe))
+            .chain(
+                muffler_elements
+                    .iter_mut()
+                    .filter_map(MufflerElement::waveguide_mut),
+            )
+            .chain(std::iter::once(egr_delay))
+    }
+
+    /// product of the `WaveGuide::frequency_response` of the straight pipe and the `MufflerElement::frequency_response`s
+    /// of all muffler elements
+    pub fn muffler_frequency_response(&self, sample_rate: u32, num_bins: usize) -> Vec<f32> {
+        std::iter::once(
+            self.muffler
+                .straight_pipe
+                .frequency_response(sample_rate, num_bins),
+        )
+        .chain(
+            self.muffler
+                .muffler_elements
+                .iter()
+                .map(|element| element.frequency_response(sample_rate, num_bins)),
+        )
+        .fold(vec![1.0; num_bins], |mut response, element| {
+            response
                 .iter_mut()
-                .for_each(|sample| *sample = 0.0);
+                .zip(element)
+                .for_each(|(response, element)| *response *= element);
+            response
+        })
+    }
+
+    /// generates one sample worth of audio
+    /// returns  `(intake, engine vibrations, exhaust)`
+    ///
+    /// Every waveguide is first popped, which yields the waves arriving at its ends, then the junctions are computed and
+    /// the waves leaving them are pushed. The cylinders see the exhaust collector pressure of the previous sample divided
+    /// by the number of active cylinders, and the intake collector, the sum of the intake waveguides' far ends, is fed back
+    /// to the cylinders together with the intake noise which only enters while their intake valve is open. The exhaust
+    /// collector feeds the straight pipe, whose far end is split evenly into the parallel muffler elements.
+    fn gen(&mut self, samples_per_second: u32) -> (f32, f32, f32) {
+        let throttle_fac =
+            1.0 - (-1.0 / (THROTTLE_SMOOTHING_TIME * samples_per_second as f32)).exp();
+        self.current_throttle +=
+            (self.throttle.clamp(0.0, 1.0) - self.current_throttle) * throttle_fac;
+        let throttle = self.current_throttle;
+
+        let intake_noise =
+            self.intake_noise_lp.filter(self.intake_noise.step()) * self.intake_noise_factor;
+        let intake_noise = match &mut self.intake_resonator {
+            Some(resonator) => resonator.filter(intake_noise, samples_per_second),
+            None => intake_noise,
+        };
+
+        // less air flows through the valves at closed throttle, but it hisses past the throttle plate
+        let valve_intake_noise = intake_noise
+            * (CLOSED_THROTTLE_INTAKE_NOISE + (1.0 - CLOSED_THROTTLE_INTAKE_NOISE) * throttle);
+        // 0.0 above `ENGINE_BRAKING_THROTTLE` - 1.0 at closed throttle
+        let engine_braking = (1.0 - throttle / ENGINE_BRAKING_THROTTLE).max(0.0);
+        let vacuum_hiss = intake_noise
+            * (1.0 - throttle)
+            * THROTTLE_VACUUM_HISS
+            * (1.0 + ENGINE_BRAKING_VACUUM_HISS * engine_braking);
+
+        let mut engine_vibration = 0.0;
+
+        // deactivated cylinders don't contribute to the collectors
+        let num_cyl = self
+            .cylinders
+            .iter()
+            .filter(|cylinder| cylinder.active)
+            .count()
+            .max(1) as f32;
+
+        let last_exhaust_collector = self.exhaust_collector / num_cyl;
+        self.exhaust_collector = 0.0;
+        self.intake_collector = 0.0;
+
+        let crankshaft_fluctuation_offset = self
+            .crankshaft_fluctuation_lp
+            .filter(self.crankshaft_noise.step());
+        // the crankshaft runs less smoothly when it is dragged along by the car instead of driven by combustion
+        let crankshaft_fluctuation = 1.0 + ENGINE_BRAKING_CRANKSHAFT_FLUCTUATION * engine_braking;
+
+        for cylinder in self.cylinders.iter_mut() {
+            let (cyl_intake, cyl_exhaust, cyl_vib) = cylinder.pop(
+                self.crankshaft_pos
+                    + self.crankshaft_fluctuation
+                        * crankshaft_fluctuation
+                        * crankshaft_fluctuation_offset,
+                last_exhaust_collector,
+                self.intake_valve_shift,
+                self.exhaust_valve_shift,
+                throttle,
+                &mut self.misfire_noise,
+                self.waveguide_adaptive_damping,
+            );
+
+            self.intake_collector += cyl_intake;
+            self.exhaust_collector += cyl_exhaust;
+
+            engine_vibration += cyl_vib;
+        }
+
+        // parallel input to the exhaust straight pipe
+        // alpha end is at exhaust collector
+        let straight_pipe_wg_ret = self
+            .muffler
+            .straight_pipe
+            .pop(self.waveguide_adaptive_damping);
+
+        // alpha end is at straight pipe end (beta)
+        let mut muffler_wg_ret = (0.0, 0.0);
+
+        for muffler_element in self.muffler.muffler_elements.iter_mut() {
+            let ret = muffler_element.pop(self.waveguide_adaptive_damping);
+            muffler_wg_ret.0 += ret.0;
+            muffler_wg_ret.1 += ret.1;
+        }
+
+        // alpha end is at the exhaust collector, the beta end feeds the intake collector,
+        // which is divided by the number of cylinders when it is distributed to them
+        if self.egr_factor > 0.0 {
+            let egr_wg_ret = self.egr_delay.pop(self.waveguide_adaptive_damping);
+            self.intake_collector += egr_wg_ret.1 * num_cyl;
+        }
+
+        // pop  //
+        //////////
+        // push //
+
+        let exhaust_pops = match &mut self.overrun_crackle {
+            Some(overrun_crackle) => overrun_crackle.step(
+                self.rpm,
+                engine_braking,
+                self.cylinders.len(),
+                samples_per_second,
+            ),
+            None => &[],
+        };
+
+        for (i, cylinder) in self.cylinders.iter_mut().enumerate() {
+            // modulate intake
+            cylinder.push(
+                self.intake_collector / num_cyl
+                    + valve_intake_noise
+                        * intake_valve((self.crankshaft_pos + cylinder.crank_offset).fract()),
+                exhaust_pops.get(i).copied().unwrap_or(0.0),
+            );
+        }
+
+        self.muffler
+            .straight_pipe
+            .push(self.exhaust_collector, muffler_wg_ret.0);
+
+        self.exhaust_collector += straight_pipe_wg_ret.0;
+
+        if self.egr_factor > 0.0 {
+            self.egr_delay.push(
+                self.exhaust_collector / num_cyl * self.egr_factor.min(1.0),
+                0.0,
+            );
+        }
+
+        let muffler_elements = self.muffler.muffler_elements.len() as f32;
+
+        for muffler_element in self.muffler.muffler_elements.iter_mut() {
+            muffler_element.push(
+                straight_pipe_wg_ret.1 / muffler_elements,
+                samples_per_second,
+            );
+        }
+
+        engine_vibration = self.engine_vibration_filter.filter(engine_vibration);
+
+        let exhaust = match &mut self.exhaust_highpass_filter {
+            Some(hpf) => hpf.filter(muffler_wg_ret.1),
+            None => muffler_wg_ret.1,
+        };
+
+        let turbo = match &mut self.turbo {
+            Some(turbo) => turbo.step(self.rpm, throttle, samples_per_second),
+            None => 0.0,
+        };
+
+        (
+            self.intake_collector + vacuum_hiss + turbo,
+            engine_vibration,
+            exhaust,
+        )
+    }
+}
+
+/// State of a `Generator::morph_to`
+pub struct MorphState {
+    /// runs in parallel to the current engine while morphing
+    pub target: Engine,
+    /// samples generated since the morph started
+    pub elapsed: usize,
+    /// duration of the morph in samples
+    pub total: usize,
+    start: MorphParameters,
+    end: MorphParameters,
+    /// set for a `Generator::swap_engine`, which only crossfades the outputs
+    swap: bool,
+}
+
+impl MorphState {
+    fn new(engine: &Engine, target: Engine, total: usize) -> MorphState {
+        MorphState {
+            start: MorphParameters::of(engine),
+            end: MorphParameters::of(&target),
+            target,
+            elapsed: 0,
+            total: total.max(1),
+            swap: false,
+        }
+    }
+
+    fn swap(engine: &Engine, target: Engine, total: usize) -> MorphState {
+        MorphState {
+            swap: true,
+            ..MorphState::new(engine, target, total)
+        }
+    }
+
+    /// whether this is a `Generator::swap_engine` instead of a `Generator::morph_to`
+    pub fn is_swap(&self) -> bool {
+        self.swap
+    }
+
+    /// 0.0 - 1.0
+    pub fn progress(&self) -> f32 {
+        self.elapsed as f32 / self.total as f32
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.elapsed >= self.total
+    }
+
+    /// advances the morph by one sample and sets the interpolated parameters for both engines
+    fn step(&mut self, engine: &mut Engine) {
+        self.elapsed = (self.elapsed + 1).min(self.total);
+
+        if self.swap {
+            // the target takes over the rpm and throttle which are still controlled through the current engine
+            self.target.rpm = engine.rpm;
+            self.target.throttle = engine.throttle;
+            return;
+        }
+
+        let parameters = self.start.lerp(&self.end, self.progress());
+        parameters.apply(engine);
+        parameters.apply(&mut self.target);
+    }
+
+    /// generates one sample of both engines and crossfades them
+    fn gen(&mut self, engine: &mut Engine, samples_per_second: u32) -> (f32, f32, f32) {
+        // both engines run in phase so the crossfade does not cancel out
+        self.target.crankshaft_pos = engine.crankshaft_pos;
+
+        let fade = self.progress();
+        let from = engine.gen(samples_per_second);
+        let to = self.target.gen(samples_per_second);
+
+        (
+            from.0 + (to.0 - from.0) * fade,
+            from.1 + (to.1 - from.1) * fade,
+            from.2 + (to.2 - from.2) * fade,
+        )
+    }
+}
+
+/// the scalar parameters of an `Engine` which are interpolated while morphing
+#[derive(Clone, Copy)]
+struct MorphParameters {
+    rpm: f32,
+    intake_volume: f32,
+    exhaust_volume: f32,
+    engine_vibrations_volume: f32,
+    throttle: f32,
+    intake_noise_factor: f32,
+    intake_valve_shift: f32,
+    exhaust_valve_shift: f32,
+    crankshaft_fluctuation: f32,
+    egr_factor: f32,
+}
+
+impl MorphParameters {
+    fn of(engine: &Engine) -> MorphParameters {
+        MorphParameters {
+            rpm: engine.rpm,
+            intake_volume: engine.intake_volume,
+            exhaust_volume: engine.exhaust_volume,
+            engine_vibrations_volume: engine.engine_vibrations_volume,
+            throttle: engine.throttle,
+            intake_noise_factor: engine.intake_noise_factor,
+            intake_valve_shift: engine.intake_valve_shift,
+            exhaust_valve_shift: engine.exhaust_valve_shift,
+            crankshaft_fluctuation: engine.crankshaft_fluctuation,
+            egr_factor: engine.egr_factor,
+        }
+    }
+
+    fn lerp(&self, other: &MorphParameters, t: f32) -> MorphParameters {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        MorphParameters {
+            rpm: lerp(self.rpm, other.rpm),
+            intake_volume: lerp(self.intake_volume, other.intake_volume),
+            exhaust_volume: lerp(self.exhaust_volume, other.exhaust_volume),
+            engine_vibrations_volume: lerp(
+                self.engine_vibrations_volume,
+                other.engine_vibrations_volume,
+            ),
+            throttle: lerp(self.throttle, other.throttle),
+            intake_noise_factor: lerp(self.intake_noise_factor, other.intake_noise_factor),
+            intake_valve_shift: lerp(self.intake_valve_shift, other.intake_valve_shift),
+            exhaust_valve_shift: lerp(self.exhaust_valve_shift, other.exhaust_valve_shift),
+            crankshaft_fluctuation: lerp(self.crankshaft_fluctuation, other.crankshaft_fluctuation),
+            egr_factor: lerp(self.egr_factor, other.egr_factor),
+        }
+    }
+
+    fn apply(&self, engine: &mut Engine) {
+        engine.rpm = self.rpm;
+        engine.intake_volume = self.intake_volume;
+        engine.exhaust_volume = self.exhaust_volume;
+        engine.engine_vibrations_volume = self.engine_vibrations_volume;
+        engine.throttle = self.throttle;
+        engine.intake_noise_factor = self.intake_noise_factor;
+        engine.intake_valve_shift = self.intake_valve_shift;
+        engine.exhaust_valve_shift = self.exhaust_valve_shift;
+        engine.crankshaft_fluctuation = self.crankshaft_fluctuation;
+        engine.egr_factor = self.egr_factor;
+    }
+}
+
+/// Keyframes of `(time in seconds, rpm)` which are linearly interpolated
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpmEnvelope {
+    keyframes: Vec<(f32, f32)>,
+}
+
+impl RpmEnvelope {
+    /// returns `None` if there are no keyframes, the keyframes are sorted by their time
+    pub fn new(mut keyframes: Vec<(f32, f32)>) -> Option<RpmEnvelope> {
+        if keyframes.is_empty() {
+            None
+        } else {
+            keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            Some(RpmEnvelope { keyframes })
+        }
+    }
+
+    pub fn keyframes(&self) -> &[(f32, f32)] {
+        &self.keyframes
+    }
+
+    /// returns the time of the last keyframe in seconds
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().unwrap().0
+    }
+
+    /// returns the linearly interpolated rpm at `time` seconds, the rpm of the first/last keyframe is held before/after them
+    pub fn sample_at(&self, time: f32) -> f32 {
+        let first = self.keyframes[0];
+        let last = self.keyframes[self.keyframes.len() - 1];
+
+        if time <= first.0 {
+            first.1
+        } else if time >= last.0 {
+            last.1
+        } else {
+            let i = self.keyframes.partition_point(|(t, _)| *t <= time);
+            let (start_time, start_rpm) = self.keyframes[i - 1];
+            let (end_time, end_rpm) = self.keyframes[i];
+
+            start_rpm + (end_rpm - start_rpm) * (time - start_time) / (end_time - start_time)
+        }
+    }
+}
+
+/// parses comma separated `time:rpm` pairs, e.g. `0.0:800,1.5:3500,4.0:6000`
+impl std::str::FromStr for RpmEnvelope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let keyframes = s
+            .split(',')
+            .map(|keyframe| {
+                let mut parts = keyframe.trim().splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(time), Some(rpm)) => match (time.trim().parse(), rpm.trim().parse()) {
+                        (Ok(time), Ok(rpm)) => Ok((time, f32::max(rpm, 0.0))),
+                        _ => Err(format!("Invalid number in keyframe \"{}\"", keyframe)),
+                    },
+                    _ => Err(format!(
+                        "Keyframe \"{}\" is not of the form time:rpm",
+                        keyframe
+                    )),
+                }
+            })
+            .collect::<Result<Vec<(f32, f32)>, String>>()?;
+
+        RpmEnvelope::new(keyframes).ok_or_else(|| "RPM envelope has no keyframes".to_string())
+    }
+}
+
+/// Digital waveguide model of a pipe: two delay lines carrying the pressure waves travelling in either direction
+///
+/// ```text
+///   x0 ---> chamber0 ---> end
+/// alpha                   beta
+///   end <--- chamber1 <--- x1
+/// ```
+///
+/// A wave arriving at an end is reflected into the other chamber multiplied by that end's reflection factor
+/// (alpha or beta, -1.0 - 1.0, negative for an open pipe end, positive for a closed one) and the rest, `1 - |factor|`,
+/// leaves the pipe, which `pop` returns. The delay of both chambers in seconds is the pipe length divided by `SPEED_OF_SOUND`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WaveGuide {
+    // goes from x0 to x1
+    pub chamber0: DelayLine,
+    // goes from x1 to x0
+    pub chamber1: DelayLine,
+    /// reflection factor for the first value of the return tuple of `pop`
+    pub alpha: f32,
+    /// reflection factor for the second value of the return tuple of `pop`
+    pub beta: f32,
+    /// fraction of a sample (0.0 - 1.0) by which the chambers' delay exceeds their integer length,
+    /// derived from the chambers' delay while fixing the engine
+    #[serde(skip)]
+    pub fractional_delay: f32,
+
+    // running values
+    #[serde(skip)]
+    c1_out: f32,
+    #[serde(skip)]
+    c0_out: f32,
+    /// maximum amplitude of the chamber outputs which decays exponentially, see `Engine::waveguide_adaptive_damping`
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub running_max: f32,
+    /// consecutive samples for which `running_max` exceeded `WAVEGUIDE_DAMPEN_THRESHOLD`
+    #[serde(skip)]
+    samples_over_threshold: usize,
+    /// multiplies the chamber outputs, reduced by the adaptive damping
+    #[doc(hidden)]
+    #[serde(skip, default = "default_gain")]
+    pub gain: f32,
+    /// set when the output was dampened since it was last cleared, which `Generator::generate_stereo` does for every buffer
+    #[serde(skip)]
+    pub dampened: bool,
+}
+
+impl WaveGuide {
+    /// keeps the contents of the chambers if their length did not change
+    fn apply_parameters(&mut self, from: WaveGuide) {
+        if self.chamber0.samples.len() == from.chamber0.samples.len() {
+            self.chamber0.samples.delay = from.chamber0.samples.delay;
+            self.chamber1.samples.delay = from.chamber1.samples.delay;
+            self.alpha = from.alpha;
+            self.beta = from.beta;
+            self.fractional_delay = from.fractional_delay;
+        } else {
+            *self = from;
+        }
+    }
+
+    /// `delay` is given in samples and may be fractional
+    pub fn new(delay: f32, alpha: f32, beta: f32, samples_per_second: u32) -> WaveGuide {
+        let delay = delay.max(1.0);
+        let len = delay.floor() as usize;
+
+        let mut chamber0 = DelayLine::new(len, samples_per_second);
+        let mut chamber1 = DelayLine::new(len, samples_per_second);
+        chamber0.samples.delay = delay / samples_per_second as f32;
+        chamber1.samples.delay = delay / samples_per_second as f32;
+
+        WaveGuide {
+            chamber0,
+            chamber1,
+            alpha,
+            beta,
+            fractional_delay: delay.fract(),
+            c1_out: 0.0,
+            c0_out: 0.0,
+            running_max: 0.0,
+            samples_over_threshold: 0,
+            gain: 1.0,
+            dampened: false,
+        }
+    }
+
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        self.chamber0.samples.visit_state(visitor);
+        self.chamber1.samples.visit_state(visitor);
+        visitor.value(&mut self.c1_out);
+        visitor.value(&mut self.c0_out);
+        visitor.value(&mut self.running_max);
+        visitor.count(&mut self.samples_over_threshold);
+        visitor.value(&mut self.gain);
+        visitor.flag(&mut self.dampened);
+    }
+
+    /// restores the full gain of an adaptively dampened waveguide
+    pub fn reset_damping(&mut self) {
+        self.running_max = 0.0;
+        self.samples_over_threshold = 0;
+        self.gain = 1.0;
+    }
+
+    /// returns the delay of the chambers in samples including the fractional part
+    #[inline]
+    pub fn delay(&self) -> f32 {
+        self.chamber0.samples.len() as f32 + self.fractional_delay
+    }
+
+    /// theoretical magnitude response from the alpha side input to the beta side output
+    /// at `num_bins` linearly spaced frequencies from 0 to `sample_rate / 2`
+    ///
+    /// the chambers form a comb filter with the round trip delay `2 * delay()` and the feedback `alpha * beta`,
+    /// which has peaks every `sample_rate / (2 * delay())` hz (`c / (2 * length)`), shifted by half of that for a negative feedback
+    pub fn frequency_response(&self, sample_rate: u32, num_bins: usize) -> Vec<f32> {
+        let round_trip = 2.0 * self.delay() / sample_rate as f32;
+        let feedback = self.alpha * self.beta;
+        let gain = 1.0 - self.beta.abs();
+        let bin_width = sample_rate as f32 * 0.5 / num_bins.saturating_sub(1).max(1) as f32;
+
+        (0..num_bins)
+            .map(|i| {
+                let phase = PI2F * i as f32 * bin_width * round_trip;
+                // |1 - feedback * e^(-j * phase)|
+                let denominator = (1.0 - 2.0 * feedback * phase.cos() + feedback * feedback).sqrt();
+
+                gain / denominator.max(1e-6)
+            })
+            .collect()
+    }
+
+    /// returns the outputs of the alpha and beta side and whether they were dampened.
+    ///
+    /// Outputs above `WAVEGUIDE_MAX_AMP` are soft clipped. With `adaptive_damping`, the gain of the waveguide is also
+    /// reduced while its running maximum amplitude exceeds `WAVEGUIDE_DAMPEN_THRESHOLD` for more than `MIN_STABLE_SAMPLES`
+    /// samples, and slowly restored once it has fallen below it again
+    pub fn pop(&mut self, adaptive_damping: bool) -> (f32, f32, bool) {
+        let c1_out = self.chamber1.pop_interpolated(self.fractional_delay);
+        let c0_out = self.chamber0.pop_interpolated(self.fractional_delay);
+
+        if adaptive_damping {
+            self.update_gain(c1_out.abs().max(c0_out.abs()));
+        } else if self.gain != 1.0 {
+            self.reset_damping();
+        }
+
+        let (c1_out, dampened_c1) = WaveGuide::dampen(c1_out * self.gain);
+        let (c0_out, dampened_c0) = WaveGuide::dampen(c0_out * self.gain);
+        self.c1_out = c1_out;
+        self.c0_out = c0_out;
+
+        let dampened = dampened_c1 | dampened_c0 | (self.gain < 1.0);
+        self.dampened |= dampened;
+
+        (
+            self.c1_out * (1.0 - self.alpha.abs()),
+            self.c0_out * (1.0 - self.beta.abs()),
+            dampened,
+        )
+    }
+
+    /// follows the amplitude `amp` of the current sample with the adaptive damping
+    #[inline]
+    fn update_gain(&mut self, amp: f32) {
+        self.running_max = amp.max(self.running_max * WAVEGUIDE_RUNNING_MAX_DECAY);
+
+        if self.running_max > WAVEGUIDE_DAMPEN_THRESHOLD {
+            self.samples_over_threshold += 1;
+
+            if self.samples_over_threshold > MIN_STABLE_SAMPLES {
+                self.gain =
+                    (self.gain * WAVEGUIDE_DAMPING_GAIN_REDUCTION).max(WAVEGUIDE_DAMPING_MIN_GAIN);
+            }
+        } else {
+            self.samples_over_threshold = 0;
+            self.gain = (self.gain + WAVEGUIDE_DAMPING_ATTACK).min(1.0);
+        }
+    }
+    #[inline]
+    pub fn dampen(sample: f32) -> (f32, bool) {
+        let sample_abs = sample.abs();
+        if sample_abs > WAVEGUIDE_MAX_AMP {
+            (
+                sample.signum()
+                    * (-1.0 / (sample_abs - WAVEGUIDE_MAX_AMP + 1.0) + 1.0 + WAVEGUIDE_MAX_AMP),
+                true,
+            )
+        } else {
+            (sample, false)
+        }
+    }
+
+    pub fn push(&mut self, x0_in: f32, x1_in: f32) {
+        let c0_in = flush_denormal(self.c1_out * self.alpha + x0_in);
+        let c1_in = flush_denormal(self.c0_out * self.beta + x1_in);
+
+        self.chamber0.push(c0_in);
+        self.chamber1.push(c1_in);
+        self.chamber0.samples.advance();
+        self.chamber1.samples.advance();
+    }
+
+    /// returns a new waveguide if the reflection factors change together with the delay in whole samples, other
+    /// changes are applied to this waveguide in place without reallocating and return `None`
+    #[allow(clippy::float_cmp)]
+    pub fn get_changed(
+        &mut self,
+        delay: f32,
+        alpha: f32,
+        beta: f32,
+        samples_per_second: u32,
+    ) -> Option<Self> {
+        let delay = delay.max(1.0);
+
+        // the strictly compared values will never change without user interaction (adjusting sliders)
+        if delay.floor() as usize == self.chamber0.samples.len()
+            && delay.fract() != self.fractional_delay
+        {
+            // only the fractional delay changed, the buffers can be kept as they are which avoids
+            // artifacts when sweeping pipe lengths
+            self.alpha = alpha;
+            self.beta = beta;
+            self.fractional_delay = delay.fract();
+            self.chamber0.samples.delay = delay / samples_per_second as f32;
+            self.chamber1.samples.delay = delay / samples_per_second as f32;
+
+            None
+        } else if delay.floor() as usize != self.chamber0.samples.len()
+            && alpha == self.alpha
+            && beta == self.beta
+        {
+            // sweeps move along the samples which are still in the buffers, which keeps the pitch continuous
+            for chamber in [&mut self.chamber0, &mut self.chamber1] {
+                if !chamber
+                    .samples
+                    .set_len(delay.floor() as usize, samples_per_second)
+                {
+                    chamber
+                        .samples
+                        .resize(delay.floor() as usize, samples_per_second);
+                }
+            }
+            self.fractional_delay = delay.fract();
+            self.chamber0.samples.delay = delay / samples_per_second as f32;
+            self.chamber1.samples.delay = delay / samples_per_second as f32;
+
+            None
+        } else if delay.floor() as usize != self.chamber0.samples.len()
+            || alpha != self.alpha
+            || beta != self.beta
+        {
+            let mut new = Self::new(delay, alpha, beta, samples_per_second);
+
+            // used to reduce artifacts while resizing pipes _a bit_
+            new.chamber0.samples.copy_faded_from(&self.chamber0.samples);
+            new.chamber1.samples.copy_faded_from(&self.chamber1.samples);
+
+            Some(new)
+        } else {
+            None
+        }
+    }
+}
+
+/// Ring buffer delaying samples by a whole number of samples, the building block of `DelayLine`s and thus `WaveGuide`s
+///
+/// Only the delay in seconds is saved in configs, the length in samples is derived from it for the sample rate
+/// while fixing the engine.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct LoopBuffer {
+    // in seconds
+    pub delay: f32,
+    /// ring buffer whose length is padded to a power of two, so positions wrap with `mask` instead of a division
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub data: Vec<f32>,
+    /// delay in whole samples
+    #[serde(skip)]
+    len: usize,
+    /// `data.len() - 1`
+    #[serde(skip)]
+    mask: usize,
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub pos: usize,
+}
+
+impl LoopBuffer {
+    /// Creates a new loop buffer with specifies length.
+    /// The internal sample buffer is padded to the next power of two above `len`, which also leaves room for the
+    /// sample `pop_interpolated` interpolates with. From 16 samples on, this is a multiple of every SIMD width of
+    /// `get_best_simd_size`, so `pop_bulk` and `push_bulk` only copy less than a vector where the buffer wraps around.
+    pub fn new(len: usize, samples_per_second: u32) -> LoopBuffer {
+        let capacity = (len + 1).next_power_of_two();
+
+        LoopBuffer {
+            delay: len as f32 / samples_per_second as f32,
+            data: vec![0.0; capacity],
+            len,
+            mask: capacity - 1,
+            pos: 0,
+        }
+    }
+
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        visitor.buffer(&mut self.data);
+        visitor.count(&mut self.pos);
+    }
+
+    /// delay in whole samples
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// index of the sample which is popped `offset` samples after the current one
+    #[inline]
+    fn index(&self, offset: usize) -> usize {
+        self.pos.wrapping_add(offset + 1).wrapping_sub(self.len) & self.mask
+    }
+
+    /// Sets the value at the current position. Must be called with `pop`.
+    /// ```rust
+    /// # use enginesound::LoopBuffer;
+    /// let mut lb = LoopBuffer::new(2, 48000);
+    /// lb.push(1.0);
+    /// lb.advance();
+    ///
+    /// assert_eq!(lb.pop(), 1.0);
+    /// ```
+    #[inline]
+    pub fn push(&mut self, value: f32) {
+        let index = self.pos & self.mask;
+        self.data[index] = value;
+    }
+
+    /// Gets the value `self.len` samples prior. Must be called with `push`.
+    /// See `push` for examples
+    #[inline]
+    pub fn pop(&mut self) -> f32 {
+        self.data[self.index(0)]
+    }
+
+    /// Gets the value `self.len - 1 + frac` samples prior by linearly interpolating between the value
+    /// returned by `pop` and the next older value. Must be called with `push`.
+    #[inline]
+    pub fn pop_interpolated(&mut self, frac: f32) -> f32 {
+        let newer = self.data[self.index(0)];
+        let older = self.data[self.pos.wrapping_sub(self.len) & self.mask];
+        // not `mul_add`, which is emulated in software on targets without FMA
+        (older - newer) * frac + newer
+    }
+
+    /// Advances the position of this loop buffer.
+    #[inline]
+    pub fn advance(&mut self) {
+        self.pos = self.pos.wrapping_add(1);
+    }
+
+    /// Gets the values the next `out.len()` calls to `pop` return when they are interleaved with `push` and
+    /// `advance`, without advancing the position. Together with `push_bulk`, this processes a delay line in blocks
+    /// of less than `len` samples, longer blocks would pop samples pushed within the block.
+    /// The samples are copied with the widest SIMD instructions the CPU supports.
+    /// ```rust
+    /// # use enginesound::LoopBuffer;
+    /// let mut lb = LoopBuffer::new(4, 48000);
+    /// lb.push_bulk(&[1.0, 2.0, 3.0]);
+    ///
+    /// let mut out = [0.0; 3];
+    /// lb.pop_bulk(&mut out);
+    /// assert_eq!(out, [1.0, 2.0, 3.0]);
+    /// ```
+    pub fn pop_bulk(&mut self, out: &mut [f32]) {
+        assert!(
+            out.len() < self.len,
+            "bulk pop is not shorter than the delay of the loop buffer"
+        );
+
+        let start = self.index(0);
+        let first = (self.data.len() - start).min(out.len());
+        let (head, tail) = out.split_at_mut(first);
+        simd_copy(&self.data[start..start + first], head);
+        simd_copy(&self.data[..tail.len()], tail);
+    }
+
+    /// Sets the values of the next `data.len()` calls to `push` and advances the position past them.
+    /// `data` must not be longer than the buffer, see `pop_bulk`.
+    pub fn push_bulk(&mut self, data: &[f32]) {
+        assert!(
+            data.len() <= self.data.len(),
+            "bulk push is longer than the loop buffer"
+        );
+
+        let start = self.pos & self.mask;
+        let first = (self.data.len() - start).min(data.len());
+        simd_copy(&data[..first], &mut self.data[start..start + first]);
+        simd_copy(&data[first..], &mut self.data[..data.len() - first]);
+
+        self.pos = self.pos.wrapping_add(data.len());
+    }
+
+    /// Changes the delay to `new_len` samples by moving the position samples are popped from through the samples
+    /// pushed before, like the read head of a tape delay. Unlike `resize`, lengthening pops the samples pushed before
+    /// the current one again, so the popped samples stay continuous while the delay is swept.
+    /// Returns `false` without changing the buffer if `new_len` exceeds its capacity.
+    /// ```rust
+    /// # use enginesound::LoopBuffer;
+    /// let mut lb = LoopBuffer::new(2, 48000);
+    /// for value in 1..=4 {
+    ///     lb.push(value as f32);
+    ///     lb.advance();
+    /// }
+    /// assert_eq!(lb.pop(), 4.0);
+    ///
+    /// assert!(lb.set_len(3, 48000));
+    /// assert_eq!(lb.pop(), 3.0);
+    /// assert!(!lb.set_len(4, 48000));
+    /// ```
+    pub fn set_len(&mut self, new_len: usize, samples_per_second: u32) -> bool {
+        if new_len + 1 > self.data.len() {
+            return false;
+        }
+
+        self.len = new_len;
+        self.delay = new_len as f32 / samples_per_second as f32;
+
+        true
+    }
+
+    /// Changes the delay to `new_len` samples while keeping the contents.
+    /// Shortening keeps the `new_len` most recently pushed samples, lengthening keeps all samples in the order they
+    /// are popped and fades the new ones from the newest back to the oldest sample like `copy_faded_from`.
+    /// Only reallocates if the buffer grows beyond its capacity.
+    /// ```rust
+    /// # use enginesound::LoopBuffer;
+    /// let mut lb = LoopBuffer::new(4, 48000);
+    /// for value in 1..=4 {
+    ///     lb.push(value as f32);
+    ///     lb.advance();
+    /// }
+    /// lb.resize(2, 48000);
+    ///
+    /// assert_eq!(lb.pop(), 4.0);
+    /// assert_eq!(lb.pop_interpolated(1.0), 3.0);
+    /// ```
+    pub fn resize(&mut self, new_len: usize, samples_per_second: u32) {
+        if new_len + 1 > self.data.len() {
+            let mut new = LoopBuffer::new(new_len, samples_per_second);
+            new.copy_faded_from(self);
+            *self = new;
+            return;
+        }
+
+        let old_len = self.len;
+        self.delay = new_len as f32 / samples_per_second as f32;
+
+        if new_len <= old_len {
+            // the newest samples stay where they are, the older ones are dropped by popping from a later position
+            self.len = new_len;
+        } else {
+            let oldest = self.data[self.index(0)];
+            let newest = self.data[self.index(old_len - 1)];
+
+            // moves the position so the old samples are popped first, the slot before the oldest sample stays the same
+            self.pos = self.pos.wrapping_add(new_len - old_len);
+            self.len = new_len;
+
+            for i in old_len..new_len {
+                let index = self.index(i);
+                self.data[index] =
+                    newest + (oldest - newest) * (i - old_len) as f32 / (new_len - old_len) as f32;
+            }
+        }
+    }
+
+    /// fills the buffer with the samples `source` pops next, the samples missing if this buffer is longer
+    /// fade from the newest back to the oldest sample of `source`
+    fn copy_faded_from(&mut self, source: &LoopBuffer) {
+        let min_len = self.len.min(source.len);
+        let oldest = source.data[source.index(0)];
+        let newest = source.data[source.index(source.len - 1)];
+
+        for i in 0..self.len {
+            let index = self.index(i);
+            self.data[index] = if i < min_len {
+                source.data[source.index(i)]
+            } else {
+                newest + (oldest - newest) * (i - min_len) as f32 / (self.len - min_len) as f32
+            };
+        }
+
+        let before_oldest = self.pos.wrapping_sub(self.len) & self.mask;
+        self.data[before_oldest] = source.data[source.pos.wrapping_sub(source.len) & source.mask];
+    }
+}
+
+/// Returns the amount of `f32`s the widest SIMD vector supported by the current CPU holds.
+/// The CPU features are detected at runtime on x86/x86-64, other targets fall back to the baseline of the target.
+pub fn get_best_simd_size() -> usize {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx512f") {
+            16
+        } else if is_x86_feature_detected!("avx") {
+            8
+        } else if is_x86_feature_detected!("sse") {
+            4
+        } else {
+            1
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    {
+        // NEON is mandatory on aarch64
+        4
+    }
+
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    {
+        1
+    }
+}
+
+/// copies `src` into `dst` of the same length with AVX if the CPU supports it, otherwise with `copy_from_slice`,
+/// which only uses the SIMD instructions of the target's baseline (SSE2 on x86-64, NEON on aarch64)
+#[inline]
+fn simd_copy(src: &[f32], dst: &mut [f32]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("avx") {
+            // safe because the CPU supports AVX
+            unsafe { simd_copy_avx(src, dst) };
+            return;
+        }
+    }
+
+    dst.copy_from_slice(src);
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx")]
+unsafe fn simd_copy_avx(src: &[f32], dst: &mut [f32]) {
+    #[cfg(target_arch = "x86")]
+    use std::arch::x86::{_mm256_loadu_ps, _mm256_storeu_ps};
+    #[cfg(target_arch = "x86_64")]
+    use std::arch::x86_64::{_mm256_loadu_ps, _mm256_storeu_ps};
+
+    assert_eq!(src.len(), dst.len());
+
+    let vectors = src.len() / 8;
+    for i in 0..vectors {
+        // both slices hold at least (i + 1) * 8 floats
+        let vector = _mm256_loadu_ps(src.as_ptr().add(i * 8));
+        _mm256_storeu_ps(dst.as_mut_ptr().add(i * 8), vector);
+    }
+    dst[vectors * 8..].copy_from_slice(&src[vectors * 8..]);
+}
+
+/// One-pole low pass filter, the discretized RC circuit `y[n] = y[n-1] + alpha * (x[n] - y[n-1])`
+///
+/// `alpha = w / (w + 1)` with `w = 2 * pi * cutoff / sample rate`, which attenuates by 6 dB per octave above the cutoff.
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct LowPassFilter {
+    /// 1 / cutoff frequency
+    pub delay: f32,
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub alpha: f32,
+    #[doc(hidden)]
+    #[serde(skip)]
+    pub last: f32,
+}
+
+impl LowPassFilter {
+    /// keeps the filter state
+    fn apply_parameters(&mut self, from: &LowPassFilter) {
+        self.delay = from.delay;
+        self.alpha = from.alpha;
+    }
+
+    pub fn new(freq: f32, samples_per_second: u32) -> LowPassFilter {
+        LowPassFilter {
+            delay: 1.0 / freq,
+            alpha: (PI2F * (1.0 / samples_per_second as f32) * freq)
+                / (PI2F * (1.0 / samples_per_second as f32) * freq + 1.0),
+            last: 0.0,
+        }
+    }
+
+    #[inline]
+    pub fn get_freq(&self) -> f32 {
+        1.0 / self.delay
+    }
+
+    /// changes the cutoff frequency, keeping the filter state
+    pub fn set_freq(&mut self, freq: f32, samples_per_second: u32) {
+        let new = Self::new(freq, samples_per_second);
+        self.delay = new.delay;
+        self.alpha = new.alpha;
+    }
+
+    /// clears the filter state, as if the filter had only received silence
+    pub fn reset(&mut self) {
+        self.last = 0.0;
+    }
+
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        let ret = flush_denormal((sample - self.last) * self.alpha + self.last);
+        self.last = ret;
+        ret
+    }
+
+    /// returns `None` if the cutoff frequency is unchanged, the filter state is carried over to keep the output continuous
+    #[allow(clippy::float_cmp)]
+    pub fn get_changed(&mut self, freq: f32, samples_per_second: u32) -> Option<Self> {
+        // the strictly compared value will never change without user interaction (adjusting sliders),
+        // filters which only differ in their state are the same
+        if 1.0 / freq == self.delay {
+            None
+        } else {
+            let mut new = self.clone();
+            new.set_freq(freq, samples_per_second);
+            Some(new)
         }
-
-        self.engine.exhaust_collector = 0.0;
-        self.engine.intake_collector = 0.0;
     }
+}
 
-    /// generates one sample worth of audio
-    /// returns  `(intake, engine vibrations, exhaust, waveguides dampened)`
-    fn gen(&mut self) -> (f32, f32, f32, bool) {
-        let intake_noise = self
-            .engine
-            .intake_noise_lp
-            .filter(self.engine.intake_noise.step())
-            * self.engine.intake_noise_factor;
-
-        let mut engine_vibration = 0.0;
+/// Second order filter in transposed direct form II, coefficients normalized by a0
+///
+/// `y[n] = b0 * x[n] + b1 * x[n-1] + b2 * x[n-2] - a1 * y[n-1] - a2 * y[n-2]`
+///
+/// The coefficients and state are double precision: the poles of a cutoff of a few hz are so close to 1 that the
+/// rounding of single precision coefficients noticeably slows down the filter's settling.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BiquadFilter {
+    pub b0: f64,
+    pub b1: f64,
+    pub b2: f64,
+    pub a1: f64,
+    pub a2: f64,
+    // running values
+    pub z1: f64,
+    pub z2: f64,
+}
 
-        let num_cyl = self.engine.cylinders.len() as f32;
+impl BiquadFilter {
+    /// Butterworth (Q = 1/sqrt(2)) high pass after the Audio EQ Cookbook, falls off by 12 dB per octave below `freq`
+    pub fn new_highpass(freq: f32, sample_rate: u32) -> BiquadFilter {
+        let w0 = std::f64::consts::PI * 2.0 * f64::from(freq) / f64::from(sample_rate);
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * std::f64::consts::FRAC_1_SQRT_2);
+        let a0 = 1.0 + alpha;
+
+        BiquadFilter {
+            b0: (1.0 + cos) / 2.0 / a0,
+            b1: -(1.0 + cos) / a0,
+            b2: (1.0 + cos) / 2.0 / a0,
+            a1: -2.0 * cos / a0,
+            a2: (1.0 - alpha) / a0,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
 
-        let last_exhaust_collector = self.engine.exhaust_collector / num_cyl;
-        self.engine.exhaust_collector = 0.0;
-        self.engine.intake_collector = 0.0;
+    /// clears the filter state, as if the filter had only received silence
+    pub fn reset(&mut self) {
+        self.z1 = 0.0;
+        self.z2 = 0.0;
+    }
 
-        let crankshaft_fluctuation_offset = self
-            .engine
-            .crankshaft_fluctuation_lp
-            .filter(self.engine.crankshaft_noise.step());
+    #[inline]
+    pub fn filter(&mut self, x: f32) -> f32 {
+        let x = f64::from(x);
+        let y = self.b0 * x + self.z1;
+        let flush = |z: f64| {
+            if z.abs() < f64::from(DENORMAL_THRESHOLD) {
+                0.0
+            } else {
+                z
+            }
+        };
+        self.z1 = flush(self.b1 * x - self.a1 * y + self.z2);
+        self.z2 = flush(self.b2 * x - self.a2 * y);
+        y as f32
+    }
+}
 
-        let mut cylinder_dampened = false;
+/// Subtracts the output of a `LowPassFilter` with the same cutoff frequency from the input
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct HighPassFilter {
+    /// 1 / cutoff frequency
+    pub delay: f32,
+    #[serde(skip)]
+    pub alpha: f32,
+    /// last output of the internal low pass filter
+    #[serde(skip)]
+    pub last: f32,
+}
 
-        for cylinder in self.engine.cylinders.iter_mut() {
-            let (cyl_intake, cyl_exhaust, cyl_vib, dampened) = cylinder.pop(
-                self.engine.crankshaft_pos
-                    + self.engine.crankshaft_fluctuation * crankshaft_fluctuation_offset,
-                last_exhaust_collector,
-                self.engine.intake_valve_shift,
-                self.engine.exhaust_valve_shift,
-            );
+impl HighPassFilter {
+    /// keeps the filter state
+    fn apply_parameters(&mut self, from: &HighPassFilter) {
+        self.delay = from.delay;
+        self.alpha = from.alpha;
+    }
 
-            self.engine.intake_collector += cyl_intake;
-            self.engine.exhaust_collector += cyl_exhaust;
+    pub fn new(freq: f32, samples_per_second: u32) -> HighPassFilter {
+        let lp = LowPassFilter::new(freq, samples_per_second);
 
-            engine_vibration += cyl_vib;
-            cylinder_dampened |= dampened;
+        HighPassFilter {
+            delay: lp.delay,
+            alpha: lp.alpha,
+            last: lp.last,
         }
+    }
 
-        // parallel input to the exhaust straight pipe
-        // alpha end is at exhaust collector
-        let straight_pipe_wg_ret = self.engine.muffler.straight_pipe.pop();
-
-        // alpha end is at straight pipe end (beta)
-        let mut muffler_wg_ret = (0.0, 0.0, false);
+    #[inline]
+    pub fn get_freq(&self) -> f32 {
+        1.0 / self.delay
+    }
 
-        for muffler_line in self.engine.muffler.muffler_elements.iter_mut() {
-            let ret = muffler_line.pop();
-            muffler_wg_ret.0 += ret.0;
-            muffler_wg_ret.1 += ret.1;
-            muffler_wg_ret.2 |= ret.2;
-        }
+    /// clears the filter state, as if the filter had only received silence
+    pub fn reset(&mut self) {
+        self.last = 0.0;
+    }
 
-        // pop  //
-        //////////
-        // push //
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        let low = flush_denormal((sample - self.last) * self.alpha + self.last);
+        self.last = low;
+        sample - low
+    }
 
-        for cylinder in self.engine.cylinders.iter_mut() {
-            // modulate intake
-            cylinder.push(
-                self.engine.intake_collector / num_cyl
-                    + intake_noise
-                        * intake_valve(
-                            (self.engine.crankshaft_pos + cylinder.crank_offset).fract(),
-                        ),
-            );
+    /// returns `None` if the cutoff frequency is unchanged, the filter state is carried over to keep the output continuous
+    #[allow(clippy::float_cmp)]
+    pub fn get_changed(&mut self, freq: f32, samples_per_second: u32) -> Option<Self> {
+        // the strictly compared value will never change without user interaction (adjusting sliders)
+        if 1.0 / freq == self.delay {
+            None
+        } else {
+            Some(Self {
+                last: self.last,
+                ..Self::new(freq, samples_per_second)
+            })
         }
+    }
+}
 
-        self.engine
-            .muffler
-            .straight_pipe
-            .push(self.engine.exhaust_collector, muffler_wg_ret.0);
+/// maximum amount of bands of a `ParametricEq` which can be edited in the GUI
+pub const MAX_EQ_BANDS: usize = 4;
 
-        self.engine.exhaust_collector += straight_pipe_wg_ret.0;
+/// Equalizer of the stereo master output, the bands are applied in series
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct ParametricEq {
+    pub bands: Vec<EqBand>,
+}
 
-        let muffler_elements = self.engine.muffler.muffler_elements.len() as f32;
+impl ParametricEq {
+    /// recomputes the filter coefficients of all bands whose parameters have changed
+    pub fn update(&mut self, samples_per_second: u32) {
+        self.bands
+            .iter_mut()
+            .for_each(|band| band.update(samples_per_second));
+    }
 
-        for muffler_delay_line in self.engine.muffler.muffler_elements.iter_mut() {
-            muffler_delay_line.push(straight_pipe_wg_ret.1 / muffler_elements, 0.0);
-        }
+    /// filters a sample of the left (0) or right (1) channel
+    pub fn filter(&mut self, sample: f32, channel: usize) -> f32 {
+        self.bands
+            .iter_mut()
+            .fold(sample, |sample, band| band.filter(sample, channel))
+    }
+}
 
-        engine_vibration = self.engine.engine_vibration_filter.filter(engine_vibration);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EqBandKind {
+    Peak,
+    LowShelf,
+    HighShelf,
+    /// removes `freq`, ignores `gain_db`
+    Notch,
+}
 
-        (
-            self.engine.intake_collector,
-            engine_vibration,
-            muffler_wg_ret.1,
-            straight_pipe_wg_ret.2 | cylinder_dampened,
-        )
+impl EqBandKind {
+    pub const ALL: [EqBandKind; 4] = [
+        EqBandKind::Peak,
+        EqBandKind::LowShelf,
+        EqBandKind::HighShelf,
+        EqBandKind::Notch,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            EqBandKind::Peak => "Peak",
+            EqBandKind::LowShelf => "Low shelf",
+            EqBandKind::HighShelf => "High shelf",
+            EqBandKind::Notch => "Notch",
+        }
     }
 }
 
+/// Second-order IIR filter with the coefficients of the Audio EQ Cookbook
 #[derive(Clone, Serialize, Deserialize)]
-pub struct WaveGuide {
-    // goes from x0 to x1
-    pub chamber0: DelayLine,
-    // goes from x1 to x0
-    pub chamber1: DelayLine,
-    /// reflection factor for the first value of the return tuple of `pop`
-    pub alpha: f32,
-    /// reflection factor for the second value of the return tuple of `pop`
-    pub beta: f32,
-
-    // running values
+pub struct EqBand {
+    /// center or corner frequency in Hz
+    pub freq: f32,
+    pub gain_db: f32,
+    pub q: f32,
+    pub kind: EqBandKind,
+    /// b0, b1, b2, a1, a2 normalized by a0
     #[serde(skip)]
-    c1_out: f32,
+    coefficients: [f32; 5],
+    /// parameters and sample rate the coefficients were computed for
     #[serde(skip)]
-    c0_out: f32,
+    computed_for: Option<(f32, f32, f32, EqBandKind, u32)>,
+    /// set for peak and shelf bands with 0 dB gain, which pass the samples through unchanged
+    #[serde(skip)]
+    bypass: bool,
+    /// last two inputs and outputs of the left and right channel
+    #[serde(skip)]
+    state: [[f32; 4]; 2],
 }
 
-impl WaveGuide {
-    pub fn new(delay: usize, alpha: f32, beta: f32, samples_per_second: u32) -> WaveGuide {
-        WaveGuide {
-            chamber0: DelayLine::new(delay, samples_per_second),
-            chamber1: DelayLine::new(delay, samples_per_second),
-            alpha,
-            beta,
-            c1_out: 0.0,
-            c0_out: 0.0,
+impl EqBand {
+    pub fn new(kind: EqBandKind, freq: f32, gain_db: f32, q: f32) -> EqBand {
+        EqBand {
+            freq,
+            gain_db,
+            q,
+            kind,
+            coefficients: [1.0, 0.0, 0.0, 0.0, 0.0],
+            computed_for: None,
+            bypass: false,
+            state: [[0.0; 4]; 2],
         }
     }
 
-    pub fn pop(&mut self) -> (f32, f32, bool) {
-        let (c1_out, dampened_c1) = WaveGuide::dampen(self.chamber1.pop());
-        let (c0_out, dampened_c0) = WaveGuide::dampen(self.chamber0.pop());
-        self.c1_out = c1_out;
-        self.c0_out = c0_out;
+    /// keeps the filter state
+    fn apply_parameters(&mut self, from: &EqBand) {
+        self.freq = from.freq;
+        self.gain_db = from.gain_db;
+        self.q = from.q;
+        self.kind = from.kind;
+    }
 
-        (
-            self.c1_out * (1.0 - self.alpha.abs()),
-            self.c0_out * (1.0 - self.beta.abs()),
-            dampened_c1 | dampened_c0,
-        )
+    /// recomputes the coefficients if the parameters have changed since the last update
+    pub fn update(&mut self, samples_per_second: u32) {
+        let parameters = (
+            self.freq,
+            self.gain_db,
+            self.q,
+            self.kind,
+            samples_per_second,
+        );
+
+        if self.computed_for == Some(parameters) {
+            return;
+        }
+        self.computed_for = Some(parameters);
+
+        let freq = self.freq.clamp(1.0, samples_per_second as f32 * 0.49);
+        let a = 10.0f32.powf(self.gain_db / 40.0);
+        let w0 = PI2F * freq / samples_per_second as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * self.q.max(0.01));
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let [b0, b1, b2, a0, a1, a2] = match self.kind {
+            EqBandKind::Peak => [
+                1.0 + alpha * a,
+                -2.0 * cos,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos,
+                1.0 - alpha / a,
+            ],
+            EqBandKind::LowShelf => [
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+            ],
+            EqBandKind::HighShelf => [
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+            ],
+            EqBandKind::Notch => [1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+        };
+
+        self.coefficients = [b0 / a0, b1 / a0, b2 / a0, a1 / a0, a2 / a0];
+        // the numerator and denominator cancel out, but not exactly after rounding
+        self.bypass = self.gain_db == 0.0 && self.kind != EqBandKind::Notch;
     }
-    #[inline]
-    pub fn dampen(sample: f32) -> (f32, bool) {
-        let sample_abs = sample.abs();
-        if sample_abs > WAVEGUIDE_MAX_AMP {
-            (
-                sample.signum()
-                    * (-1.0 / (sample_abs - WAVEGUIDE_MAX_AMP + 1.0) + 1.0 + WAVEGUIDE_MAX_AMP),
-                true,
-            )
+
+    /// magnitude response at `freq` of the coefficients computed by the last `update`
+    pub fn magnitude(&self, freq: f32, samples_per_second: u32) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let w = PI2F * freq / samples_per_second as f32;
+        let (sin1, cos1) = w.sin_cos();
+        let (sin2, cos2) = (2.0 * w).sin_cos();
+
+        // |b0 + b1 e^-jw + b2 e^-2jw| / |1 + a1 e^-jw + a2 e^-2jw|
+        let numerator = (b0 + b1 * cos1 + b2 * cos2).hypot(b1 * sin1 + b2 * sin2);
+        let denominator = (1.0 + a1 * cos1 + a2 * cos2).hypot(a1 * sin1 + a2 * sin2);
+
+        numerator / denominator.max(1e-6)
+    }
+
+    /// filters a sample of the left (0) or right (1) channel, `update` has to be called after changing the parameters
+    pub fn filter(&mut self, sample: f32, channel: usize) -> f32 {
+        let [b0, b1, b2, a1, a2] = self.coefficients;
+        let [x1, x2, y1, y2] = self.state[channel];
+
+        // the state keeps running so the gain can be changed without a click
+        let ret = if self.bypass {
+            sample
         } else {
-            (sample, false)
+            b0 * sample + b1 * x1 + b2 * x2 - a1 * y1 - a2 * y2
+        };
+        self.state[channel] = [sample, x1, ret, y1];
+        ret
+    }
+}
+
+/// Applies a `HighPassFilter` (lower cutoff frequency) and a `LowPassFilter` (upper cutoff frequency) in sequence
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct BandPassFilter {
+    pub low: LowPassFilter,
+    pub high: HighPassFilter,
+}
+
+impl BandPassFilter {
+    /// passes frequencies between `low_freq` and `high_freq`
+    pub fn new(low_freq: f32, high_freq: f32, samples_per_second: u32) -> BandPassFilter {
+        BandPassFilter {
+            low: LowPassFilter::new(high_freq, samples_per_second),
+            high: HighPassFilter::new(low_freq, samples_per_second),
         }
     }
 
-    pub fn push(&mut self, x0_in: f32, x1_in: f32) {
-        let c0_in = self.c1_out * self.alpha + x0_in;
-        let c1_in = self.c0_out * self.beta + x1_in;
+    /// returns `(low_freq, high_freq)`
+    #[inline]
+    pub fn get_freqs(&self) -> (f32, f32) {
+        (self.high.get_freq(), self.low.get_freq())
+    }
 
-        self.chamber0.push(c0_in);
-        self.chamber1.push(c1_in);
-        self.chamber0.samples.advance();
-        self.chamber1.samples.advance();
+    pub fn filter(&mut self, sample: f32) -> f32 {
+        self.low.filter(self.high.filter(sample))
     }
 
-    #[allow(clippy::float_cmp)]
+    /// returns `None` if both frequencies are unchanged, the filter states are carried over to keep the output continuous
     pub fn get_changed(
         &mut self,
-        delay: usize,
-        alpha: f32,
-        beta: f32,
+        low_freq: f32,
+        high_freq: f32,
         samples_per_second: u32,
     ) -> Option<Self> {
-        // the strictly compared values will never change without user interaction (adjusting sliders)
-        if delay != self.chamber0.samples.data.len() || alpha != self.alpha || beta != self.beta {
-            let mut new = Self::new(delay, alpha, beta, samples_per_second);
+        let low = self.low.get_changed(high_freq, samples_per_second);
+        let high = self.high.get_changed(low_freq, samples_per_second);
 
-            // used to reduce artifacts while resizing pipes _a bit_
-            fn copy_samples_faded(source: &[f32], dest: &mut [f32]) {
-                let min_len = source.len().min(dest.len());
+        if low.is_none() && high.is_none() {
+            None
+        } else {
+            Some(BandPassFilter {
+                low: low.unwrap_or_else(|| self.low.clone()),
+                high: high.unwrap_or_else(|| self.high.clone()),
+            })
+        }
+    }
+}
 
-                dest[0..min_len].copy_from_slice(&source[0..min_len]);
-                let (a, b) = (*source.last().unwrap(), source[0]);
-                let dest_len = dest.len();
-                dest[min_len..]
-                    .iter_mut()
-                    .enumerate()
-                    .for_each(|(i, x)| *x = a + (b - a) * i as f32 / (dest_len - min_len) as f32);
-            }
+/// Helmholtz resonance of a volume like the intake airbox, a second-order band pass with a gain of 1 at the resonant frequency
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Resonator {
+    /// resonant frequency in Hz
+    pub freq: f32,
+    /// higher values narrow the resonance down
+    pub q: f32,
+    /// b0 (= -b2), a1, a2 normalized by a0
+    #[serde(skip)]
+    coefficients: [f32; 3],
+    /// parameters and sample rate the coefficients were computed for
+    #[serde(skip)]
+    computed_for: Option<(f32, f32, u32)>,
+    /// last two inputs and outputs
+    #[serde(skip)]
+    state: [f32; 4],
+}
 
-            copy_samples_faded(&self.chamber0.samples.data, &mut new.chamber0.samples.data);
-            copy_samples_faded(&self.chamber1.samples.data, &mut new.chamber1.samples.data);
+impl Resonator {
+    pub fn new(freq: f32, q: f32) -> Resonator {
+        Resonator {
+            freq,
+            q,
+            coefficients: [0.0; 3],
+            computed_for: None,
+            state: [0.0; 4],
+        }
+    }
 
-            Some(new)
-        } else {
-            None
+    /// keeps the filter state
+    fn apply_parameters(&mut self, from: &Resonator) {
+        self.freq = from.freq;
+        self.q = from.q;
+    }
+
+    pub fn filter(&mut self, sample: f32, samples_per_second: u32) -> f32 {
+        let parameters = (self.freq, self.q, samples_per_second);
+        if self.computed_for != Some(parameters) {
+            self.computed_for = Some(parameters);
+
+            // band pass with a constant peak gain of the Audio EQ Cookbook
+            let w0 = PI2F * self.freq.clamp(1.0, samples_per_second as f32 * 0.49)
+                / samples_per_second as f32;
+            let (sin, cos) = w0.sin_cos();
+            let alpha = sin / (2.0 * self.q.max(0.01));
+            let a0 = 1.0 + alpha;
+
+            self.coefficients = [alpha / a0, -2.0 * cos / a0, (1.0 - alpha) / a0];
         }
+
+        let [b0, a1, a2] = self.coefficients;
+        let [x1, x2, y1, y2] = self.state;
+
+        let ret = b0 * (sample - x2) - a1 * y1 - a2 * y2;
+        self.state = [sample, x1, ret, y1];
+        ret
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Default)]
-pub struct LoopBuffer {
-    // in seconds
-    pub delay: f32,
+/// turbo shaft rpm at which the whistle has a frequency of `Turbo::whistle_freq_hz`
+pub const TURBO_REFERENCE_RPM: f32 = 100_000.0;
+/// fraction of the turbo shaft rpm at closed throttle
+pub const TURBO_CLOSED_THROTTLE_SPOOL: f32 = 0.3;
+/// throttle closing speed (per second) which opens the blow-off valve
+pub const TURBO_BLOWOFF_THRESHOLD: f32 = 3.0;
+/// time constant in seconds of the blow-off burst's decay
+pub const TURBO_BLOWOFF_DECAY_TIME: f32 = 0.25;
+/// cutoff frequency of the blow-off noise filter of configs without one
+const TURBO_BLOWOFF_FREQ: f32 = 3000.0;
+
+/// Turbocharger whine, a sine whose frequency follows the turbo shaft rpm, and a blow-off valve which vents noise when the throttle closes quickly
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Turbo {
+    /// turbo shaft rpm / engine rpm at wide open throttle
+    pub rpm_scale: f32,
+    /// frequency of the whistle at `TURBO_REFERENCE_RPM`
+    pub whistle_freq_hz: f32,
+    pub whistle_volume: f32,
+    /// applied to the turbo shaft rpm to simulate the turbo's inertia (spool up/down lag)
+    pub spool_lp: LowPassFilter,
+    #[serde(default)]
+    pub blowoff_volume: f32,
+    /// applied to the blow-off noise
+    #[serde(default = "default_blowoff_lp")]
+    pub blowoff_lp: LowPassFilter,
     #[serde(skip)]
-    pub data: Vec<f32>,
+    pub blowoff_noise: Noise,
+    // running values
+    /// phase of the whistle oscillator, 0.0-1.0
     #[serde(skip)]
-    pub pos: usize,
+    pub phase: f32,
+    /// amplitude of the blow-off burst, 0.0-1.0
+    #[serde(skip)]
+    blowoff_env: f32,
+    #[serde(skip)]
+    last_throttle: f32,
 }
 
-impl LoopBuffer {
-    /// Creates a new loop buffer with specifies length.
-    /// The internal sample buffer size is rounded up to the currently best SIMD implementation's float vector size.
-    pub fn new(len: usize, samples_per_second: u32) -> LoopBuffer {
-        LoopBuffer {
-            delay: len as f32 / samples_per_second as f32,
-            data: vec![0.0; len],
-            pos: 0,
+fn default_blowoff_lp() -> LowPassFilter {
+    // the remaining fields are calculated while fixing the engine
+    LowPassFilter {
+        delay: 1.0 / TURBO_BLOWOFF_FREQ,
+        ..LowPassFilter::default()
+    }
+}
+
+impl Turbo {
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        visitor.value(&mut self.spool_lp.last);
+        visitor.value(&mut self.blowoff_lp.last);
+        visitor.noise(&mut self.blowoff_noise);
+        visitor.value(&mut self.phase);
+        visitor.value(&mut self.blowoff_env);
+        visitor.value(&mut self.last_throttle);
+    }
+
+    /// keeps the turbo rpm, whistle phase and blow-off state
+    fn apply_parameters(&mut self, from: &Turbo) {
+        self.rpm_scale = from.rpm_scale;
+        self.whistle_freq_hz = from.whistle_freq_hz;
+        self.whistle_volume = from.whistle_volume;
+        self.spool_lp.apply_parameters(&from.spool_lp);
+        self.blowoff_volume = from.blowoff_volume;
+        self.blowoff_lp.apply_parameters(&from.blowoff_lp);
+    }
+
+    pub fn new(
+        rpm_scale: f32,
+        whistle_freq_hz: f32,
+        whistle_volume: f32,
+        spool_freq: f32,
+        blowoff_volume: f32,
+        samples_per_second: u32,
+    ) -> Turbo {
+        Turbo {
+            rpm_scale,
+            whistle_freq_hz,
+            whistle_volume,
+            spool_lp: LowPassFilter::new(spool_freq, samples_per_second),
+            blowoff_volume,
+            blowoff_lp: LowPassFilter::new(TURBO_BLOWOFF_FREQ, samples_per_second),
+            blowoff_noise: Noise::default(),
+            phase: 0.0,
+            blowoff_env: 0.0,
+            last_throttle: 1.0,
         }
     }
 
-    /// Sets the value at the current position. Must be called with `pop`.
-    /// ```rust
-    /// let mut lb = LoopBuffer::new(2);
-    /// lb.push(1.0);
-    /// lb.advance();
-    ///
-    /// assert_eq(lb.pop(), 1.0);
-    ///
-    /// ```
-    pub fn push(&mut self, value: f32) {
-        let len = self.data.len();
-        self.data[self.pos % len] = value;
+    /// the current (lagging) turbo shaft rpm
+    #[inline]
+    pub fn rpm(&self) -> f32 {
+        self.spool_lp.last
     }
 
-    /// Gets the value `self.len` samples prior. Must be called with `push`.
-    /// See `push` for examples
-    pub fn pop(&mut self) -> f32 {
-        let len = self.data.len();
-        self.data[(self.pos + 1) % len]
+    /// frequency of the whistle at the given turbo shaft rpm
+    #[inline]
+    pub fn whistle_freq(&self, turbo_rpm: f32) -> f32 {
+        self.whistle_freq_hz * turbo_rpm / TURBO_REFERENCE_RPM
     }
 
-    /// Advances the position of this loop buffer.
-    pub fn advance(&mut self) {
-        self.pos += 1;
+    /// generates one sample of the whistle and blow-off
+    pub fn step(&mut self, engine_rpm: f32, throttle: f32, samples_per_second: u32) -> f32 {
+        let spool = TURBO_CLOSED_THROTTLE_SPOOL + (1.0 - TURBO_CLOSED_THROTTLE_SPOOL) * throttle;
+        let turbo_rpm = self.spool_lp.filter(engine_rpm * self.rpm_scale * spool);
+
+        self.phase =
+            (self.phase + self.whistle_freq(turbo_rpm) / samples_per_second as f32).fract();
+
+        // the blow-off valve vents the remaining boost, which depends on the turbo rpm
+        let throttle_closing_speed = (self.last_throttle - throttle) * samples_per_second as f32;
+        self.last_throttle = throttle;
+
+        if throttle_closing_speed > TURBO_BLOWOFF_THRESHOLD {
+            self.blowoff_env = self
+                .blowoff_env
+                .max((turbo_rpm / TURBO_REFERENCE_RPM).min(1.0));
+        }
+
+        let blowoff = self.blowoff_lp.filter(self.blowoff_noise.step())
+            * self.blowoff_env
+            * self.blowoff_volume;
+        self.blowoff_env *= (-1.0 / (TURBO_BLOWOFF_DECAY_TIME * samples_per_second as f32)).exp();
+
+        (self.phase * PI2F).sin() * self.whistle_volume + blowoff
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Default)]
-pub struct LowPassFilter {
-    /// 1 / cutoff frequency
-    pub delay: f32,
+/// Pops of unburnt fuel igniting in the exhaust when the fuel is cut off during engine braking (overrun)
+///
+/// The pops are injected into the exhaust waveguides of random cylinders right behind the valves,
+/// so they pass through the exhaust, extractor and muffler
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OverrunCrackle {
+    /// pops only occur above this rpm
+    pub min_rpm: f32,
+    /// chance of a pop per cylinder cycle at closed throttle (0.0 - 1.0), fades out towards `ENGINE_BRAKING_THROTTLE`
+    pub probability: f32,
+    /// amplitude of a pop
+    pub intensity: f32,
+    /// time constant of the decay of a pop in seconds
+    pub decay_time: f32,
     #[serde(skip)]
-    pub alpha: f32,
+    pub noise: Noise,
+    // running values
+    /// amplitude of the current pop of every cylinder
     #[serde(skip)]
-    pub last: f32,
+    envelopes: Vec<f32>,
+    /// pressure of the current pop of every cylinder, returned by `step`
+    #[serde(skip)]
+    pops: Vec<f32>,
 }
 
-impl LowPassFilter {
-    pub fn new(freq: f32, samples_per_second: u32) -> LowPassFilter {
-        LowPassFilter {
-            delay: 1.0 / freq,
-            alpha: (PI2F * (1.0 / samples_per_second as f32) * freq)
-                / (PI2F * (1.0 / samples_per_second as f32) * freq + 1.0),
-            last: 0.0,
+impl OverrunCrackle {
+    pub fn new(min_rpm: f32, probability: f32, intensity: f32, decay_time: f32) -> OverrunCrackle {
+        OverrunCrackle {
+            min_rpm,
+            probability,
+            intensity,
+            decay_time,
+            noise: Noise::default(),
+            envelopes: Vec::new(),
+            pops: Vec::new(),
         }
     }
 
-    #[inline]
-    pub fn get_freq(&self) -> f32 {
-        1.0 / self.delay
+    fn visit_state(&mut self, visitor: &mut impl StateVisitor) {
+        visitor.noise(&mut self.noise);
+        visitor.samples(&mut self.envelopes);
+        visitor.samples(&mut self.pops);
     }
 
-    pub fn filter(&mut self, sample: f32) -> f32 {
-        let ret = (sample - self.last).mul_add(self.alpha, self.last);
-        self.last = ret;
-        ret
+    /// keeps the running pops
+    fn apply_parameters(&mut self, from: &OverrunCrackle) {
+        self.min_rpm = from.min_rpm;
+        self.probability = from.probability;
+        self.intensity = from.intensity;
+        self.decay_time = from.decay_time;
     }
 
-    pub fn get_changed(&mut self, freq: f32, samples_per_second: u32) -> Option<Self> {
-        Some(Self::new(freq, samples_per_second))
+    /// generates one sample of the pops of every cylinder,
+    /// `engine_braking` is 0.0 above `ENGINE_BRAKING_THROTTLE` - 1.0 at closed throttle
+    pub fn step(
+        &mut self,
+        rpm: f32,
+        engine_braking: f32,
+        num_cylinders: usize,
+        samples_per_second: u32,
+    ) -> &[f32] {
+        self.envelopes.resize(num_cylinders, 0.0);
+        self.pops.resize(num_cylinders, 0.0);
+
+        if rpm > self.min_rpm && engine_braking > 0.0 && num_cylinders > 0 {
+            // one cylinder cycle takes two crankshaft revolutions
+            let pops_per_sample = rpm / 120.0 * num_cylinders as f32 * self.probability
+                / samples_per_second as f32
+                * engine_braking;
+
+            if (self.noise.step() + 1.0) * 0.5 < pops_per_sample {
+                let cylinder = (((self.noise.step() + 1.0) * 0.5 * num_cylinders as f32) as usize)
+                    .min(num_cylinders - 1);
+                self.envelopes[cylinder] = self.intensity;
+            }
+        }
+
+        let decay = (-1.0 / (self.decay_time.max(1e-4) * samples_per_second as f32)).exp();
+        for (envelope, pop) in self.envelopes.iter_mut().zip(self.pops.iter_mut()) {
+            if *envelope > 0.0 {
+                // rough positive pressure burst like an ignition
+                *pop = *envelope * (1.0 + self.noise.step()) * 0.5;
+                *envelope *= decay;
+
+                // stops drawing noise for inaudible pops
+                if *envelope < 1e-5 {
+                    *envelope = 0.0;
+                }
+            } else {
+                *pop = 0.0;
+            }
+        }
+
+        &self.pops
     }
 }
 
@@ -567,11 +3716,29 @@ impl DelayLine {
         self.samples.pop()
     }
 
+    pub fn pop_interpolated(&mut self, frac: f32) -> f32 {
+        self.samples.pop_interpolated(frac)
+    }
+
     pub fn push(&mut self, sample: f32) {
         self.samples.push(sample);
     }
 }
 
+/// returns 0.0 if `x` is smaller than `DENORMAL_THRESHOLD`
+#[inline]
+pub fn flush_denormal(x: f32) -> f32 {
+    if x.abs() < DENORMAL_THRESHOLD {
+        0.0
+    } else {
+        x
+    }
+}
+
+/// opening 0.0 (closed) - 1.0 (fully open) of the exhaust valve at `crank_pos` (0.0 - 1.0 cycles)
+///
+/// The valve is open during the exhaust stroke 0.75 - 1.0, its lift is half a sine period which is
+/// fully open in the middle of the stroke where the piston moves fastest: `-sin(4 * pi * x)`
 fn exhaust_valve(crank_pos: f32) -> f32 {
     if 0.75 < crank_pos && crank_pos < 1.0 {
         -(crank_pos * PI4F).sin()
@@ -580,6 +3747,9 @@ fn exhaust_valve(crank_pos: f32) -> f32 {
     }
 }
 
+/// opening 0.0 (closed) - 1.0 (fully open) of the intake valve at `crank_pos` (0.0 - 1.0 cycles)
+///
+/// Like `exhaust_valve` during the intake stroke 0.0 - 0.25: `sin(4 * pi * x)`
 fn intake_valve(crank_pos: f32) -> f32 {
     if 0.0 < crank_pos && crank_pos < 0.25 {
         (crank_pos * PI4F).sin()
@@ -588,10 +3758,18 @@ fn intake_valve(crank_pos: f32) -> f32 {
     }
 }
 
+/// pressure -1.0 - 1.0 in the cylinder caused by the piston at `crank_pos` (0.0 - 1.0 cycles)
+///
+/// The piston moves up and down once per revolution, twice per cycle. With a connecting rod much longer than the crank
+/// its position is harmonic: `cos(4 * pi * x)`, 1.0 at the top dead centers (0.0 and 0.5) and -1.0 at the bottom ones
 fn piston_motion(crank_pos: f32) -> f32 {
     (crank_pos * PI4F).cos()
 }
 
+/// combustion pressure 0.0 - 1.0 in the cylinder at `crank_pos` (0.0 - 1.0 cycles)
+///
+/// The fuel ignites at the top dead center at the start of the power stroke (0.5). The pressure rises and falls as half a
+/// sine period which lasts `ignition_time / 2` cycles: `sin(2 * pi * (x - 0.5) / ignition_time)`
 fn fuel_ignition(crank_pos: f32, ignition_time: f32) -> f32 {
     /*if 0.0 < crank_pos && crank_pos < ignition_time {
         (PI2F * (crank_pos * ignition_time + 0.5)).sin()
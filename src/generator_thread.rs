@@ -0,0 +1,253 @@
+//! ## Generator thread module ##
+//!
+//! Runs a `Generator` on its own thread, which sends the generated buffers to a channel.
+//! Other threads control it by sending `GeneratorCommand`s through a `GeneratorHandle` instead of locking it,
+//! so the generator never waits for a thread which is busy while holding a lock.
+//!
+
+use crate::gen::{Cylinder, Engine, Generator};
+use crate::recorder::RecorderError;
+use crossbeam_channel::{Receiver, Select, Sender};
+use std::time::Duration;
+
+/// amount of commands which can be queued before `GeneratorHandle::send` blocks
+pub const COMMAND_CHANNEL_SIZE: usize = 64;
+/// buffers which aren't consumed for this long are dropped, e.g. while an audio device is reconnecting,
+/// so the engine keeps running
+pub const OUTPUT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// changes to the generator which are applied between two buffers
+pub enum GeneratorCommand {
+    /// moves the rpm towards this value, see `Generator::set_target_rpm`
+    SetRpm(f32),
+    /// sets the master volume
+    SetVolume(f32),
+    /// replaces the cylinder at this index, does nothing if it doesn't exist
+    SetCylinder(usize, Box<Cylinder>),
+    /// silences all waveguides of the engine
+    Reset,
+    /// replies with the current state of the generator
+    Snapshot(Sender<GeneratorSnapshot>),
+    /// applies any other change to the generator
+    Apply(Box<dyn FnOnce(&mut Generator) + Send>),
+}
+
+/// mono downmix of a generated buffer which is sent to the monitors of a `GeneratorThread`, e.g. the FFT and the oscilloscope
+pub struct MonitorBuffer {
+    pub samples: Vec<f32>,
+    /// crankshaft position (0.0-1.0) before the first sample was generated
+    pub crankshaft_pos: f32,
+    /// advance of the crankshaft position per sample at the end of the buffer
+    pub crankshaft_step: f32,
+}
+
+impl MonitorBuffer {
+    /// indices of the samples at which a new crankshaft cycle starts, assuming the rpm stayed constant during the buffer
+    pub fn cycle_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        let step = self.crankshaft_step;
+
+        // the crankshaft position is advanced before every sample is generated,
+        // so the position of the sample at `index` is `crankshaft_pos + (index + 1) * step`
+        (1..)
+            .map(move |cycle| ((cycle as f32 - self.crankshaft_pos) / step).ceil() - 1.0)
+            .take_while(move |&index| step > 0.0 && index < self.samples.len() as f32)
+            .map(|index| index.max(0.0) as usize)
+    }
+}
+
+impl AsRef<[f32]> for MonitorBuffer {
+    fn as_ref(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+/// state of a recorder while it is attached to the generator
+pub struct RecorderStatus {
+    pub running: bool,
+    /// recorded frames
+    pub len: usize,
+    /// frames until the recorder receives samples, see `Generator::recorder_preroll`
+    pub preroll: usize,
+    /// error of a stopped recording, which is taken from the recorder by the snapshot
+    pub error: Option<RecorderError>,
+}
+
+/// copy of the state of the generator, see `GeneratorHandle::snapshot`
+pub struct GeneratorSnapshot {
+    pub engine: Engine,
+    pub volume: f32,
+    pub stereo_width: f32,
+    pub samples_per_second: u32,
+    pub engine_revision: usize,
+    /// progress of a running morph, swaps are not included
+    pub morph_progress: Option<f32>,
+    pub target_rpm: Option<f32>,
+    pub recorder: Option<RecorderStatus>,
+    pub recording_currently_clipping: bool,
+    pub dampened_waveguides: usize,
+}
+
+impl GeneratorSnapshot {
+    fn new(generator: &Generator) -> GeneratorSnapshot {
+        GeneratorSnapshot {
+            engine: generator.engine.clone(),
+            volume: generator.volume,
+            stereo_width: generator.stereo_width,
+            samples_per_second: generator.samples_per_second,
+            engine_revision: generator.engine_revision(),
+            morph_progress: generator
+                .morph()
+                .filter(|morph| !morph.is_swap())
+                .map(|morph| morph.progress()),
+            target_rpm: generator.target_rpm(),
+            recorder: generator.recorder.as_ref().map(|recorder| {
+                let running = recorder.is_running();
+
+                RecorderStatus {
+                    running,
+                    len: recorder.get_len(),
+                    preroll: generator.recorder_preroll,
+                    // the error is only final once the recorder has stopped
+                    error: if running { None } else { recorder.take_error() },
+                }
+            }),
+            recording_currently_clipping: generator.recording_currently_clipping,
+            dampened_waveguides: generator.dampened_waveguides,
+        }
+    }
+}
+
+/// controls a `GeneratorThread` from any thread, the commands of a handle are applied in the order they were sent
+#[derive(Clone)]
+pub struct GeneratorHandle {
+    commands: Sender<GeneratorCommand>,
+}
+
+impl GeneratorHandle {
+    /// queues `command`, which is dropped if the generator thread has stopped
+    pub fn send(&self, command: GeneratorCommand) {
+        let _ = self.commands.send(command);
+    }
+
+    /// queues a change which is applied to the generator on its thread
+    pub fn apply(&self, change: impl FnOnce(&mut Generator) + Send + 'static) {
+        self.send(GeneratorCommand::Apply(Box::new(change)));
+    }
+
+    /// waits until the generator thread has finished its current buffer and returns a copy of its state,
+    /// or `None` if the generator thread has stopped
+    pub fn snapshot(&self) -> Option<GeneratorSnapshot> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+        self.send(GeneratorCommand::Snapshot(sender));
+        receiver.recv().ok()
+    }
+}
+
+/// owns a generator and generates buffers in a loop until its output is disconnected, see `run`
+pub struct GeneratorThread {
+    generator: Generator,
+    commands: Receiver<GeneratorCommand>,
+}
+
+impl GeneratorThread {
+    /// returns the thread, which starts generating with `run`, and a handle to control it
+    pub fn new(generator: Generator) -> (GeneratorThread, GeneratorHandle) {
+        let (sender, receiver) = crossbeam_channel::bounded(COMMAND_CHANNEL_SIZE);
+
+        (
+            GeneratorThread {
+                generator,
+                commands: receiver,
+            },
+            GeneratorHandle { commands: sender },
+        )
+    }
+
+    /// generates interleaved stereo buffers of `buffer_size` frames on the current thread and sends them to `output`,
+    /// the mono downmix of every buffer is sent to every monitor unless its channel is full.
+    ///
+    /// returns when `output` is disconnected, commands are applied while waiting for `output` to accept a buffer
+    pub fn run(
+        self,
+        buffer_size: usize,
+        output: Sender<Vec<f32>>,
+        monitors: &[Sender<MonitorBuffer>],
+    ) {
+        let GeneratorThread {
+            mut generator,
+            commands,
+        } = self;
+        // the generator keeps running without commands once all handles are dropped
+        let mut commands_connected = true;
+
+        loop {
+            while let Ok(command) = commands.try_recv() {
+                apply_command(&mut generator, command);
+            }
+
+            let crankshaft_pos = generator.engine.crankshaft_pos;
+            let mut buf = vec![0.0; buffer_size * 2];
+            generator.generate_stereo(&mut buf);
+
+            if !monitors.is_empty() {
+                let mono = buf
+                    .chunks_exact(2)
+                    .map(|lr| (lr[0] + lr[1]) * 0.5)
+                    .collect::<Vec<f32>>();
+                let crankshaft_step =
+                    generator.engine.rpm / (generator.samples_per_second as f32 * 120.0);
+
+                for monitor in monitors {
+                    let _ = monitor.try_send(MonitorBuffer {
+                        samples: mono.clone(),
+                        crankshaft_pos,
+                        crankshaft_step,
+                    });
+                }
+            }
+
+            let mut buf = Some(buf);
+            while let Some(pending) = buf.take() {
+                let mut select = Select::new();
+                let send = select.send(&output);
+                if commands_connected {
+                    select.recv(&commands);
+                }
+
+                match select.select_timeout(OUTPUT_TIMEOUT) {
+                    // nothing consumes the buffers, it is dropped so the generator keeps running
+                    Err(_) => {}
+                    Ok(operation) if operation.index() == send => {
+                        if operation.send(&output, pending).is_err() {
+                            return;
+                        }
+                    }
+                    Ok(operation) => {
+                        match operation.recv(&commands) {
+                            Ok(command) => apply_command(&mut generator, command),
+                            Err(_) => commands_connected = false,
+                        }
+                        buf = Some(pending);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn apply_command(generator: &mut Generator, command: GeneratorCommand) {
+    match command {
+        GeneratorCommand::SetRpm(rpm) => generator.set_target_rpm(rpm),
+        GeneratorCommand::SetVolume(volume) => generator.volume = volume,
+        GeneratorCommand::SetCylinder(index, cylinder) => {
+            if let Some(old) = generator.engine.cylinders.get_mut(index) {
+                *old = *cylinder;
+            }
+        }
+        GeneratorCommand::Reset => generator.reset(),
+        GeneratorCommand::Snapshot(reply) => {
+            let _ = reply.send(GeneratorSnapshot::new(generator));
+        }
+        GeneratorCommand::Apply(change) => change(generator),
+    }
+}
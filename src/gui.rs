@@ -1,18 +1,89 @@
-use crate::constants::{MAX_CYLINDERS, MUFFLER_ELEMENT_COUNT};
-use crate::utils::{distance_to_samples, samples_to_distance, SPEED_OF_SOUND};
-use crate::{gen::Generator, recorder::Recorder};
-use chrono::{Datelike, Local, Timelike};
+use crate::audio::DeviceSwitch;
+use crate::fft::{Spectrum, WindowFunction};
+#[cfg(feature = "midi")]
+use crate::midi::MidiController;
+use crate::parameters::{
+    muffler_elements_beta, mutate, new_seed, randomize, set_cylinder_count, CAVITY_VOLUME,
+    CRANKSHAFT_FLUCTUATION, CYLINDER_COUNT, EGR_FACTOR, EGR_PIPE_LENGTH, EXHAUST_PIPE_LENGTH,
+    EXTRACTOR_PIPE_LENGTH, FILTER_FREQ, IGNITION_FACTOR, IGNITION_TIME, INTAKE_NOISE_FACTOR,
+    INTAKE_PIPE_LENGTH, MIX_VOLUME, MUFFLER_ELEMENT_LENGTH, MUFFLER_OPEN_END_REFL, MUTATE_AMOUNT,
+    NECK_LENGTH, NECK_RADIUS, PISTON_MOTION_FACTOR, PRESSURE_RELEASE_FACTOR, REFLECTIVITY,
+    RESONATOR_REFLECTIVITY, STRAIGHT_PIPE_LENGTH, VALVE_SHIFT,
+};
+use crate::spectrogram::Spectrogram;
+use crate::undo::UndoStack;
+use crate::watcher::ConfigWatcher;
 use conrod_core::{
     position::{Align, Direction, Padding, Relative},
     *,
 };
-use parking_lot::RwLock;
+use enginesound::utils::{
+    apply_firing_preset, config_name, distance_to_samples, recording_name, samples_to_distance,
+    save_engine, seconds_to_samples, timestamp, FIRING_PRESETS, SPEED_OF_SOUND,
+};
+use enginesound::{
+    gen::{
+        helmholtz_frequency, Engine, EqBand, EqBandKind, HighPassFilter, MufflerElement, Noise,
+        OverrunCrackle, ParametricEq, Resonator, Turbo, WaveGuide, ENGINE_BRAKING_THROTTLE,
+        ENGINE_SWAP_TIME, MAX_EQ_BANDS, PI2F, TURBO_REFERENCE_RPM,
+    },
+    generator_thread::{GeneratorCommand, GeneratorHandle, GeneratorSnapshot, MonitorBuffer},
+    presets::{load_preset, PRESETS},
+    recorder::{Normalization, NormalizationTarget, OutputFormat, Recorder, SampleDepth},
+};
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
-use std::{fs::File, io::Write, sync::Arc};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 // must be 2^n
 pub const WATERFALL_WIDTH: u32 = 512;
+/// default number of rows of the waterfall, see `GUIState::set_waterfall_height`
 pub const WATERFALL_HEIGHT: u32 = 50;
+const MIN_WATERFALL_HEIGHT: usize = 10;
+const MAX_WATERFALL_HEIGHT: usize = 500;
+/// magnitude of the waterfall before any lines were received, shown just above black at the default gain
+const WATERFALL_BACKGROUND: f32 = 0.22;
+/// FFT magnitudes are multiplied by this and the display gain before they are mapped to colors, see `display_value`
+const MAGNITUDE_SCALE: f32 = 0.008;
+const MIN_WATERFALL_GAIN_DB: f32 = -20.0;
+const MAX_WATERFALL_GAIN_DB: f32 = 40.0;
+/// default number of waterfall lines kept for the "Export Spectrogram" button, each line is the spectrum of `--fft-size` samples
+pub const SPECTROGRAM_HISTORY: usize = 500;
+/// waterfall lines kept while recording (about 4 minutes at 48 kHz), later lines are not exported
+const MAX_RECORDING_SPECTROGRAM_LINES: usize = 12000;
+/// range of the seconds which the engine is warmed up for after pressing record before the recording starts
+const MAX_RECORDING_PREROLL: f32 = 10.0;
+const DEFAULT_RECORDING_PREROLL: f32 = 0.0;
+const SPECTRUM_HEIGHT: f64 = 80.0;
+/// opacity of the firing frequency harmonics drawn over the waterfall
+const HARMONIC_LINE_ALPHA: f32 = 0.5;
+/// harmonics which are closer than this many columns to the previous drawn one are left out,
+/// the logarithmic frequency axis of the waterfall would merge them into a solid band
+const MIN_HARMONIC_DISTANCE: f32 = 3.0;
+/// frequencies in hz of the labeled gridlines of the spectrum plot
+const SPECTRUM_GRID_FREQS: [f32; 7] = [50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0];
+/// lowest level of the muffler response plot relative to its peak
+const MUFFLER_RESPONSE_RANGE_DB: f32 = -40.0;
+/// duration in seconds of a morph to a loaded config
+pub const MORPH_TIME: f32 = 2.0;
+/// default number of samples shown by the oscilloscope, 20 ms at 48 khz
+pub const OSCILLOSCOPE_LEN: usize = 960;
+const OSCILLOSCOPE_HEIGHT: f64 = 60.0;
+/// vertical zoom range of the oscilloscope, at 1x the height shows -1.0 - 1.0
+const MIN_OSCILLOSCOPE_ZOOM: f32 = 0.5;
+const MAX_OSCILLOSCOPE_ZOOM: f32 = 50.0;
+/// the ±1.0 lines are inside of the oscilloscope at this zoom
+const DEFAULT_OSCILLOSCOPE_ZOOM: f32 = 0.8;
+/// the triggered oscilloscope keeps showing the last complete cycle while the next one is shorter than this many windows,
+/// slower crankshafts let it run freely
+const MAX_TRIGGER_WAIT: usize = 16;
+/// the oscilloscope stays red for this long after the last clipping sample
+const CLIP_INDICATOR_TIME: Duration = Duration::from_millis(500);
+/// the presets directory list scrolls if it contains more files than this
+const PRESET_DIR_ROWS: usize = 6;
+/// title of the window, followed by the name of the engine if it has one
+pub const WINDOW_TITLE: &str = "Engine Sound Generator";
 
 /// A set of reasonable stylistic defaults that works for the `gui` below.
 pub fn theme() -> conrod_core::Theme {
@@ -39,14 +110,39 @@ pub fn theme() -> conrod_core::Theme {
 // Generate a unique `WidgetId` for each widget.
 pub struct Ids {
     pub canvas: widget::Id,
-    pub title: widget::Id,
     pub record_button: widget::Id,
+    pub record_sample_depth: widget::Id,
+    pub record_multichannel: widget::Id,
+    pub record_normalize: widget::Id,
+    pub record_preroll: widget::Id,
     pub file_chooser_button: widget::Id,
+    pub morph_button: widget::Id,
+    pub ab_store_button: widget::Id,
+    pub ab_toggle: widget::Id,
+    pub config_watch_status: widget::Id,
+    pub settings_title: widget::Id,
+    pub audio_device_dropdown: widget::Id,
+    pub osc_status: widget::Id,
+    #[cfg(feature = "midi")]
+    pub midi_button: widget::Id,
     pub panic_button: widget::Id,
+    pub soft_reset_button: widget::Id,
+    pub adaptive_damping_toggle: widget::Id,
+    pub randomize_button: widget::Id,
+    pub mutate_button: widget::Id,
+    pub randomize_seed_title: widget::Id,
+    pub randomize_seed: widget::Id,
     pub save_button: widget::Id,
+    pub analyze_button: widget::Id,
     pub mix_title: widget::Id,
     pub engine_rpm_slider: widget::Id,
+    pub engine_rpm_slew_toggle: widget::Id,
+    pub engine_rpm_slew_rate: widget::Id,
+    pub engine_throttle_slider: widget::Id,
     pub engine_master_volume_slider: widget::Id,
+    pub engine_stereo_width_slider: widget::Id,
+    pub engine_output_highpass_toggle: widget::Id,
+    pub engine_output_highpass_freq: widget::Id,
     pub engine_intake_volume_slider: widget::Id,
     pub engine_intake_lp_filter_freq: widget::Id,
     pub engine_exhaust_volume_slider: widget::Id,
@@ -54,19 +150,57 @@ pub struct Ids {
     pub engine_title: widget::Id,
     pub engine_vibrations_lp_filter_freq: widget::Id,
     pub engine_intake_noise_factor: widget::Id,
+    pub engine_intake_resonator_toggle: widget::Id,
+    pub engine_intake_resonator_freq: widget::Id,
+    pub engine_intake_resonator_q: widget::Id,
+    pub engine_egr_factor: widget::Id,
+    pub engine_egr_alpha: widget::Id,
+    pub engine_egr_beta: widget::Id,
+    pub engine_egr_length: widget::Id,
     pub engine_intake_valve_shift: widget::Id,
     pub engine_exhaust_valve_shift: widget::Id,
     pub engine_crankshaft_fluctuation_lp_freq: widget::Id,
     pub engine_crankshaft_fluctuation: widget::Id,
+    pub engine_exhaust_highpass_toggle: widget::Id,
+    pub engine_exhaust_highpass_freq: widget::Id,
+    pub engine_noise_seed_title: widget::Id,
+    pub engine_noise_seed: widget::Id,
+    pub turbo_title: widget::Id,
+    pub engine_turbo_toggle: widget::Id,
+    pub engine_turbo_rpm_scale: widget::Id,
+    pub engine_turbo_whistle_freq: widget::Id,
+    pub engine_turbo_whistle_volume: widget::Id,
+    pub engine_turbo_spool_time: widget::Id,
+    pub engine_turbo_blowoff_volume: widget::Id,
+    pub overrun_crackle_title: widget::Id,
+    pub engine_overrun_crackle_toggle: widget::Id,
+    pub engine_overrun_crackle_probability: widget::Id,
+    pub engine_overrun_crackle_intensity: widget::Id,
+    pub eq_title: widget::Id,
+    pub eq_toggle: widget::Id,
+    pub eq_band_kind: Vec<widget::Id>,
+    pub eq_band_freq: Vec<widget::Id>,
+    pub eq_band_gain: Vec<widget::Id>,
+    pub eq_band_q: Vec<widget::Id>,
     pub muffler_title: widget::Id,
     pub muffler_straight_pipe_alpha: widget::Id,
     pub muffler_straight_pipe_beta: widget::Id,
     pub muffler_straight_pipe_length: widget::Id,
     pub engine_muffler_open_end_refl: widget::Id,
-    pub muffler_element_length: Vec<widget::Id>,
+    pub muffler_element_kind: HashMap<usize, widget::Id>,
+    pub muffler_element_length: HashMap<usize, widget::Id>,
+    pub muffler_resonator_neck_length: HashMap<usize, widget::Id>,
+    pub muffler_resonator_neck_radius: HashMap<usize, widget::Id>,
+    pub muffler_resonator_cavity_volume: HashMap<usize, widget::Id>,
+    pub muffler_resonator_alpha: HashMap<usize, widget::Id>,
     pub cylinder_title: widget::Id,
-    pub cylinder_offset_growl: widget::Id,
     pub cylinder_num: widget::Id,
+    pub cylinder_active: HashMap<usize, widget::Id>,
+    pub cylinder_firing_preset: widget::Id,
+    pub cylinder_firing_order: widget::Id,
+    pub cylinder_per_cylinder_mode: widget::Id,
+    pub cylinder_advanced_mode: widget::Id,
+    pub cylinder_selected: widget::Id,
     pub cylinder_intake_open_refl: widget::Id,
     pub cylinder_intake_closed_refl: widget::Id,
     pub cylinder_exhaust_open_refl: widget::Id,
@@ -77,11 +211,44 @@ pub struct Ids {
     pub cylinder_ignition_factor: widget::Id,
     pub cylinder_ignition_time: widget::Id,
     pub cylinder_pressure_release_factor: widget::Id,
-    pub cylinder_intake_pipe_length: Vec<widget::Id>,
-    pub cylinder_exhaust_pipe_length: Vec<widget::Id>,
-    pub cylinder_extractor_pipe_length: Vec<widget::Id>,
-    pub cylinder_crank_offset: Vec<widget::Id>,
+    pub cylinder_intake_pipe_length: HashMap<usize, widget::Id>,
+    pub cylinder_exhaust_pipe_length: HashMap<usize, widget::Id>,
+    pub cylinder_extractor_pipe_length: HashMap<usize, widget::Id>,
+    pub cylinder_crank_offset: HashMap<usize, widget::Id>,
+    pub cylinder_misfire_probability: HashMap<usize, widget::Id>,
+    pub cylinder_intake_open_refl_per: HashMap<usize, widget::Id>,
+    pub cylinder_exhaust_open_refl_per: HashMap<usize, widget::Id>,
+    pub cylinder_piston_motion_factor_per: HashMap<usize, widget::Id>,
+    pub cylinder_ignition_factor_per: HashMap<usize, widget::Id>,
     pub waterfall: widget::Id,
+    pub spectrum_background: widget::Id,
+    pub spectrum_plot: widget::Id,
+    pub spectrum_grid_lines: Vec<widget::Id>,
+    pub spectrum_grid_labels: Vec<widget::Id>,
+    pub spectrum_cursor_freq: widget::Id,
+    pub oscilloscope_background: widget::Id,
+    pub oscilloscope_plot: widget::Id,
+    pub oscilloscope_reference: [widget::Id; 2],
+    pub oscilloscope_peak: widget::Id,
+    pub oscilloscope_zoom_slider: widget::Id,
+    pub oscilloscope_trigger_button: widget::Id,
+    pub load_error_banner: widget::Id,
+    pub engine_info: [widget::Id; 3],
+    pub engine_info_labels: [widget::Id; 3],
+    pub preset_dropdown: widget::Id,
+    pub muffler_response_plot: widget::Id,
+    pub muffler_response_button: widget::Id,
+    pub harmonics_button: widget::Id,
+    pub harmonics_label: widget::Id,
+    pub spectrogram_button: widget::Id,
+    pub waterfall_height_slider: widget::Id,
+    pub waterfall_pause_button: widget::Id,
+    pub waterfall_gain_slider: widget::Id,
+    pub waterfall_colormap: widget::Id,
+    pub fft_window: widget::Id,
+    pub preset_dir_title: widget::Id,
+    pub preset_dir_list: widget::Id,
+    pub preset_dir_error: widget::Id,
     pub canvas_scrollbar: widget::Id,
 }
 
@@ -91,14 +258,39 @@ impl Ids {
     pub fn new(mut generator: widget::id::Generator) -> Self {
         Ids {
             canvas: generator.next(),
-            title: generator.next(),
             record_button: generator.next(),
+            record_sample_depth: generator.next(),
+            record_multichannel: generator.next(),
+            record_normalize: generator.next(),
+            record_preroll: generator.next(),
             panic_button: generator.next(),
+            soft_reset_button: generator.next(),
+            adaptive_damping_toggle: generator.next(),
+            randomize_button: generator.next(),
+            mutate_button: generator.next(),
+            randomize_seed_title: generator.next(),
+            randomize_seed: generator.next(),
             file_chooser_button: generator.next(),
+            morph_button: generator.next(),
+            ab_store_button: generator.next(),
+            ab_toggle: generator.next(),
+            config_watch_status: generator.next(),
+            settings_title: generator.next(),
+            audio_device_dropdown: generator.next(),
+            osc_status: generator.next(),
+            #[cfg(feature = "midi")]
+            midi_button: generator.next(),
             save_button: generator.next(),
+            analyze_button: generator.next(),
             mix_title: generator.next(),
             engine_rpm_slider: generator.next(),
+            engine_rpm_slew_toggle: generator.next(),
+            engine_rpm_slew_rate: generator.next(),
+            engine_throttle_slider: generator.next(),
             engine_master_volume_slider: generator.next(),
+            engine_stereo_width_slider: generator.next(),
+            engine_output_highpass_toggle: generator.next(),
+            engine_output_highpass_freq: generator.next(),
             engine_intake_volume_slider: generator.next(),
             engine_intake_lp_filter_freq: generator.next(),
             engine_exhaust_volume_slider: generator.next(),
@@ -106,21 +298,57 @@ impl Ids {
             engine_title: generator.next(),
             engine_vibrations_lp_filter_freq: generator.next(),
             engine_intake_noise_factor: generator.next(),
+            engine_intake_resonator_toggle: generator.next(),
+            engine_intake_resonator_freq: generator.next(),
+            engine_intake_resonator_q: generator.next(),
+            engine_egr_factor: generator.next(),
+            engine_egr_alpha: generator.next(),
+            engine_egr_beta: generator.next(),
+            engine_egr_length: generator.next(),
             engine_intake_valve_shift: generator.next(),
             engine_exhaust_valve_shift: generator.next(),
             engine_crankshaft_fluctuation_lp_freq: generator.next(),
             engine_crankshaft_fluctuation: generator.next(),
+            engine_exhaust_highpass_toggle: generator.next(),
+            engine_exhaust_highpass_freq: generator.next(),
+            engine_noise_seed_title: generator.next(),
+            engine_noise_seed: generator.next(),
+            turbo_title: generator.next(),
+            engine_turbo_toggle: generator.next(),
+            engine_turbo_rpm_scale: generator.next(),
+            engine_turbo_whistle_freq: generator.next(),
+            engine_turbo_whistle_volume: generator.next(),
+            engine_turbo_spool_time: generator.next(),
+            engine_turbo_blowoff_volume: generator.next(),
+            overrun_crackle_title: generator.next(),
+            engine_overrun_crackle_toggle: generator.next(),
+            engine_overrun_crackle_probability: generator.next(),
+            engine_overrun_crackle_intensity: generator.next(),
+            eq_title: generator.next(),
+            eq_toggle: generator.next(),
+            eq_band_kind: (0..MAX_EQ_BANDS).map(|_| generator.next()).collect(),
+            eq_band_freq: (0..MAX_EQ_BANDS).map(|_| generator.next()).collect(),
+            eq_band_gain: (0..MAX_EQ_BANDS).map(|_| generator.next()).collect(),
+            eq_band_q: (0..MAX_EQ_BANDS).map(|_| generator.next()).collect(),
             muffler_title: generator.next(),
             muffler_straight_pipe_alpha: generator.next(),
             muffler_straight_pipe_beta: generator.next(),
             muffler_straight_pipe_length: generator.next(),
             engine_muffler_open_end_refl: generator.next(),
-            muffler_element_length: (0..MUFFLER_ELEMENT_COUNT)
-                .map(|_| generator.next())
-                .collect(),
+            muffler_element_kind: HashMap::new(),
+            muffler_element_length: HashMap::new(),
+            muffler_resonator_neck_length: HashMap::new(),
+            muffler_resonator_neck_radius: HashMap::new(),
+            muffler_resonator_cavity_volume: HashMap::new(),
+            muffler_resonator_alpha: HashMap::new(),
             cylinder_title: generator.next(),
-            cylinder_offset_growl: generator.next(),
             cylinder_num: generator.next(),
+            cylinder_active: HashMap::new(),
+            cylinder_firing_preset: generator.next(),
+            cylinder_firing_order: generator.next(),
+            cylinder_per_cylinder_mode: generator.next(),
+            cylinder_advanced_mode: generator.next(),
+            cylinder_selected: generator.next(),
             cylinder_intake_open_refl: generator.next(),
             cylinder_intake_closed_refl: generator.next(),
             cylinder_exhaust_open_refl: generator.next(),
@@ -131,52 +359,461 @@ impl Ids {
             cylinder_ignition_factor: generator.next(),
             cylinder_ignition_time: generator.next(),
             cylinder_pressure_release_factor: generator.next(),
-            cylinder_intake_pipe_length: (0..MAX_CYLINDERS).map(|_| generator.next()).collect(),
-            cylinder_exhaust_pipe_length: (0..MAX_CYLINDERS).map(|_| generator.next()).collect(),
-            cylinder_extractor_pipe_length: (0..MAX_CYLINDERS).map(|_| generator.next()).collect(),
-            cylinder_crank_offset: (0..MAX_CYLINDERS).map(|_| generator.next()).collect(),
+            cylinder_intake_pipe_length: HashMap::new(),
+            cylinder_exhaust_pipe_length: HashMap::new(),
+            cylinder_extractor_pipe_length: HashMap::new(),
+            cylinder_crank_offset: HashMap::new(),
+            cylinder_misfire_probability: HashMap::new(),
+            cylinder_intake_open_refl_per: HashMap::new(),
+            cylinder_exhaust_open_refl_per: HashMap::new(),
+            cylinder_piston_motion_factor_per: HashMap::new(),
+            cylinder_ignition_factor_per: HashMap::new(),
             waterfall: generator.next(),
+            spectrum_background: generator.next(),
+            spectrum_plot: generator.next(),
+            spectrum_grid_lines: SPECTRUM_GRID_FREQS
+                .iter()
+                .map(|_| generator.next())
+                .collect(),
+            spectrum_grid_labels: SPECTRUM_GRID_FREQS
+                .iter()
+                .map(|_| generator.next())
+                .collect(),
+            spectrum_cursor_freq: generator.next(),
+            oscilloscope_background: generator.next(),
+            oscilloscope_plot: generator.next(),
+            oscilloscope_reference: [generator.next(), generator.next()],
+            oscilloscope_peak: generator.next(),
+            oscilloscope_zoom_slider: generator.next(),
+            oscilloscope_trigger_button: generator.next(),
+            load_error_banner: generator.next(),
+            engine_info: [generator.next(), generator.next(), generator.next()],
+            engine_info_labels: [generator.next(), generator.next(), generator.next()],
+            preset_dropdown: generator.next(),
+            muffler_response_plot: generator.next(),
+            muffler_response_button: generator.next(),
+            harmonics_button: generator.next(),
+            harmonics_label: generator.next(),
+            spectrogram_button: generator.next(),
+            waterfall_height_slider: generator.next(),
+            waterfall_pause_button: generator.next(),
+            waterfall_gain_slider: generator.next(),
+            waterfall_colormap: generator.next(),
+            fft_window: generator.next(),
+            preset_dir_title: generator.next(),
+            preset_dir_list: generator.next(),
+            preset_dir_error: generator.next(),
             canvas_scrollbar: generator.next(),
         }
     }
 }
 
+/// id of the widget of the `i`th muffler element or cylinder, generated on first use as configs can have any number of
+/// them
+pub fn list_id(
+    ids: &mut HashMap<usize, widget::Id>,
+    i: usize,
+    generator: &mut widget::id::Generator,
+) -> widget::Id {
+    *ids.entry(i).or_insert_with(|| generator.next())
+}
+
 /// Contains the waterfall bitmap
+#[derive(Copy, Clone)]
+enum UndoRequest {
+    Undo,
+    Redo,
+}
+
 pub struct GUIState {
-    waterfall: [f32; (WATERFALL_WIDTH * WATERFALL_HEIGHT) as usize],
-    input: crossbeam_channel::Receiver<Vec<f32>>,
+    /// `waterfall_height` rows of `WATERFALL_WIDTH` FFT magnitudes, newest first
+    waterfall: Vec<f32>,
+    waterfall_height: usize,
+    /// new lines are dropped instead of being added to the waterfall and the spectrum plot
+    waterfall_paused: bool,
+    /// amplifies the magnitudes of the waterfall and the spectrum plot before they are mapped to colors
+    waterfall_gain_db: f32,
+    waterfall_colormap: Colormap,
+    /// window function of the FFT thread selected by the "Window" dropdown, see `set_fft_window_function`
+    fft_window_function: Option<Arc<Mutex<WindowFunction>>>,
+    /// the most recent waterfall lines, newest first, which are exported by the "Export Spectrogram" button
+    history: VecDeque<Vec<f32>>,
+    /// number of lines kept in `history`, see `set_max_history`
+    max_history: usize,
+    /// error of the last spectrogram which failed to save, shown on the "Export Spectrogram" button
+    spectrogram_error: Option<String>,
+    /// receives the result of the spectrogram which is being saved in the background
+    spectrogram_export: Option<crossbeam_channel::Receiver<Result<PathBuf, String>>>,
+    /// set while the generator is recording, see `set_recording`
+    recording: bool,
+    /// waterfall lines since the start of the current or last recording, oldest first, which are exported instead of the history
+    recording_lines: Vec<Vec<f32>>,
+    input: crossbeam_channel::Receiver<Spectrum>,
+    /// the latest spectrum received from `input`, shown by the spectrum plot
+    spectrum: Option<Spectrum>,
+    oscilloscope: Oscilloscope,
     recording_save_path: Option<PathBuf>,
     config_save_path: Option<PathBuf>,
     config_load_path: Option<PathBuf>,
+    recording_sample_depth: SampleDepth,
+    /// records the intake, engine vibrations and exhaust as separate tracks
+    recording_multichannel: bool,
+    /// keeps recordings in memory and normalizes their peak once they are stopped
+    recording_normalize: bool,
+    /// seconds between pressing record and the start of the recording, counted down in samples by the generator
+    recording_preroll: f32,
+    /// writes a `RecordingMetadata` next to every recording, see `set_recording_metadata`
+    recording_metadata: bool,
+    /// error of the last config which failed to load, shown at the top of the window until it is clicked
+    load_error: Option<String>,
+    /// the window title is only set again when it changes, see `window_title`
+    window_title: String,
+    /// engine states before and after every mouse interaction which changed the engine, created from the first snapshot.
+    /// Every entry is a complete clone of the engine including the sample buffers of its waveguides, which take a few KB
+    /// for short pipes and some hundred KB for many cylinders with long pipes, so the stack is capped at `undo::MAX_UNDO_STEPS`
+    undo_stack: Option<UndoStack<Engine>>,
+    /// RON config of the current state of `undo_stack`, which is compared to the engine to detect changes
+    undo_config: String,
+    /// set by `undo` and `redo`, applied to the engine in the next frame
+    undo_request: Option<UndoRequest>,
+    /// index of the last loaded preset in `PRESETS`
+    preset: Option<usize>,
+    /// directory of the presets panel, see `set_preset_dir`
+    preset_dir: PathBuf,
+    /// sorted `*.esc` files in `preset_dir`
+    preset_dir_files: Vec<PathBuf>,
+    /// last loaded file of `preset_dir_files`
+    preset_dir_active: Option<PathBuf>,
+    /// error of the last preset file which failed to load or of reading the directory, shown below the presets panel
+    preset_dir_error: Option<String>,
+    /// error of the last recording which failed, shown on the record button
+    recording_error: Option<String>,
+    /// error of the last file dialog of the "Load…" button which failed, shown on the button
+    config_load_error: Option<String>,
+    /// error of the last config which failed to save, shown on the "Save As…" button
+    config_save_error: Option<String>,
+    /// shows sliders for some parameters for every cylinder instead of one slider for all cylinders
+    per_cylinder_mode: bool,
+    /// edits the parameters of the selected cylinder only instead of copying them to all cylinders
+    advanced_cylinder_mode: bool,
+    /// index of the cylinder edited in advanced per-cylinder mode
+    selected_cylinder: usize,
+    /// text of the noise seed text box while it is being edited
+    noise_seed_text: Option<String>,
+    /// seed of the last press of the "Randomize" button, shown so the result can be reproduced
+    randomize_seed: Option<u64>,
+    /// text of the randomize seed text box while it is being edited
+    randomize_seed_text: Option<String>,
+    /// if set, the next loaded config is morphed to instead of replacing the engine
+    morph_on_load: bool,
+    /// engine of the A/B comparison slot which isn't running, kept when another config is loaded
+    ab_stored: Option<Engine>,
+    /// the generator is running the B slot of the A/B comparison
+    ab_b_active: bool,
+    /// watches the last loaded config, which replaces the engine when it is changed on disk
+    config_watcher: Option<ConfigWatcher>,
+    /// shown below the config watch status, e.g. the address of the OSC listener
+    osc_status: Option<String>,
+    /// names of the audio output devices listed by the "Audio device" dropdown, see `set_audio_devices`
+    audio_devices: Vec<String>,
+    /// index into `audio_devices` of the device which is played back on, `None` is the default device
+    audio_device: Option<usize>,
+    /// `None` if the audio backend can't switch devices, which hides the dropdown
+    audio_device_switch: Option<DeviceSwitch>,
+    /// draws the frequency response of the muffler over the waterfall
+    show_muffler_response: bool,
+    /// draws the firing frequency and its harmonics over the waterfall, see `firing_harmonics`
+    show_harmonics: bool,
+    /// MIDI device name used by the "Connect MIDI" button, connects to the first device if empty
+    #[cfg(feature = "midi")]
+    midi_device_name: String,
+    /// MIDI events are processed as long as the connection is alive
+    #[cfg(feature = "midi")]
+    midi: Option<midir::MidiInputConnection<()>>,
+    /// error of the last failed MIDI connection, shown on the "Connect MIDI" button
+    #[cfg(feature = "midi")]
+    midi_error: Option<String>,
 }
 
 impl GUIState {
-    pub fn new(input: crossbeam_channel::Receiver<Vec<f32>>) -> Self {
+    pub fn new(input: crossbeam_channel::Receiver<Spectrum>, oscilloscope: Oscilloscope) -> Self {
         GUIState {
-            waterfall: [0.07f32; (WATERFALL_WIDTH * WATERFALL_HEIGHT) as usize],
+            waterfall: vec![WATERFALL_BACKGROUND; (WATERFALL_WIDTH * WATERFALL_HEIGHT) as usize],
+            waterfall_height: WATERFALL_HEIGHT as usize,
+            waterfall_paused: false,
+            waterfall_gain_db: 0.0,
+            waterfall_colormap: Colormap::Classic,
+            fft_window_function: None,
+            history: VecDeque::new(),
+            max_history: SPECTROGRAM_HISTORY,
+            spectrogram_error: None,
+            spectrogram_export: None,
+            recording: false,
+            recording_lines: Vec::new(),
             input,
+            spectrum: None,
+            oscilloscope,
             recording_save_path: None,
             config_save_path: None,
             config_load_path: None,
+            recording_sample_depth: SampleDepth::default(),
+            recording_multichannel: false,
+            recording_normalize: false,
+            recording_preroll: DEFAULT_RECORDING_PREROLL,
+            recording_metadata: true,
+            recording_error: None,
+            config_load_error: None,
+            config_save_error: None,
+            load_error: None,
+            window_title: WINDOW_TITLE.to_string(),
+            undo_stack: None,
+            undo_config: String::new(),
+            undo_request: None,
+            preset: None,
+            preset_dir: PathBuf::from("presets"),
+            preset_dir_files: Vec::new(),
+            preset_dir_active: None,
+            preset_dir_error: None,
+            per_cylinder_mode: false,
+            advanced_cylinder_mode: false,
+            selected_cylinder: 0,
+            noise_seed_text: None,
+            randomize_seed: None,
+            randomize_seed_text: None,
+            morph_on_load: false,
+            ab_stored: None,
+            ab_b_active: false,
+            config_watcher: None,
+            osc_status: None,
+            audio_devices: Vec::new(),
+            audio_device: None,
+            audio_device_switch: None,
+            show_muffler_response: false,
+            show_harmonics: false,
+            #[cfg(feature = "midi")]
+            midi_device_name: String::new(),
+            #[cfg(feature = "midi")]
+            midi: None,
+            #[cfg(feature = "midi")]
+            midi_error: None,
+        }
+    }
+
+    /// reverts the engine to the state before the last change in the next frame
+    pub fn undo(&mut self) {
+        self.undo_request = Some(UndoRequest::Undo);
+    }
+
+    /// restores the last undone state in the next frame
+    pub fn redo(&mut self) {
+        self.undo_request = Some(UndoRequest::Redo);
+    }
+
+    /// returns whether the next loaded config should be morphed to and resets it
+    pub fn take_morph_on_load(&mut self) -> bool {
+        std::mem::replace(&mut self.morph_on_load, false)
+    }
+
+    /// reloads the config at `path` whenever it is changed on disk, replacing the previously watched config
+    /// and the last loaded preset
+    pub fn watch_config(&mut self, path: &str, sample_rate: u32) {
+        self.preset = None;
+        self.preset_dir_active = None;
+        self.config_watcher = match ConfigWatcher::new(path, sample_rate) {
+            Ok(config_watcher) => Some(config_watcher),
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            }
+        };
+    }
+
+    /// lists the `*.esc` files in `path` in the presets panel
+    pub fn set_preset_dir(&mut self, path: &str) {
+        self.preset_dir = PathBuf::from(path);
+        self.refresh_preset_dir();
+    }
+
+    /// reads the files of the presets panel again, a missing directory is shown as an empty list
+    fn refresh_preset_dir(&mut self) {
+        self.preset_dir_files = match std::fs::read_dir(&self.preset_dir) {
+            Ok(entries) => {
+                let mut files = entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| {
+                        path.is_file()
+                            && path.extension().is_some_and(|extension| extension == "esc")
+                    })
+                    .collect::<Vec<PathBuf>>();
+                files.sort();
+                files
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                self.preset_dir_error = Some(format!(
+                    "Failed to read the presets directory \"{}\": {}",
+                    self.preset_dir.display(),
+                    e
+                ));
+                Vec::new()
+            }
+        };
+    }
+
+    pub fn set_load_error(&mut self, error: String) {
+        self.load_error = Some(error);
+    }
+
+    #[cfg(feature = "osc")]
+    pub fn set_osc_status(&mut self, status: String) {
+        self.osc_status = Some(status);
+    }
+
+    /// lists `devices` in the "Audio device" dropdown which switches the output device with `device_switch`,
+    /// `device_name` is the `--audio-device` substring the playback was started with
+    pub fn set_audio_devices(
+        &mut self,
+        devices: Vec<String>,
+        device_name: Option<&str>,
+        device_switch: DeviceSwitch,
+    ) {
+        self.audio_device = device_name
+            .and_then(|device_name| devices.iter().position(|name| name.contains(device_name)));
+        self.audio_devices = devices;
+        self.audio_device_switch = Some(device_switch);
+    }
+
+    /// sets the device name used by the "Connect MIDI" button and the already opened connection
+    #[cfg(feature = "midi")]
+    pub fn set_midi(
+        &mut self,
+        device_name: &str,
+        connection: Option<midir::MidiInputConnection<()>>,
+    ) {
+        self.midi_device_name = device_name.to_string();
+        self.midi = connection;
+    }
+
+    /// sets whether the engine config, rpm, seed, duration, peak and loudness of recordings are written to a JSON file next to them
+    pub fn set_recording_metadata(&mut self, enabled: bool) {
+        self.recording_metadata = enabled;
+    }
+
+    /// shows the "Window" dropdown which changes the window function of the FFT thread, see `FFTStreamer::window_function`
+    pub fn set_fft_window_function(&mut self, window_function: Arc<Mutex<WindowFunction>>) {
+        self.fft_window_function = Some(window_function);
+    }
+
+    /// sets the number of waterfall lines which are exported by the "Export Spectrogram" button, at least the lines shown by the waterfall are kept
+    pub fn set_max_history(&mut self, max_history: usize) {
+        self.max_history = max_history.max(self.waterfall_height);
+        self.history.truncate(self.max_history);
+    }
+
+    /// sets the number of rows of the waterfall, the newest rows are kept and added rows are filled from the history
+    pub fn set_waterfall_height(&mut self, height: usize) {
+        let height = height.clamp(MIN_WATERFALL_HEIGHT, MAX_WATERFALL_HEIGHT);
+        let background = [WATERFALL_BACKGROUND; WATERFALL_WIDTH as usize];
+
+        self.waterfall
+            .truncate(WATERFALL_WIDTH as usize * height.min(self.waterfall_height));
+        for row in self.waterfall_height..height {
+            self.waterfall.extend_from_slice(
+                self.history
+                    .get(row)
+                    .map(Vec::as_slice)
+                    .unwrap_or(&background),
+            );
+        }
+
+        self.waterfall_height = height;
+        self.max_history = self.max_history.max(height);
+    }
+
+    /// samples of one waterfall line, the size of the FFT of the latest spectrum (see `--fft-size`)
+    fn fft_size(&self) -> usize {
+        self.spectrum
+            .as_ref()
+            .map_or(WATERFALL_WIDTH as usize * 2, |spectrum| {
+                spectrum.values.len()
+            })
+    }
+
+    /// linear factor of `waterfall_gain_db`
+    fn waterfall_gain(&self) -> f32 {
+        10.0f32.powf(self.waterfall_gain_db / 20.0)
+    }
+
+    /// clears the lines of the last recording when a new recording starts
+    pub fn set_recording(&mut self, recording: bool) {
+        if recording && !self.recording {
+            self.recording_lines.clear();
+        }
+
+        self.recording = recording;
+    }
+
+    /// the waterfall lines since the start of the current or last recording, otherwise the lines kept in the history,
+    /// but at least as many lines as the waterfall has, for the "Export Spectrogram" button.
+    /// Each line is the spectrum of `fft_size` samples, so it covers `fft_size / sample_rate` seconds
+    fn spectrogram(&self) -> Spectrogram {
+        let lines = if self.recording_lines.is_empty() {
+            let height = self.history.len().max(self.waterfall_height);
+            let background = vec![WATERFALL_BACKGROUND; WATERFALL_WIDTH as usize];
+
+            (0..height)
+                .map(|row| self.history.get(row).unwrap_or(&background).clone())
+                .collect()
+        } else {
+            self.recording_lines.iter().rev().cloned().collect()
+        };
+
+        Spectrogram {
+            lines,
+            colormap: self.waterfall_colormap,
+            gain: self.waterfall_gain(),
+            bin_width: self.spectrum.as_ref().map(|spectrum| spectrum.bin_width),
+            fft_size: self.fft_size(),
         }
     }
 
     fn update(&mut self) {
-        while let Ok(new_line) = self.input.try_recv() {
+        while let Ok(spectrum) = self.input.try_recv() {
+            // the channel is still drained so the FFT thread doesn't block
+            if self.waterfall_paused {
+                continue;
+            }
+
             let log_scale = (0..WATERFALL_WIDTH as usize)
                 .map(|i| {
-                    let new = ((1.0 - (i + 1) as f32 / (WATERFALL_WIDTH + 1) as f32).log2()
-                        / (WATERFALL_WIDTH as f32).recip().log2()
-                        * (WATERFALL_WIDTH - 1) as f32)
-                        .max(1e-3);
-
-                    let idx = new.floor() as usize;
-                    new_line[idx.saturating_sub(1)] * (1.0 - new.fract())
-                        + new_line[idx] * new.fract()
+                    sample_waterfall_column(&spectrum.values, spectrum.values.len() / 2, i as f32)
                 })
                 .collect::<Vec<f32>>();
             self.add_line(&log_scale);
+            self.spectrum = Some(spectrum);
+        }
+
+        if let Some(result) = self
+            .spectrogram_export
+            .as_ref()
+            .and_then(|export| export.try_recv().ok())
+        {
+            self.spectrogram_export = None;
+
+            match result {
+                Ok(path) => {
+                    println!("Saved spectrogram to \"{}\"", path.display());
+                    self.spectrogram_error = None;
+                }
+                Err(e) => {
+                    eprintln!("Failed to save spectrogram: {}", e);
+                    self.spectrogram_error = Some(e);
+                }
+            }
         }
+
+        self.oscilloscope.update();
     }
 
     /// Shift the waterfall down by one and add the new line
@@ -188,10 +825,324 @@ impl GUIState {
         );
 
         self.waterfall.copy_within(
-            0..((WATERFALL_WIDTH * (WATERFALL_HEIGHT - 1)) as usize),
+            0..(WATERFALL_WIDTH as usize * (self.waterfall_height - 1)),
             WATERFALL_WIDTH as usize,
         );
         self.waterfall[..WATERFALL_WIDTH as usize].copy_from_slice(line);
+
+        // reuses the allocation of the oldest line
+        let mut history_line = if self.history.len() >= self.max_history {
+            self.history.pop_back().unwrap()
+        } else {
+            Vec::with_capacity(line.len())
+        };
+        history_line.clear();
+        history_line.extend_from_slice(line);
+        self.history.push_front(history_line);
+
+        if self.recording && self.recording_lines.len() < MAX_RECORDING_SPECTROGRAM_LINES {
+            self.recording_lines.push(line.to_vec());
+        }
+    }
+}
+
+/// Holds the most recently generated samples for the oscilloscope below the waterfall
+pub struct Oscilloscope {
+    input: crossbeam_channel::Receiver<MonitorBuffer>,
+    /// the oldest sample is at the front, the first `len` samples are shown
+    samples: VecDeque<f32>,
+    len: usize,
+    /// number of samples received before the first one in `samples`
+    offset: usize,
+    /// numbers of the received samples at which a crankshaft cycle starts, which are still in `samples`
+    cycle_starts: VecDeque<usize>,
+    /// starts the window at the crankshaft cycle instead of showing the most recent samples, so the waveform stands still
+    triggered: bool,
+    zoom: f32,
+    /// time at which the last received buffer contained a sample outside of -1.0 - 1.0
+    last_clip: Option<Instant>,
+}
+
+impl Oscilloscope {
+    /// shows `len` samples received from `input`
+    pub fn new(input: crossbeam_channel::Receiver<MonitorBuffer>, len: usize) -> Self {
+        let len = len.max(2);
+
+        Oscilloscope {
+            input,
+            samples: std::iter::repeat_n(0.0, len).collect(),
+            len,
+            offset: 0,
+            cycle_starts: VecDeque::new(),
+            triggered: true,
+            zoom: DEFAULT_OSCILLOSCOPE_ZOOM,
+            last_clip: None,
+        }
+    }
+
+    fn update(&mut self) {
+        while let Ok(buf) = self.input.try_recv() {
+            if buf.samples.iter().any(|sample| sample.abs() > 1.0) {
+                self.last_clip = Some(Instant::now());
+            }
+
+            let received = self.offset + self.samples.len();
+            self.cycle_starts
+                .extend(buf.cycle_starts().map(|index| received + index));
+            self.samples.extend(buf.samples);
+        }
+
+        let received = self.offset + self.samples.len();
+        let len = self.len;
+        // the most recent cycle start which is followed by a whole window
+        let trigger = self
+            .cycle_starts
+            .iter()
+            .rev()
+            .copied()
+            .find(|&cycle_start| cycle_start + len <= received)
+            .filter(|_| self.triggered);
+
+        let start = match trigger {
+            Some(cycle_start) => cycle_start,
+            None if self.triggered && received - self.offset <= len * MAX_TRIGGER_WAIT => {
+                self.offset
+            }
+            None => received - len,
+        };
+
+        self.samples.drain(..start - self.offset);
+        self.offset = start;
+        self.cycle_starts
+            .retain(|&cycle_start| cycle_start >= start);
+    }
+
+    /// the shown samples
+    fn window(&self) -> impl Iterator<Item = f32> + '_ {
+        self.samples.iter().take(self.len).copied()
+    }
+
+    /// highest absolute sample value in the window
+    fn peak(&self) -> f32 {
+        self.window()
+            .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+    }
+
+    fn clipping(&self) -> bool {
+        self.last_clip
+            .is_some_and(|last_clip| last_clip.elapsed() < CLIP_INDICATOR_TIME)
+    }
+}
+
+/// names of the `MufflerElement` variants in the dropdowns of the muffler elements
+const MUFFLER_ELEMENT_KINDS: [&str; 2] = ["Straight pipe", "Helmholtz resonator"];
+/// length in meters of a muffler element which was switched to a straight pipe
+const DEFAULT_MUFFLER_ELEMENT_LENGTH: f32 = 0.1;
+/// neck length, neck radius, cavity volume and reflectivity of a muffler element which was switched to a resonator,
+/// which has its notch at about 190hz
+const DEFAULT_HELMHOLTZ_RESONATOR: (f32, f32, f32, f32) = (0.05, 0.02, 0.002, 0.5);
+
+/// shown after the label of a waveguide's slider while the waveguide dampens its output
+fn damping_indicator(waveguide: &WaveGuide) -> &'static str {
+    if waveguide.dampened {
+        "   !!damping active!!"
+    } else {
+        ""
+    }
+}
+
+/// Color ramps of the waterfall and the saved spectrogram
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Colormap {
+    /// black over blue and purple to orange and white
+    Classic,
+    Viridis,
+    Grayscale,
+}
+
+impl Colormap {
+    pub const ALL: [Colormap; 3] = [Colormap::Classic, Colormap::Viridis, Colormap::Grayscale];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Colormap::Classic => "Classic",
+            Colormap::Viridis => "Viridis",
+            Colormap::Grayscale => "Grayscale",
+        }
+    }
+
+    /// (color, position) from position 0.0 to 1.0
+    fn stops(self) -> &'static [([f32; 3], f32)] {
+        match self {
+            Colormap::Classic => &[
+                ([0.0, 0.0, 0.0], 0.0),
+                ([0.0, 0.2, 0.23], 0.21),
+                ([0.0, 0.3, 0.6], 0.325),
+                ([0.51, 0.36, 1.0], 0.44),
+                ([1.0, 0.55, 0.0], 0.69),
+                ([1.0, 0.86, 0.69], 0.85),
+                ([1.0, 1.0, 1.0], 1.0),
+            ],
+            Colormap::Viridis => &[
+                ([0.267, 0.005, 0.329], 0.0),
+                ([0.283, 0.141, 0.458], 0.1),
+                ([0.254, 0.265, 0.53], 0.2),
+                ([0.207, 0.372, 0.553], 0.3),
+                ([0.164, 0.471, 0.558], 0.4),
+                ([0.128, 0.567, 0.551], 0.5),
+                ([0.135, 0.659, 0.518], 0.6),
+                ([0.267, 0.749, 0.441], 0.7),
+                ([0.478, 0.821, 0.3182], 0.8),
+                ([0.741, 0.873, 0.15], 0.9),
+                ([0.993, 0.906, 0.144], 1.0),
+            ],
+            Colormap::Grayscale => &[([0.0, 0.0, 0.0], 0.0), ([1.0, 1.0, 1.0], 1.0)],
+        }
+    }
+
+    /// maps a display value (0.0 - 1.0, see `display_value`) to its RGB color, values outside of the range are clamped
+    pub fn color(self, x: f32) -> [u8; 3] {
+        let x = if x.is_nan() { 0.0 } else { x.clamp(0.0, 1.0) };
+
+        let colors = self
+            .stops()
+            .windows(2)
+            .find(|colors| {
+                let (_, end) = colors[1];
+                x <= end
+            })
+            .expect("invalid color mix range");
+
+        let (low_color, low) = colors[0];
+        let (high_color, high) = colors[1];
+
+        let ratio = (x - low) / (high - low);
+        [
+            low_color[0] + (high_color[0] - low_color[0]) * ratio,
+            low_color[1] + (high_color[1] - low_color[1]) * ratio,
+            low_color[2] + (high_color[2] - low_color[2]) * ratio,
+        ]
+        .map(|x| (x.clamp(0.0, 1.0) * 255.0) as u8)
+    }
+}
+
+/// maps an FFT magnitude amplified by `gain` to 0.0 - 1.0, NaNs and negative magnitudes map to 0.0
+pub fn display_value(magnitude: f32, gain: f32) -> f32 {
+    let x = (((magnitude * gain * MAGNITUDE_SCALE).exp() - 1.0) * 0.7).sqrt() * 2.0;
+
+    if x.is_nan() {
+        0.0
+    } else {
+        x.clamp(0.0, 1.0)
+    }
+}
+
+/// frequency difference of two neighboring bins of the frequency axes of the waterfall and the spectrum plot, which span
+/// `WATERFALL_WIDTH` of these bins up to the nyquist frequency, of an FFT of `fft_size` bins of `bin_width`
+pub fn axis_bin_width(bin_width: f32, fft_size: usize) -> f32 {
+    bin_width * (fft_size / 2) as f32 / WATERFALL_WIDTH as f32
+}
+
+/// interpolates `spectrum` (linearly spaced bins, `bins` of them up to the nyquist frequency) at the logarithmically spaced
+/// frequency of the waterfall column `column`
+fn sample_waterfall_column(spectrum: &[f32], bins: usize, column: f32) -> f32 {
+    let position = (waterfall_column_position(column) - 1.0).max(0.0);
+
+    sample_spectrum(spectrum, position * bins as f32 / WATERFALL_WIDTH as f32)
+}
+
+/// position of the waterfall column `column` between the bins of the frequency axis, the column shows bin `position - 1`,
+/// see `axis_bin_width`
+fn waterfall_column_position(column: f32) -> f32 {
+    ((1.0 - (column + 1.0) / (WATERFALL_WIDTH + 1) as f32).log2()
+        / (WATERFALL_WIDTH as f32).recip().log2()
+        * (WATERFALL_WIDTH - 1) as f32)
+        .max(1e-3)
+}
+
+/// frequency in hz shown by the waterfall column `column`, see `sample_waterfall_column`
+pub fn waterfall_column_freq(column: f32, bin_width: f32) -> f32 {
+    (waterfall_column_position(column) - 1.0).max(0.0) * bin_width
+}
+
+/// inverse of `waterfall_column_freq`, the column may be fractional and lies outside of the waterfall above the nyquist frequency
+fn waterfall_freq_column(freq: f32, bin_width: f32) -> f32 {
+    let position = freq / bin_width + 1.0;
+
+    (1.0 - (WATERFALL_WIDTH as f32).powf(-position / (WATERFALL_WIDTH - 1) as f32))
+        * (WATERFALL_WIDTH + 1) as f32
+        - 1.0
+}
+
+/// firing frequency of a four-stroke engine (`rpm / 60 * cylinders / 2`) and its harmonics up to the nyquist frequency
+/// as `(frequency, waterfall column)`, without the harmonics closer than `MIN_HARMONIC_DISTANCE` columns to the previous one
+fn firing_harmonics(
+    rpm: f32,
+    cylinders: usize,
+    sample_rate: u32,
+    bin_width: f32,
+) -> Vec<(f32, usize)> {
+    let firing_freq = rpm / 60.0 * cylinders as f32 / 2.0;
+    if !firing_freq.is_finite() || firing_freq <= 0.0 {
+        return Vec::new();
+    }
+
+    let mut harmonics = Vec::new();
+    let mut last_column = f32::NEG_INFINITY;
+    for freq in (1..)
+        .map(|harmonic| firing_freq * harmonic as f32)
+        .take_while(|&freq| freq <= sample_rate as f32 * 0.5)
+    {
+        let column = waterfall_freq_column(freq, bin_width);
+        if column >= WATERFALL_WIDTH as f32 {
+            break;
+        }
+        if column - last_column >= MIN_HARMONIC_DISTANCE {
+            harmonics.push((freq, column.round() as usize));
+            last_column = column;
+        }
+    }
+
+    harmonics
+}
+
+/// frequency in hz at `x` (0..1) on the logarithmic axis of the spectrum plot,
+/// which spans from the first bin to the nyquist frequency (`WATERFALL_WIDTH` bins)
+fn spectrum_plot_freq(x: f32, bin_width: f32) -> f32 {
+    bin_width * (WATERFALL_WIDTH as f32).powf(x)
+}
+
+/// inverse of `spectrum_plot_freq`
+fn spectrum_plot_x(freq: f32, bin_width: f32) -> f32 {
+    (freq / bin_width).ln() / (WATERFALL_WIDTH as f32).ln()
+}
+
+/// linearly interpolates `spectrum` at the fractional bin `bin`
+fn sample_spectrum(spectrum: &[f32], bin: f32) -> f32 {
+    let idx = (bin.floor() as usize).min(spectrum.len() - 2);
+    let fract = (bin - idx as f32).clamp(0.0, 1.0);
+    spectrum[idx] * (1.0 - fract) + spectrum[idx + 1] * fract
+}
+
+/// compared to detect changes of the engine, the running values aren't serialized
+fn undo_config(engine: &Engine) -> String {
+    ron::ser::to_string(engine).expect("failed to serialize engine")
+}
+
+/// `WINDOW_TITLE` followed by the name of the engine
+pub fn window_title(name: Option<&str>) -> String {
+    match name {
+        Some(name) => format!("{} - {}", WINDOW_TITLE, name),
+        None => WINDOW_TITLE.to_string(),
+    }
+}
+
+/// labels frequencies above 1 khz in khz
+pub fn format_freq(freq: f32) -> String {
+    if freq >= 1000.0 {
+        format!("{}k", freq / 1000.0)
+    } else {
+        format!("{}", freq)
     }
 }
 
@@ -200,8 +1151,8 @@ impl GUIState {
 #[allow(clippy::cognitive_complexity)]
 pub fn gui(
     ui: &mut conrod_core::UiCell,
-    ids: &Ids,
-    generator: Arc<RwLock<Generator>>,
+    ids: &mut Ids,
+    generator: &GeneratorHandle,
     gui_state: &mut GUIState,
     display: &glium::Display,
 ) -> conrod_core::image::Map<glium::texture::Texture2d> {
@@ -211,6 +1162,11 @@ pub fn gui(
     const BUTTON_LINE_SIZE: conrod_core::Scalar = 16.0;
     const DOWN_SPACE: conrod_core::Scalar = 6.0;
     const LABEL_FONT_SIZE: u32 = 10;
+    const SAMPLE_DEPTH_WIDTH: conrod_core::Scalar = 100.0;
+    const MULTICHANNEL_WIDTH: conrod_core::Scalar = 100.0;
+    const PREROLL_WIDTH: conrod_core::Scalar = 100.0;
+    const NORMALIZE_WIDTH: conrod_core::Scalar = 100.0;
+    const ENGINE_INFO_LABEL_WIDTH: conrod_core::Scalar = 80.0;
 
     widget::Canvas::new()
         .pad(MARGIN)
@@ -223,177 +1179,685 @@ pub fn gui(
         .w(20.0)
         .set(ids.canvas_scrollbar, ui);
 
-    fn mix(x: f32, colors: &[([f32; 3], f32)]) -> [f32; 3] {
-        let colors = colors
-            .windows(2)
-            .find(|colors| {
-                let (_, start) = colors[0];
-                let (_, end) = colors[1];
-                start <= x && x < end
-            })
-            .expect("invalid color mix range");
-
-        let (low_color, low) = colors[0];
-        let (high_color, high) = colors[1];
-
-        let ratio = (x - low) / (high - low);
-        [
-            low_color[0] + (high_color[0] - low_color[0]) * ratio,
-            low_color[1] + (high_color[1] - low_color[1]) * ratio,
-            low_color[2] + (high_color[2] - low_color[2]) * ratio,
-        ]
-    }
+    // the generator thread only waits for the GUI while it copies its state
+    let snapshot = generator
+        .snapshot()
+        .expect("the generator thread has stopped");
 
     let image_map = {
         // receives (maybe) new FFT data
         gui_state.update();
 
+        let gain = gui_state.waterfall_gain();
+        // the bin width is only known once the first spectrum has been received
+        let harmonics = match &gui_state.spectrum {
+            Some(spectrum) if gui_state.show_harmonics => firing_harmonics(
+                snapshot.engine.rpm,
+                snapshot.engine.cylinders.len(),
+                snapshot.samples_per_second,
+                axis_bin_width(spectrum.bin_width, spectrum.values.len()),
+            ),
+            _ => Vec::new(),
+        };
+        let mut harmonic_columns = vec![false; WATERFALL_WIDTH as usize];
+        for &(_, column) in harmonics.iter() {
+            harmonic_columns[column] = true;
+        }
+
+        let colormap = gui_state.waterfall_colormap;
         let raw_image = glium::texture::RawImage2d::from_raw_rgb_reversed(
             gui_state
                 .waterfall
                 .iter()
-                .flat_map(|x| {
-                    let color = mix(
-                        x.max(0.0).min(10.0),
-                        &[
-                            ([0.0, 0.0, 0.0], 0.0),
-                            ([0.0, 0.2, 0.23], 0.21),
-                            ([0.0, 0.3, 0.6], 0.325),
-                            ([0.51, 0.36, 1.0], 0.44),
-                            ([1.0, 0.55, 0.0], 0.69),
-                            ([1.0, 0.86, 0.69], 0.85),
-                            ([1.0, 1.0, 1.0], 1.0),
-                            ([1.0, 1.0, 1.0], 10.01),
-                        ],
-                    );
+                .enumerate()
+                .flat_map(|(i, &x)| {
+                    let value = display_value(x, gain);
+                    let color = colormap.color(value);
 
-                    color
-                        .to_vec()
-                        .into_iter()
-                        .map(|x| (x.max(0.0).min(1.0) * 255.0) as u8)
+                    if harmonic_columns[i % WATERFALL_WIDTH as usize] {
+                        // the opposite end of the colormap contrasts with the magnitude
+                        let line = colormap.color(1.0 - value);
+                        [0, 1, 2].map(|c| {
+                            (color[c] as f32 * (1.0 - HARMONIC_LINE_ALPHA)
+                                + line[c] as f32 * HARMONIC_LINE_ALPHA)
+                                as u8
+                        })
+                    } else {
+                        color
+                    }
                 })
                 .collect::<Vec<_>>()
                 .as_slice(),
-            (WATERFALL_WIDTH, WATERFALL_HEIGHT),
+            (WATERFALL_WIDTH, gui_state.waterfall_height as u32),
         );
 
         let mut image_map = conrod_core::image::Map::<glium::texture::Texture2d>::new();
         let waterfall_image_id =
             image_map.insert(glium::texture::Texture2d::new(display, raw_image).unwrap());
 
-        widget::Image::new(waterfall_image_id)
+        // spectrum plot of the latest waterfall line with a logarithmic frequency axis
+        {
+            widget::Rectangle::fill_with(
+                [BUTTON_WIDTH, SPECTRUM_HEIGHT],
+                Color::Rgba(0.0, 0.0, 0.0, 1.0),
+            )
             .mid_top_with_margin(TOP_MARGIN)
             .mid_left_of(ids.canvas)
+            .set(ids.spectrum_background, ui);
+
+            if let Some(spectrum) = &gui_state.spectrum {
+                let bin_width = axis_bin_width(spectrum.bin_width, spectrum.values.len());
+
+                for ((&freq, &line), &label) in SPECTRUM_GRID_FREQS
+                    .iter()
+                    .zip(&ids.spectrum_grid_lines)
+                    .zip(&ids.spectrum_grid_labels)
+                {
+                    let x = spectrum_plot_x(freq, bin_width);
+                    if !(0.0..=1.0).contains(&x) {
+                        continue;
+                    }
+                    let x = (x as f64 - 0.5) * BUTTON_WIDTH;
+
+                    widget::Line::centred([x, -SPECTRUM_HEIGHT * 0.5], [x, SPECTRUM_HEIGHT * 0.5])
+                        .middle_of(ids.spectrum_background)
+                        .color(Color::Rgba(0.3, 0.3, 0.35, 1.0))
+                        .thickness(1.0)
+                        .set(line, ui);
+
+                    widget::Text::new(format_freq(freq).as_str())
+                        .font_size(LABEL_FONT_SIZE)
+                        .color(Color::Rgba(0.6, 0.6, 0.6, 1.0))
+                        .x_y_relative_to(
+                            ids.spectrum_background,
+                            x + 12.0,
+                            SPECTRUM_HEIGHT * 0.5 - 8.0,
+                        )
+                        .set(label, ui);
+                }
+
+                widget::PlotPath::new(0.0, 1.0, 0.0, 1.0, |x: f32| {
+                    display_value(
+                        sample_spectrum(
+                            &spectrum.values,
+                            spectrum_plot_freq(x, bin_width) / spectrum.bin_width,
+                        ),
+                        gain,
+                    )
+                })
+                .w_h(BUTTON_WIDTH, SPECTRUM_HEIGHT)
+                .middle_of(ids.spectrum_background)
+                .color(Color::Rgba(0.2, 1.0, 0.3, 1.0))
+                .thickness(1.0)
+                .set(ids.spectrum_plot, ui);
+            }
+        }
+
+        widget::Image::new(waterfall_image_id)
+            .down_from(ids.spectrum_background, DOWN_SPACE)
+            .align_left_of(ids.spectrum_background)
             .w(BUTTON_WIDTH)
             .h(140.0)
             .set(ids.waterfall, ui);
 
-        image_map
-    };
+        if let Some(&(freq, column)) = harmonics.first() {
+            let x = (column as f64 + 0.5) / WATERFALL_WIDTH as f64 * BUTTON_WIDTH;
 
-    {
-        let mut generator = generator.write();
-        let sample_rate = generator.samples_per_second;
+            widget::Text::new(format!("{:.1} hz", freq).as_str())
+                .font_size(LABEL_FONT_SIZE)
+                .color(Color::Rgba(1.0, 1.0, 1.0, 1.0))
+                .x_y_relative_to(ids.waterfall, x - BUTTON_WIDTH * 0.5 + 25.0, 70.0 - 8.0)
+                .set(ids.harmonics_label, ui);
+        }
 
-        {
-            let (mut button_label, remove_recorder) = match &mut generator.recorder {
-                None => ("Start recording".to_string(), false),
-                Some(recorder) => {
-                    if recorder.is_running() {
-                        ui.needs_redraw();
-                        (
-                            format!(
-                                "Stop recording [{:.3} sec recorded]",
-                                recorder.get_len() as f32 / sample_rate as f32
-                            ),
-                            false,
-                        )
-                    } else {
-                        ("Start recording".to_string(), true)
-                    }
-                }
+        // frequency under the cursor while it hovers the spectrum plot or the waterfall
+        if let Some(spectrum) = &gui_state.spectrum {
+            let mouse = ui.global_input().current.mouse.xy;
+            // position of the cursor from the left edge of the widget (0..1)
+            let hovered = |id| {
+                ui.rect_of(id)
+                    .filter(|rect| rect.is_over(mouse))
+                    .map(|rect| ((mouse[0] - rect.left()) / rect.w()) as f32)
             };
 
-            if generator.recording_currently_clipping {
-                button_label.push_str("   !!Recording clipping!! (decrease master volume)");
-            }
+            let bin_width = axis_bin_width(spectrum.bin_width, spectrum.values.len());
+            let cursor_freq = hovered(ids.spectrum_background)
+                .map(|x| spectrum_plot_freq(x, bin_width))
+                .or_else(|| {
+                    hovered(ids.waterfall)
+                        .map(|x| waterfall_column_freq(x * WATERFALL_WIDTH as f32, bin_width))
+                });
 
-            if remove_recorder {
-                generator.recorder = None;
+            if let Some(freq) = cursor_freq {
+                widget::Text::new(format!("{:.0} hz", freq).as_str())
+                    .font_size(LABEL_FONT_SIZE)
+                    .color(Color::Rgba(1.0, 1.0, 1.0, 1.0))
+                    .bottom_right_with_margin_on(ids.spectrum_background, 3.0)
+                    .set(ids.spectrum_cursor_freq, ui);
             }
+        }
 
-            for _press in widget::Button::new()
-                .label(button_label.as_str())
-                .down(DOWN_SPACE + 2.0)
-                .w(BUTTON_WIDTH)
-                .h(BUTTON_LINE_SIZE)
-                .set(ids.record_button, ui)
-            {
-                let sample_rate = sample_rate;
-                match &mut generator.recorder {
-                    None => {
-                        let rec_name = recording_name();
+        if gui_state.show_muffler_response {
+            // the same bins as the lower half of the waterfall's fft, which has twice as many bins as there are columns
+            let response = snapshot.engine.muffler_frequency_response(
+                snapshot.samples_per_second,
+                WATERFALL_WIDTH as usize + 1,
+            );
+            let max = response.iter().copied().fold(f32::MIN_POSITIVE, f32::max);
+
+            widget::PlotPath::new(
+                0.0,
+                (WATERFALL_WIDTH - 1) as f32,
+                MUFFLER_RESPONSE_RANGE_DB,
+                0.0,
+                |column: f32| {
+                    20.0 * (sample_waterfall_column(&response, WATERFALL_WIDTH as usize, column)
+                        / max)
+                        .max(1e-10)
+                        .log10()
+                        .max(MUFFLER_RESPONSE_RANGE_DB)
+                },
+            )
+            .w_h(BUTTON_WIDTH, 140.0)
+            .middle_of(ids.waterfall)
+            .color(Color::Rgba(0.2, 1.0, 0.3, 1.0))
+            .thickness(1.5)
+            .set(ids.muffler_response_plot, ui);
+        }
 
-                        let mut dialog = native_dialog::FileDialog::new()
-                            .set_filename(&rec_name)
-                            .add_filter("MONO Wave Audio file", &["wav"]);
+        // oscilloscope
+        {
+            let oscilloscope = &gui_state.oscilloscope;
+            let range = 1.0 / oscilloscope.zoom;
 
-                        if let Some(recording_save_path) = &gui_state.recording_save_path {
-                            dialog = dialog.set_location(recording_save_path);
-                        }
+            widget::Rectangle::fill_with(
+                [BUTTON_WIDTH, OSCILLOSCOPE_HEIGHT],
+                if oscilloscope.clipping() {
+                    Color::Rgba(0.6, 0.05, 0.05, 1.0)
+                } else {
+                    Color::Rgba(0.0, 0.0, 0.0, 1.0)
+                },
+            )
+            .down_from(ids.waterfall, DOWN_SPACE)
+            .align_left_of(ids.waterfall)
+            .set(ids.oscilloscope_background, ui);
 
-                        if let Some(save_path) = dialog
-                            .show_save_single_file()
-                            .expect("Failed to open file save dialog")
-                        {
-                            gui_state.recording_save_path =
-                                save_path.parent().map(|p| p.to_owned());
-                            generator.recorder = Some(Recorder::new(save_path, sample_rate));
-                        } else {
-                            println!("Aborted recording");
-                        }
-                    }
-                    Some(recorder) => {
-                        recorder.stop();
-                    }
+            // samples beyond these lines clip
+            if range >= 1.0 {
+                for (&id, level) in ids.oscilloscope_reference.iter().zip([1.0, -1.0]) {
+                    let y = (level / range) as f64 * OSCILLOSCOPE_HEIGHT * 0.5;
+                    widget::Line::centred([-BUTTON_WIDTH * 0.5, y], [BUTTON_WIDTH * 0.5, y])
+                        .middle_of(ids.oscilloscope_background)
+                        .color(Color::Rgba(0.8, 0.1, 0.1, 1.0))
+                        .thickness(1.0)
+                        .set(id, ui);
                 }
             }
+
+            widget::PlotPath::new(
+                0.0,
+                (oscilloscope.len - 1) as f32,
+                -range,
+                range,
+                |x: f32| {
+                    oscilloscope.samples[(x.round() as usize).min(oscilloscope.len - 1)]
+                        .clamp(-range, range)
+                },
+            )
+            .w_h(BUTTON_WIDTH, OSCILLOSCOPE_HEIGHT)
+            .middle_of(ids.oscilloscope_background)
+            .color(Color::Rgba(0.2, 1.0, 0.3, 1.0))
+            .thickness(1.0)
+            .set(ids.oscilloscope_plot, ui);
+
+            widget::Text::new(
+                format!(
+                    "Peak {:.1} dB{}",
+                    20.0 * oscilloscope.peak().max(1e-5).log10(),
+                    if oscilloscope.triggered {
+                        ", triggered on the crankshaft cycle"
+                    } else {
+                        ""
+                    }
+                )
+                .as_str(),
+            )
+            .font_size(LABEL_FONT_SIZE)
+            .color(Color::Rgba(0.8, 0.8, 0.8, 1.0))
+            .top_left_with_margin_on(ids.oscilloscope_background, 3.0)
+            .set(ids.oscilloscope_peak, ui);
         }
 
-        {
-            for _press in widget::Button::new()
-                .label("Open file")
-                .down(DOWN_SPACE + 2.0)
-                .w(BUTTON_WIDTH)
+        image_map
+    };
+
+    // drawn over the waterfall
+    if let Some(e) = &gui_state.load_error {
+        for _click in widget::Button::new()
+            .label(format!("{}   (click to dismiss)", e).as_str())
+            .label_font_size(LABEL_FONT_SIZE)
+            .label_color(Color::Rgba(1.0, 1.0, 1.0, 1.0))
+            .color(Color::Rgba(0.8, 0.1, 0.1, 1.0))
+            .mid_top_of(ids.waterfall)
+            .w(BUTTON_WIDTH)
+            // invalid configs list one error per line
+            .h(BUTTON_LINE_SIZE * (e.lines().count() + 1) as f64)
+            .set(ids.load_error_banner, ui)
+        {
+            gui_state.load_error = None;
+        }
+    }
+
+    {
+        // the parameters are edited on the snapshot's copy of the engine which is sent to the generator thread
+        // at the end of frames in which it may have changed, so the audio thread never has to wait for the GUI
+        let GeneratorSnapshot {
+            mut engine,
+            mut volume,
+            mut stereo_width,
+            samples_per_second: sample_rate,
+            engine_revision,
+            morph_progress,
+            target_rpm,
+            recorder,
+            recording_currently_clipping,
+            dampened_waveguides,
+        } = snapshot;
+        // set when a config has been loaded which replaces the engine instead of updating its parameters
+        let mut engine_replaced = false;
+        // set when a config has been loaded which the engine should morph to
+        let mut morph_target = None;
+        // set when a seed has been entered, the noise sources are running values which are not copied from the engine copy
+        let mut reseed_noise = false;
+        // set when the rpm slider has been moved while the rpm slew rate is limited
+        let mut new_target_rpm = None;
+
+        // the spectrogram of a recording starts once its pre-roll is over
+        gui_state.set_recording(
+            recorder
+                .as_ref()
+                .is_some_and(|recorder| recorder.preroll == 0),
+        );
+
+        if let Some(new_engine) = gui_state
+            .config_watcher
+            .as_ref()
+            .and_then(ConfigWatcher::poll)
+        {
+            engine = new_engine;
+            engine_replaced = true;
+        }
+
+        // undo/redo
+        {
+            let undo_stack = gui_state
+                .undo_stack
+                .get_or_insert_with(|| UndoStack::new(engine.clone()));
+            if gui_state.undo_config.is_empty() {
+                gui_state.undo_config = undo_config(undo_stack.current());
+            }
+
+            let state = match gui_state.undo_request.take() {
+                Some(UndoRequest::Undo) => undo_stack.undo(),
+                Some(UndoRequest::Redo) => undo_stack.redo(),
+                None => None,
+            };
+            if let Some(state) = state {
+                engine = state.clone();
+                gui_state.undo_config = undo_config(&engine);
+            }
+        }
+
+        // name, author and description
+        {
+            let title = window_title(engine.name.as_deref());
+            if title != gui_state.window_title {
+                display.gl_window().window().set_title(&title);
+                gui_state.window_title = title;
+            }
+
+            for (i, (label, text)) in IntoIterator::into_iter([
+                ("Name", &mut engine.name),
+                ("Author", &mut engine.author),
+                ("Description", &mut engine.description),
+            ])
+            .enumerate()
+            {
+                let text_box = widget::TextBox::new(text.as_deref().unwrap_or_default())
+                    .font_size(LABEL_FONT_SIZE)
+                    .left_justify()
+                    .w(BUTTON_WIDTH - ENGINE_INFO_LABEL_WIDTH)
+                    .h(BUTTON_LINE_SIZE);
+                let text_box = if i == 0 {
+                    text_box
+                        .down_from(ids.oscilloscope_background, DOWN_SPACE + 2.0)
+                        .align_right_of(ids.oscilloscope_background)
+                } else {
+                    text_box
+                        .down_from(ids.engine_info[i - 1], DOWN_SPACE)
+                        .align_right_of(ids.engine_info[i - 1])
+                };
+
+                for event in text_box.set(ids.engine_info[i], ui) {
+                    if let widget::text_box::Event::Update(new) = event {
+                        *text = Some(new).filter(|new| !new.is_empty());
+                    }
+                }
+
+                widget::Text::new(label)
+                    .font_size(LABEL_FONT_SIZE)
+                    .left_from(ids.engine_info[i], 0.0)
+                    .w(ENGINE_INFO_LABEL_WIDTH)
+                    .set(ids.engine_info_labels[i], ui);
+            }
+        }
+
+        {
+            let preset_names = PRESETS
+                .iter()
+                .map(|(name, _)| format!("Preset: {}", name))
+                .collect::<Vec<String>>();
+
+            if let Some(selected) = widget::DropDownList::new(&preset_names, gui_state.preset)
+                .label("Load preset")
+                .label_font_size(LABEL_FONT_SIZE)
+                .down_from(ids.engine_info[2], DOWN_SPACE)
+                .align_left_of(ids.oscilloscope_background)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.preset_dropdown, ui)
+            {
+                let (name, _) = PRESETS[selected];
+
+                let recording = recorder.is_some();
+                let confirmed = !recording
+                    || native_dialog::MessageDialog::new()
+                        .set_type(native_dialog::MessageType::Warning)
+                        .set_title("Load preset")
+                        .set_text(&format!(
+                            "A recording is running, load the preset \"{}\" anyway?",
+                            name
+                        ))
+                        .show_confirm()
+                        .unwrap_or(false);
+
+                if confirmed {
+                    match load_preset(name, sample_rate) {
+                        Ok(new_engine) => {
+                            println!("Successfully loaded preset \"{}\"", name);
+                            gui_state.preset = Some(selected);
+                            gui_state.preset_dir_active = None;
+                            // the last loaded config would replace the preset when it changes
+                            gui_state.config_watcher = None;
+                            if gui_state.take_morph_on_load() {
+                                morph_target = Some(new_engine);
+                            } else {
+                                engine = new_engine;
+                                engine_replaced = true;
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            gui_state.load_error = Some(e);
+                        }
+                    }
+                }
+            }
+        }
+
+        {
+            let (mut button_label, remove_recorder) = match &recorder {
+                None => ("Start recording".to_string(), false),
+                Some(recorder) => {
+                    if recorder.running && recorder.preroll > 0 {
+                        ui.needs_redraw();
+                        (
+                            format!(
+                                "Recording in {:.1} sec (click to cancel)",
+                                recorder.preroll as f32 / sample_rate as f32
+                            ),
+                            false,
+                        )
+                    } else if recorder.running {
+                        ui.needs_redraw();
+                        (
+                            format!(
+                                "Stop recording [{:.3} sec recorded]",
+                                recorder.len as f32 / sample_rate as f32
+                            ),
+                            false,
+                        )
+                    } else {
+                        if let Some(e) = &recorder.error {
+                            gui_state.recording_error = Some(e.to_string());
+                        }
+                        ("Start recording".to_string(), true)
+                    }
+                }
+            };
+
+            if let (Some(e), None) = (&gui_state.recording_error, &recorder) {
+                button_label.push_str(&format!("   !!Recording failed!! ({})", e));
+            }
+
+            if recording_currently_clipping {
+                button_label.push_str("   !!Recording clipping!! (decrease master volume)");
+            }
+
+            if remove_recorder {
+                generator.apply(|generator| generator.recorder = None);
+            }
+
+            for _press in widget::Button::new()
+                .label(button_label.as_str())
+                .down_from(ids.preset_dropdown, DOWN_SPACE)
+                .align_left_of(ids.preset_dropdown)
+                .w(BUTTON_WIDTH
+                    - SAMPLE_DEPTH_WIDTH
+                    - MULTICHANNEL_WIDTH
+                    - PREROLL_WIDTH
+                    - NORMALIZE_WIDTH
+                    - DOWN_SPACE * 4.0)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.record_button, ui)
+            {
+                if recorder.is_some() {
+                    generator.apply(|generator| {
+                        if generator.recorder_preroll > 0 {
+                            // cancelled during the pre-roll, nothing has been recorded yet
+                            generator.recorder_preroll = 0;
+                            if let Some(mut recorder) = generator.recorder.take() {
+                                recorder.set_metadata(None);
+                                // the writer thread is joined off the generator thread
+                                std::thread::spawn(move || {
+                                    recorder.stop_wait();
+                                    let _ = std::fs::remove_file(recorder.file());
+                                    println!("Cancelled recording");
+                                });
+                            }
+                        } else if let Some(recorder) = &mut generator.recorder {
+                            recorder.stop();
+                        }
+                    });
+                } else {
+                    let rec_name = recording_name();
+
+                    let mut dialog = native_dialog::FileDialog::new()
+                        .set_filename(&rec_name)
+                        .add_filter("MONO Wave Audio file", &["wav"]);
+                    #[cfg(feature = "ogg")]
+                    {
+                        dialog = dialog.add_filter("MONO Ogg Vorbis Audio file", &["ogg"]);
+                    }
+
+                    if let Some(recording_save_path) = &gui_state.recording_save_path {
+                        dialog = dialog.set_location(recording_save_path);
+                    }
+
+                    let save_path = dialog.show_save_single_file().unwrap_or_else(|e| {
+                        eprintln!("Failed to open file save dialog: {}", e);
+                        gui_state.recording_error = Some(format!("File dialog failed: {}", e));
+                        None
+                    });
+
+                    if let Some(save_path) = save_path {
+                        gui_state.recording_save_path = save_path.parent().map(|p| p.to_owned());
+                        // the sample depth only applies to WAV files
+                        let format = OutputFormat::from_extension(
+                            &save_path,
+                            gui_state.recording_sample_depth,
+                        );
+                        let recorder = if gui_state.recording_multichannel {
+                            Recorder::new_multichannel(save_path, sample_rate, format, 3)
+                        } else {
+                            Recorder::new(save_path, sample_rate, format)
+                        };
+
+                        let metadata = gui_state.recording_metadata;
+                        let preroll = seconds_to_samples(gui_state.recording_preroll, sample_rate);
+                        match recorder {
+                            Ok(mut recorder) => {
+                                gui_state.recording_error = None;
+                                // the recording is kept in memory until it is stopped
+                                if gui_state.recording_normalize {
+                                    recorder.set_normalization(Some(Normalization::new(
+                                        NormalizationTarget::Peak(
+                                            NormalizationTarget::DEFAULT_PEAK,
+                                        ),
+                                    )));
+                                }
+                                generator.apply(move |generator| {
+                                    recorder.set_info(&generator.engine);
+                                    if metadata {
+                                        recorder.set_metadata(Some(generator.engine.clone()));
+                                    }
+                                    generator.recorder_preroll = preroll;
+                                    generator.recorder = Some(recorder);
+                                });
+                            }
+                            Err(e) => {
+                                eprintln!("Failed to start recording: {}", e);
+                                gui_state.recording_error = Some(e.to_string());
+                            }
+                        }
+                    } else {
+                        println!("Aborted recording");
+                    }
+                }
+            }
+
+            let sample_depth_names = SampleDepth::ALL
+                .iter()
+                .map(|depth| depth.name().to_string())
+                .collect::<Vec<String>>();
+            let selected = SampleDepth::ALL
+                .iter()
+                .position(|depth| *depth == gui_state.recording_sample_depth);
+
+            if let Some(selected) = widget::DropDownList::new(&sample_depth_names, selected)
+                .label_font_size(LABEL_FONT_SIZE)
+                .right(DOWN_SPACE)
+                .w(SAMPLE_DEPTH_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.record_sample_depth, ui)
+            {
+                gui_state.recording_sample_depth = SampleDepth::ALL[selected];
+            }
+
+            // tracks are in the order intake, engine vibrations, exhaust
+            for multichannel in widget::Toggle::new(gui_state.recording_multichannel)
+                .label(if gui_state.recording_multichannel {
+                    "3 tracks"
+                } else {
+                    "Mono mix"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .right(DOWN_SPACE)
+                .w(MULTICHANNEL_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.record_multichannel, ui)
+            {
+                gui_state.recording_multichannel = multichannel;
+            }
+
+            for normalize in widget::Toggle::new(gui_state.recording_normalize)
+                .label(if gui_state.recording_normalize {
+                    "Peak normalized"
+                } else {
+                    "Unnormalized"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .right(DOWN_SPACE)
+                .w(NORMALIZE_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.record_normalize, ui)
+            {
+                gui_state.recording_normalize = normalize;
+            }
+
+            let prev_val = gui_state.recording_preroll;
+            if let Some(value) = widget::Slider::new(prev_val, 0.0, MAX_RECORDING_PREROLL)
+                .label(format!("Pre-roll {:.1} sec", prev_val).as_str())
+                .label_font_size(LABEL_FONT_SIZE)
+                .right(DOWN_SPACE)
+                .w(PREROLL_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.record_preroll, ui)
+            {
+                gui_state.recording_preroll = (value * 10.0).round() / 10.0;
+            }
+        }
+
+        {
+            let label = match &gui_state.config_load_error {
+                Some(e) => format!("Load…   !!{}!!", e),
+                None => "Load…".to_string(),
+            };
+
+            for _press in widget::Button::new()
+                .label(label.as_str())
+                .down_from(ids.record_button, DOWN_SPACE + 2.0)
+                .w(BUTTON_WIDTH)
                 .h(BUTTON_LINE_SIZE)
                 .set(ids.file_chooser_button, ui)
             {
                 let mut dialog = native_dialog::FileDialog::new()
-                    .add_filter("Engine sound configuration files", &["esc", "es"])
+                    .add_filter(
+                        "Engine sound configuration files",
+                        &["esc", "es", "ron", "json", "toml"],
+                    )
                     .add_filter("All files", &["*"]);
 
                 if let Some(config_load_path) = &gui_state.config_load_path {
                     dialog = dialog.set_location(config_load_path);
                 }
 
-                let load_file_path = dialog.show_open_single_file().unwrap();
+                let load_file_path = dialog.show_open_single_file().unwrap_or_else(|e| {
+                    eprintln!("Failed to open file loading dialog: {}", e);
+                    gui_state.config_load_error = Some(format!("File dialog failed: {}", e));
+                    None
+                });
 
                 if let Some(load_file_path) = load_file_path {
                     gui_state.config_load_path = load_file_path.parent().map(|p| p.to_owned());
 
                     let string_path = load_file_path.display().to_string();
 
-                    match crate::load_engine(
-                        &string_path,
-                        sample_rate,
-                        string_path.ends_with("json"),
-                    ) {
+                    match enginesound::load_engine(&string_path, sample_rate, None) {
                         Ok(new_engine) => {
                             println!("Successfully loaded engine config \"{}\"", &string_path);
-                            generator.engine = new_engine;
+                            gui_state.config_load_error = None;
+                            gui_state.watch_config(&string_path, sample_rate);
+                            if gui_state.take_morph_on_load() {
+                                morph_target = Some(new_engine);
+                            } else {
+                                engine = new_engine;
+                                engine_replaced = true;
+                            }
                         }
                         Err(e) => {
                             eprintln!("Failed to load engine config \"{}\": {}", &string_path, e);
+                            gui_state.config_load_error = Some(e);
                         }
                     }
                 } else {
@@ -402,11 +1866,484 @@ pub fn gui(
             }
         }
 
+        {
+            let label = match morph_progress {
+                Some(progress) => {
+                    ui.needs_redraw();
+                    format!("Morphing.. {:.0}%", progress * 100.0)
+                }
+                None if gui_state.morph_on_load => format!(
+                    "Morphing to the next dropped/opened config over {} sec (click to cancel)",
+                    MORPH_TIME
+                ),
+                None => "Morph to loaded config".to_string(),
+            };
+
+            for _press in widget::Button::new()
+                .label(label.as_str())
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.morph_button, ui)
+            {
+                gui_state.morph_on_load = !gui_state.morph_on_load;
+            }
+        }
+
+        // A/B comparison, edits always change the running slot
+        {
+            let (active, other) = if gui_state.ab_b_active {
+                ("B", "A")
+            } else {
+                ("A", "B")
+            };
+
+            for _press in widget::Button::new()
+                .label(format!("Store to {}", other).as_str())
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.ab_store_button, ui)
+            {
+                gui_state.ab_stored = Some(engine.clone());
+            }
+
+            let label = if gui_state.ab_stored.is_some() {
+                format!("A/B: {} active (click to switch to {})", active, other)
+            } else {
+                format!("A/B: {} active (store to {} first)", active, other)
+            };
+
+            for _press in widget::Button::new()
+                .label(label.as_str())
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.ab_toggle, ui)
+            {
+                if let Some(stored) = gui_state.ab_stored.as_mut() {
+                    // the running engine is kept in the slot which is switched away from
+                    std::mem::swap(stored, &mut engine);
+                    engine_replaced = true;
+                    gui_state.ab_b_active = !gui_state.ab_b_active;
+                }
+            }
+        }
+
+        {
+            let label = if gui_state.show_muffler_response {
+                "Hide muffler response"
+            } else {
+                "Show muffler response"
+            };
+
+            for _press in widget::Button::new()
+                .label(label)
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.muffler_response_button, ui)
+            {
+                gui_state.show_muffler_response = !gui_state.show_muffler_response;
+            }
+        }
+
+        {
+            let label = if gui_state.show_harmonics {
+                "Hide firing frequency harmonics"
+            } else {
+                "Show firing frequency harmonics"
+            };
+
+            for _press in widget::Button::new()
+                .label(label)
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.harmonics_button, ui)
+            {
+                gui_state.show_harmonics = !gui_state.show_harmonics;
+            }
+        }
+
+        {
+            let label = if gui_state.spectrogram_export.is_some() {
+                "Exporting spectrogram..".to_string()
+            } else {
+                let source = if gui_state.recording_lines.is_empty() {
+                    ""
+                } else {
+                    " of the recording"
+                };
+
+                match &gui_state.spectrogram_error {
+                    Some(e) => format!("Export Spectrogram{}   !!{}!!", source, e),
+                    None => format!("Export Spectrogram{}", source),
+                }
+            };
+
+            for _press in widget::Button::new()
+                .label(label.as_str())
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.spectrogram_button, ui)
+            {
+                if gui_state.spectrogram_export.is_none() {
+                    // next to the last recording
+                    let path = match &gui_state.recording_save_path {
+                        Some(dir) => dir.join(spectrogram_name()),
+                        None => PathBuf::from(spectrogram_name()),
+                    };
+
+                    gui_state.spectrogram_export =
+                        Some(gui_state.spectrogram().save_in_background(path));
+                }
+            }
+
+            {
+                let prev_val = gui_state.waterfall_height as f32;
+                if let Some(value) = widget::Slider::new(
+                    prev_val,
+                    MIN_WATERFALL_HEIGHT as f32,
+                    MAX_WATERFALL_HEIGHT as f32,
+                )
+                .label(
+                    format!(
+                        "Waterfall history {} lines ({:.1} sec)",
+                        prev_val,
+                        prev_val * gui_state.fft_size() as f32 / sample_rate as f32
+                    )
+                    .as_str(),
+                )
+                .label_font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.waterfall_height_slider, ui)
+                {
+                    gui_state.set_waterfall_height(value.round() as usize);
+                }
+            }
+
+            for _press in widget::Button::new()
+                .label(if gui_state.waterfall_paused {
+                    "Resume waterfall"
+                } else {
+                    "Pause waterfall"
+                })
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.waterfall_pause_button, ui)
+            {
+                gui_state.waterfall_paused = !gui_state.waterfall_paused;
+            }
+
+            {
+                let prev_val = gui_state.waterfall_gain_db;
+                if let Some(value) =
+                    widget::Slider::new(prev_val, MIN_WATERFALL_GAIN_DB, MAX_WATERFALL_GAIN_DB)
+                        .label(format!("Waterfall gain {:.1} dB", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .down(DOWN_SPACE)
+                        .w(BUTTON_WIDTH)
+                        .h(BUTTON_LINE_SIZE)
+                        .set(ids.waterfall_gain_slider, ui)
+                {
+                    gui_state.waterfall_gain_db = value;
+                }
+            }
+
+            {
+                let colormap_names = Colormap::ALL
+                    .iter()
+                    .map(|colormap| format!("Colors: {}", colormap.name()))
+                    .collect::<Vec<String>>();
+                let selected = Colormap::ALL
+                    .iter()
+                    .position(|colormap| *colormap == gui_state.waterfall_colormap);
+
+                if let Some(selected) = widget::DropDownList::new(&colormap_names, selected)
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .down(DOWN_SPACE)
+                    .w(BUTTON_WIDTH)
+                    .h(BUTTON_LINE_SIZE)
+                    .set(ids.waterfall_colormap, ui)
+                {
+                    gui_state.waterfall_colormap = Colormap::ALL[selected];
+                }
+            }
+
+            if let Some(window_function) = &gui_state.fft_window_function {
+                let window_names = WindowFunction::ALL
+                    .iter()
+                    .map(|window| format!("Window: {}", window.name()))
+                    .collect::<Vec<String>>();
+                let current = *window_function.lock().unwrap();
+                let selected = WindowFunction::ALL
+                    .iter()
+                    .position(|window| *window == current);
+
+                if let Some(selected) = widget::DropDownList::new(&window_names, selected)
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .down(DOWN_SPACE)
+                    .w(BUTTON_WIDTH)
+                    .h(BUTTON_LINE_SIZE)
+                    .set(ids.fft_window, ui)
+                {
+                    *window_function.lock().unwrap() = WindowFunction::ALL[selected];
+                }
+            }
+
+            {
+                let prev_val = gui_state.oscilloscope.zoom;
+                if let Some(value) =
+                    widget::Slider::new(prev_val, MIN_OSCILLOSCOPE_ZOOM, MAX_OSCILLOSCOPE_ZOOM)
+                        .label(format!("Oscilloscope zoom {:.1}x", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .down(DOWN_SPACE)
+                        .w(BUTTON_WIDTH)
+                        .h(BUTTON_LINE_SIZE)
+                        .skew(3.0)
+                        .set(ids.oscilloscope_zoom_slider, ui)
+                {
+                    gui_state.oscilloscope.zoom = value;
+                }
+            }
+
+            for _press in widget::Button::new()
+                .label(if gui_state.oscilloscope.triggered {
+                    "Oscilloscope: triggered on the crankshaft cycle"
+                } else {
+                    "Oscilloscope: free running"
+                })
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.oscilloscope_trigger_button, ui)
+            {
+                gui_state.oscilloscope.triggered = !gui_state.oscilloscope.triggered;
+            }
+        }
+
+        // presets panel
+        {
+            let file_name = |path: &PathBuf| {
+                path.file_stem()
+                    .map(|file_stem| file_stem.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            };
+
+            let title = match (
+                &gui_state.preset_dir_active,
+                gui_state.preset_dir_files.is_empty(),
+            ) {
+                (Some(active), _) => format!(
+                    "Presets in {} (active: {})",
+                    gui_state.preset_dir.display(),
+                    file_name(active)
+                ),
+                (None, false) => format!("Presets in {}", gui_state.preset_dir.display()),
+                (None, true) => format!("No *.esc presets in {}", gui_state.preset_dir.display()),
+            };
+
+            widget::Text::new(title.as_str())
+                .font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .set(ids.preset_dir_title, ui);
+
+            let num_files = gui_state.preset_dir_files.len();
+            let mut clicked = None;
+
+            if num_files > 0 {
+                let item_size = BUTTON_LINE_SIZE + 2.0;
+                let (mut items, scrollbar) = widget::List::flow_down(num_files)
+                    .item_size(item_size)
+                    .scrollbar_next_to()
+                    .down(DOWN_SPACE)
+                    .w(BUTTON_WIDTH)
+                    .h(item_size * num_files.min(PRESET_DIR_ROWS) as f64)
+                    .set(ids.preset_dir_list, ui);
+
+                while let Some(item) = items.next(ui) {
+                    let path = &gui_state.preset_dir_files[item.i];
+                    let active = gui_state.preset_dir_active.as_ref() == Some(path);
+                    let label = file_name(path);
+
+                    let button = widget::Button::new()
+                        .left_justify_label()
+                        .label(label.as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .color(if active {
+                            Color::Rgba(0.2, 0.6, 0.3, 1.0)
+                        } else {
+                            Color::Rgba(0.3, 0.3, 0.3, 1.0)
+                        });
+
+                    for _press in item.set(button, ui) {
+                        clicked = Some(item.i);
+                    }
+                }
+
+                if let Some(scrollbar) = scrollbar {
+                    scrollbar.set(ui);
+                }
+            }
+
+            if let Some(i) = clicked {
+                let path = gui_state.preset_dir_files[i].clone();
+                let string_path = path.display().to_string();
+
+                match enginesound::load_engine(&string_path, sample_rate, None) {
+                    Ok(new_engine) => {
+                        println!("Successfully loaded engine config \"{}\"", &string_path);
+                        gui_state.watch_config(&string_path, sample_rate);
+                        gui_state.preset_dir_active = Some(path);
+                        gui_state.preset_dir_error = None;
+                        if gui_state.take_morph_on_load() {
+                            morph_target = Some(new_engine);
+                        } else {
+                            engine = new_engine;
+                            engine_replaced = true;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to load engine config \"{}\": {}", &string_path, e);
+                        gui_state.preset_dir_error =
+                            Some(format!("Failed to load \"{}\": {}", file_name(&path), e));
+                    }
+                }
+            }
+
+            if let Some(e) = &gui_state.preset_dir_error {
+                for _click in widget::Button::new()
+                    .label(format!("{}   (click to dismiss)", e).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .label_color(Color::Rgba(1.0, 1.0, 1.0, 1.0))
+                    .color(Color::Rgba(0.8, 0.1, 0.1, 1.0))
+                    .down(DOWN_SPACE)
+                    .w(BUTTON_WIDTH)
+                    .h(BUTTON_LINE_SIZE)
+                    .set(ids.preset_dir_error, ui)
+                {
+                    gui_state.preset_dir_error = None;
+                }
+            }
+        }
+
+        if let Some(config_watcher) = &gui_state.config_watcher {
+            let file_name = config_watcher
+                .path()
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let status = match config_watcher.last_reload() {
+                Some(last_reload) => {
+                    ui.needs_redraw();
+                    format!(
+                        "Config: watching {} (last reloaded {:.1}s ago)",
+                        file_name,
+                        last_reload.elapsed().as_secs_f32()
+                    )
+                }
+                None => format!("Config: watching {}", file_name),
+            };
+
+            widget::Text::new(status.as_str())
+                .font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .set(ids.config_watch_status, ui);
+        }
+
+        // settings
+        {
+            widget::Text::new("Settings")
+                .font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .set(ids.settings_title, ui);
+
+            if let Some(device_switch) = &gui_state.audio_device_switch {
+                let device_names = std::iter::once("Audio device: default".to_string())
+                    .chain(
+                        gui_state
+                            .audio_devices
+                            .iter()
+                            .map(|name| format!("Audio device: {}", name)),
+                    )
+                    .collect::<Vec<String>>();
+                let selected = gui_state.audio_device.map_or(0, |device| device + 1);
+
+                if let Some(selected) = widget::DropDownList::new(&device_names, Some(selected))
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .down(DOWN_SPACE)
+                    .w(BUTTON_WIDTH)
+                    .h(BUTTON_LINE_SIZE)
+                    .set(ids.audio_device_dropdown, ui)
+                {
+                    // the generator thread keeps running while the stream is rebuilt on the new device
+                    gui_state.audio_device = selected.checked_sub(1);
+                    device_switch.switch(
+                        gui_state
+                            .audio_device
+                            .map(|device| gui_state.audio_devices[device].clone()),
+                    );
+                }
+            }
+        }
+
+        if let Some(osc_status) = &gui_state.osc_status {
+            widget::Text::new(osc_status.as_str())
+                .font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .set(ids.osc_status, ui);
+        }
+
+        #[cfg(feature = "midi")]
+        {
+            let label = match (&gui_state.midi, &gui_state.midi_error) {
+                (Some(_), _) => "Disconnect MIDI".to_string(),
+                (None, Some(e)) => format!("Connect MIDI   !!{}!!", e),
+                (None, None) => "Connect MIDI".to_string(),
+            };
+
+            for _press in widget::Button::new()
+                .label(label.as_str())
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.midi_button, ui)
+            {
+                // dropping the connection disconnects
+                if gui_state.midi.take().is_none() {
+                    match MidiController::new(gui_state.midi_device_name.as_str())
+                        .run(generator.clone())
+                    {
+                        Ok(connection) => {
+                            gui_state.midi = Some(connection);
+                            gui_state.midi_error = None;
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            gui_state.midi_error = Some(e);
+                        }
+                    }
+                }
+            }
+        }
+
         {
             let mut reset_sampler_label = String::from("Panic!");
 
-            if generator.waveguides_dampened {
-                reset_sampler_label.push_str("   !!Resonances dampened!! (change parameters)");
+            if dampened_waveguides > 0 {
+                reset_sampler_label.push_str(&format!(
+                    "   !!{} resonances dampened!! (change parameters)",
+                    dampened_waveguides
+                ));
             }
 
             for _press in widget::Button::new()
@@ -418,88 +2355,167 @@ pub fn gui(
                 .color(Color::Rgba(0.8, 0.1, 0.1, 1.0))
                 .set(ids.panic_button, ui)
             {
-                generator.volume = generator.volume.min(0.01);
-                generator.reset();
+                volume = volume.min(0.01);
+                generator.send(GeneratorCommand::Reset);
+            }
+
+            for _press in widget::Button::new()
+                .label("Soft reset (fades to silent waveguides without a click, keeps the volume)")
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.soft_reset_button, ui)
+            {
+                generator.apply(move |generator| {
+                    generator.soft_reset(seconds_to_samples(ENGINE_SWAP_TIME, sample_rate))
+                });
+            }
+
+            let enabled = engine.waveguide_adaptive_damping;
+            for enable in widget::Toggle::new(enabled)
+                .label(if enabled {
+                    "Adaptive waveguide damping enabled (reduces the gain of resonating waveguides)"
+                } else {
+                    "Adaptive waveguide damping disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.adaptive_damping_toggle, ui)
+            {
+                engine.waveguide_adaptive_damping = enable;
+            }
+        }
+        // randomize
+        {
+            let mut seed = None;
+
+            for _press in widget::Button::new()
+                .label("Randomize")
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.randomize_button, ui)
+            {
+                seed = Some(new_seed());
+            }
+
+            for _press in widget::Button::new()
+                .label(format!("Mutate {:.0}%", MUTATE_AMOUNT * 100.0).as_str())
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.mutate_button, ui)
+            {
+                mutate(&mut engine, new_seed(), sample_rate);
+            }
+
+            widget::Text::new("Randomize seed (press enter to randomize with this seed)")
+                .font_size(LABEL_FONT_SIZE)
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .set(ids.randomize_seed_title, ui);
+
+            let text = gui_state.randomize_seed_text.clone().unwrap_or_else(|| {
+                gui_state
+                    .randomize_seed
+                    .map(|seed| seed.to_string())
+                    .unwrap_or_default()
+            });
+
+            for event in widget::TextBox::new(&text)
+                .font_size(LABEL_FONT_SIZE)
+                .left_justify()
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.randomize_seed, ui)
+            {
+                match event {
+                    widget::text_box::Event::Update(text) => {
+                        gui_state.randomize_seed_text =
+                            Some(text.chars().filter(char::is_ascii_digit).collect());
+                    }
+                    widget::text_box::Event::Enter => {
+                        if let Some(text) = gui_state.randomize_seed_text.take() {
+                            seed = text.parse::<u64>().ok();
+                        }
+                    }
+                }
+            }
+
+            if let Some(seed) = seed {
+                randomize(&mut engine, seed, sample_rate);
+                gui_state.randomize_seed = Some(seed);
+                gui_state.randomize_seed_text = None;
             }
         }
         // save
         {
+            let label = match &gui_state.config_save_error {
+                Some(e) => format!("Save As…   !!{}!!", e),
+                None => "Save As…".to_string(),
+            };
+
             for _press in widget::Button::new()
                 .left_justify_label()
-                .label("Save")
+                .label(label.as_str())
                 .down(DOWN_SPACE)
                 .w(BUTTON_WIDTH)
                 .h(BUTTON_LINE_SIZE)
                 .set(ids.save_button, ui)
             {
-                let pretty = ron::ser::PrettyConfig::new()
-                    .with_separate_tuple_members(true)
-                    .with_enumerate_arrays(true);
-
                 let name = config_name();
 
                 let mut dialog = native_dialog::FileDialog::new()
                     .set_filename(&name)
                     .add_filter("Engine sound RON file", &["esc", "ron"])
-                    .add_filter("Engine sound JSON file", &["json"]);
+                    .add_filter("Engine sound JSON file", &["json"])
+                    .add_filter("Engine sound TOML file", &["toml"]);
 
                 if let Some(config_save_path) = &gui_state.config_save_path {
                     dialog = dialog.set_location(config_save_path);
                 }
 
-                if let Some(path) = dialog
-                    .show_save_single_file()
-                    .expect("Failed to open file save dialog")
-                {
+                let save_path = dialog.show_save_single_file().unwrap_or_else(|e| {
+                    eprintln!("Failed to open file save dialog: {}", e);
+                    gui_state.config_save_error = Some(format!("File dialog failed: {}", e));
+                    None
+                });
+
+                if let Some(path) = save_path {
                     gui_state.config_save_path = path.parent().map(|p| p.to_owned());
 
-                    match path.extension() {
-                        Some(str) if str == "json" => {
-                            match serde_json::to_string_pretty(&generator.engine) {
-                                Ok(s) => match File::create(&path) {
-                                    Ok(mut file) => {
-                                        file.write_all(s.as_bytes()).unwrap();
-
-                                        println!(
-                                            "Successfully saved engine config \"{}\"",
-                                            &path.display()
-                                        );
-                                    }
-                                    Err(e) => {
-                                        eprintln!(
-                                            "Failed to create file for saving engine config: {}",
-                                            e
-                                        )
-                                    }
-                                },
-                                Err(e) => eprintln!("Failed to save engine config: {}", e),
-                            }
+                    match save_engine(&path, &engine) {
+                        Ok(()) => {
+                            println!("Successfully saved engine config \"{}\"", path.display());
+                            gui_state.config_save_error = None;
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            gui_state.config_save_error = Some(e);
                         }
-                        _ => match ron::ser::to_string_pretty(&generator.engine, pretty) {
-                            Ok(s) => match File::create(&path) {
-                                Ok(mut file) => {
-                                    file.write_all(s.as_bytes()).unwrap();
-
-                                    println!(
-                                        "Successfully saved engine config \"{}\"",
-                                        &path.display()
-                                    );
-                                }
-                                Err(e) => {
-                                    eprintln!(
-                                        "Failed to create file for saving engine config: {}",
-                                        e
-                                    )
-                                }
-                            },
-                            Err(e) => eprintln!("Failed to save engine config: {}", e),
-                        },
                     }
+
+                    // the saved file may be in the presets directory
+                    gui_state.refresh_preset_dir();
                 } else {
                     println!("Cancelled saving");
                 }
             }
 
+            for _press in widget::Button::new()
+                .left_justify_label()
+                .label("Print resonance report")
+                .down(DOWN_SPACE)
+                .w(BUTTON_WIDTH)
+                .h(BUTTON_LINE_SIZE)
+                .set(ids.analyze_button, ui)
+            {
+                print!("{}", enginesound::analysis::analyze(&engine));
+            }
+
             widget::Text::new("Mix")
                 .font_size(16)
                 .down(DOWN_SPACE)
@@ -508,16 +2524,82 @@ pub fn gui(
         }
 
         {
-            let prev_val = generator.engine.rpm;
-            if let Some(value) = widget::Slider::new(prev_val, 300.0, 13000.0)
-                .label(format!("Engine RPM {:.2} ({:.1} hz)", prev_val, prev_val / 60.0).as_str())
+            // the slider shows the rpm the engine is slewing towards
+            let prev_val = target_rpm.unwrap_or(engine.rpm);
+            if let Some(value) = widget::Slider::new(prev_val, 300.0, 13000.0)
+                .label(format!("Engine RPM {:.2} ({:.1} hz)", prev_val, prev_val / 60.0).as_str())
+                .label_font_size(LABEL_FONT_SIZE)
+                .align_left()
+                .padded_w_of(ids.canvas, MARGIN)
+                .down(DOWN_SPACE)
+                .set(ids.engine_rpm_slider, ui)
+            {
+                if engine.rpm_slew_rate.is_some() {
+                    new_target_rpm = Some(value);
+                } else {
+                    engine.rpm = value;
+                }
+            }
+        }
+
+        // rpm_slew_rate
+        {
+            const DEFAULT_RATE: f32 = 5000.0;
+            let enabled = engine.rpm_slew_rate.is_some();
+            for enable in widget::Toggle::new(enabled)
+                .label(if enabled {
+                    "RPM slew limit enabled"
+                } else {
+                    "RPM slew limit disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.engine_rpm_slew_toggle, ui)
+            {
+                engine.rpm_slew_rate = if enable { Some(DEFAULT_RATE) } else { None };
+            }
+
+            if let Some(rate) = &mut engine.rpm_slew_rate {
+                const MIN: f32 = 100.0;
+                const MAX: f32 = 50000.0;
+                let prev_val = *rate;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("RPM slew rate {:.0} RPM/sec", prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .skew(3.0)
+                    .set(ids.engine_rpm_slew_rate, ui)
+                {
+                    *rate = value;
+                }
+            }
+        }
+
+        {
+            let prev_val = engine.throttle;
+            if let Some(value) = widget::Slider::new(prev_val, 0.0, 1.0)
+                .label(
+                    format!(
+                        "Throttle {:.0}%{}",
+                        prev_val * 100.0,
+                        if prev_val < ENGINE_BRAKING_THROTTLE {
+                            " (engine braking)"
+                        } else {
+                            ""
+                        }
+                    )
+                    .as_str(),
+                )
                 .label_font_size(LABEL_FONT_SIZE)
                 .align_left()
                 .padded_w_of(ids.canvas, MARGIN)
                 .down(DOWN_SPACE)
-                .set(ids.engine_rpm_slider, ui)
+                .set(ids.engine_throttle_slider, ui)
             {
-                generator.engine.rpm = value;
+                engine.throttle = value;
             }
         }
 
@@ -527,7 +2609,7 @@ pub fn gui(
 
         {
             {
-                let prev_val = generator.volume;
+                let prev_val = volume;
                 if let Some(value) = widget::Slider::new(prev_val, 0.0, 3.0)
                     .label(format!("Master volume {:.0}%", prev_val * 100.0).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
@@ -535,13 +2617,69 @@ pub fn gui(
                     .down(DOWN_SPACE)
                     .set(ids.engine_master_volume_slider, ui)
                 {
-                    generator.volume = value;
+                    volume = value;
                 }
             }
 
             {
-                let prev_val = generator.engine.intake_volume;
+                let prev_val = stereo_width;
                 if let Some(value) = widget::Slider::new(prev_val, 0.0, 1.0)
+                    .label(format!("Stereo width {:.0}%", prev_val * 100.0).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_stereo_width_slider, ui)
+                {
+                    stereo_width = value;
+                }
+            }
+
+            // output_highpass_filter
+            {
+                const DEFAULT_FREQ: f32 = 30.0;
+                let enabled = engine.output_highpass_filter.is_some();
+                for enable in widget::Toggle::new(enabled)
+                    .label(if enabled {
+                        "Output Highpass-Filter enabled"
+                    } else {
+                        "Output Highpass-Filter disabled"
+                    })
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .h(BUTTON_LINE_SIZE)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_output_highpass_toggle, ui)
+                {
+                    engine.output_highpass_filter = if enable {
+                        Some(HighPassFilter::new(DEFAULT_FREQ, sample_rate))
+                    } else {
+                        None
+                    };
+                }
+            }
+
+            // output_highpass_filter_freq
+            if let Some(hpf) = &mut engine.output_highpass_filter {
+                const MIN: f32 = 5.0;
+                const MAX: f32 = 500.0;
+                let prev_val = hpf.get_freq();
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("Output Highpass-Filter frequency {:.2}hz", prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .skew(3.0)
+                    .set(ids.engine_output_highpass_freq, ui)
+                {
+                    if let Some(new) = hpf.get_changed(value, sample_rate) {
+                        *hpf = new;
+                    }
+                }
+            }
+
+            {
+                let prev_val = engine.intake_volume;
+                if let Some(value) = widget::Slider::new(prev_val, MIX_VOLUME.min, MIX_VOLUME.max)
                     .label(format!("Intake volume {:.0}%", prev_val * 100.0).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
@@ -549,26 +2687,26 @@ pub fn gui(
                     .set(ids.engine_intake_volume_slider, ui)
                 {
                     let mut dif = value - prev_val;
-                    generator.engine.intake_volume = value;
-                    let v1 = generator.engine.exhaust_volume;
-                    let v2 = generator.engine.engine_vibrations_volume;
+                    engine.intake_volume = value;
+                    let v1 = engine.exhaust_volume;
+                    let v2 = engine.engine_vibrations_volume;
                     if v1 < v2 {
                         let vv1 = v1.min(dif * 0.5);
                         dif -= vv1;
-                        generator.engine.exhaust_volume = (v1 - vv1).min(1.0).max(0.0);
-                        generator.engine.engine_vibrations_volume = (v2 - dif).min(1.0).max(0.0);
+                        engine.exhaust_volume = (v1 - vv1).clamp(0.0, 1.0);
+                        engine.engine_vibrations_volume = (v2 - dif).clamp(0.0, 1.0);
                     } else {
                         let vv2 = v2.min(dif * 0.5);
                         dif -= vv2;
-                        generator.engine.engine_vibrations_volume = (v2 - vv2).min(1.0).max(0.0);
-                        generator.engine.exhaust_volume = (v1 - dif).min(1.0).max(0.0);
+                        engine.engine_vibrations_volume = (v2 - vv2).clamp(0.0, 1.0);
+                        engine.exhaust_volume = (v1 - dif).clamp(0.0, 1.0);
                     }
                 }
             }
 
             {
-                let prev_val = generator.engine.exhaust_volume;
-                if let Some(value) = widget::Slider::new(prev_val, 0.0, 1.0)
+                let prev_val = engine.exhaust_volume;
+                if let Some(value) = widget::Slider::new(prev_val, MIX_VOLUME.min, MIX_VOLUME.max)
                     .label(format!("Exhaust volume {:.0}%", prev_val * 100.0).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
@@ -576,26 +2714,26 @@ pub fn gui(
                     .set(ids.engine_exhaust_volume_slider, ui)
                 {
                     let mut dif = value - prev_val;
-                    generator.engine.exhaust_volume = value;
-                    let v1 = generator.engine.intake_volume;
-                    let v2 = generator.engine.engine_vibrations_volume;
+                    engine.exhaust_volume = value;
+                    let v1 = engine.intake_volume;
+                    let v2 = engine.engine_vibrations_volume;
                     if v1 < v2 {
                         let vv1 = v1.min(dif * 0.5);
                         dif -= vv1;
-                        generator.engine.intake_volume = (v1 - vv1).min(1.0).max(0.0);
-                        generator.engine.engine_vibrations_volume = (v2 - dif).min(1.0).max(0.0);
+                        engine.intake_volume = (v1 - vv1).clamp(0.0, 1.0);
+                        engine.engine_vibrations_volume = (v2 - dif).clamp(0.0, 1.0);
                     } else {
                         let vv2 = v2.min(dif * 0.5);
                         dif -= vv2;
-                        generator.engine.engine_vibrations_volume = (v2 - vv2).min(1.0).max(0.0);
-                        generator.engine.intake_volume = (v1 - dif).min(1.0).max(0.0);
+                        engine.engine_vibrations_volume = (v2 - vv2).clamp(0.0, 1.0);
+                        engine.intake_volume = (v1 - dif).clamp(0.0, 1.0);
                     }
                 }
             }
 
             {
-                let prev_val = generator.engine.engine_vibrations_volume;
-                if let Some(value) = widget::Slider::new(prev_val, 0.0, 1.0)
+                let prev_val = engine.engine_vibrations_volume;
+                if let Some(value) = widget::Slider::new(prev_val, MIX_VOLUME.min, MIX_VOLUME.max)
                     .label(format!("Engine vibrations volume {:.0}%", prev_val * 100.0).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
@@ -603,32 +2741,32 @@ pub fn gui(
                     .set(ids.engine_engine_vibrations_volume_slider, ui)
                 {
                     let mut dif = value - prev_val;
-                    generator.engine.engine_vibrations_volume = value;
-                    let v1 = generator.engine.exhaust_volume;
-                    let v2 = generator.engine.intake_volume;
+                    engine.engine_vibrations_volume = value;
+                    let v1 = engine.exhaust_volume;
+                    let v2 = engine.intake_volume;
                     if v1 < v2 {
                         let vv1 = v1.min(dif * 0.5);
                         dif -= vv1;
-                        generator.engine.exhaust_volume = (v1 - vv1).min(1.0).max(0.0);
-                        generator.engine.intake_volume = (v2 - dif).min(1.0).max(0.0);
+                        engine.exhaust_volume = (v1 - vv1).clamp(0.0, 1.0);
+                        engine.intake_volume = (v2 - dif).clamp(0.0, 1.0);
                     } else {
                         let vv2 = v2.min(dif * 0.5);
                         dif -= vv2;
-                        generator.engine.intake_volume = (v2 - vv2).min(1.0).max(0.0);
-                        generator.engine.exhaust_volume = (v1 - dif).min(1.0).max(0.0);
+                        engine.intake_volume = (v2 - vv2).clamp(0.0, 1.0);
+                        engine.exhaust_volume = (v1 - dif).clamp(0.0, 1.0);
                     }
                 }
             }
 
             // normalize again to mitigate any floating point error
             {
-                let iv = generator.engine.intake_volume;
-                let ev = generator.engine.exhaust_volume;
-                let evv = generator.engine.engine_vibrations_volume;
+                let iv = engine.intake_volume;
+                let ev = engine.exhaust_volume;
+                let evv = engine.engine_vibrations_volume;
                 let sum = iv + ev + evv;
-                generator.engine.intake_volume = iv / sum;
-                generator.engine.exhaust_volume = ev / sum;
-                generator.engine.engine_vibrations_volume = evv / sum;
+                engine.intake_volume = iv / sum;
+                engine.exhaust_volume = ev / sum;
+                engine.engine_vibrations_volume = evv / sum;
             }
         }
 
@@ -641,10 +2779,9 @@ pub fn gui(
         {
             // engine_vibrations_lowpassfilter_freq
             {
-                const MIN: f32 = 10.0;
-                let max = sample_rate as f32 * 0.5;
-                let prev_val = generator.engine.engine_vibration_filter.get_freq();
-                if let Some(value) = widget::Slider::new(prev_val, MIN, max)
+                let (min, max) = FILTER_FREQ.bounds(sample_rate);
+                let prev_val = engine.engine_vibration_filter.get_freq();
+                if let Some(value) = widget::Slider::new(prev_val, min, max)
                     .label(
                         format!(
                             "Engine vibrations Lowpass-Filter Frequency {:.2}hz",
@@ -655,24 +2792,23 @@ pub fn gui(
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
                     .down(DOWN_SPACE)
-                    .skew(10.0)
+                    .skew(FILTER_FREQ.skew)
                     .set(ids.engine_vibrations_lp_filter_freq, ui)
                 {
-                    let new = generator
-                        .engine
+                    let new = engine
                         .engine_vibration_filter
                         .get_changed(value, sample_rate);
 
                     if let Some(new) = new {
-                        generator.engine.engine_vibration_filter = new;
+                        engine.engine_vibration_filter = new;
                     }
                 }
             }
             // intake_noise_factor
             {
-                const MIN: f32 = 0.0;
-                const MAX: f32 = 3.0;
-                let prev_val = generator.engine.intake_noise_factor;
+                const MIN: f32 = INTAKE_NOISE_FACTOR.min;
+                const MAX: f32 = INTAKE_NOISE_FACTOR.max;
+                let prev_val = engine.intake_noise_factor;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(format!("Intake noise volume {:.2}", prev_val).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
@@ -680,107 +2816,638 @@ pub fn gui(
                     .down(DOWN_SPACE)
                     .set(ids.engine_intake_noise_factor, ui)
                 {
-                    generator.engine.intake_noise_factor = value;
+                    engine.intake_noise_factor = value;
                 }
             }
             // intake_noise_lowpassfilter_freq
             {
-                const MIN: f32 = 10.0;
-                let max = sample_rate as f32 * 0.5;
-                let prev_val = generator.engine.intake_noise_lp.get_freq();
-                if let Some(value) = widget::Slider::new(prev_val, MIN, max)
+                let (min, max) = FILTER_FREQ.bounds(sample_rate);
+                let prev_val = engine.intake_noise_lp.get_freq();
+                if let Some(value) = widget::Slider::new(prev_val, min, max)
                     .label(
                         format!("Intake noise Lowpass-Filter Frequency {:.2}hz", prev_val).as_str(),
                     )
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
                     .down(DOWN_SPACE)
-                    .skew(10.0)
+                    .skew(FILTER_FREQ.skew)
                     .set(ids.engine_intake_lp_filter_freq, ui)
                 {
-                    let new = generator
-                        .engine
-                        .intake_noise_lp
+                    let new = engine.intake_noise_lp.get_changed(value, sample_rate);
+
+                    if let Some(new) = new {
+                        engine.intake_noise_lp = new;
+                    }
+                }
+            }
+            // intake_resonator
+            {
+                const DEFAULT_FREQ: f32 = 150.0;
+                const DEFAULT_Q: f32 = 4.0;
+                let enabled = engine.intake_resonator.is_some();
+                for enable in widget::Toggle::new(enabled)
+                    .label(if enabled {
+                        "Intake airbox resonance enabled"
+                    } else {
+                        "Intake airbox resonance disabled"
+                    })
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .h(BUTTON_LINE_SIZE)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_intake_resonator_toggle, ui)
+                {
+                    engine.intake_resonator = if enable {
+                        Some(Resonator::new(DEFAULT_FREQ, DEFAULT_Q))
+                    } else {
+                        None
+                    };
+                }
+            }
+            if let Some(resonator) = &mut engine.intake_resonator {
+                // intake_resonator_freq
+                {
+                    const MIN: f32 = 20.0;
+                    const MAX: f32 = 1000.0;
+                    let prev_val = resonator.freq;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(
+                            format!("Intake airbox resonant frequency {:.2}hz", prev_val).as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(3.0)
+                        .set(ids.engine_intake_resonator_freq, ui)
+                    {
+                        resonator.freq = value;
+                    }
+                }
+                // intake_resonator_q
+                {
+                    const MIN: f32 = 0.5;
+                    const MAX: f32 = 20.0;
+                    let prev_val = resonator.q;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(format!("Intake airbox resonance Q {:.2}", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(2.0)
+                        .set(ids.engine_intake_resonator_q, ui)
+                    {
+                        resonator.q = value;
+                    }
+                }
+            }
+            // egr_factor
+            {
+                const MIN: f32 = EGR_FACTOR.min;
+                const MAX: f32 = EGR_FACTOR.max;
+                let prev_val = engine.egr_factor;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("Exhaust gas recirculation {:.0}%", prev_val * 100.0).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_egr_factor, ui)
+                {
+                    engine.egr_factor = value;
+                }
+            }
+            if engine.egr_factor > 0.0 {
+                // egr_alpha
+                {
+                    const MIN: f32 = REFLECTIVITY.min;
+                    const MAX: f32 = REFLECTIVITY.max;
+                    let prev_val = engine.egr_delay.alpha;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(
+                            format!("EGR pipe exhaust-side reflectivity {:.2}", prev_val).as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .set(ids.engine_egr_alpha, ui)
+                    {
+                        engine.egr_delay.alpha = value;
+                    }
+                }
+                // egr_beta
+                {
+                    const MIN: f32 = REFLECTIVITY.min;
+                    const MAX: f32 = REFLECTIVITY.max;
+                    let prev_val = engine.egr_delay.beta;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(
+                            format!("EGR pipe intake-side reflectivity {:.2}", prev_val).as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .set(ids.engine_egr_beta, ui)
+                    {
+                        engine.egr_delay.beta = value;
+                    }
+                }
+                // egr_length
+                {
+                    const MIN: f32 = EGR_PIPE_LENGTH.min;
+                    const MAX: f32 = EGR_PIPE_LENGTH.max;
+                    let prev_val = engine.egr_delay.delay() * SPEED_OF_SOUND / sample_rate as f32;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(
+                            format!(
+                                "EGR pipe length {:.2}m{}",
+                                prev_val,
+                                damping_indicator(&engine.egr_delay)
+                            )
+                            .as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .set(ids.engine_egr_length, ui)
+                    {
+                        let alpha = engine.egr_delay.alpha;
+                        let beta = engine.egr_delay.beta;
+
+                        if let Some(newgen) = engine.egr_delay.get_changed(
+                            distance_to_samples(value, sample_rate),
+                            alpha,
+                            beta,
+                            sample_rate,
+                        ) {
+                            engine.egr_delay = newgen;
+                        }
+                    }
+                }
+            }
+            // intake_valve_shift
+            {
+                const MIN: f32 = VALVE_SHIFT.min;
+                const MAX: f32 = VALVE_SHIFT.max;
+                let prev_val = engine.intake_valve_shift;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("Intake valve cam shift {:.2} cycles", -prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_intake_valve_shift, ui)
+                {
+                    engine.intake_valve_shift = value;
+                }
+            }
+            // exhaust_valve_shift
+            {
+                const MIN: f32 = VALVE_SHIFT.min;
+                const MAX: f32 = VALVE_SHIFT.max;
+                let prev_val = engine.exhaust_valve_shift;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("Exhaust valve cam shift {:.2} cycles", -prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_exhaust_valve_shift, ui)
+                {
+                    engine.exhaust_valve_shift = value;
+                }
+            }
+
+            // crankshaft_fluctuation
+            {
+                const MIN: f32 = CRANKSHAFT_FLUCTUATION.min;
+                const MAX: f32 = CRANKSHAFT_FLUCTUATION.max; // lower filter frequencies require more amplitude so its noticable
+                let prev_val = engine.crankshaft_fluctuation;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("Crankshaft fluctuation factor {:.2}x", prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_crankshaft_fluctuation, ui)
+                {
+                    engine.crankshaft_fluctuation = value;
+                }
+            }
+
+            // crankshaft_fluctuation_lowpassfilter_freq
+            {
+                let (min, max) = FILTER_FREQ.bounds(sample_rate);
+                let prev_val = engine.crankshaft_fluctuation_lp.get_freq();
+                if let Some(value) = widget::Slider::new(prev_val, min, max)
+                    .label(
+                        format!(
+                            "Crankshaft fluctuation noise Lowpass-Filter frequency {:.2}hz",
+                            prev_val
+                        )
+                        .as_str(),
+                    )
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .skew(FILTER_FREQ.skew)
+                    .set(ids.engine_crankshaft_fluctuation_lp_freq, ui)
+                {
+                    let new = engine
+                        .crankshaft_fluctuation_lp
                         .get_changed(value, sample_rate);
 
-                    if let Some(new) = new {
-                        generator.engine.intake_noise_lp = new;
+                    if let Some(new) = new {
+                        engine.crankshaft_fluctuation_lp = new;
+                    }
+                }
+            }
+
+            // exhaust_highpass_filter
+            {
+                const DEFAULT_FREQ: f32 = 40.0;
+                let enabled = engine.exhaust_highpass_filter.is_some();
+                for enable in widget::Toggle::new(enabled)
+                    .label(if enabled {
+                        "Exhaust Highpass-Filter enabled"
+                    } else {
+                        "Exhaust Highpass-Filter disabled"
+                    })
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .h(BUTTON_LINE_SIZE)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_exhaust_highpass_toggle, ui)
+                {
+                    engine.exhaust_highpass_filter = if enable {
+                        Some(HighPassFilter::new(DEFAULT_FREQ, sample_rate))
+                    } else {
+                        None
+                    };
+                }
+            }
+
+            // exhaust_highpass_filter_freq
+            if let Some(hpf) = &mut engine.exhaust_highpass_filter {
+                let (min, max) = FILTER_FREQ.bounds(sample_rate);
+                let prev_val = hpf.get_freq();
+                if let Some(value) = widget::Slider::new(prev_val, min, max)
+                    .label(format!("Exhaust Highpass-Filter frequency {:.2}hz", prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .skew(FILTER_FREQ.skew)
+                    .set(ids.engine_exhaust_highpass_freq, ui)
+                {
+                    if let Some(new) = hpf.get_changed(value, sample_rate) {
+                        *hpf = new;
+                    }
+                }
+            }
+
+            // noise_seed
+            {
+                widget::Text::new("Noise seed (0 = randomized, press enter to apply)")
+                    .font_size(LABEL_FONT_SIZE)
+                    .down(DOWN_SPACE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .set(ids.engine_noise_seed_title, ui);
+
+                let text = gui_state
+                    .noise_seed_text
+                    .clone()
+                    .unwrap_or_else(|| engine.noise_seed.unwrap_or(0).to_string());
+
+                for event in widget::TextBox::new(&text)
+                    .font_size(LABEL_FONT_SIZE)
+                    .left_justify()
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .h(BUTTON_LINE_SIZE)
+                    .down(DOWN_SPACE)
+                    .set(ids.engine_noise_seed, ui)
+                {
+                    match event {
+                        widget::text_box::Event::Update(text) => {
+                            gui_state.noise_seed_text =
+                                Some(text.chars().filter(char::is_ascii_digit).collect());
+                        }
+                        widget::text_box::Event::Enter => {
+                            if let Some(text) = gui_state.noise_seed_text.take() {
+                                match text.parse::<u64>() {
+                                    Ok(0) | Err(_) => engine.noise_seed = None,
+                                    Ok(seed) => {
+                                        engine.noise_seed = Some(seed);
+                                        reseed_noise = true;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        {
+            widget::Text::new("Turbo")
+                .font_size(16)
+                .down(DOWN_SPACE)
+                .w(ui.window_dim()[0] - MARGIN * 2.0)
+                .set(ids.turbo_title, ui);
+
+            let enabled = engine.turbo.is_some();
+            for enable in widget::Toggle::new(enabled)
+                .label(if enabled {
+                    "Turbo enabled"
+                } else {
+                    "Turbo disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.engine_turbo_toggle, ui)
+            {
+                engine.turbo = if enable {
+                    let mut turbo = Turbo::new(20.0, 4000.0, 0.05, 2.0, 0.3, sample_rate);
+                    if let Some(seed) = engine.noise_seed {
+                        turbo.blowoff_noise = Noise::from_seed(seed.wrapping_add(2));
+                    }
+                    Some(turbo)
+                } else {
+                    None
+                };
+            }
+
+            if let Some(turbo) = &mut engine.turbo {
+                // turbo_rpm_scale
+                {
+                    const MIN: f32 = 1.0;
+                    const MAX: f32 = 40.0;
+                    let prev_val = turbo.rpm_scale;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(format!("Turbo RPM scale {:.2}x", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .set(ids.engine_turbo_rpm_scale, ui)
+                    {
+                        turbo.rpm_scale = value;
+                    }
+                }
+
+                // turbo_whistle_freq_hz
+                {
+                    const MIN: f32 = 100.0;
+                    let max = sample_rate as f32 * 0.5;
+                    let prev_val = turbo.whistle_freq_hz;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, max)
+                        .label(
+                            format!(
+                                "Turbo whine frequency at {} turbo RPM {:.2}hz",
+                                TURBO_REFERENCE_RPM, prev_val
+                            )
+                            .as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(10.0)
+                        .set(ids.engine_turbo_whistle_freq, ui)
+                    {
+                        turbo.whistle_freq_hz = value;
+                    }
+                }
+
+                // turbo_whistle_volume
+                {
+                    const MIN: f32 = 0.0;
+                    const MAX: f32 = 1.0;
+                    let prev_val = turbo.whistle_volume;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(format!("Turbo whine volume {:.3}", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(2.0)
+                        .set(ids.engine_turbo_whistle_volume, ui)
+                    {
+                        turbo.whistle_volume = value;
+                    }
+                }
+
+                // turbo_spool_time_constant
+                {
+                    const MIN: f32 = 0.01;
+                    const MAX: f32 = 3.0;
+                    // time constant of the first-order spool filter
+                    let prev_val = 1.0 / (PI2F * turbo.spool_lp.get_freq());
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(format!("Turbo spool time constant {:.2} sec", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(2.0)
+                        .set(ids.engine_turbo_spool_time, ui)
+                    {
+                        if let Some(mut new) = turbo
+                            .spool_lp
+                            .get_changed(1.0 / (PI2F * value), sample_rate)
+                        {
+                            // keep the current turbo rpm
+                            new.last = turbo.spool_lp.last;
+                            turbo.spool_lp = new;
+                        }
                     }
                 }
-            }
-            // intake_valve_shift
-            {
-                const MIN: f32 = -0.5;
-                const MAX: f32 = 0.5;
-                let prev_val = generator.engine.intake_valve_shift;
-                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Intake valve cam shift {:.2} cycles", -prev_val).as_str())
-                    .label_font_size(LABEL_FONT_SIZE)
-                    .padded_w_of(ids.canvas, MARGIN)
-                    .down(DOWN_SPACE)
-                    .set(ids.engine_intake_valve_shift, ui)
+
+                // turbo_blowoff_volume
                 {
-                    generator.engine.intake_valve_shift = value;
+                    const MIN: f32 = 0.0;
+                    const MAX: f32 = 2.0;
+                    let prev_val = turbo.blowoff_volume;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(format!("Blow-off valve volume {:.3}", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(2.0)
+                        .set(ids.engine_turbo_blowoff_volume, ui)
+                    {
+                        turbo.blowoff_volume = value;
+                    }
                 }
             }
-            // exhaust_valve_shift
+        }
+
+        {
+            widget::Text::new("Overrun crackle")
+                .font_size(16)
+                .down(DOWN_SPACE)
+                .w(ui.window_dim()[0] - MARGIN * 2.0)
+                .set(ids.overrun_crackle_title, ui);
+
+            let enabled = engine.overrun_crackle.is_some();
+            for enable in widget::Toggle::new(enabled)
+                .label(if enabled {
+                    "Overrun crackle enabled (pops while engine braking)"
+                } else {
+                    "Overrun crackle disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.engine_overrun_crackle_toggle, ui)
             {
-                const MIN: f32 = -0.5;
-                const MAX: f32 = 0.5;
-                let prev_val = generator.engine.exhaust_valve_shift;
-                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Exhaust valve cam shift {:.2} cycles", -prev_val).as_str())
-                    .label_font_size(LABEL_FONT_SIZE)
-                    .padded_w_of(ids.canvas, MARGIN)
-                    .down(DOWN_SPACE)
-                    .set(ids.engine_exhaust_valve_shift, ui)
+                engine.overrun_crackle = if enable {
+                    let mut overrun_crackle = OverrunCrackle::new(2500.0, 0.3, 1.0, 0.004);
+                    if let Some(seed) = engine.noise_seed {
+                        overrun_crackle.noise = Noise::from_seed(seed.wrapping_add(4));
+                    }
+                    Some(overrun_crackle)
+                } else {
+                    None
+                };
+            }
+
+            if let Some(overrun_crackle) = &mut engine.overrun_crackle {
+                // overrun_crackle_probability
                 {
-                    generator.engine.exhaust_valve_shift = value;
+                    const MIN: f32 = 0.0;
+                    const MAX: f32 = 1.0;
+                    let prev_val = overrun_crackle.probability;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(
+                            format!(
+                                "Pop probability per cylinder cycle above {} RPM {:.3}",
+                                overrun_crackle.min_rpm, prev_val
+                            )
+                            .as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .set(ids.engine_overrun_crackle_probability, ui)
+                    {
+                        overrun_crackle.probability = value;
+                    }
                 }
-            }
 
-            // crankshaft_fluctuation
-            {
-                const MIN: f32 = 0.0;
-                const MAX: f32 = 2.5; // lower filter frequencies require more amplitude so its noticable
-                let prev_val = generator.engine.crankshaft_fluctuation;
-                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Crankshaft fluctuation factor {:.2}x", prev_val).as_str())
-                    .label_font_size(LABEL_FONT_SIZE)
-                    .padded_w_of(ids.canvas, MARGIN)
-                    .down(DOWN_SPACE)
-                    .set(ids.engine_crankshaft_fluctuation, ui)
+                // overrun_crackle_intensity
                 {
-                    generator.engine.crankshaft_fluctuation = value;
+                    const MIN: f32 = 0.0;
+                    const MAX: f32 = 5.0;
+                    let prev_val = overrun_crackle.intensity;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(format!("Pop intensity {:.3}", prev_val).as_str())
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(2.0)
+                        .set(ids.engine_overrun_crackle_intensity, ui)
+                    {
+                        overrun_crackle.intensity = value;
+                    }
                 }
             }
+        }
 
-            // crankshaft_fluctuation_lowpassfilter_freq
+        {
+            widget::Text::new("Equalizer")
+                .font_size(16)
+                .down(DOWN_SPACE)
+                .w(ui.window_dim()[0] - MARGIN * 2.0)
+                .set(ids.eq_title, ui);
+
+            let enabled = engine.eq.is_some();
+            for enable in widget::Toggle::new(enabled)
+                .label(if enabled {
+                    "Equalizer enabled"
+                } else {
+                    "Equalizer disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.eq_toggle, ui)
             {
-                const MIN: f32 = 10.0;
-                let max = sample_rate as f32 * 0.5;
-                let prev_val = generator.engine.crankshaft_fluctuation_lp.get_freq();
-                if let Some(value) = widget::Slider::new(prev_val, MIN, max)
-                    .label(
-                        format!(
-                            "Crankshaft fluctuation noise Lowpass-Filter frequency {:.2}hz",
-                            prev_val
-                        )
-                        .as_str(),
-                    )
-                    .label_font_size(LABEL_FONT_SIZE)
-                    .padded_w_of(ids.canvas, MARGIN)
-                    .down(DOWN_SPACE)
-                    .skew(10.0)
-                    .set(ids.engine_crankshaft_fluctuation_lp_freq, ui)
-                {
-                    let new = generator
-                        .engine
-                        .crankshaft_fluctuation_lp
-                        .get_changed(value, sample_rate);
+                engine.eq = if enable {
+                    // all bands start out flat
+                    Some(ParametricEq {
+                        bands: vec![
+                            EqBand::new(EqBandKind::LowShelf, 100.0, 0.0, 0.7),
+                            EqBand::new(EqBandKind::Peak, 500.0, 0.0, 1.0),
+                            EqBand::new(EqBandKind::Peak, 2000.0, 0.0, 1.0),
+                            EqBand::new(EqBandKind::HighShelf, 8000.0, 0.0, 0.7),
+                        ],
+                    })
+                } else {
+                    None
+                };
+            }
 
-                    if let Some(new) = new {
-                        generator.engine.crankshaft_fluctuation_lp = new;
+            if let Some(eq) = &mut engine.eq {
+                let kind_names = EqBandKind::ALL
+                    .iter()
+                    .map(|kind| kind.name().to_string())
+                    .collect::<Vec<String>>();
+
+                for (i, band) in eq.bands.iter_mut().take(MAX_EQ_BANDS).enumerate() {
+                    let selected = EqBandKind::ALL.iter().position(|kind| *kind == band.kind);
+                    if let Some(selected) = widget::DropDownList::new(&kind_names, selected)
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .h(BUTTON_LINE_SIZE)
+                        .down(DOWN_SPACE)
+                        .set(ids.eq_band_kind[i], ui)
+                    {
+                        band.kind = EqBandKind::ALL[selected];
+                    }
+
+                    // freq
+                    {
+                        const MIN: f32 = 20.0;
+                        let max = sample_rate as f32 * 0.49;
+                        let prev_val = band.freq;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, max)
+                            .label(format!("{} / Frequency {:.1}hz", i + 1, prev_val).as_str())
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .skew(10.0)
+                            .set(ids.eq_band_freq[i], ui)
+                        {
+                            band.freq = value;
+                        }
+                    }
+
+                    // gain_db
+                    if band.kind != EqBandKind::Notch {
+                        const MIN: f32 = -24.0;
+                        const MAX: f32 = 24.0;
+                        let prev_val = band.gain_db;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                            .label(format!("{} / Gain {:.1}dB", i + 1, prev_val).as_str())
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .set(ids.eq_band_gain[i], ui)
+                        {
+                            band.gain_db = value;
+                        }
+                    }
+
+                    // q
+                    {
+                        const MIN: f32 = 0.1;
+                        const MAX: f32 = 10.0;
+                        let prev_val = band.q;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                            .label(format!("{} / Q {:.2}", i + 1, prev_val).as_str())
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .skew(2.0)
+                            .set(ids.eq_band_q[i], ui)
+                        {
+                            band.q = value;
+                        }
                     }
                 }
             }
@@ -795,9 +3462,9 @@ pub fn gui(
 
             // engine_muffler_straight_pipe_alpha
             {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
-                let prev_val = generator.engine.muffler.straight_pipe.alpha;
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
+                let prev_val = engine.muffler.straight_pipe.alpha;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!("Straight Pipe extractor-side reflectivity {:.2}", prev_val)
@@ -808,14 +3475,14 @@ pub fn gui(
                     .down(DOWN_SPACE)
                     .set(ids.muffler_straight_pipe_alpha, ui)
                 {
-                    generator.engine.muffler.straight_pipe.alpha = value;
+                    engine.muffler.straight_pipe.alpha = value;
                 }
             }
             // engine_muffler_straight_pipe_beta
             {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
-                let prev_val = generator.engine.muffler.straight_pipe.beta;
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
+                let prev_val = engine.muffler.straight_pipe.beta;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!("Straight Pipe muffler-side reflectivity {:.2}", prev_val).as_str(),
@@ -825,30 +3492,23 @@ pub fn gui(
                     .down(DOWN_SPACE)
                     .set(ids.muffler_straight_pipe_beta, ui)
                 {
-                    generator.engine.muffler.straight_pipe.beta = value;
+                    engine.muffler.straight_pipe.beta = value;
                 }
             }
 
             // muffler_straight_pipe_length
             {
-                const MIN: f32 = 0.1;
-                const MAX: f32 = 3.0;
-                let prev_val = generator
-                    .engine
-                    .muffler
-                    .straight_pipe
-                    .chamber0
-                    .samples
-                    .data
-                    .len() as f32
-                    * SPEED_OF_SOUND
-                    / sample_rate as f32;
+                const MIN: f32 = STRAIGHT_PIPE_LENGTH.min;
+                const MAX: f32 = STRAIGHT_PIPE_LENGTH.max;
+                let prev_val =
+                    engine.muffler.straight_pipe.delay() * SPEED_OF_SOUND / sample_rate as f32;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!(
-                            "Straight Pipe length {:.2}m ({:.1}hz sine peak)",
+                            "Straight Pipe length {:.2}m ({:.1}hz sine peak){}",
                             prev_val,
-                            SPEED_OF_SOUND / prev_val * 2.0
+                            SPEED_OF_SOUND / prev_val * 2.0,
+                            damping_indicator(&engine.muffler.straight_pipe)
                         )
                         .as_str(),
                     )
@@ -857,27 +3517,25 @@ pub fn gui(
                     .down(DOWN_SPACE)
                     .set(ids.muffler_straight_pipe_length, ui)
                 {
-                    let alpha = generator.engine.muffler.straight_pipe.alpha;
-                    let beta = generator.engine.muffler.straight_pipe.beta;
+                    let alpha = engine.muffler.straight_pipe.alpha;
+                    let beta = engine.muffler.straight_pipe.beta;
 
-                    if let Some(newgen) = generator.engine.muffler.straight_pipe.get_changed(
-                        (value / SPEED_OF_SOUND * sample_rate as f32) as usize,
+                    if let Some(newgen) = engine.muffler.straight_pipe.get_changed(
+                        distance_to_samples(value, sample_rate),
                         alpha,
                         beta,
                         sample_rate,
                     ) {
-                        generator.engine.muffler.straight_pipe = newgen;
+                        engine.muffler.straight_pipe = newgen;
                     }
                 }
             }
 
             // muffler_open_end_refl
-            let mut muffler_elements_beta;
-            {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 0.3;
-                let prev_val = generator.engine.muffler.muffler_elements[0].beta;
-                muffler_elements_beta = prev_val;
+            let mut muffler_elements_beta = muffler_elements_beta(&engine);
+            if let Some(prev_val) = muffler_elements_beta {
+                const MIN: f32 = MUFFLER_OPEN_END_REFL.min;
+                const MAX: f32 = MUFFLER_OPEN_END_REFL.max;
 
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
@@ -892,98 +3550,430 @@ pub fn gui(
                     .down(DOWN_SPACE)
                     .set(ids.engine_muffler_open_end_refl, ui)
                 {
-                    muffler_elements_beta = value;
+                    muffler_elements_beta = Some(value);
                 }
             }
 
-            for (i, muffler_element) in generator
-                .engine
-                .muffler
-                .muffler_elements
-                .iter_mut()
-                .enumerate()
-            {
-                // element_length
+            let kind_names = MUFFLER_ELEMENT_KINDS
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect::<Vec<String>>();
+
+            for (i, muffler_element) in engine.muffler.muffler_elements.iter_mut().enumerate() {
+                let selected = match muffler_element {
+                    MufflerElement::StraightPipe(_) => 0,
+                    MufflerElement::HelmholtzResonator { .. } => 1,
+                };
+                if let Some(kind) = widget::DropDownList::new(&kind_names, Some(selected))
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .h(BUTTON_LINE_SIZE)
+                    .down(DOWN_SPACE)
+                    .set(
+                        list_id(
+                            &mut ids.muffler_element_kind,
+                            i,
+                            &mut ui.widget_id_generator(),
+                        ),
+                        ui,
+                    )
                 {
-                    const MIN: f32 = 0.001;
-                    const MAX: f32 = 0.6;
-                    let prev_val = samples_to_distance(
-                        muffler_element.chamber0.samples.data.len(),
-                        sample_rate,
-                    );
-                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                        .label(
-                            format!(
-                                "{} / Muffler cavity length {:.2}m ({:.1}hz sine peak)",
-                                i + 1,
-                                prev_val,
-                                SPEED_OF_SOUND / prev_val * 2.0
+                    if kind != selected {
+                        *muffler_element = if kind == 0 {
+                            MufflerElement::StraightPipe(WaveGuide::new(
+                                distance_to_samples(DEFAULT_MUFFLER_ELEMENT_LENGTH, sample_rate),
+                                0.0,
+                                muffler_elements_beta.unwrap_or(0.0),
+                                sample_rate,
+                            ))
+                        } else {
+                            let (neck_length, neck_radius, cavity_volume, alpha) =
+                                DEFAULT_HELMHOLTZ_RESONATOR;
+                            MufflerElement::helmholtz_resonator(
+                                neck_length,
+                                neck_radius,
+                                cavity_volume,
+                                alpha,
                             )
-                            .as_str(),
-                        )
+                        };
+                    }
+                }
+
+                match muffler_element {
+                    MufflerElement::StraightPipe(waveguide) => {
+                        // element_length
+                        {
+                            const MIN: f32 = MUFFLER_ELEMENT_LENGTH.min;
+                            const MAX: f32 = MUFFLER_ELEMENT_LENGTH.max;
+                            let prev_val = samples_to_distance(waveguide.delay(), sample_rate);
+                            if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                                .label(
+                                    format!(
+                                        "{} / Muffler cavity length {:.2}m ({:.1}hz sine peak){}",
+                                        i + 1,
+                                        prev_val,
+                                        SPEED_OF_SOUND / prev_val * 2.0,
+                                        damping_indicator(waveguide)
+                                    )
+                                    .as_str(),
+                                )
+                                .label_font_size(LABEL_FONT_SIZE)
+                                .padded_w_of(ids.canvas, MARGIN)
+                                .down(DOWN_SPACE)
+                                .set(
+                                    list_id(
+                                        &mut ids.muffler_element_length,
+                                        i,
+                                        &mut ui.widget_id_generator(),
+                                    ),
+                                    ui,
+                                )
+                            {
+                                let new = waveguide.get_changed(
+                                    distance_to_samples(value, sample_rate),
+                                    waveguide.alpha,
+                                    waveguide.beta,
+                                    sample_rate,
+                                );
+
+                                if let Some(new) = new {
+                                    waveguide.clone_from(&new);
+                                }
+                            }
+                        }
+                        if let Some(beta) = muffler_elements_beta {
+                            waveguide.beta = beta;
+                        }
+                    }
+                    MufflerElement::HelmholtzResonator {
+                        neck_length,
+                        neck_radius,
+                        cavity_volume,
+                        alpha,
+                        ..
+                    } => {
+                        let freq = helmholtz_frequency(*neck_length, *neck_radius, *cavity_volume);
+
+                        // neck_length
+                        {
+                            const MIN: f32 = NECK_LENGTH.min;
+                            const MAX: f32 = NECK_LENGTH.max;
+                            let prev_val = *neck_length;
+                            if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                                .label(
+                                    format!(
+                                        "{} / Resonator neck length {:.1}cm ({:.1}hz notch)",
+                                        i + 1,
+                                        prev_val * 100.0,
+                                        freq
+                                    )
+                                    .as_str(),
+                                )
+                                .label_font_size(LABEL_FONT_SIZE)
+                                .padded_w_of(ids.canvas, MARGIN)
+                                .down(DOWN_SPACE)
+                                .set(
+                                    list_id(
+                                        &mut ids.muffler_resonator_neck_length,
+                                        i,
+                                        &mut ui.widget_id_generator(),
+                                    ),
+                                    ui,
+                                )
+                            {
+                                *neck_length = value;
+                            }
+                        }
+
+                        // neck_radius
+                        {
+                            const MIN: f32 = NECK_RADIUS.min;
+                            const MAX: f32 = NECK_RADIUS.max;
+                            let prev_val = *neck_radius;
+                            if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                                .label(
+                                    format!(
+                                        "{} / Resonator neck radius {:.1}cm",
+                                        i + 1,
+                                        prev_val * 100.0
+                                    )
+                                    .as_str(),
+                                )
+                                .label_font_size(LABEL_FONT_SIZE)
+                                .padded_w_of(ids.canvas, MARGIN)
+                                .down(DOWN_SPACE)
+                                .set(
+                                    list_id(
+                                        &mut ids.muffler_resonator_neck_radius,
+                                        i,
+                                        &mut ui.widget_id_generator(),
+                                    ),
+                                    ui,
+                                )
+                            {
+                                *neck_radius = value;
+                            }
+                        }
+
+                        // cavity_volume
+                        {
+                            const MIN: f32 = CAVITY_VOLUME.min;
+                            const MAX: f32 = CAVITY_VOLUME.max;
+                            let prev_val = *cavity_volume;
+                            if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                                .label(
+                                    format!(
+                                        "{} / Resonator cavity volume {:.2}l",
+                                        i + 1,
+                                        prev_val * 1000.0
+                                    )
+                                    .as_str(),
+                                )
+                                .label_font_size(LABEL_FONT_SIZE)
+                                .padded_w_of(ids.canvas, MARGIN)
+                                .down(DOWN_SPACE)
+                                .skew(CAVITY_VOLUME.skew)
+                                .set(
+                                    list_id(
+                                        &mut ids.muffler_resonator_cavity_volume,
+                                        i,
+                                        &mut ui.widget_id_generator(),
+                                    ),
+                                    ui,
+                                )
+                            {
+                                *cavity_volume = value;
+                            }
+                        }
+
+                        // alpha
+                        {
+                            const MIN: f32 = RESONATOR_REFLECTIVITY.min;
+                            const MAX: f32 = RESONATOR_REFLECTIVITY.max;
+                            let prev_val = *alpha;
+                            if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                                .label(
+                                    format!("{} / Resonator reflectivity {:.2}x", i + 1, prev_val)
+                                        .as_str(),
+                                )
+                                .label_font_size(LABEL_FONT_SIZE)
+                                .padded_w_of(ids.canvas, MARGIN)
+                                .down(DOWN_SPACE)
+                                .set(
+                                    list_id(
+                                        &mut ids.muffler_resonator_alpha,
+                                        i,
+                                        &mut ui.widget_id_generator(),
+                                    ),
+                                    ui,
+                                )
+                            {
+                                *alpha = value;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        widget::Text::new("Cylinder parameters")
+            .font_size(16)
+            .down(DOWN_SPACE)
+            .w(ui.window_dim()[0] - MARGIN * 2.0)
+            .set(ids.cylinder_title, ui);
+
+        {
+            // if a ui element is being changed, the cylinders need to be replaced
+            let mut changed = false;
+            let mut num_cylinders = engine.cylinders.len();
+
+            {
+                const MIN: f32 = CYLINDER_COUNT.min;
+                const MAX: f32 = CYLINDER_COUNT.max;
+                let prev_val = num_cylinders as f32;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(format!("Cylinder count {}", prev_val).as_str())
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .set(ids.cylinder_num, ui)
+                {
+                    let value = value.round() as usize;
+                    if value != prev_val as usize {
+                        changed = true;
+                        num_cylinders = value;
+                        // the offsets of the existing cylinders are kept
+                        engine.firing_preset = None;
+                    }
+                }
+            }
+
+            // cylinder deactivation, keeps the cylinders and their waveguides
+            {
+                let count = engine.cylinders.len();
+                let width = (ui.window_dim()[0] - MARGIN * 2.0 - DOWN_SPACE * (count - 1) as f64)
+                    / count as f64;
+
+                for (i, cyl) in engine.cylinders.iter_mut().enumerate() {
+                    let label = format!("{}", i + 1);
+                    let toggle = widget::Toggle::new(cyl.active)
+                        .label(label.as_str())
                         .label_font_size(LABEL_FONT_SIZE)
-                        .padded_w_of(ids.canvas, MARGIN)
-                        .down(DOWN_SPACE)
-                        .set(ids.muffler_element_length[i], ui)
+                        .color(Color::Rgba(0.2, 0.6, 0.3, 1.0))
+                        .w(width)
+                        .h(BUTTON_LINE_SIZE);
+
+                    let toggle = if i == 0 {
+                        toggle.down(DOWN_SPACE)
+                    } else {
+                        toggle.right(DOWN_SPACE)
+                    };
+
+                    for active in toggle.set(
+                        list_id(&mut ids.cylinder_active, i, &mut ui.widget_id_generator()),
+                        ui,
+                    ) {
+                        cyl.active = active;
+                    }
+                }
+            }
+
+            // firing preset
+            {
+                let preset_names = std::iter::once("custom")
+                    .chain(FIRING_PRESETS.iter().copied())
+                    .map(|name| format!("Firing preset: {}", name))
+                    .collect::<Vec<String>>();
+                let selected = match &engine.firing_preset {
+                    Some(preset) => FIRING_PRESETS
+                        .iter()
+                        .position(|name| name == preset)
+                        .map(|i| i + 1),
+                    None => Some(0),
+                };
+
+                if let Some(selected) = widget::DropDownList::new(&preset_names, selected)
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .h(BUTTON_LINE_SIZE)
+                    .down_from(
+                        list_id(&mut ids.cylinder_active, 0, &mut ui.widget_id_generator()),
+                        DOWN_SPACE,
+                    )
+                    .align_left_of(list_id(
+                        &mut ids.cylinder_active,
+                        0,
+                        &mut ui.widget_id_generator(),
+                    ))
+                    .set(ids.cylinder_firing_preset, ui)
+                {
+                    if selected == 0 {
+                        engine.firing_preset = None;
+                    } else if let Err(e) =
+                        apply_firing_preset(&mut engine, FIRING_PRESETS[selected - 1])
                     {
-                        let new = muffler_element.get_changed(
-                            distance_to_samples(value, sample_rate),
-                            muffler_element.alpha,
-                            muffler_element.beta,
-                            sample_rate,
-                        );
+                        eprintln!("{}", e);
+                    } else {
+                        num_cylinders = engine.cylinders.len();
+                    }
+                }
+            }
+
+            // firing order, red if it is uneven
+            {
+                let firing_order = engine
+                    .cylinder_firing_order()
+                    .iter()
+                    .map(|cylinder| (cylinder + 1).to_string())
+                    .collect::<Vec<String>>()
+                    .join("-");
+                let label = format!("Firing order: {}", firing_order);
+
+                let mut text = widget::Text::new(&label)
+                    .font_size(LABEL_FONT_SIZE)
+                    .down(DOWN_SPACE)
+                    .w(ui.window_dim()[0] - MARGIN * 2.0);
+                if !engine.validate_firing_order().is_empty() {
+                    text = text.color(Color::Rgba(0.8, 0.1, 0.1, 1.0));
+                }
+                text.set(ids.cylinder_firing_order, ui);
+            }
+
+            for per_cylinder_mode in widget::Toggle::new(gui_state.per_cylinder_mode)
+                .label(if gui_state.per_cylinder_mode {
+                    "Per-cylinder mode enabled"
+                } else {
+                    "Per-cylinder mode disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.cylinder_per_cylinder_mode, ui)
+            {
+                gui_state.per_cylinder_mode = per_cylinder_mode;
+            }
 
-                        if let Some(new) = new {
-                            muffler_element.clone_from(&new);
-                        }
-                    }
-                }
-                muffler_element.beta = muffler_elements_beta;
+            for advanced_cylinder_mode in widget::Toggle::new(gui_state.advanced_cylinder_mode)
+                .label(if gui_state.advanced_cylinder_mode {
+                    "Advanced per-cylinder mode enabled"
+                } else {
+                    "Advanced per-cylinder mode disabled"
+                })
+                .label_font_size(LABEL_FONT_SIZE)
+                .padded_w_of(ids.canvas, MARGIN)
+                .h(BUTTON_LINE_SIZE)
+                .down(DOWN_SPACE)
+                .set(ids.cylinder_advanced_mode, ui)
+            {
+                gui_state.advanced_cylinder_mode = advanced_cylinder_mode;
             }
-        }
+            let advanced_cylinder_mode = gui_state.advanced_cylinder_mode;
+            // the advanced mode shows all parameters of the selected cylinder instead
+            let per_cylinder_mode = gui_state.per_cylinder_mode && !advanced_cylinder_mode;
 
-        widget::Text::new("Cylinder parameters")
-            .font_size(16)
-            .down(DOWN_SPACE)
-            .w(ui.window_dim()[0] - MARGIN * 2.0)
-            .set(ids.cylinder_title, ui);
+            // the selected cylinder may have been removed by a loaded config or the cylinder count
+            gui_state.selected_cylinder =
+                gui_state.selected_cylinder.min(engine.cylinders.len() - 1);
 
-        {
-            // if a ui element is being changed, the cylinders need to be replaced
-            let mut changed = false;
-            let mut num_cylinders = generator.engine.cylinders.len();
+            if advanced_cylinder_mode {
+                let cylinder_names = (1..=engine.cylinders.len())
+                    .map(|i| format!("Editing cylinder {}", i))
+                    .collect::<Vec<String>>();
 
-            {
-                const MIN: f32 = 1.0;
-                const MAX: f32 = MAX_CYLINDERS as f32;
-                let prev_val = num_cylinders as f32;
-                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Cylinder count {}", prev_val).as_str())
-                    .label_font_size(LABEL_FONT_SIZE)
-                    .padded_w_of(ids.canvas, MARGIN)
-                    .down(DOWN_SPACE)
-                    .set(ids.cylinder_num, ui)
+                if let Some(selected) =
+                    widget::DropDownList::new(&cylinder_names, Some(gui_state.selected_cylinder))
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .h(BUTTON_LINE_SIZE)
+                        .down(DOWN_SPACE)
+                        .set(ids.cylinder_selected, ui)
                 {
-                    let value = value.round() as usize;
-                    if value != prev_val as usize {
-                        changed = true;
-                        num_cylinders = value as usize;
-                    }
+                    gui_state.selected_cylinder = selected;
                 }
             }
 
-            let mut cylinder = generator.engine.cylinders[0].clone();
+            let (template_index, label_prefix) = if advanced_cylinder_mode {
+                (
+                    gui_state.selected_cylinder,
+                    format!("{} / ", gui_state.selected_cylinder + 1),
+                )
+            } else {
+                (0, String::new())
+            };
+
+            let mut cylinder = engine.cylinders[template_index].clone();
 
             // intake_open_refl
-            {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
+            if !per_cylinder_mode {
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
                 let prev_val = cylinder.intake_open_refl;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!(
-                            "Opened intake valve intake-cavity reflectivity {:.2}",
-                            prev_val
+                            "{}Opened intake valve intake-cavity reflectivity {:.2}",
+                            label_prefix, prev_val
                         )
                         .as_str(),
                     )
@@ -998,14 +3988,14 @@ pub fn gui(
             }
             // intake_closed_refl
             {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
                 let prev_val = cylinder.intake_closed_refl;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!(
-                            "Closed intake valve intake-cavity reflectivity {:.2}",
-                            prev_val
+                            "{}Closed intake valve intake-cavity reflectivity {:.2}",
+                            label_prefix, prev_val
                         )
                         .as_str(),
                     )
@@ -1019,15 +4009,15 @@ pub fn gui(
                 }
             }
             // exhaust_open_refl
-            {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
+            if !per_cylinder_mode {
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
                 let prev_val = cylinder.exhaust_open_refl;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!(
-                            "Opened exhaust valve exhaust-cavity reflectivity {:.2}",
-                            prev_val
+                            "{}Opened exhaust valve exhaust-cavity reflectivity {:.2}",
+                            label_prefix, prev_val
                         )
                         .as_str(),
                     )
@@ -1042,14 +4032,14 @@ pub fn gui(
             }
             // exhaust_closed_refl
             {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
                 let prev_val = cylinder.exhaust_closed_refl;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!(
-                            "Closed exhaust valve exhaust-cavity reflectivity {:.2}",
-                            prev_val
+                            "{}Closed exhaust valve exhaust-cavity reflectivity {:.2}",
+                            label_prefix, prev_val
                         )
                         .as_str(),
                     )
@@ -1064,11 +4054,17 @@ pub fn gui(
             }
             // cylinder_intake_open_end_refl
             {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
                 let prev_val = cylinder.intake_waveguide.beta;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Intake-cavity open end reflectivity {:.2}", prev_val).as_str())
+                    .label(
+                        format!(
+                            "{}Intake-cavity open end reflectivity {:.2}",
+                            label_prefix, prev_val
+                        )
+                        .as_str(),
+                    )
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
                     .down(DOWN_SPACE)
@@ -1080,14 +4076,14 @@ pub fn gui(
             }
             // cylinder_extractor_open_end_refl
             {
-                const MIN: f32 = -1.0;
-                const MAX: f32 = 1.0;
+                const MIN: f32 = REFLECTIVITY.min;
+                const MAX: f32 = REFLECTIVITY.max;
                 let prev_val = cylinder.extractor_waveguide.beta;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                     .label(
                         format!(
-                            "Extractor-cavity straight pipe side reflectivity {:.2}",
-                            prev_val
+                            "{}Extractor-cavity straight pipe side reflectivity {:.2}",
+                            label_prefix, prev_val
                         )
                         .as_str(),
                     )
@@ -1101,12 +4097,12 @@ pub fn gui(
                 }
             }
             // piston_motion_factor
-            {
-                const MIN: f32 = 0.0;
-                const MAX: f32 = 20.0;
+            if !per_cylinder_mode {
+                const MIN: f32 = PISTON_MOTION_FACTOR.min;
+                const MAX: f32 = PISTON_MOTION_FACTOR.max;
                 let prev_val = cylinder.piston_motion_factor;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Piston motion volume {:.2}", prev_val).as_str())
+                    .label(format!("{}Piston motion volume {:.2}", label_prefix, prev_val).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
                     .down(DOWN_SPACE)
@@ -1117,12 +4113,12 @@ pub fn gui(
                 }
             }
             // ignition_factor
-            {
-                const MIN: f32 = 0.0;
-                const MAX: f32 = 20.0;
+            if !per_cylinder_mode {
+                const MIN: f32 = IGNITION_FACTOR.min;
+                const MAX: f32 = IGNITION_FACTOR.max;
                 let prev_val = cylinder.ignition_factor;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Ignition volume {:.2}", prev_val).as_str())
+                    .label(format!("{}Ignition volume {:.2}", label_prefix, prev_val).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
                     .down(DOWN_SPACE)
@@ -1134,11 +4130,11 @@ pub fn gui(
             }
             // ignition_time
             {
-                const MIN: f32 = 0.0;
-                const MAX: f32 = 0.3;
+                const MIN: f32 = IGNITION_TIME.min;
+                const MAX: f32 = IGNITION_TIME.max;
                 let prev_val = cylinder.ignition_time;
                 if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
-                    .label(format!("Ignition time {:.2}", prev_val).as_str())
+                    .label(format!("{}Ignition time {:.2}", label_prefix, prev_val).as_str())
                     .label_font_size(LABEL_FONT_SIZE)
                     .padded_w_of(ids.canvas, MARGIN)
                     .down(DOWN_SPACE)
@@ -1148,69 +4144,84 @@ pub fn gui(
                     cylinder.ignition_time = value;
                 }
             }
+            // pressure_release_factor
+            {
+                const MIN: f32 = PRESSURE_RELEASE_FACTOR.min;
+                const MAX: f32 = PRESSURE_RELEASE_FACTOR.max;
+                let prev_val = cylinder.pressure_release_factor;
+                if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                    .label(
+                        format!(
+                            "{}Exhaust valve pressure release volume {:.2}",
+                            label_prefix, prev_val
+                        )
+                        .as_str(),
+                    )
+                    .label_font_size(LABEL_FONT_SIZE)
+                    .padded_w_of(ids.canvas, MARGIN)
+                    .down(DOWN_SPACE)
+                    .skew(PRESSURE_RELEASE_FACTOR.skew)
+                    .set(ids.cylinder_pressure_release_factor, ui)
+                {
+                    changed = true;
+                    cylinder.pressure_release_factor = value;
+                }
+            }
 
             if changed {
-                // copy all previous waveguides but modify the values that all cylinders have in common
-
-                generator.engine.cylinders = if num_cylinders <= generator.engine.cylinders.len() {
-                    let mut new_cylinders = generator.engine.cylinders[0..num_cylinders].to_vec();
-
-                    for cyl in new_cylinders.iter_mut() {
-                        cyl.intake_open_refl = cylinder.intake_open_refl;
-                        cyl.intake_closed_refl = cylinder.intake_closed_refl;
-                        cyl.exhaust_open_refl = cylinder.exhaust_open_refl;
-                        cyl.exhaust_closed_refl = cylinder.exhaust_closed_refl;
-                        cyl.piston_motion_factor = cylinder.piston_motion_factor;
-                        cyl.ignition_factor = cylinder.ignition_factor;
-                        cyl.ignition_time = cylinder.ignition_time;
-                        cyl.intake_waveguide.beta = cylinder.intake_waveguide.beta;
-                        cyl.extractor_waveguide.beta = cylinder.extractor_waveguide.beta;
-                    }
-
-                    new_cylinders
+                if advanced_cylinder_mode {
+                    // only the selected cylinder was edited, the others are left untouched
+                    engine.cylinders[template_index] = cylinder.clone();
                 } else {
-                    let mut new_cylinders = generator.engine.cylinders.to_vec();
-
-                    for cyl in new_cylinders.iter_mut() {
-                        cyl.intake_open_refl = cylinder.intake_open_refl;
+                    // keep all previous waveguides but modify the values that all cylinders have in common
+                    for cyl in engine.cylinders.iter_mut() {
+                        if !per_cylinder_mode {
+                            cyl.intake_open_refl = cylinder.intake_open_refl;
+                            cyl.exhaust_open_refl = cylinder.exhaust_open_refl;
+                            cyl.piston_motion_factor = cylinder.piston_motion_factor;
+                            cyl.ignition_factor = cylinder.ignition_factor;
+                        }
                         cyl.intake_closed_refl = cylinder.intake_closed_refl;
-                        cyl.exhaust_open_refl = cylinder.exhaust_open_refl;
                         cyl.exhaust_closed_refl = cylinder.exhaust_closed_refl;
-                        cyl.piston_motion_factor = cylinder.piston_motion_factor;
-                        cyl.ignition_factor = cylinder.ignition_factor;
                         cyl.ignition_time = cylinder.ignition_time;
+                        cyl.pressure_release_factor = cylinder.pressure_release_factor;
                         cyl.intake_waveguide.beta = cylinder.intake_waveguide.beta;
                         cyl.extractor_waveguide.beta = cylinder.extractor_waveguide.beta;
                     }
+                }
 
-                    for _ in generator.engine.cylinders.len()..num_cylinders {
-                        // set the last cylinder's crank offset correctly
-                        cylinder.crank_offset = (num_cylinders - 1) as f32 / num_cylinders as f32;
-
-                        new_cylinders.push(cylinder.clone());
-                    }
-
-                    new_cylinders
-                };
+                set_cylinder_count(&mut engine, num_cylinders, &cylinder);
             }
 
-            for (i, mut cyl) in generator.engine.cylinders.iter_mut().enumerate() {
+            let mut crank_offset_edited = false;
+
+            for (i, cyl) in engine.cylinders.iter_mut().enumerate() {
                 // intake_pipe_length
                 {
-                    const MIN: f32 = 0.0;
-                    const MAX: f32 = 1.0;
-                    let prev_val = samples_to_distance(
-                        cyl.intake_waveguide.chamber0.samples.data.len(),
-                        sample_rate,
-                    );
+                    const MIN: f32 = INTAKE_PIPE_LENGTH.min;
+                    const MAX: f32 = INTAKE_PIPE_LENGTH.max;
+                    let prev_val = samples_to_distance(cyl.intake_waveguide.delay(), sample_rate);
                     if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                         .label(
-                            format!("{} / Intake-cavity length {:.2}m", i + 1, prev_val).as_str(),
+                            format!(
+                                "{} / Intake-cavity length {:.2}m{}",
+                                i + 1,
+                                prev_val,
+                                damping_indicator(&cyl.intake_waveguide)
+                            )
+                            .as_str(),
                         )
                         .label_font_size(LABEL_FONT_SIZE)
                         .padded_w_of(ids.canvas, MARGIN)
                         .down(DOWN_SPACE * 2.3)
-                        .set(ids.cylinder_intake_pipe_length[i], ui)
+                        .set(
+                            list_id(
+                                &mut ids.cylinder_intake_pipe_length,
+                                i,
+                                &mut ui.widget_id_generator(),
+                            ),
+                            ui,
+                        )
                     {
                         let new = cyl.intake_waveguide.get_changed(
                             distance_to_samples(value, sample_rate),
@@ -1226,20 +4237,30 @@ pub fn gui(
                 }
                 // exhaust_pipe_length
                 {
-                    const MIN: f32 = 0.0;
-                    const MAX: f32 = 1.7;
-                    let prev_val = samples_to_distance(
-                        cyl.exhaust_waveguide.chamber0.samples.data.len(),
-                        sample_rate,
-                    );
+                    const MIN: f32 = EXHAUST_PIPE_LENGTH.min;
+                    const MAX: f32 = EXHAUST_PIPE_LENGTH.max;
+                    let prev_val = samples_to_distance(cyl.exhaust_waveguide.delay(), sample_rate);
                     if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                         .label(
-                            format!("{} / Exhaust-cavity length {:.2}m", i + 1, prev_val).as_str(),
+                            format!(
+                                "{} / Exhaust-cavity length {:.2}m{}",
+                                i + 1,
+                                prev_val,
+                                damping_indicator(&cyl.exhaust_waveguide)
+                            )
+                            .as_str(),
                         )
                         .label_font_size(LABEL_FONT_SIZE)
                         .padded_w_of(ids.canvas, MARGIN)
                         .down(DOWN_SPACE)
-                        .set(ids.cylinder_exhaust_pipe_length[i], ui)
+                        .set(
+                            list_id(
+                                &mut ids.cylinder_exhaust_pipe_length,
+                                i,
+                                &mut ui.widget_id_generator(),
+                            ),
+                            ui,
+                        )
                     {
                         let new = cyl.exhaust_waveguide.get_changed(
                             distance_to_samples(value, sample_rate),
@@ -1255,21 +4276,31 @@ pub fn gui(
                 }
                 // extractor_pipe_length
                 {
-                    const MIN: f32 = 0.0;
-                    const MAX: f32 = 10.0;
-                    let prev_val = samples_to_distance(
-                        cyl.extractor_waveguide.chamber0.samples.data.len(),
-                        sample_rate,
-                    );
+                    const MIN: f32 = EXTRACTOR_PIPE_LENGTH.min;
+                    const MAX: f32 = EXTRACTOR_PIPE_LENGTH.max;
+                    let prev_val =
+                        samples_to_distance(cyl.extractor_waveguide.delay(), sample_rate);
                     if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
                         .label(
-                            format!("{} / Extractor-cavity length {:.2}m", i + 1, prev_val)
-                                .as_str(),
+                            format!(
+                                "{} / Extractor-cavity length {:.2}m{}",
+                                i + 1,
+                                prev_val,
+                                damping_indicator(&cyl.extractor_waveguide)
+                            )
+                            .as_str(),
                         )
                         .label_font_size(LABEL_FONT_SIZE)
                         .padded_w_of(ids.canvas, MARGIN)
                         .down(DOWN_SPACE)
-                        .set(ids.cylinder_extractor_pipe_length[i], ui)
+                        .set(
+                            list_id(
+                                &mut ids.cylinder_extractor_pipe_length,
+                                i,
+                                &mut ui.widget_id_generator(),
+                            ),
+                            ui,
+                        )
                     {
                         let new = cyl.extractor_waveguide.get_changed(
                             distance_to_samples(value, sample_rate),
@@ -1293,42 +4324,516 @@ pub fn gui(
                         .label_font_size(LABEL_FONT_SIZE)
                         .padded_w_of(ids.canvas, MARGIN)
                         .down(DOWN_SPACE)
-                        .set(ids.cylinder_crank_offset[i], ui)
+                        .set(
+                            list_id(
+                                &mut ids.cylinder_crank_offset,
+                                i,
+                                &mut ui.widget_id_generator(),
+                            ),
+                            ui,
+                        )
                     {
                         cyl.crank_offset = value;
+                        crank_offset_edited = true;
+                    }
+                }
+                // misfire_probability
+                {
+                    const MIN: f32 = 0.0;
+                    const MAX: f32 = 1.0;
+                    let prev_val = cyl.misfire_probability;
+                    if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                        .label(
+                            format!("{} / Misfire probability {:.1}%", i + 1, prev_val * 100.0)
+                                .as_str(),
+                        )
+                        .label_font_size(LABEL_FONT_SIZE)
+                        .padded_w_of(ids.canvas, MARGIN)
+                        .down(DOWN_SPACE)
+                        .skew(2.0)
+                        .set(
+                            list_id(
+                                &mut ids.cylinder_misfire_probability,
+                                i,
+                                &mut ui.widget_id_generator(),
+                            ),
+                            ui,
+                        )
+                    {
+                        cyl.misfire_probability = value;
+                    }
+                }
+
+                if per_cylinder_mode {
+                    // intake_open_refl
+                    {
+                        const MIN: f32 = REFLECTIVITY.min;
+                        const MAX: f32 = REFLECTIVITY.max;
+                        let prev_val = cyl.intake_open_refl;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                            .label(
+                                format!(
+                                    "{} / Opened intake valve intake-cavity reflectivity {:.2}",
+                                    i + 1,
+                                    prev_val
+                                )
+                                .as_str(),
+                            )
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .set(
+                                list_id(
+                                    &mut ids.cylinder_intake_open_refl_per,
+                                    i,
+                                    &mut ui.widget_id_generator(),
+                                ),
+                                ui,
+                            )
+                        {
+                            cyl.intake_open_refl = value;
+                        }
+                    }
+                    // exhaust_open_refl
+                    {
+                        const MIN: f32 = REFLECTIVITY.min;
+                        const MAX: f32 = REFLECTIVITY.max;
+                        let prev_val = cyl.exhaust_open_refl;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                            .label(
+                                format!(
+                                    "{} / Opened exhaust valve exhaust-cavity reflectivity {:.2}",
+                                    i + 1,
+                                    prev_val
+                                )
+                                .as_str(),
+                            )
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .set(
+                                list_id(
+                                    &mut ids.cylinder_exhaust_open_refl_per,
+                                    i,
+                                    &mut ui.widget_id_generator(),
+                                ),
+                                ui,
+                            )
+                        {
+                            cyl.exhaust_open_refl = value;
+                        }
+                    }
+                    // piston_motion_factor
+                    {
+                        const MIN: f32 = PISTON_MOTION_FACTOR.min;
+                        const MAX: f32 = PISTON_MOTION_FACTOR.max;
+                        let prev_val = cyl.piston_motion_factor;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                            .label(
+                                format!("{} / Piston motion volume {:.2}", i + 1, prev_val)
+                                    .as_str(),
+                            )
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .set(
+                                list_id(
+                                    &mut ids.cylinder_piston_motion_factor_per,
+                                    i,
+                                    &mut ui.widget_id_generator(),
+                                ),
+                                ui,
+                            )
+                        {
+                            cyl.piston_motion_factor = value;
+                        }
+                    }
+                    // ignition_factor
+                    {
+                        const MIN: f32 = IGNITION_FACTOR.min;
+                        const MAX: f32 = IGNITION_FACTOR.max;
+                        let prev_val = cyl.ignition_factor;
+                        if let Some(value) = widget::Slider::new(prev_val, MIN, MAX)
+                            .label(format!("{} / Ignition volume {:.2}", i + 1, prev_val).as_str())
+                            .label_font_size(LABEL_FONT_SIZE)
+                            .padded_w_of(ids.canvas, MARGIN)
+                            .down(DOWN_SPACE)
+                            .set(
+                                list_id(
+                                    &mut ids.cylinder_ignition_factor_per,
+                                    i,
+                                    &mut ui.widget_id_generator(),
+                                ),
+                                ui,
+                            )
+                        {
+                            cyl.ignition_factor = value;
+                        }
+                    }
+                }
+            }
+
+            if crank_offset_edited {
+                engine.firing_preset = None;
+            }
+        }
+
+        // a slider drag or a click is one step of the undo history
+        let mouse_released = ui.global_input().events().ui().any(|event| {
+            matches!(
+                event,
+                event::Ui::Release(
+                    _,
+                    event::Release {
+                        button: event::Button::Mouse(input::MouseButton::Left, _),
+                        ..
                     }
+                )
+            )
+        });
+        if mouse_released {
+            let config = undo_config(&engine);
+            if config != gui_state.undo_config {
+                if let Some(undo_stack) = &mut gui_state.undo_stack {
+                    undo_stack.push(engine.clone());
                 }
+                gui_state.undo_config = config;
             }
         }
+
+        // the copy only differs from the generator's engine if a widget has been used or a config has been loaded,
+        // otherwise the generator thread would copy all parameters every frame for nothing
+        let widget_events = ui.global_input().events().next().is_some();
+        if widget_events
+            || engine_replaced
+            || morph_target.is_some()
+            || reseed_noise
+            || new_target_rpm.is_some()
+        {
+            generator.apply(move |generator| {
+                if engine_replaced {
+                    // keeps the rpm so editing the config doesn't interrupt playing
+                    generator
+                        .swap_engine(engine, seconds_to_samples(ENGINE_SWAP_TIME, sample_rate));
+                } else if generator.engine_revision() == engine_revision {
+                    // the rpm of the copy is outdated while the generator is slewing it
+                    if new_target_rpm.is_some() || generator.target_rpm().is_some() {
+                        engine.rpm = generator.engine.rpm;
+                    }
+                    generator.engine.apply_parameters(engine);
+                }
+                // otherwise the engine has been swapped while the frame was built and the copy is outdated
+
+                if let Some(target_rpm) = new_target_rpm {
+                    generator.set_target_rpm(target_rpm);
+                }
+
+                if let Some(morph_target) = morph_target {
+                    generator.morph_to(morph_target, seconds_to_samples(MORPH_TIME, sample_rate));
+                }
+
+                if reseed_noise {
+                    generator.engine.seed_noise();
+                }
+
+                generator.volume = volume;
+                generator.stereo_width = stereo_width;
+            });
+        }
     }
 
     image_map
 }
 
-fn recording_name() -> String {
-    let time = Local::now();
-
-    format!(
-        "enginesound_{:02}{:02}{:04}-{:02}{:02}{:02}.wav",
-        time.day(),
-        time.month(),
-        time.year(),
-        time.hour(),
-        time.minute(),
-        time.second()
-    )
+/// uses the timestamp scheme of `recording_name`
+fn spectrogram_name() -> String {
+    format!("enginesound_spectrogram_{}.png", timestamp())
 }
 
-fn config_name() -> String {
-    let time = Local::now();
-
-    format!(
-        "enginesound_{:02}{:02}{:04}-{:02}{:02}{:02}.esc",
-        time.day(),
-        time.month(),
-        time.year(),
-        time.hour(),
-        time.minute(),
-        time.second()
-    )
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_gui_state() -> GUIState {
+        let (_, fft) = crossbeam_channel::bounded(1);
+        let (_, oscilloscope) = crossbeam_channel::bounded(1);
+
+        GUIState::new(fft, Oscilloscope::new(oscilloscope, OSCILLOSCOPE_LEN))
+    }
+
+    #[test]
+    fn saved_spectrogram_is_a_png_of_the_history() {
+        let path = std::env::temp_dir().join("enginesound_test_spectrogram.png");
+        let path = path.to_str().unwrap();
+
+        let mut gui_state = new_gui_state();
+        gui_state.set_max_history(80);
+        for i in 0..100 {
+            gui_state.add_line(&[i as f32 * 0.01; WATERFALL_WIDTH as usize]);
+        }
+        gui_state.spectrogram().save(path).unwrap();
+        assert_eq!(gui_state.spectrogram().lines.len(), 80);
+        assert_eq!(
+            ::image::image_dimensions(path).unwrap(),
+            gui_state.spectrogram().size()
+        );
+
+        // shorter histories are padded to the height of the waterfall
+        let gui_state = new_gui_state();
+        gui_state.spectrogram().save(path).unwrap();
+        assert_eq!(
+            gui_state.spectrogram().lines.len(),
+            WATERFALL_HEIGHT as usize
+        );
+        assert_eq!(
+            ::image::image_dimensions(path).unwrap(),
+            gui_state.spectrogram().size()
+        );
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn spectrogram_covers_the_last_recording() {
+        let mut gui_state = new_gui_state();
+        gui_state.set_max_history(80);
+        gui_state.add_line(&[-1.0; WATERFALL_WIDTH as usize]);
+
+        gui_state.set_recording(true);
+        for i in 0..200 {
+            gui_state.add_line(&[i as f32; WATERFALL_WIDTH as usize]);
+        }
+        gui_state.set_recording(false);
+        gui_state.add_line(&[-1.0; WATERFALL_WIDTH as usize]);
+
+        // the whole recording is kept although it is longer than the history, newest line first
+        let lines = gui_state.spectrogram().lines;
+        assert_eq!(lines.len(), 200);
+        assert_eq!(lines[0][0], 199.0);
+        assert_eq!(lines[199][0], 0.0);
+
+        // the next recording replaces it
+        gui_state.set_recording(true);
+        gui_state.add_line(&[300.0; WATERFALL_WIDTH as usize]);
+        assert_eq!(
+            gui_state.spectrogram().lines,
+            vec![vec![300.0; WATERFALL_WIDTH as usize]]
+        );
+    }
+
+    #[test]
+    fn resizing_the_waterfall_keeps_the_newest_lines() {
+        let mut gui_state = new_gui_state();
+        for i in 0..100 {
+            gui_state.add_line(&[i as f32; WATERFALL_WIDTH as usize]);
+        }
+        let row =
+            |gui_state: &GUIState, row: usize| gui_state.waterfall[row * WATERFALL_WIDTH as usize];
+
+        gui_state.set_waterfall_height(20);
+        assert_eq!(gui_state.waterfall.len(), WATERFALL_WIDTH as usize * 20);
+        assert_eq!(row(&gui_state, 0), 99.0);
+        assert_eq!(row(&gui_state, 19), 80.0);
+
+        // the rows which were cut off are restored from the history
+        gui_state.set_waterfall_height(80);
+        assert_eq!(gui_state.waterfall.len(), WATERFALL_WIDTH as usize * 80);
+        assert_eq!(row(&gui_state, 79), 20.0);
+
+        // lines older than the history are filled with the background
+        gui_state.set_waterfall_height(MAX_WATERFALL_HEIGHT);
+        assert_eq!(row(&gui_state, 99), 0.0);
+        assert_eq!(row(&gui_state, 100), WATERFALL_BACKGROUND);
+
+        gui_state.add_line(&[100.0; WATERFALL_WIDTH as usize]);
+        assert_eq!(row(&gui_state, 0), 100.0);
+        assert_eq!(row(&gui_state, 100), 0.0);
+    }
+
+    #[test]
+    fn display_values_are_monotonic_and_clamped() {
+        for &gain in &[0.1, 1.0, 100.0] {
+            let values = (0..1000)
+                .map(|i| display_value(i as f32 * 0.5, gain))
+                .collect::<Vec<f32>>();
+            assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(values.iter().all(|x| (0.0..=1.0).contains(x)));
+        }
+
+        assert_eq!(display_value(0.0, 1.0), 0.0);
+        assert_eq!(display_value(-5.0, 1.0), 0.0);
+        assert_eq!(display_value(f32::NAN, 1.0), 0.0);
+        assert_eq!(display_value(1e30, 1.0), 1.0);
+        assert_eq!(display_value(f32::INFINITY, 1.0), 1.0);
+    }
+
+    #[test]
+    fn colormaps_span_their_stops() {
+        for colormap in Colormap::ALL.iter() {
+            let (first, _) = colormap.stops()[0];
+            let (last, _) = colormap.stops()[colormap.stops().len() - 1];
+            let bytes = |color: [f32; 3]| color.map(|x| (x * 255.0) as u8);
+
+            assert_eq!(colormap.color(0.0), bytes(first));
+            assert_eq!(colormap.color(1.0), bytes(last));
+            assert_eq!(colormap.color(f32::NAN), bytes(first));
+            assert_eq!(colormap.color(-1.0), bytes(first));
+            assert_eq!(colormap.color(f32::INFINITY), bytes(last));
+        }
+
+        let brightness = (0..=100)
+            .map(|i| {
+                let [r, g, b] = Colormap::Grayscale.color(i as f32 * 0.01);
+                r as u32 + g as u32 + b as u32
+            })
+            .collect::<Vec<u32>>();
+        assert!(brightness.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn frequency_axes_span_the_fft_bins() {
+        // the fft of the waterfall has twice as many bins as it has columns
+        let bin_width = 48000.0 / (WATERFALL_WIDTH * 2) as f32;
+        let nyquist = 24000.0;
+
+        assert!((spectrum_plot_freq(0.0, bin_width) - bin_width).abs() < 1e-3);
+        assert!((spectrum_plot_freq(1.0, bin_width) - nyquist).abs() < 1.0);
+        for &freq in SPECTRUM_GRID_FREQS.iter() {
+            let x = spectrum_plot_x(freq, bin_width);
+            assert!((0.0..=1.0).contains(&x));
+            assert!((spectrum_plot_freq(x, bin_width) - freq).abs() < 0.1);
+        }
+
+        let columns = (0..WATERFALL_WIDTH)
+            .map(|column| waterfall_column_freq(column as f32, bin_width))
+            .collect::<Vec<f32>>();
+        assert!(columns.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(columns[columns.len() - 1] > nyquist * 0.99);
+
+        for column in [10.0, 100.0, 400.0] {
+            let freq = waterfall_column_freq(column, bin_width);
+            assert!((waterfall_freq_column(freq, bin_width) - column).abs() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn waterfall_columns_show_the_same_frequencies_with_every_fft_size() {
+        for &fft_size in [256, 1024, 8192].iter() {
+            let bin_width = 48000.0 / fft_size as f32;
+            // a single bin at 1.5 khz
+            let mut spectrum = vec![0.0; fft_size];
+            spectrum[(1500.0 / bin_width) as usize] = 1.0;
+
+            let axis_bin_width = axis_bin_width(bin_width, fft_size);
+            assert_eq!(axis_bin_width, 48000.0 / (WATERFALL_WIDTH * 2) as f32);
+
+            let column = waterfall_freq_column(1500.0, axis_bin_width);
+            let value = sample_waterfall_column(&spectrum, fft_size / 2, column);
+            assert!((value - 1.0).abs() < 1e-3, "{}: {}", fft_size, value);
+        }
+    }
+
+    #[test]
+    fn harmonics_follow_the_rpm_and_cylinder_count() {
+        let bin_width = 48000.0 / (WATERFALL_WIDTH * 2) as f32;
+
+        // a four cylinder engine at 3000 rpm fires at 100 hz
+        let harmonics = firing_harmonics(3000.0, 4, 48000, bin_width);
+        assert_eq!(harmonics[0].0, 100.0);
+        assert_eq!(harmonics[1].0, 200.0);
+        assert!(harmonics.iter().all(|&(freq, _)| freq <= 24000.0));
+        // the dense harmonics at high frequencies are thinned out
+        assert!(harmonics
+            .windows(2)
+            .all(|pair| pair[1].1 as f32 - pair[0].1 as f32 >= MIN_HARMONIC_DISTANCE - 1.0));
+
+        assert_eq!(firing_harmonics(6000.0, 4, 48000, bin_width)[0].0, 200.0);
+        assert_eq!(firing_harmonics(3000.0, 8, 48000, bin_width)[0].0, 200.0);
+        assert!(firing_harmonics(0.0, 4, 48000, bin_width).is_empty());
+    }
+
+    #[test]
+    fn widget_ids_grow_with_the_muffler_elements() {
+        let mut engine = enginesound::utils::load_engine("example6.esc", 48000, None).unwrap();
+        let elements = engine.muffler.muffler_elements.clone();
+        engine
+            .muffler
+            .muffler_elements
+            .extend(elements.iter().cycle().take(8 - elements.len()).cloned());
+
+        let path = std::env::temp_dir().join("enginesound_test_8_muffler_elements.esc");
+        save_engine(&path, &engine).unwrap();
+        let engine = enginesound::utils::load_engine(path.to_str().unwrap(), 48000, None).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(engine.muffler.muffler_elements.len(), 8);
+
+        let mut ui = conrod_core::UiBuilder::new([800.0, 600.0]).build();
+        let mut ids = Ids::new(ui.widget_id_generator());
+
+        let lengths = (0..engine.muffler.muffler_elements.len())
+            .map(|i| {
+                list_id(
+                    &mut ids.muffler_element_length,
+                    i,
+                    &mut ui.widget_id_generator(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let kinds = (0..engine.muffler.muffler_elements.len())
+            .map(|i| {
+                list_id(
+                    &mut ids.muffler_element_kind,
+                    i,
+                    &mut ui.widget_id_generator(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let mut all = lengths.iter().chain(kinds.iter()).collect::<Vec<_>>();
+        all.push(&ids.canvas_scrollbar);
+        all.sort();
+        all.dedup();
+        assert_eq!(all.len(), 8 * 2 + 1);
+
+        // the ids are generated once and then reused every frame
+        assert_eq!(
+            list_id(
+                &mut ids.muffler_element_length,
+                7,
+                &mut ui.widget_id_generator()
+            ),
+            lengths[7]
+        );
+    }
+
+    #[test]
+    fn triggered_oscilloscope_starts_at_the_crankshaft_cycle() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut oscilloscope = Oscilloscope::new(receiver, 100);
+
+        // cycles of 128 samples whose values are their index within the cycle
+        const CYCLE_LEN: usize = 128;
+        for buffer in 0..10 {
+            let first = buffer * 48;
+            sender
+                .send(MonitorBuffer {
+                    samples: (first..first + 48)
+                        .map(|i| (i % CYCLE_LEN) as f32)
+                        .collect(),
+                    crankshaft_pos: ((first + CYCLE_LEN - 1) % CYCLE_LEN) as f32 / CYCLE_LEN as f32,
+                    crankshaft_step: 1.0 / CYCLE_LEN as f32,
+                })
+                .unwrap();
+        }
+
+        // the last cycle starting at sample 384 isn't long enough for the window yet
+        oscilloscope.update();
+        assert!(oscilloscope.window().eq((0..100).map(|i| i as f32)));
+
+        oscilloscope.triggered = false;
+        oscilloscope.update();
+        assert!(oscilloscope
+            .window()
+            .eq((380..480).map(|i| (i % CYCLE_LEN) as f32)));
+    }
 }
@@ -1,7 +1,29 @@
-mod constants;
+//! Purely synthetic engine sound generation.
+//!
+//! The GUI and audio playback live in the binary behind the `gui` feature, this library only contains the
+//! generator itself, so it can be driven from any audio thread by calling `Generator::generate`.
+//! With the `wasm` feature on `wasm32`, `wasm::JsGenerator` exposes the generator to JavaScript.
+//! With the `ffi` (or `capi`) feature, `ffi` exposes a C API (see `include/enginesound.h` and `examples/render_wav.c`).
+
+pub mod analysis;
+pub mod automation;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 pub mod gen;
-mod recorder;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod generator_thread;
+pub mod presets;
+pub mod recorder;
+pub mod state;
 pub mod utils;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
 
 pub use gen::*;
+pub use presets::EnginePreset;
+pub use recorder::{
+    EngineInfo, LufsMonitor, Normalization, NormalizationTarget, OutputFormat, Recorder,
+    RecorderError, RecordingMetadata, SampleDepth,
+};
+pub use state::GeneratorState;
 pub use utils::*;
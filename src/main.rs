@@ -1,16 +1,31 @@
-use crate::exactstreamer::ExactStreamer;
-use crate::gen::LowPassFilter;
-use crate::recorder::Recorder;
-use crate::utils::{fix_engine, load_engine, seconds_to_samples};
-use clap::{value_t, value_t_or_exit, App, Arg};
-use parking_lot::RwLock;
+use clap::{value_t, value_t_or_exit, App, Arg, ArgGroup, SubCommand};
+use enginesound::{
+    analysis,
+    automation::Automation,
+    gen::{self, BiquadFilter, DC_OFFSET_LP_FREQ},
+    presets,
+    recorder::{
+        normalize, write_loop_points, LufsMonitor, Normalization, NormalizationTarget,
+        OutputFormat, Recorder, RecorderError, SampleDepth,
+    },
+    utils::{
+        crossfade, crossfade_loop, estimate_generation_time, find_loop_point, fix_engine,
+        load_engine, recommended_crossfade, samples_to_seconds, save_engine, seam_quality,
+        seconds_to_samples, set_parameters, ConfigFormat, CrossfadeShape, ProgressReporter,
+    },
+};
+
+#[cfg(feature = "audio")]
+use enginesound::generator_thread::{GeneratorHandle, GeneratorThread};
 use std::sync::Arc;
+use watcher::ConfigWatcher;
 
 #[cfg(feature = "gui")]
 use crate::{
     audio::GENERATOR_BUFFER_SIZE,
-    fft::FFTStreamer,
-    gui::{GUIState, WATERFALL_WIDTH},
+    exactstreamer::ExactStreamer,
+    fft::{FFTStreamer, WindowFunction, MAX_FFT_SIZE, MIN_FFT_SIZE},
+    gui::{GUIState, Oscilloscope, OSCILLOSCOPE_LEN, WATERFALL_WIDTH},
 };
 #[cfg(feature = "gui")]
 use conrod_core::text::Font;
@@ -22,20 +37,39 @@ use winit::dpi::PhysicalSize;
 #[cfg(all(feature = "gui", target_os = "windows"))]
 use winit::platform::windows::WindowBuilderExtWindows;
 
-#[cfg(feature = "gui")]
+#[cfg(feature = "audio")]
 mod audio;
 #[cfg(feature = "gui")]
+mod constants;
+#[cfg(feature = "audio")]
+mod control;
+#[cfg(feature = "audio")]
+mod control_pipe;
+#[cfg(feature = "audio")]
+mod exactstreamer;
+#[cfg(feature = "gui")]
 mod fft;
 #[cfg(feature = "gui")]
 mod gui;
+#[cfg(feature = "midi")]
+mod midi;
+#[cfg(feature = "osc")]
+mod osc;
+#[cfg(feature = "gui")]
+mod parameters;
+#[cfg(feature = "audio")]
+mod resampler;
+#[cfg(feature = "jack")]
+mod samplering;
+#[cfg(feature = "gui")]
+mod spectrogram;
 #[cfg(feature = "gui")]
 mod support;
-
-mod constants;
-mod exactstreamer;
-mod gen;
-mod recorder;
-mod utils;
+#[cfg(all(feature = "terminal", unix))]
+mod terminal;
+#[cfg(feature = "gui")]
+mod undo;
+mod watcher;
 
 #[cfg(feature = "gui")]
 const WINDOW_WIDTH: f64 = 800.0;
@@ -44,27 +78,152 @@ const WINDOW_HEIGHT: f64 = 800.0;
 
 const DEFAULT_CONFIG: &[u8] = include_bytes!("default.esc");
 
+/// interval at which the watched config is checked for changes during control port playback
+#[cfg(feature = "audio")]
+const CONFIG_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// amount of samples generated at a constant RPM while ramping the RPM or following an automation in headless mode
+const RPM_RAMP_STEP: usize = 64;
+
 fn main() {
     let matches = App::new("Engine Sound Generator")
         .version(clap::crate_version!())
         .author(clap::crate_authors!())
         .about(clap::crate_description!())
-        .arg(Arg::with_name("headless").short("h").long("headless").help("CLI mode without GUI or audio playback").requires("config"))
+        .arg(Arg::with_name("headless").short("h").long("headless").help("CLI mode without GUI or audio playback").requires("engine"))
         .arg(Arg::with_name("config").short("c").long("config").help("Sets the input file to load as an engine config").takes_value(true))
+        .arg(Arg::with_name("preset").long("preset").help("Loads the bundled engine preset with this name instead of a config file, see --list-presets").takes_value(true))
+        .group(ArgGroup::with_name("engine").args(&["config", "preset"]))
+        .arg(Arg::with_name("list-presets").long("list-presets").help("Prints the names of the bundled engine presets and exits"))
         .arg(Arg::with_name("volume").short("v").long("volume").help("Sets the master volume").default_value( "0.1"))
         .arg(Arg::with_name("rpm").short("r").long("rpm").help("Engine RPM").takes_value(true))
+        .arg(Arg::with_name("throttle").short("t").long("throttle").help("Sets the engine load from 0.0 (closed throttle) to 1.0 (wide open throttle), the ignition fades out below 0.2 (engine braking)").takes_value(true))
+        .arg(Arg::with_name("rpm-start").long("rpm-start").help("Sets the engine RPM at the start of the recording, the RPM is ramped to --rpm-end over the recording length. The warmup runs at this RPM").takes_value(true).requires("rpm-end").conflicts_with("rpm"))
+        .arg(Arg::with_name("rpm-end").long("rpm-end").help("Sets the engine RPM at the end of the recording").takes_value(true).requires("rpm-start"))
+        .arg(Arg::with_name("rpm-log").long("rpm-log").help("Writes the RPM at the start of every 64 sample step of the recording as sample_index,rpm rows of a CSV file next to the output file (e.g. output_rpm.csv)").requires("headless").conflicts_with("measure-lufs"))
+        .arg(Arg::with_name("rpm-envelope").long("rpm-envelope").alias("rpm-ramp").help("Sets comma separated time:rpm keyframes (e.g. 0.0:800,1.5:3500,4.0:6000) which the engine RPM linearly follows during the recording, starting after the warmup, or a sweep start_rpm:end_rpm:duration (e.g. 1000:6000:4.0) from start_rpm to end_rpm over duration seconds, which is the length of the recording unless --length is set. The warmup runs at the RPM of the first keyframe").takes_value(true).requires("headless").conflicts_with_all(&["rpm", "rpm-start"]))
+        .arg(Arg::with_name("throttle-ramp").long("throttle-ramp").help("Sets comma separated time:throttle keyframes (e.g. 0.0:1.0,0.5:0.0) which the throttle linearly follows during the recording, starting after the warmup. The warmup runs at the throttle of the first keyframe. Combined with a falling --rpm-envelope, this records a deceleration with engine braking").takes_value(true).requires("headless").conflicts_with("throttle"))
+        .arg(Arg::with_name("automation").long("automation").help("Loads time-stamped keyframes of the RPM, throttle and master volume from a RON file (a list of (time: 2.0, rpm: Some(800.0), throttle: None, volume: None, hold: false)) or a CSV file with a time,rpm,throttle,volume,hold header, which are linearly interpolated (or held until the next keyframe with hold) during the recording. The recording length is the time of the last keyframe unless --length is set").takes_value(true).requires("headless").conflicts_with_all(&["rpm", "rpm-start", "rpm-envelope", "rpm-list", "throttle", "throttle-ramp", "auto-loop"]))
+        .arg(Arg::with_name("rpm-list").long("rpm-list").help("Sets comma separated RPMs (e.g. 1000,2000,4000) which are recorded one after another into separate files named {output-base}_{rpm}.wav (or .ogg with --format ogg)").takes_value(true).requires("headless").conflicts_with_all(&["rpm", "rpm-start", "rpm-envelope"]))
+        .arg(Arg::with_name("output-base").long("output-base").help("Sets the file name prefix of the recordings of --rpm-list").default_value("output"))
+        .arg(Arg::with_name("exponential-ramp").long("exponential-ramp").help("Ramps the RPM exponentially instead of linearly from --rpm-start to --rpm-end").requires("rpm-start"))
         .arg(Arg::with_name("warmup_time").short("w").long("warmup_time").help("Sets the time to wait in seconds before recording").default_value_if("headless", None, "3.0"))
         .arg(Arg::with_name("reclen").short("l").long("length").help("Sets the time to record in seconds. The formula for the recommended time to record to get a seamless loop is as follows:\n    let wavelength = 120.0 / rpm;\n    let crossfade = wavelength * 2.0;\n    let reclen = n * wavelength + crossfade / 2.0;").default_value_if("headless", None, "5.0"))
-        .arg(Arg::with_name("output_file").short("o").long("output").help("Sets the output .wav file path").default_value_if("headless", None, "output.wav"))
+        .arg(Arg::with_name("output_file").short("o").long("output").help("Sets the output .wav or .ogg file path").default_value_if("headless", None, "output.wav"))
+        .arg(Arg::with_name("format").long("format").help("Sets the output file format, inferred from the output file extension by default. OGG output requires the \"ogg\" feature").possible_values(&["wav", "ogg"]).takes_value(true))
+        .arg(Arg::with_name("ogg-quality").long("ogg-quality").help("Sets the Ogg Vorbis VBR quality from -0.2 to 1.0").default_value("0.6"))
+        .arg(Arg::with_name("bit-depth").short("b").long("bit-depth").help("Sets the sample format of the output .wav file, integer formats are dithered").possible_values(&["f32", "16", "24"]).default_value("f32"))
         .arg(Arg::with_name("crossfade").short("f").long("crossfade").help("Crossfades the recording in the middle end-to-start to create a seamless loop, although adjusting the recording's length to the rpm is recommended. The value sets the size of the crossfade, where the final output is decreased in length by crossfade_time/2.").default_value_if("headless", None, "0.00133"))
-        .arg(Arg::with_name("samplerate").short("q").long("samplerate").help("Generator sample rate").default_value("48000"))
+        .arg(Arg::with_name("crossfade-shape").long("crossfade-shape").help("Sets the gain curves of the crossfade, equal-power keeps the loudness of uncorrelated signals constant while linear may dip in the middle").possible_values(&["linear", "equal-power"]).default_value("linear"))
+        .arg(Arg::with_name("auto-loop").long("auto-loop").help("Records this many full engine cycles (120 / RPM seconds each) plus the recommended crossfade of two cycles and crossfades them into a seamless loop, replacing --length and --crossfade").takes_value(true).requires("headless"))
+        .arg(Arg::with_name("autocorrelation-loop").long("autocorrelation-loop").help("Instead of crossfading the middle of the recording, cuts the recording to the loop length (up to half of the recording) at which the recording is most similar to its start and crossfades the --crossfade seconds after the loop into its start").requires("headless").conflicts_with("auto-loop"))
+        .arg(Arg::with_name("stereo").short("s").long("stereo").help("Sets the stereo width (0.0 - 1.0) by which the intake is panned to the left and the exhaust to the right, headless mode records a stereo WAV when set").takes_value(true))
+        .arg(Arg::with_name("dc-filter-freq").long("dc-filter-freq").help("Sets the cutoff frequency in Hz of the high pass which removes the dc offset of the output, 4 by default").takes_value(true))
+        .arg(Arg::with_name("set").long("set").help("Overrides a parameter of the config, e.g. --set muffler.straight_pipe.alpha=0.12 or --set cylinders[0].ignition_time=0.08, waveguide lengths are set in meters with e.g. --set muffler.straight_pipe.length=1.5, can be given multiple times and applies in order").takes_value(true).multiple(true).number_of_values(1))
+        .arg(Arg::with_name("seed").long("seed").help("Seeds the noise sources to generate deterministic output, overrides the seed of the config").takes_value(true))
+        .arg(Arg::with_name("samplerate").short("q").long("samplerate").help("Generator and recording sample rate, the configs are fixed for this rate").default_value("48000"))
+        .arg(Arg::with_name("stems").long("stems").help("Additionally records the intake, engine vibrations and exhaust as separate mono files next to the output file (e.g. output_intake.wav), which sum up to the mono mix").requires("headless"))
+        .arg(Arg::with_name("multichannel").long("multichannel").help("Records the intake, engine vibrations and exhaust as separate tracks of a 3 channel output file in this order").requires("headless").conflicts_with("stereo"))
+        .arg(Arg::with_name("lufs-target").long("lufs-target").help("Normalizes the integrated loudness (ITU-R BS.1770) of the output file to this many LUFS (e.g. -14.0), the gain is reduced if the output would clip").takes_value(true).allow_hyphen_values(true).requires("headless").conflicts_with("stems"))
+        .arg(Arg::with_name("normalize").long("normalize").help("Normalizes the generated audio before crossfading, either its peak to 0.99 (\"peak\") or its integrated loudness (ITU-R BS.1770) to a target in LUFS (e.g. \"lufs:-14\"), the stems and tracks get the same gain as the mix").takes_value(true).allow_hyphen_values(true).requires("headless").conflicts_with("lufs-target"))
+        .arg(Arg::with_name("loop-start").long("loop-start").help("Marks a forward loop starting at this sample frame in the smpl chunk of the WAV output, e.g. a section of an RPM sweep or automation, instead of marking the whole crossfaded output").takes_value(true).requires_all(&["headless", "loop-end"]))
+        .arg(Arg::with_name("loop-end").long("loop-end").help("Last sample frame of the loop marked by --loop-start").takes_value(true).requires_all(&["headless", "loop-start"]))
+        .arg(Arg::with_name("no-loop-markers").long("no-loop-markers").help("Does not write the smpl chunk with the loop markers of crossfaded WAV outputs, for tools which can't read extra chunks").requires("headless").conflicts_with("loop-start"))
+        .arg(Arg::with_name("dry-run").long("dry-run").help("Validates the config and prints the expected size of the output files, the estimated generation time and the recommended crossfade for the RPM without generating anything, exits with a non-zero code if the config is invalid").requires("headless"))
+        .arg(Arg::with_name("measure-lufs").long("measure-lufs").help("Prints the integrated loudness (ITU-R BS.1770) of the generated audio in LUFS instead of saving it").requires("headless").conflicts_with_all(&["lufs-target", "stems"]))
+        .arg(Arg::with_name("no-metadata").long("no-metadata").help("Does not write the engine config, RPM, seed, duration, peak and loudness of a recording to a JSON file next to it (e.g. output.json)"))
+        .arg(Arg::with_name("no-progress").long("no-progress").help("Does not print a progress bar while recording in headless mode, which falls back to percent-done lines if NO_COLOR is set").requires("headless"))
+        .arg(Arg::with_name("watch").long("watch").help("Reloads the config when it is changed on disk, during the warmup in headless mode, which restarts the warmup, and during control port playback, which keeps the current RPM. The GUI always reloads the last loaded config when it changes").requires("config"))
+        .arg(Arg::with_name("audio-device").long("audio-device").help("Plays back audio on the first output device whose name contains this text instead of the default output device, see the list-devices subcommand").takes_value(true).conflicts_with_all(&["headless", "jack"]))
+        .arg(Arg::with_name("jack").long("jack").help("Plays back audio through a JACK client instead of the default audio host, requires the \"jack\" feature").conflicts_with("headless"))
+        .arg(Arg::with_name("control-port").short("p").long("control-port").help("Plays back the engine without a GUI and listens on this UDP port for datagrams setting the RPM, either as text (\"rpm 4500.0\") or as a little-endian binary f32").takes_value(true).conflicts_with("headless"))
+        .arg(Arg::with_name("terminal").long("terminal").help("Plays back the engine without a GUI and controls it with the keyboard: up/down arrows change the RPM by 100, page up/down by 1000, r starts or stops recording to a timestamped WAV file and q quits, requires the \"terminal\" feature and a Unix terminal").conflicts_with("headless").hidden(!cfg!(unix)))
+        .arg(Arg::with_name("osc-port").long("osc-port").help("Listens on this UDP port for OSC messages setting /engine/rpm, /engine/intake_volume and /engine/cylinder/<index>/crank_offset during playback, requires the \"osc\" feature").takes_value(true).conflicts_with("headless"))
+        .arg(Arg::with_name("control-pipe").long("control-pipe").help("Reads text commands (e.g. \"rpm 3500\", \"intake_volume 0.4\", \"save\" or \"quit\") line by line from this named pipe (Unix only), or from stdin if \"-\" is given, during playback").takes_value(true).conflicts_with("headless"))
+        .arg(Arg::with_name("midi-device").long("midi-device").help("Controls the engine during playback with the first MIDI input device whose name contains this text: Note On velocity sets the master volume, Pitch Bend the RPM (300 - 13000), CC 7 the intake volume and CC 11 the exhaust volume, requires the \"midi\" feature").takes_value(true).conflicts_with("headless"))
+        .arg(Arg::with_name("list-midi").long("list-midi").help("Prints the names of all MIDI input devices and exits, requires the \"midi\" feature"))
         .arg(Arg::with_name("no-drag-drop").short("d").long("no-drag-drop").help("Disabled drag-and-drop support for the window").conflicts_with("headless"))
+        .arg(Arg::with_name("preset-dir").long("preset-dir").help("Lists the *.esc files of this directory in the presets panel of the GUI, ./presets by default").takes_value(true).conflicts_with_all(&["headless", "control-port", "terminal"]))
+        .arg(Arg::with_name("oscilloscope-len").long("oscilloscope-len").help("Sets the number of samples shown by the oscilloscope below the waterfall, 960 by default").takes_value(true).conflicts_with_all(&["headless", "control-port", "terminal"]))
+        .arg(Arg::with_name("fft-window").long("fft-window").help("Sets the window function of the FFT of the waterfall, hann resolves close frequencies best and flat-top measures amplitudes most accurately, hamming by default").possible_values(&["hann", "hamming", "blackman", "flat-top", "rectangular"]).takes_value(true).conflicts_with_all(&["headless", "control-port", "terminal"]))
+        .arg(Arg::with_name("fft-size").long("fft-size").help("Sets the number of samples of the FFT of the waterfall, a power of two between 256 and 8192, 1024 by default. Larger sizes resolve lower frequencies but add fewer lines per second").takes_value(true).conflicts_with_all(&["headless", "control-port", "terminal"]))
+        .arg(Arg::with_name("spectrogram-history").long("spectrogram-history").help("Sets the number of waterfall lines exported by the \"Export Spectrogram\" button, 500 by default").takes_value(true).conflicts_with_all(&["headless", "control-port", "terminal"]))
+        .arg(Arg::with_name("analyze").long("analyze").help("Prints the resonances of every pipe of the engine and the ones which coincide with harmonics of the firing frequency at the RPM, then exits"))
+        .arg(Arg::with_name("analyze-out").long("analyze-out").help("Additionally writes the resonances found by --analyze to this CSV file").takes_value(true).requires("analyze"))
+        .arg(Arg::with_name("list-audio-devices").long("list-audio-devices").help("Prints the names of all audio output devices and exits, like the list-devices subcommand"))
+        .arg(Arg::with_name("config-format").long("config-format").help("Reads the config in this format instead of the one of its extension (.json is JSON, .toml is TOML, everything else RON)").takes_value(true).possible_values(&["ron", "json", "toml"]).global(true))
+        .subcommand(SubCommand::with_name("list-devices").about("Prints the names of all audio output devices"))
+        .subcommand(SubCommand::with_name("convert").about("Converts an engine config into the format of the output file's extension (.json, .toml or RON)")
+            .arg(Arg::with_name("input").help("Engine config to convert").required(true))
+            .arg(Arg::with_name("output").help("File to write the converted config to").required(true)))
         .get_matches();
 
+    if matches.subcommand_matches("list-devices").is_some()
+        || matches.is_present("list-audio-devices")
+    {
+        #[cfg(not(feature = "audio"))]
+        {
+            eprintln!("Builds without the \"audio\" feature do not supply audio playback");
+        }
+        #[cfg(feature = "audio")]
+        {
+            if let Err(e) = audio::print_devices() {
+                eprintln!("{}", e);
+                std::process::exit(3);
+            }
+        }
+        return;
+    }
+
+    if matches.is_present("list-presets") {
+        println!("Engine presets:");
+        for (name, _) in presets::PRESETS {
+            println!("    {}", name);
+        }
+        return;
+    }
+
+    if matches.is_present("list-midi") {
+        #[cfg(not(feature = "midi"))]
+        {
+            eprintln!("Builds without the \"midi\" feature do not supply MIDI input");
+        }
+        #[cfg(feature = "midi")]
+        {
+            if let Err(e) = midi::list_devices() {
+                eprintln!("{}", e);
+                std::process::exit(7);
+            }
+        }
+        return;
+    }
+
     let sample_rate = value_t_or_exit!(matches, "samplerate", u32);
+    let config_format = matches
+        .value_of("config-format")
+        .map(|format| ConfigFormat::parse(format).unwrap()); // checked by possible_values
+
+    if let Some(convert) = matches.subcommand_matches("convert") {
+        let input = convert.value_of("input").unwrap();
+        let output = std::path::Path::new(convert.value_of("output").unwrap());
 
-    let mut engine = match matches.value_of("config") {
-        Some(path) => match load_engine(path, sample_rate, path.ends_with("json")) {
+        if let Err(e) = load_engine(input, sample_rate, config_format)
+            .and_then(|engine| save_engine(output, &engine))
+        {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!(
+            "Converted \"{}\" to {} config \"{}\"",
+            input,
+            ConfigFormat::from_path(output).name(),
+            output.display()
+        );
+        return;
+    }
+
+    let mut engine = match (matches.value_of("config"), matches.value_of("preset")) {
+        (Some(path), _) => match load_engine(path, sample_rate, config_format) {
             Ok(engine) => {
                 println!("Successfully loaded config \"{}\"", path);
                 engine
@@ -74,10 +233,20 @@ fn main() {
                 std::process::exit(1);
             }
         },
-        None => {
+        (None, Some(name)) => match presets::load_preset(name, sample_rate) {
+            Ok(engine) => {
+                println!("Successfully loaded preset \"{}\"", name);
+                engine
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => {
             let mut engine =
                 ron::de::from_bytes(DEFAULT_CONFIG).expect("default config is invalid");
-            fix_engine(&mut engine, sample_rate);
+            fix_engine(&mut engine, sample_rate).expect("default config is invalid");
             engine
         }
     };
@@ -86,87 +255,780 @@ fn main() {
         engine.rpm = rpm.max(0.0);
     }
 
+    apply_engine_overrides(&mut engine, &matches, sample_rate);
+
+    if matches.is_present("analyze") {
+        let report = analysis::analyze(&engine);
+        print!("{}", report);
+
+        if let Some(path) = matches.value_of("analyze-out") {
+            match std::fs::write(path, report.to_csv()) {
+                Ok(()) => println!("Saved the resonances to \"{}\"", path),
+                Err(e) => {
+                    eprintln!("Failed to write \"{}\": {}", path, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
+    // --rpm-start ramps over the recording length
+    let rpm_ramp = if matches.is_present("rpm-start") {
+        let rpm_start = value_t_or_exit!(matches, "rpm-start", f32).max(0.0);
+        let rpm_end = value_t_or_exit!(matches, "rpm-end", f32).max(0.0);
+        engine.rpm = rpm_start;
+        Some((rpm_start, rpm_end))
+    } else {
+        None
+    };
+
+    // the duration is only set by start_rpm:end_rpm:duration sweeps
+    let (mut rpm_envelope, rpm_ramp_duration) = match matches.value_of("rpm-envelope") {
+        Some(rpm_envelope) => {
+            let (rpm_envelope, duration) = parse_rpm_ramp(rpm_envelope).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+            engine.rpm = rpm_envelope.sample_at(0.0);
+            (Some(rpm_envelope), duration)
+        }
+        None => (None, None),
+    };
+
+    let throttle_envelope = if matches.is_present("throttle-ramp") {
+        let throttle_envelope = value_t_or_exit!(matches, "throttle-ramp", gen::RpmEnvelope);
+        engine.throttle = throttle_envelope.sample_at(0.0).clamp(0.0, 1.0);
+        engine.current_throttle = engine.throttle;
+        Some(throttle_envelope)
+    } else {
+        None
+    };
+
+    let automation = matches.value_of("automation").map(|path| {
+        let automation = Automation::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        println!("Successfully loaded automation \"{}\"", path);
+
+        // the warmup runs at the values of the first keyframes
+        let values = automation.sample_at(0.0);
+        if let Some(rpm) = values.rpm {
+            engine.rpm = rpm.max(0.0);
+        }
+        if let Some(throttle) = values.throttle {
+            engine.throttle = throttle.clamp(0.0, 1.0);
+            engine.current_throttle = engine.throttle;
+        }
+        automation
+    });
+
     let cli_mode = matches.is_present("headless");
 
+    let dc_filter_freq = if matches.is_present("dc-filter-freq") {
+        let freq = value_t_or_exit!(matches, "dc-filter-freq", f32);
+        if !(freq > 0.0 && freq < sample_rate as f32 / 2.0) {
+            eprintln!(
+                "--dc-filter-freq {} is not between 0 and the nyquist frequency {} Hz",
+                freq,
+                sample_rate / 2
+            );
+            std::process::exit(1);
+        }
+        freq
+    } else {
+        DC_OFFSET_LP_FREQ
+    };
+
     // sound generator
-    let mut generator =
-        gen::Generator::new(sample_rate, engine, LowPassFilter::new(0.5, sample_rate));
+    let mut generator = gen::Generator::new(
+        sample_rate,
+        engine,
+        BiquadFilter::new_highpass(dc_filter_freq, sample_rate),
+    );
 
     generator.volume = value_t!(matches.value_of("volume"), f32).unwrap();
+    if let Some(volume) = automation
+        .as_ref()
+        .and_then(|automation| automation.sample_at(0.0).volume)
+    {
+        generator.volume = volume.max(0.0);
+    }
+
+    let stereo = matches.is_present("stereo");
+    if stereo {
+        generator.stereo_width = value_t_or_exit!(matches, "stereo", f32).clamp(0.0, 1.0);
+    }
 
     if cli_mode {
         let warmup_time = value_t!(matches.value_of("warmup_time"), f32)
             .unwrap()
             .max(0.0); // has default value
-        let record_time = value_t!(matches.value_of("reclen"), f32).unwrap().max(0.0); // has default value
-        let output_filename = matches.value_of("output_file").unwrap(); // has default value
+        let record_time = match (&automation, rpm_ramp_duration) {
+            (_, Some(duration)) if matches.occurrences_of("reclen") == 0 => duration,
+            (Some(automation), _) if matches.occurrences_of("reclen") == 0 => automation.duration(),
+            _ => value_t!(matches.value_of("reclen"), f32).unwrap(), // has default value
+        }
+        .max(0.0);
+
+        // the crossfade flag has a default value in headless mode, so it can't conflict with a sweep
+        if rpm_ramp_duration.is_some()
+            && (matches.occurrences_of("crossfade") != 0
+                || matches.is_present("autocorrelation-loop"))
+        {
+            eprintln!("An RPM sweep can't be combined with --crossfade or --autocorrelation-loop, a sweep can't loop");
+            std::process::exit(4);
+        }
+
+        let auto_loop_cycles = if matches.is_present("auto-loop") {
+            if rpm_ramp.is_some() || rpm_envelope.is_some() || throttle_envelope.is_some() {
+                eprintln!("--auto-loop can't be combined with an RPM or throttle ramp, a sweep can't loop");
+                std::process::exit(4);
+            }
+            if matches.occurrences_of("reclen") != 0 || matches.occurrences_of("crossfade") != 0 {
+                eprintln!("--auto-loop computes the recording length and crossfade, --length and --crossfade can't be set");
+                std::process::exit(4);
+            }
 
-        println!("Warming up..");
+            let cycles = value_t_or_exit!(matches, "auto-loop", u32);
+            // the crossfade of two cycles has to fit into the recording
+            if cycles < 2 {
+                eprintln!("--auto-loop needs at least 2 engine cycles");
+                std::process::exit(4);
+            }
+            Some(cycles)
+        } else {
+            None
+        };
+        let sample_depth = match matches.value_of("bit-depth") {
+            Some("16") => SampleDepth::I16,
+            Some("24") => SampleDepth::I24,
+            _ => SampleDepth::F32,
+        };
+        let ogg_quality = value_t_or_exit!(matches, "ogg-quality", f32);
+        let output_format = match matches.value_of("format") {
+            Some("ogg") => OutputFormat::Ogg {
+                quality: ogg_quality,
+            },
+            Some(_) => sample_depth.into(),
+            None => match OutputFormat::from_extension(
+                std::path::Path::new(matches.value_of("output_file").unwrap()), // has default value
+                sample_depth,
+            ) {
+                OutputFormat::Ogg { .. } => OutputFormat::Ogg {
+                    quality: ogg_quality,
+                },
+                wav => wav,
+            },
+        };
+        // the default output file name follows an explicitly set format
+        let output_filename = if matches.occurrences_of("output_file") == 0 {
+            format!("output.{}", output_format.extension())
+        } else {
+            matches.value_of("output_file").unwrap().to_string()
+        };
 
-        // warm up
-        generator.generate(&mut vec![0.0; seconds_to_samples(warmup_time, sample_rate)]);
+        // (rpm, output file) of every recording
+        let jobs = if matches.is_present("rpm-list") {
+            let output_base = matches.value_of("output-base").unwrap(); // has default value
 
-        println!("Recording..");
+            matches
+                .value_of("rpm-list")
+                .unwrap()
+                .split(',')
+                .map(|rpm| match rpm.trim().parse::<f32>() {
+                    Ok(rpm) => (
+                        Some(rpm.max(0.0)),
+                        format!("{}_{}.{}", output_base, rpm, output_format.extension()),
+                    ),
+                    Err(e) => {
+                        eprintln!("Invalid RPM \"{}\" in RPM list: {}", rpm, e);
+                        std::process::exit(1);
+                    }
+                })
+                .collect::<Vec<_>>()
+        } else {
+            vec![(None, output_filename)]
+        };
+        let batch = matches.is_present("rpm-list");
 
-        // record
-        let mut output = vec![0.0; seconds_to_samples(record_time, sample_rate)];
+        let lufs_target = if matches.is_present("lufs-target") {
+            let lufs_target = value_t_or_exit!(matches, "lufs-target", f32);
+            if !lufs_target.is_finite() {
+                eprintln!("Invalid LUFS target {}", lufs_target);
+                std::process::exit(6);
+            }
+            Some(lufs_target)
+        } else {
+            None
+        };
 
-        generator.generate(&mut output);
+        let normalization = matches.value_of("normalize").map(|normalization| {
+            NormalizationTarget::parse(normalization).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(6);
+            })
+        });
 
-        if matches.occurrences_of("crossfade") != 0 {
-            let crossfade_duration = value_t!(matches.value_of("crossfade"), f32).unwrap();
-            let crossfade_size = seconds_to_samples(
-                crossfade_duration.max(1.0 / sample_rate as f32),
-                sample_rate,
+        let loop_points = if matches.is_present("loop-start") {
+            let start = value_t_or_exit!(matches, "loop-start", u32);
+            let end = value_t_or_exit!(matches, "loop-end", u32);
+            if end <= start {
+                eprintln!(
+                    "The loop end {} must be after the loop start {}",
+                    end, start
+                );
+                std::process::exit(6);
+            }
+            if let OutputFormat::Ogg { .. } = output_format {
+                eprintln!("Loop markers can only be written to WAV files");
+                std::process::exit(6);
+            }
+            Some((start, end))
+        } else {
+            None
+        };
+        let loop_markers = !matches.is_present("no-loop-markers")
+            && matches!(output_format, OutputFormat::Wav { .. });
+
+        if matches.is_present("dry-run") {
+            dry_run(
+                &matches,
+                &generator.engine,
+                &jobs,
+                DryRun {
+                    sample_rate,
+                    warmup_time,
+                    record_time,
+                    auto_loop_cycles,
+                    output_format,
+                    channels: if matches.is_present("multichannel") {
+                        3
+                    } else if stereo {
+                        2
+                    } else {
+                        1
+                    },
+                },
             );
+            return;
+        }
 
-            if crossfade_size >= output.len() {
-                println!("Crossfade duration is too long {}", crossfade_duration);
-                std::process::exit(4);
+        // every recording after the first one uses a fresh copy of the engine so they don't influence each other
+        let mut fresh_engine = generator.engine.clone();
+
+        let config_watcher = watch_config(&matches, sample_rate);
+
+        for (i, (rpm, output_filename)) in jobs.iter().enumerate() {
+            if i != 0 {
+                let (volume, stereo_width) = (generator.volume, generator.stereo_width);
+                generator = gen::Generator::new(
+                    sample_rate,
+                    fresh_engine.clone(),
+                    BiquadFilter::new_highpass(dc_filter_freq, sample_rate),
+                );
+                generator.volume = volume;
+                generator.stereo_width = stereo_width;
+            }
+
+            if let Some(rpm) = rpm {
+                generator.engine.rpm = *rpm;
+            }
+
+            println!("Warming up..");
+
+            // warm up
+            generator.skip(seconds_to_samples(warmup_time, sample_rate));
+
+            // a changed config replaces the engine and restarts the warmup
+            while let Some(mut new_engine) = config_watcher.as_ref().and_then(ConfigWatcher::poll) {
+                new_engine.rpm = generator.engine.rpm;
+                apply_engine_overrides(&mut new_engine, &matches, sample_rate);
+                fresh_engine = new_engine.clone();
+                generator.engine = new_engine;
+
+                println!("Config changed, warming up again..");
+                generator.skip(seconds_to_samples(warmup_time, sample_rate));
+            }
+
+            let channels = if stereo { 2 } else { 1 };
+            let multichannel = matches.is_present("multichannel");
+            let generate: fn(&mut gen::Generator, &mut [f32]) = if stereo {
+                gen::Generator::generate_stereo
+            } else {
+                gen::Generator::generate
+            };
+
+            if matches.is_present("stems") || multichannel {
+                generator.start_stems();
+            }
+
+            // (loop length, crossfade size) in samples
+            let auto_loop = auto_loop_cycles.map(|cycles| {
+                if generator.engine.rpm <= 0.0 {
+                    eprintln!("--auto-loop needs an RPM above 0");
+                    std::process::exit(4);
+                }
+
+                let cycle_samples = 120.0 / generator.engine.rpm * sample_rate as f32;
+                (
+                    (cycles as f32 * cycle_samples).round() as usize,
+                    (cycle_samples * 2.0).round() as usize,
+                )
+            });
+
+            // record
+            let frames = match auto_loop {
+                // the crossfade shortens the recording by half of its size
+                Some((loop_len, crossfade_size)) => loop_len + crossfade_size / 2,
+                None => seconds_to_samples(record_time, sample_rate),
+            };
+            let mut output = vec![0.0; frames * channels];
+
+            if let Some(throttle_envelope) = &throttle_envelope {
+                println!(
+                    "Following the throttle envelope from {:.0}% to {:.0}% over {} sec",
+                    throttle_envelope.sample_at(0.0).clamp(0.0, 1.0) * 100.0,
+                    throttle_envelope
+                        .sample_at(throttle_envelope.duration())
+                        .clamp(0.0, 1.0)
+                        * 100.0,
+                    throttle_envelope.duration()
+                );
+
+                generator.set_throttle_envelope(Some(throttle_envelope.clone()));
+            }
+
+            let progress_reporter = if matches.is_present("no-progress") {
+                None
+            } else {
+                let progress_reporter = Arc::new(ProgressReporter::new(frames));
+                let callback_reporter = progress_reporter.clone();
+                generator.set_progress_callback(
+                    Some(Box::new(move |frames_done, _| {
+                        callback_reporter.update(frames_done)
+                    })),
+                    frames,
+                );
+                Some(progress_reporter)
+            };
+
+            // (sample index, rpm) at the start of every RPM_RAMP_STEP samples
+            let mut rpm_log = Vec::new();
+            // written to the metadata of the recording
+            let recorded_engine = generator.engine.clone();
+
+            match (rpm_ramp, &automation) {
+                (_, Some(automation)) => {
+                    println!(
+                        "Recording (following the automation of {} keyframes over {} sec)..",
+                        automation.keyframes().len(),
+                        automation.duration()
+                    );
+
+                    for (i, chunk) in output.chunks_mut(RPM_RAMP_STEP * channels).enumerate() {
+                        let values =
+                            automation.sample_at((i * RPM_RAMP_STEP) as f32 / sample_rate as f32);
+
+                        if let Some(rpm) = values.rpm {
+                            generator.engine.rpm = rpm.max(0.0);
+                        }
+                        if let Some(throttle) = values.throttle {
+                            generator.set_throttle(throttle);
+                        }
+                        if let Some(volume) = values.volume {
+                            generator.volume = volume.max(0.0);
+                        }
+
+                        rpm_log.push((i * RPM_RAMP_STEP, generator.engine.rpm));
+                        generate(&mut generator, chunk);
+                    }
+                }
+                (Some((rpm_start, rpm_end)), None) => {
+                    let exponential = matches.is_present("exponential-ramp");
+
+                    println!(
+                        "Recording (sweeping from {} to {} RPM{})..",
+                        rpm_start,
+                        rpm_end,
+                        if exponential { " exponentially" } else { "" }
+                    );
+
+                    for (i, chunk) in output.chunks_mut(RPM_RAMP_STEP * channels).enumerate() {
+                        let progress = (i * RPM_RAMP_STEP) as f32 / frames as f32;
+
+                        generator.engine.rpm = if exponential {
+                            // an exponential ramp can't start or end at 0 RPM
+                            let (rpm_start, rpm_end) = (rpm_start.max(1.0), rpm_end.max(1.0));
+                            rpm_start * (rpm_end / rpm_start).powf(progress)
+                        } else {
+                            rpm_start + (rpm_end - rpm_start) * progress
+                        };
+
+                        rpm_log.push((i * RPM_RAMP_STEP, generator.engine.rpm));
+                        generate(&mut generator, chunk);
+                    }
+                }
+                (None, None) => {
+                    // the generator follows the envelope by itself
+                    rpm_log = (0..frames)
+                        .step_by(RPM_RAMP_STEP)
+                        .map(|sample_index| {
+                            let rpm = match &rpm_envelope {
+                                Some(rpm_envelope) => rpm_envelope
+                                    .sample_at(samples_to_seconds(sample_index, sample_rate)),
+                                None => generator.engine.rpm,
+                            };
+                            (sample_index, rpm)
+                        })
+                        .collect();
+
+                    match rpm_envelope.take() {
+                        Some(rpm_envelope) => {
+                            println!(
+                                "Recording (following the RPM envelope from {} to {} RPM over {} sec)..",
+                                rpm_envelope.sample_at(0.0),
+                                rpm_envelope.sample_at(rpm_envelope.duration()),
+                                rpm_envelope.duration()
+                            );
+
+                            generator.set_rpm_envelope(Some(rpm_envelope));
+                        }
+                        None if batch => println!(
+                            "[{}/{}] Recording {} RPM..",
+                            i + 1,
+                            jobs.len(),
+                            generator.engine.rpm
+                        ),
+                        None => println!("Recording.."),
+                    }
+
+                    generate(&mut generator, &mut output);
+                }
+            }
+
+            if let Some(progress_reporter) = progress_reporter {
+                generator.set_progress_callback(None, 0);
+                progress_reporter.finish();
+            }
+
+            // intake, engine vibrations and exhaust
+            let mut tracks = generator
+                .take_stems()
+                .map(|stems| vec![stems.intake, stems.engine_vibrations, stems.exhaust])
+                .unwrap_or_default();
+
+            if let Some(target) = normalization {
+                match normalize(&mut output, target, sample_rate, channels as u16) {
+                    Some(gain) => {
+                        for track in tracks.iter_mut() {
+                            track.iter_mut().for_each(|sample| *sample *= gain);
+                        }
+                        println!("Normalized by {:+.2} dB", 20.0 * gain.log10());
+                    }
+                    None => println!("Output is silent, skipping the normalization"),
+                }
+            }
+
+            let autocorrelation_loop = matches.is_present("autocorrelation-loop");
+            let looped = matches.occurrences_of("crossfade") != 0
+                || auto_loop.is_some()
+                || autocorrelation_loop;
+            if looped {
+                let crossfade_duration = value_t!(matches.value_of("crossfade"), f32).unwrap();
+                let crossfade_size = match auto_loop {
+                    Some((_, crossfade_size)) => crossfade_size,
+                    None => seconds_to_samples(
+                        crossfade_duration.max(1.0 / sample_rate as f32),
+                        sample_rate,
+                    ),
+                };
+
+                if crossfade_size >= frames {
+                    println!("Crossfade duration is too long {}", crossfade_duration);
+                    std::process::exit(4);
+                }
+
+                let shape = match matches.value_of("crossfade-shape") {
+                    Some("equal-power") => CrossfadeShape::EqualPower,
+                    _ => CrossfadeShape::Linear,
+                };
+
+                // the loop point is searched in the mono mix and applied to all channels and tracks
+                let loop_len = if autocorrelation_loop {
+                    let loop_len = if stereo {
+                        find_loop_point(
+                            &output
+                                .chunks_exact(2)
+                                .map(|lr| (lr[0] + lr[1]) * 0.5)
+                                .collect::<Vec<f32>>(),
+                            crossfade_size,
+                        )
+                    } else {
+                        find_loop_point(&output, crossfade_size)
+                    };
+
+                    if loop_len == 0 {
+                        println!("Recording is too short to find a loop point");
+                        std::process::exit(4);
+                    }
+
+                    println!(
+                        "Found loop point after {} samples ({:.5} sec)",
+                        loop_len,
+                        samples_to_seconds(loop_len, sample_rate)
+                    );
+                    Some(loop_len)
+                } else {
+                    None
+                };
+                let crossfade = |samples: &[f32]| match loop_len {
+                    Some(loop_len) => crossfade_loop(samples, loop_len, crossfade_size, shape),
+                    None => crossfade(samples, crossfade_size, shape),
+                };
+
+                println!("Crossfading..");
+
+                output = if stereo {
+                    let left = output.iter().step_by(2).copied().collect::<Vec<f32>>();
+                    let right = output
+                        .iter()
+                        .skip(1)
+                        .step_by(2)
+                        .copied()
+                        .collect::<Vec<f32>>();
+
+                    crossfade(&left)
+                        .into_iter()
+                        .zip(crossfade(&right))
+                        .flat_map(|(l, r)| vec![l, r])
+                        .collect()
+                } else {
+                    crossfade(&output)
+                };
+
+                for track in tracks.iter_mut() {
+                    *track = crossfade(track);
+                }
+
+                if let (Some(cycles), Some((loop_len, _))) = (auto_loop_cycles, auto_loop) {
+                    println!(
+                        "Loop length: {:.5} sec ({} samples, {} engine cycles at {} RPM)",
+                        samples_to_seconds(loop_len, sample_rate),
+                        loop_len,
+                        cycles,
+                        generator.engine.rpm
+                    );
+                }
+            }
+
+            if matches.is_present("measure-lufs") {
+                let mut monitor = LufsMonitor::new(sample_rate, channels as u16);
+                monitor.update(&output);
+                println!("Integrated loudness: {:.1} LUFS", monitor.lufs());
+                continue;
+            }
+
+            let frames_written = if multichannel {
+                tracks.first().map_or(0, Vec::len)
+            } else {
+                output.len() / channels
+            };
+            // crossfaded outputs loop as a whole
+            let loop_points = match loop_points {
+                Some((_, end)) if end as usize >= frames_written => {
+                    eprintln!(
+                        "The loop end {} is after the end of the output ({} samples)",
+                        end, frames_written
+                    );
+                    std::process::exit(6);
+                }
+                Some(loop_points) => Some(loop_points),
+                None if looped && frames_written > 0 => Some((0, frames_written as u32 - 1)),
+                None => None,
+            }
+            .filter(|_| loop_markers);
+            let mark_loop = |filename: &str| {
+                if let Some((start, end)) = loop_points {
+                    if let Err(e) =
+                        write_loop_points(std::path::Path::new(filename), sample_rate, start, end)
+                    {
+                        eprintln!(
+                            "Failed to write the loop markers to \"{}\": {}",
+                            filename, e
+                        );
+                        std::process::exit(6);
+                    }
+                }
+            };
+
+            let recorder = if multichannel {
+                Recorder::new_multichannel(
+                    output_filename.as_str().into(),
+                    sample_rate,
+                    output_format,
+                    3,
+                )
+            } else if stereo {
+                Recorder::new_stereo(output_filename.as_str().into(), sample_rate, output_format)
+            } else {
+                Recorder::new(output_filename.as_str().into(), sample_rate, output_format)
+            };
+            let mut recorder =
+                recorder.unwrap_or_else(|e| exit_recording_failed(output_filename, e));
+            recorder.set_normalization(
+                lufs_target.map(|lufs| Normalization::new(NormalizationTarget::Lufs(lufs))),
+            );
+            recorder.set_info(&recorded_engine);
+            if !matches.is_present("no-metadata") {
+                recorder.set_metadata(Some(recorded_engine.clone()));
+            }
+
+            println!("Started recording to \"{}\"", output_filename);
+
+            // records into the output file asynchronously
+            if multichannel {
+                recorder.record_multichannel(tracks.clone());
+            } else {
+                recorder.record(output.to_vec());
             }
+            recorder.stop_wait();
 
-            println!("Crossfading..");
+            if let Some(e) = recorder.take_error() {
+                exit_recording_failed(output_filename, e);
+            }
+            mark_loop(output_filename);
+            if let Some((start, end)) = loop_points {
+                println!("Marked the loop from sample {} to {}", start, end);
+            }
 
-            let len = output.len();
-            let half_len = len / 2;
+            if matches.is_present("rpm-log") {
+                let rpm_log_filename = rpm_log_file_name(output_filename);
+                if let Err(e) = write_rpm_log(&rpm_log_filename, &rpm_log) {
+                    eprintln!(
+                        "Failed to write the RPM log \"{}\": {}",
+                        rpm_log_filename, e
+                    );
+                    std::process::exit(6);
+                }
+                println!("Wrote the RPM log to \"{}\"", rpm_log_filename);
+            }
+
+            // the seam is only meaningful if the recording is played as a loop
+            if looped {
+                let seam = if stereo {
+                    seam_quality(
+                        &output
+                            .chunks_exact(2)
+                            .map(|lr| (lr[0] + lr[1]) * 0.5)
+                            .collect::<Vec<f32>>(),
+                    )
+                } else {
+                    seam_quality(&output)
+                };
+                println!(
+                    "Loop seam: discontinuity {:.2}x the average sample step, spectral difference {:.2} dB",
+                    seam.discontinuity, seam.spectral_difference_db
+                );
+            }
 
-            let mut shifted = output.clone();
+            if matches.is_present("stems") {
+                for (stem, track) in ["intake", "vibrations", "exhaust"].iter().zip(tracks) {
+                    let stem_filename = stem_file_name(output_filename, stem);
+                    let mut recorder =
+                        Recorder::new(stem_filename.clone().into(), sample_rate, output_format)
+                            .unwrap_or_else(|e| exit_recording_failed(&stem_filename, e));
+                    recorder.set_info(&recorded_engine);
 
-            shifted
-                .iter_mut()
-                .enumerate()
-                .for_each(|(i, x)| *x = output[(half_len + i) % len]);
+                    println!("Started recording stem to \"{}\"", stem_filename);
 
-            output = Vec::with_capacity(shifted.len() - crossfade_size / 2);
-            output.extend_from_slice(&shifted[..half_len]);
-            output.extend_from_slice(&shifted[(half_len + crossfade_size / 2)..]);
+                    recorder.record(track);
+                    recorder.stop_wait();
 
-            let fade_len = crossfade_size / 2;
-            let start = half_len - fade_len;
-            let end = half_len;
-            for i in start..end {
-                let fade = (i - start) as f32 / fade_len as f32;
-                output[i] = shifted[i] * (1.0 - fade) + shifted[i + fade_len] * fade;
+                    if let Some(e) = recorder.take_error() {
+                        exit_recording_failed(&stem_filename, e);
+                    }
+                    mark_loop(&stem_filename);
+                }
             }
         }
+    } else if matches.is_present("control-port") || matches.is_present("terminal") {
+        #[cfg(not(feature = "audio"))]
+        {
+            eprintln!("Builds without the \"audio\" feature do not supply audio playback");
+        }
+        #[cfg(feature = "audio")]
+        {
+            let (generator_thread, generator) = GeneratorThread::new(generator);
 
-        let mut recorder = Recorder::new(output_filename.into(), sample_rate);
+            // the fft data is not used without a GUI
+            let (_audio, _) = match init_audio(
+                generator_thread,
+                sample_rate,
+                matches.is_present("jack"),
+                matches.value_of("audio-device"),
+            ) {
+                Ok(audio) => audio,
+                Err(e) => {
+                    eprintln!("Failed to initialize audio: {}", e);
+                    std::process::exit(3);
+                }
+            };
+
+            let _osc = start_osc(&matches, generator.clone());
+            let _midi = start_midi(&matches, generator.clone());
+            start_control_pipe(&matches, generator.clone());
+            let config_watcher = watch_config(&matches, sample_rate);
+
+            if matches.is_present("control-port") {
+                let control_port = value_t_or_exit!(matches, "control-port", u16);
+                if let Err(e) = control::listen(control_port, generator.clone()) {
+                    eprintln!("{}", e);
+                    std::process::exit(5);
+                }
+            }
+            start_terminal(&matches, generator.clone(), sample_rate);
+
+            // playback and control run on their own threads until the process is killed
+            match config_watcher {
+                Some(config_watcher) => loop {
+                    std::thread::sleep(CONFIG_POLL_INTERVAL);
 
-        println!("Started recording to \"{}\"", output_filename);
+                    // a config which fails to load is reported by the watcher and keeps the old engine running
+                    if let Some(mut new_engine) = config_watcher.poll() {
+                        apply_engine_overrides(&mut new_engine, &matches, sample_rate);
 
-        // records into wav file asynchronously
-        recorder.record(output.to_vec());
-        recorder.stop_wait();
+                        // keeps the current rpm
+                        generator.apply(move |generator| {
+                            generator.swap_engine(
+                                new_engine,
+                                seconds_to_samples(gen::ENGINE_SWAP_TIME, sample_rate),
+                            )
+                        });
+                    }
+                },
+                None => loop {
+                    std::thread::park();
+                },
+            }
+        }
     } else {
-        #[cfg(not(gui))]
+        #[cfg(not(feature = "gui"))]
         {
             eprintln!("Headless builds do not supply GUI");
         }
         #[cfg(feature = "gui")]
         {
-            let generator = Arc::new(RwLock::new(generator));
+            let (generator_thread, generator) = GeneratorThread::new(generator);
 
-            let (audio, fft_receiver) = match audio::init(generator.clone(), sample_rate) {
+            // audio lives until the end of this block
+            let (audio, receivers) = match init_audio(
+                generator_thread,
+                sample_rate,
+                matches.is_present("jack"),
+                matches.value_of("audio-device"),
+            ) {
                 Ok(audio) => audio,
                 Err(e) => {
                     eprintln!("Failed to initialize SDL2 audio: {}", e);
@@ -177,12 +1039,34 @@ fn main() {
             // this channel is bounded in practice by the channel between the following ExactStreamer of the FFTStreamer and it's channel's capacity (created in crate::audio::init)
             let (fft_sender, gui_fft_receiver) = crossbeam_channel::bounded(4);
 
+            let fft_size = if matches.is_present("fft-size") {
+                let size = value_t_or_exit!(matches, "fft-size", usize);
+                if !size.is_power_of_two() || !(MIN_FFT_SIZE..=MAX_FFT_SIZE).contains(&size) {
+                    eprintln!(
+                        "--fft-size {} is not a power of two between {} and {}",
+                        size, MIN_FFT_SIZE, MAX_FFT_SIZE
+                    );
+                    std::process::exit(1);
+                }
+                size
+            } else {
+                WATERFALL_WIDTH as usize * 2 /* only half of the spectrum can be used */
+            };
+
             let mut fft = FFTStreamer::new(
-                WATERFALL_WIDTH as usize * 2, /* only half of the spectrum can be used */
-                ExactStreamer::new(GENERATOR_BUFFER_SIZE, fft_receiver),
+                fft_size,
+                sample_rate,
+                matches
+                    .value_of("fft-window")
+                    .map_or(WindowFunction::Hamming, |window| {
+                        WindowFunction::parse(window).unwrap() // checked by possible_values
+                    }),
+                ExactStreamer::new(GENERATOR_BUFFER_SIZE, receivers.fft),
                 fft_sender,
             );
 
+            let fft_window_function = fft.window_function();
+
             // spawns thread for fft to create the waterfall lines
             std::thread::spawn(move || {
                 fft.run();
@@ -194,8 +1078,8 @@ fn main() {
 
                 // Build the window.
                 let mut events_loop = glium::glutin::event_loop::EventLoop::new();
-                let mut window = glium::glutin::window::WindowBuilder::new()
-                    .with_title("Engine Sound Generator")
+                let window = glium::glutin::window::WindowBuilder::new()
+                    .with_title(gui::WINDOW_TITLE)
                     .with_inner_size::<PhysicalSize<u32>>((WINDOW_WIDTH, WINDOW_HEIGHT).into())
                     .with_max_inner_size::<PhysicalSize<u32>>(
                         (WINDOW_WIDTH, WINDOW_HEIGHT + 1000.0).into(),
@@ -222,18 +1106,64 @@ fn main() {
                 let mut ui = conrod_core::UiBuilder::new([WINDOW_WIDTH, WINDOW_HEIGHT])
                     .theme(gui::theme())
                     .build();
-                let ids = gui::Ids::new(ui.widget_id_generator());
+                let mut ids = gui::Ids::new(ui.widget_id_generator());
 
                 ui.fonts.insert(
                     Font::from_bytes(&include_bytes!("../fonts/NotoSans/NotoSans-Regular.ttf")[..])
                         .unwrap(),
                 );
 
-                let mut gui_state = GUIState::new(gui_fft_receiver);
+                let mut gui_state = GUIState::new(
+                    gui_fft_receiver,
+                    Oscilloscope::new(
+                        receivers.oscilloscope,
+                        if matches.is_present("oscilloscope-len") {
+                            value_t_or_exit!(matches, "oscilloscope-len", usize)
+                        } else {
+                            OSCILLOSCOPE_LEN
+                        },
+                    ),
+                );
+                gui_state.set_preset_dir(matches.value_of("preset-dir").unwrap_or("presets"));
+                if let Some(device_switch) = audio.device_switch() {
+                    gui_state.set_audio_devices(
+                        audio::list_devices(),
+                        matches.value_of("audio-device"),
+                        device_switch,
+                    );
+                }
+                gui_state.set_recording_metadata(!matches.is_present("no-metadata"));
+                gui_state.set_fft_window_function(fft_window_function);
+                if matches.is_present("spectrogram-history") {
+                    gui_state.set_max_history(value_t_or_exit!(
+                        matches,
+                        "spectrogram-history",
+                        usize
+                    ));
+                }
+                if let Some(path) = matches.value_of("config") {
+                    gui_state.watch_config(path, sample_rate);
+                }
+
+                // the OSC listener lives until the end of this block
+                let _osc = start_osc(&matches, generator.clone());
+                #[cfg(feature = "midi")]
+                gui_state.set_midi(
+                    matches.value_of("midi-device").unwrap_or_default(),
+                    start_midi(&matches, generator.clone()),
+                );
+                #[cfg(not(feature = "midi"))]
+                start_midi(&matches, generator.clone());
+                start_control_pipe(&matches, generator.clone());
+                #[cfg(feature = "osc")]
+                if let Some(osc) = &_osc {
+                    gui_state.set_osc_status(format!("OSC: listening on {}", osc.addr()));
+                }
 
                 let mut renderer = conrod_glium::Renderer::new(display.get()).unwrap();
 
                 let mut event_loop = support::EventLoop::new();
+                let mut modifiers = glium::glutin::event::ModifiersState::empty();
                 'main: loop {
                     event_loop.needs_update();
                     for event in event_loop.next(&mut events_loop).iter() {
@@ -251,23 +1181,41 @@ fn main() {
                             match event {
                                 glium::glutin::event::WindowEvent::DroppedFile(path) => {
                                     if let Some(path) = path.to_str() {
-                                        match crate::load_engine(
-                                            path,
-                                            sample_rate,
-                                            path.ends_with("json"),
-                                        ) {
+                                        match load_engine(path, sample_rate, None) {
                                             Ok(new_engine) => {
                                                 println!(
                                                     "Successfully loaded engine config \"{}\"",
                                                     &path
                                                 );
-                                                generator.write().engine = new_engine;
+                                                gui_state.watch_config(path, sample_rate);
+                                                if gui_state.take_morph_on_load() {
+                                                    generator.apply(move |generator| {
+                                                        generator.morph_to(
+                                                            new_engine,
+                                                            seconds_to_samples(
+                                                                gui::MORPH_TIME,
+                                                                sample_rate,
+                                                            ),
+                                                        )
+                                                    });
+                                                } else {
+                                                    generator.apply(move |generator| {
+                                                        generator.swap_engine(
+                                                            new_engine,
+                                                            seconds_to_samples(
+                                                                gen::ENGINE_SWAP_TIME,
+                                                                sample_rate,
+                                                            ),
+                                                        )
+                                                    });
+                                                }
                                             }
                                             Err(e) => {
                                                 eprintln!(
                                                     "Failed to load engine config \"{}\": {}",
                                                     path, e
                                                 );
+                                                gui_state.set_load_error(e);
                                             }
                                         }
                                     }
@@ -282,6 +1230,27 @@ fn main() {
                                         },
                                     ..
                                 } => break 'main,
+                                glium::glutin::event::WindowEvent::ModifiersChanged(new) => {
+                                    modifiers = *new;
+                                }
+                                glium::glutin::event::WindowEvent::KeyboardInput {
+                                    input:
+                                        glium::glutin::event::KeyboardInput {
+                                            state: glium::glutin::event::ElementState::Pressed,
+                                            virtual_keycode: Some(key),
+                                            ..
+                                        },
+                                    ..
+                                } if modifiers.ctrl() => {
+                                    use glium::glutin::event::VirtualKeyCode;
+
+                                    match key {
+                                        VirtualKeyCode::Z if modifiers.shift() => gui_state.redo(),
+                                        VirtualKeyCode::Z => gui_state.undo(),
+                                        VirtualKeyCode::Y => gui_state.redo(),
+                                        _ => (),
+                                    }
+                                }
                                 _ => (),
                             }
                         }
@@ -289,8 +1258,8 @@ fn main() {
 
                     let image_map = gui::gui(
                         &mut ui.set_widgets(),
-                        &ids,
-                        generator.clone(),
+                        &mut ids,
+                        &generator,
                         &mut gui_state,
                         display.get(),
                     );
@@ -304,9 +1273,353 @@ fn main() {
                     target.finish().unwrap();
                 }
             }
+        }
+    }
+}
+
+/// starts watching the config if `--watch` is given, exits if the config can't be watched
+fn watch_config(matches: &clap::ArgMatches, sample_rate: u32) -> Option<ConfigWatcher> {
+    if !matches.is_present("watch") {
+        return None;
+    }
+
+    let path = matches.value_of("config").unwrap(); // required by watch
+    match ConfigWatcher::new(path, sample_rate) {
+        Ok(config_watcher) => {
+            println!("Watching config \"{}\"", config_watcher.path().display());
+            Some(config_watcher)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// applies the engine parameters given as arguments which are not handled by the recording itself
+fn apply_engine_overrides(engine: &mut gen::Engine, matches: &clap::ArgMatches, sample_rate: u32) {
+    if let Some(assignments) = matches.values_of("set") {
+        if let Err(e) = set_parameters(engine, &assignments.collect::<Vec<_>>(), sample_rate) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if matches.is_present("throttle") {
+        engine.throttle = value_t_or_exit!(matches, "throttle", f32).clamp(0.0, 1.0);
+        engine.current_throttle = engine.throttle;
+    }
+
+    if matches.is_present("seed") {
+        engine.noise_seed = Some(value_t_or_exit!(matches, "seed", u64));
+        engine.seed_noise();
+    }
+}
+
+/// settings of the recordings which `dry_run` reports on
+struct DryRun {
+    sample_rate: u32,
+    warmup_time: f32,
+    record_time: f32,
+    auto_loop_cycles: Option<u32>,
+    output_format: OutputFormat,
+    /// channels of the output files
+    channels: usize,
+}
+
+/// prints what the recordings of `jobs` would produce, see `--dry-run`
+fn dry_run(
+    matches: &clap::ArgMatches,
+    engine: &gen::Engine,
+    jobs: &[(Option<f32>, String)],
+    settings: DryRun,
+) {
+    println!("Dry run, nothing is generated");
+
+    // the errors have already been reported while loading the config
+    match engine.validate(settings.sample_rate).len() {
+        0 => println!("The config is valid"),
+        warnings => println!("The config is valid with {} warning(s)", warnings),
+    }
+
+    let sample_rate = settings.sample_rate;
+    let looped = matches.occurrences_of("crossfade") != 0
+        || settings.auto_loop_cycles.is_some()
+        || matches.is_present("autocorrelation-loop");
+    let bytes_per_sample = match settings.output_format {
+        OutputFormat::Wav { bits, .. } => Some(bits as usize / 8),
+        // the size of compressed files depends on the audio
+        OutputFormat::Ogg { .. } => None,
+    };
+    let stems = if matches.is_present("stems") { 3 } else { 0 };
+
+    let mut total_samples = 0;
+    for (rpm, output_filename) in jobs {
+        let rpm = rpm.unwrap_or(engine.rpm);
+        let crossfade = match settings.auto_loop_cycles {
+            Some(_) => recommended_crossfade(rpm),
+            None => value_t!(matches.value_of("crossfade"), f32).unwrap_or(0.0),
+        };
+        let record_time = match settings.auto_loop_cycles {
+            Some(cycles) => cycles as f32 * 120.0 / rpm + crossfade / 2.0,
+            None => settings.record_time,
+        };
+
+        let frames = seconds_to_samples(record_time, sample_rate);
+        total_samples += seconds_to_samples(settings.warmup_time, sample_rate) + frames;
+        // the crossfade shortens the recording by half of its size
+        let output_frames = if looped {
+            frames.saturating_sub(seconds_to_samples(crossfade, sample_rate) / 2)
+        } else {
+            frames
+        };
+
+        match bytes_per_sample {
+            Some(bytes_per_sample) => {
+                let bytes = output_frames * settings.channels * bytes_per_sample;
+                println!(
+                    "\"{}\": {:.3} sec, about {} bytes ({:.2} MB){}",
+                    output_filename,
+                    samples_to_seconds(output_frames, sample_rate),
+                    bytes,
+                    bytes as f32 / 1_000_000.0,
+                    if stems > 0 {
+                        format!(
+                            " and {} stems of {} bytes each",
+                            stems,
+                            output_frames * bytes_per_sample
+                        )
+                    } else {
+                        String::new()
+                    }
+                );
+            }
+            None => println!(
+                "\"{}\": {:.3} sec, the size of Ogg Vorbis files depends on the audio",
+                output_filename,
+                samples_to_seconds(output_frames, sample_rate)
+            ),
+        }
+    }
+
+    println!(
+        "Estimated generation time: {:.2} sec",
+        estimate_generation_time(engine, sample_rate, total_samples).as_secs_f32()
+    );
 
-            // audio lives until here
-            std::mem::drop(audio);
+    if engine.rpm > 0.0 {
+        let crossfade = recommended_crossfade(engine.rpm);
+        println!(
+            "Recommended crossfade at {:.0} RPM: {:.5} sec (--crossfade {:.5}), or use --auto-loop",
+            engine.rpm, crossfade, crossfade
+        );
+    }
+}
+
+/// prints the recording error and exits
+fn exit_recording_failed(output_filename: &str, e: RecorderError) -> ! {
+    eprintln!("Failed to record to \"{}\": {}", output_filename, e);
+    std::process::exit(6);
+}
+
+/// parses the `time:rpm` keyframes of `--rpm-envelope`/`--rpm-ramp` or a `start_rpm:end_rpm:duration` sweep,
+/// which also returns its duration
+fn parse_rpm_ramp(rpm_ramp: &str) -> Result<(gen::RpmEnvelope, Option<f32>), String> {
+    // a single keyframe has one colon
+    if rpm_ramp.contains(',') || rpm_ramp.matches(':').count() != 2 {
+        return rpm_ramp.parse().map(|rpm_envelope| (rpm_envelope, None));
+    }
+
+    let values = rpm_ramp
+        .split(':')
+        .map(|value| value.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| format!("Invalid RPM ramp \"{}\": {}", rpm_ramp, e))?;
+
+    match values[..] {
+        [rpm_start, rpm_end, duration] if duration > 0.0 => {
+            let rpm_envelope = gen::RpmEnvelope::new(vec![
+                (0.0, rpm_start.max(0.0)),
+                (duration, rpm_end.max(0.0)),
+            ])
+            .expect("the sweep has keyframes");
+            Ok((rpm_envelope, Some(duration)))
         }
+        [_, _, duration] => Err(format!(
+            "Invalid RPM ramp \"{}\": the duration {} has to be greater than 0",
+            rpm_ramp, duration
+        )),
+        _ => Err(format!(
+            "Invalid RPM ramp \"{}\": expected start_rpm:end_rpm:duration",
+            rpm_ramp
+        )),
+    }
+}
+
+/// replaces the extension of the output file name with `_rpm.csv`, e.g. `output.wav` -> `output_rpm.csv`
+fn rpm_log_file_name(output_filename: &str) -> String {
+    let path = std::path::Path::new(output_filename);
+    let file_stem = path
+        .file_stem()
+        .map(|file_stem| file_stem.to_string_lossy())
+        .unwrap_or_default();
+
+    path.with_file_name(format!("{}_rpm.csv", file_stem))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// writes (sample index, rpm) rows with a `sample_index,rpm` header
+fn write_rpm_log(path: &str, rpm_log: &[(usize, f32)]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    writeln!(file, "sample_index,rpm")?;
+    for (sample_index, rpm) in rpm_log {
+        writeln!(file, "{},{}", sample_index, rpm)?;
     }
+    file.flush()
+}
+
+/// inserts `_{stem}` before the extension of the output file name, e.g. `output.wav` -> `output_intake.wav`
+fn stem_file_name(output_filename: &str, stem: &str) -> String {
+    let path = std::path::Path::new(output_filename);
+    let file_stem = path
+        .file_stem()
+        .map(|file_stem| file_stem.to_string_lossy())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy())
+        .unwrap_or_else(|| "wav".into());
+
+    path.with_file_name(format!("{}_{}.{}", file_stem, stem, extension))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// starts the OSC listener if an OSC port was given, exits if the socket can't be bound
+#[cfg(feature = "osc")]
+fn start_osc(matches: &clap::ArgMatches, generator: GeneratorHandle) -> Option<osc::OscHandler> {
+    if !matches.is_present("osc-port") {
+        return None;
+    }
+
+    let osc_port = value_t_or_exit!(matches, "osc-port", u16);
+    match osc::OscHandler::new(osc_port, generator) {
+        Ok(osc) => {
+            println!("Listening for OSC messages on {}", osc.addr());
+            Some(osc)
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(5);
+        }
+    }
+}
+
+#[cfg(all(feature = "audio", not(feature = "osc")))]
+fn start_osc(matches: &clap::ArgMatches, _generator: GeneratorHandle) -> Option<()> {
+    if matches.is_present("osc-port") {
+        eprintln!("Builds without the \"osc\" feature do not supply OSC control");
+    }
+
+    None
+}
+
+/// connects to the MIDI input if a MIDI device was given, exits if it can't be opened
+#[cfg(feature = "midi")]
+fn start_midi(
+    matches: &clap::ArgMatches,
+    generator: GeneratorHandle,
+) -> Option<midir::MidiInputConnection<()>> {
+    let device_name = matches.value_of("midi-device")?;
+
+    match midi::MidiController::new(device_name).run(generator) {
+        Ok(connection) => Some(connection),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(7);
+        }
+    }
+}
+
+#[cfg(all(feature = "audio", not(feature = "midi")))]
+fn start_midi(matches: &clap::ArgMatches, _generator: GeneratorHandle) -> Option<()> {
+    if matches.is_present("midi-device") {
+        eprintln!("Builds without the \"midi\" feature do not supply MIDI input");
+    }
+
+    None
+}
+
+/// reads commands from the control pipe on a new thread if a path was given,
+/// exits once `quit` was read or if the pipe can't be opened, playback continues if the input ends
+#[cfg(feature = "audio")]
+fn start_control_pipe(matches: &clap::ArgMatches, generator: GeneratorHandle) {
+    let server = match matches.value_of("control-pipe") {
+        Some(path) => control_pipe::ControlServer::new(path),
+        None => return,
+    };
+
+    std::thread::spawn(move || match server.serve(generator) {
+        Ok(true) => std::process::exit(0),
+        Ok(false) => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(5);
+        }
+    });
+}
+
+/// reads keys from the terminal on a new thread if `--terminal` was given, exits once `q` was pressed
+#[cfg(all(feature = "terminal", unix))]
+fn start_terminal(matches: &clap::ArgMatches, generator: GeneratorHandle, sample_rate: u32) {
+    if !matches.is_present("terminal") {
+        return;
+    }
+
+    std::thread::spawn(move || match terminal::run(generator, sample_rate) {
+        Ok(()) => std::process::exit(0),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(5);
+        }
+    });
+}
+
+#[cfg(all(feature = "audio", not(all(feature = "terminal", unix))))]
+fn start_terminal(matches: &clap::ArgMatches, _generator: GeneratorHandle, _sample_rate: u32) {
+    if matches.is_present("terminal") {
+        if cfg!(unix) {
+            eprintln!("Builds without the \"terminal\" feature do not supply keyboard control");
+        } else {
+            eprintln!("Keyboard control with --terminal is only supported on Unix");
+        }
+        std::process::exit(1);
+    }
+}
+
+/// starts audio playback using either the JACK backend or the default audio host on the device matching `device_name`
+#[cfg(feature = "audio")]
+fn init_audio(
+    generator_thread: GeneratorThread,
+    sample_rate: u32,
+    jack: bool,
+    device_name: Option<&str>,
+) -> Result<(audio::Audio, audio::AudioReceivers), String> {
+    #[cfg(feature = "jack")]
+    if jack {
+        return audio::init_jack(generator_thread, sample_rate);
+    }
+    #[cfg(not(feature = "jack"))]
+    if jack {
+        eprintln!("Builds without the \"jack\" feature do not supply the JACK backend, using the default audio host");
+    }
+
+    audio::init(
+        generator_thread,
+        sample_rate,
+        device_name.map(str::to_string),
+    )
 }
@@ -0,0 +1,176 @@
+//! ## MIDI module ##
+//!
+//! Maps MIDI input to the generator:
+//! Note On velocity sets the master volume, Pitch Bend the RPM, CC 7 the intake volume and CC 11 the exhaust volume.
+//!
+
+use enginesound::generator_thread::{GeneratorCommand, GeneratorHandle};
+use midir::{MidiInput, MidiInputConnection};
+
+const CLIENT_NAME: &str = "enginesound";
+
+/// lowest and highest rpm which the pitch bend range is mapped to by default
+pub const DEFAULT_RPM_RANGE: (f32, f32) = (300.0, 13000.0);
+
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xB0;
+const PITCH_BEND: u8 = 0xE0;
+const CC_VOLUME: u8 = 7;
+const CC_EXPRESSION: u8 = 11;
+
+pub struct MidiController {
+    /// the first input port whose name contains this text is opened, the first port if empty
+    pub device_name: String,
+    /// rpm at the lowest pitch bend
+    pub rpm_min: f32,
+    /// rpm at the highest pitch bend
+    pub rpm_max: f32,
+}
+
+impl MidiController {
+    pub fn new(device_name: impl Into<String>) -> MidiController {
+        MidiController {
+            device_name: device_name.into(),
+            rpm_min: DEFAULT_RPM_RANGE.0,
+            rpm_max: DEFAULT_RPM_RANGE.1,
+        }
+    }
+
+    /// opens the MIDI input, the events are processed on midir's thread as long as the returned connection is alive
+    pub fn run(self, generator: GeneratorHandle) -> Result<MidiInputConnection<()>, String> {
+        let input = MidiInput::new(CLIENT_NAME)
+            .map_err(|e| format!("Failed to initialize MIDI input: {}", e))?;
+
+        let port = input
+            .ports()
+            .into_iter()
+            .find(|port| {
+                input
+                    .port_name(port)
+                    .is_ok_and(|name| name.contains(self.device_name.as_str()))
+            })
+            .ok_or_else(|| format!("No MIDI input device matching \"{}\"", self.device_name))?;
+        let port_name = input
+            .port_name(&port)
+            .map_err(|e| format!("Failed to get MIDI input device name: {}", e))?;
+
+        let connection = input
+            .connect(
+                &port,
+                CLIENT_NAME,
+                move |_, message, _| {
+                    self.handle_message(message, |command| generator.send(command))
+                },
+                (),
+            )
+            .map_err(|e| format!("Failed to connect to MIDI input \"{}\": {}", port_name, e))?;
+
+        println!("Connected to MIDI input \"{}\"", port_name);
+
+        Ok(connection)
+    }
+
+    /// passes the command a MIDI message is mapped to to `send`, messages which aren't mapped are ignored
+    fn handle_message(&self, message: &[u8], mut send: impl FnMut(GeneratorCommand)) {
+        match *message {
+            // a Note On with zero velocity is a Note Off
+            [status, _, velocity] if status & 0xF0 == NOTE_ON && velocity != 0 => {
+                send(GeneratorCommand::SetVolume(velocity as f32 / 127.0));
+            }
+            [status, CC_VOLUME, value] if status & 0xF0 == CONTROL_CHANGE => {
+                send(GeneratorCommand::Apply(Box::new(move |generator| {
+                    generator.engine.intake_volume = value as f32 / 127.0
+                })));
+            }
+            [status, CC_EXPRESSION, value] if status & 0xF0 == CONTROL_CHANGE => {
+                send(GeneratorCommand::Apply(Box::new(move |generator| {
+                    generator.engine.exhaust_volume = value as f32 / 127.0
+                })));
+            }
+            [status, lsb, msb] if status & 0xF0 == PITCH_BEND => {
+                let bend = ((msb as u16) << 7 | lsb as u16) as f32 / 16383.0;
+                send(GeneratorCommand::SetRpm(
+                    self.rpm_min + (self.rpm_max - self.rpm_min) * bend,
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// prints the names of all MIDI input devices
+pub fn list_devices() -> Result<(), String> {
+    let input = MidiInput::new(CLIENT_NAME)
+        .map_err(|e| format!("Failed to initialize MIDI input: {}", e))?;
+
+    println!("MIDI input devices:");
+
+    for port in input.ports() {
+        let name = input
+            .port_name(&port)
+            .unwrap_or_else(|_| "<unknown>".to_string());
+        println!("    {}", name);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enginesound::presets::load_preset;
+    use enginesound::{BiquadFilter, Generator, DC_OFFSET_LP_FREQ};
+
+    /// the commands `handle_message` sends for `message`
+    fn commands(message: &[u8]) -> Vec<GeneratorCommand> {
+        let mut commands = Vec::new();
+        MidiController::new("").handle_message(message, |command| commands.push(command));
+        commands
+    }
+
+    #[test]
+    fn messages_are_mapped_to_commands() {
+        // on any channel
+        assert!(matches!(
+            commands(&[0x93, 60, 127])[..],
+            [GeneratorCommand::SetVolume(volume)] if volume == 1.0
+        ));
+        assert!(commands(&[0x90, 60, 0]).is_empty());
+
+        assert!(matches!(
+            commands(&[0xE0, 0, 0])[..],
+            [GeneratorCommand::SetRpm(rpm)] if rpm == DEFAULT_RPM_RANGE.0
+        ));
+        assert!(matches!(
+            commands(&[0xE0, 0x7F, 0x7F])[..],
+            [GeneratorCommand::SetRpm(rpm)] if rpm == DEFAULT_RPM_RANGE.1
+        ));
+
+        // other controllers and messages are ignored
+        assert!(commands(&[0xB0, 1, 64]).is_empty());
+        assert!(commands(&[0xF8]).is_empty());
+    }
+
+    #[test]
+    fn controllers_set_the_volumes() {
+        let sample_rate = 48000;
+        let mut generator = Generator::new(
+            sample_rate,
+            load_preset("i4", sample_rate).unwrap(),
+            BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, sample_rate),
+        );
+
+        for command in commands(&[0xB0, 7, 0])
+            .into_iter()
+            .chain(commands(&[0xB5, 11, 127]))
+        {
+            match command {
+                GeneratorCommand::Apply(change) => change(&mut generator),
+                _ => panic!("the volumes are changed with GeneratorCommand::Apply"),
+            }
+        }
+
+        assert_eq!(generator.engine.intake_volume, 0.0);
+        assert_eq!(generator.engine.exhaust_volume, 1.0);
+    }
+}
@@ -0,0 +1,205 @@
+//! ## OSC module ##
+//!
+//! Receives parameter updates as Open Sound Control messages over UDP.
+//! Supported addresses are `/engine/rpm`, `/engine/intake_volume` and `/engine/cylinder/<index>/crank_offset`,
+//! each taking a single float or int argument. Bundles are applied immediately, ignoring their time tag.
+//!
+
+use enginesound::generator_thread::{GeneratorCommand, GeneratorHandle};
+use rosc::{OscMessage, OscPacket, OscType};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// interval at which the listener thread checks whether it was stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub struct OscHandler {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl OscHandler {
+    /// binds a UDP socket to `port` on all interfaces and spawns a thread which applies received messages to the generator
+    pub fn new(port: u16, generator: GeneratorHandle) -> Result<OscHandler, String> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Failed to bind OSC socket to port {}: {}", port, e))?;
+        socket
+            .set_read_timeout(Some(STOP_POLL_INTERVAL))
+            .map_err(|e| format!("Failed to set OSC socket timeout: {}", e))?;
+        let addr = socket
+            .local_addr()
+            .map_err(|e| format!("Failed to get OSC socket address: {}", e))?;
+
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        {
+            let stopped = stopped.clone();
+            std::thread::spawn(move || {
+                let mut buf = [0u8; rosc::decoder::MTU];
+
+                while !stopped.load(Ordering::Relaxed) {
+                    match socket.recv_from(&mut buf) {
+                        Ok((len, source)) => match rosc::decoder::decode_udp(&buf[..len]) {
+                            Ok((_, packet)) => handle_packet(packet, &generator),
+                            Err(e) => {
+                                eprintln!("Ignoring malformed OSC packet from {}: {:?}", source, e)
+                            }
+                        },
+                        Err(e)
+                            if e.kind() == ErrorKind::WouldBlock
+                                || e.kind() == ErrorKind::TimedOut => {}
+                        Err(e) => eprintln!("Failed to receive OSC packet: {}", e),
+                    }
+                }
+            });
+        }
+
+        Ok(OscHandler { addr, stopped })
+    }
+
+    /// address the socket is bound to
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// stops the listener thread, the socket is closed once it notices
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for OscHandler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn handle_packet(packet: OscPacket, generator: &GeneratorHandle) {
+    match packet {
+        OscPacket::Message(message) => handle_message(message, generator),
+        OscPacket::Bundle(bundle) => {
+            for packet in bundle.content {
+                handle_packet(packet, generator);
+            }
+        }
+    }
+}
+
+fn handle_message(message: OscMessage, generator: &GeneratorHandle) {
+    let value = match message.args.as_slice() {
+        [OscType::Float(value)] => *value,
+        [OscType::Int(value)] => *value as f32,
+        _ => {
+            eprintln!(
+                "Ignoring OSC message to \"{}\", expected a single float or int argument",
+                message.addr
+            );
+            return;
+        }
+    };
+
+    if !value.is_finite() {
+        eprintln!("Ignoring non-finite OSC value for \"{}\"", message.addr);
+        return;
+    }
+
+    let path = message.addr.split('/').skip(1).collect::<Vec<&str>>();
+
+    match path.as_slice() {
+        ["engine", "rpm"] => generator.send(GeneratorCommand::SetRpm(value.max(0.0))),
+        ["engine", "intake_volume"] => {
+            generator.apply(move |generator| generator.engine.intake_volume = value.max(0.0))
+        }
+        ["engine", "cylinder", index, "crank_offset"] => {
+            let index = index.to_string();
+
+            generator.apply(move |generator| {
+                let engine = &mut generator.engine;
+
+                match index
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|index| engine.cylinders.get_mut(index))
+                {
+                    Some(cylinder) => {
+                        cylinder.crank_offset = value.rem_euclid(1.0);
+                        engine.firing_preset = None;
+                    }
+                    None => {
+                        eprintln!("Ignoring OSC message to nonexistent cylinder \"{}\"", index)
+                    }
+                }
+            })
+        }
+        _ => eprintln!(
+            "Ignoring OSC message to unknown address \"{}\"",
+            message.addr
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enginesound::gen::{BiquadFilter, Generator, DC_OFFSET_LP_FREQ};
+    use enginesound::generator_thread::{GeneratorSnapshot, GeneratorThread};
+    use enginesound::presets::load_preset;
+    use std::time::Instant;
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    /// polls snapshots of the generator until `done` returns true, panics after a second
+    fn wait_for(generator: &GeneratorHandle, done: impl Fn(&GeneratorSnapshot) -> bool) {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            if generator.snapshot().as_ref().is_some_and(&done) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        panic!("the OSC message was not applied");
+    }
+
+    #[test]
+    fn packets_sent_to_the_socket_change_the_generator() {
+        let mut engine = load_preset("i4", SAMPLE_RATE).unwrap();
+        engine.rpm = 1000.0;
+        engine.rpm_slew_rate = None;
+        let generator = Generator::new(
+            SAMPLE_RATE,
+            engine,
+            BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, SAMPLE_RATE),
+        );
+        let (generator_thread, generator) = GeneratorThread::new(generator);
+
+        let (output, buffers) = crossbeam_channel::bounded(1);
+        std::thread::spawn(move || generator_thread.run(256, output, &[]));
+        std::thread::spawn(move || buffers.iter().for_each(drop));
+
+        let handler = OscHandler::new(0, generator.clone()).unwrap();
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let target = ("127.0.0.1", handler.addr().port());
+
+        // "/engine/rpm" padded to 4 bytes, the type tag of a single float and 3500.0 in big endian
+        let mut packet = b"/engine/rpm\0,f\0\0".to_vec();
+        packet.extend_from_slice(&3500.0f32.to_be_bytes());
+        socket.send_to(&packet, target).unwrap();
+
+        // the rpm is reached while generating the next buffer
+        wait_for(&generator, |snapshot| {
+            snapshot.target_rpm.unwrap_or(snapshot.engine.rpm) == 3500.0
+        });
+
+        let packet = rosc::encoder::encode(&OscPacket::Message(OscMessage {
+            addr: "/engine/intake_volume".to_string(),
+            args: vec![OscType::Float(0.4)],
+        }))
+        .unwrap();
+        socket.send_to(&packet, target).unwrap();
+
+        wait_for(&generator, |snapshot| snapshot.engine.intake_volume == 0.4);
+    }
+}
@@ -0,0 +1,339 @@
+//! ## Parameters module ##
+//!
+//! Ranges of the engine parameter sliders of the GUI, which are shared with the "Randomize" and "Mutate" buttons
+//! so the generated engines stay within what the sliders can show.
+//!
+
+use crate::constants::MAX_CYLINDERS;
+use enginesound::gen::{Cylinder, Engine, LowPassFilter, MufflerElement, Noise, WaveGuide};
+use enginesound::utils::{distance_to_samples, samples_to_distance};
+use std::time::SystemTime;
+
+/// fraction of a slider's travel by which `mutate` moves each parameter at most
+pub const MUTATE_AMOUNT: f32 = 0.1;
+
+/// range of a slider, `skew` is the skew of the slider which weights lower values if it is above 1
+#[derive(Copy, Clone)]
+pub struct ParameterRange {
+    pub min: f32,
+    pub max: f32,
+    pub skew: f32,
+}
+
+impl ParameterRange {
+    pub const fn new(min: f32, max: f32) -> Self {
+        ParameterRange {
+            min,
+            max,
+            skew: 1.0,
+        }
+    }
+
+    pub const fn skew(self, skew: f32) -> Self {
+        ParameterRange { skew, ..self }
+    }
+
+    /// returns the bounds of the slider, `max` is limited to the nyquist frequency
+    pub fn bounds(&self, sample_rate: u32) -> (f32, f32) {
+        (self.min, self.max.min(sample_rate as f32 * 0.5))
+    }
+
+    /// the value at `position` (0..1) of the slider
+    fn value(&self, position: f32, sample_rate: u32) -> f32 {
+        let (min, max) = self.bounds(sample_rate);
+        min + (max - min) * position.clamp(0.0, 1.0).powf(self.skew)
+    }
+
+    /// the position (0..1) of `value` on the slider
+    fn position(&self, value: f32, sample_rate: u32) -> f32 {
+        let (min, max) = self.bounds(sample_rate);
+        ((value - min) / (max - min))
+            .clamp(0.0, 1.0)
+            .powf(1.0 / self.skew)
+    }
+}
+
+/// the lowpass and highpass filter sliders reach up to the nyquist frequency, see `ParameterRange::bounds`
+pub const FILTER_FREQ: ParameterRange = ParameterRange::new(10.0, f32::INFINITY).skew(10.0);
+/// intake, exhaust and engine vibrations volume, which are normalized to a sum of 1
+pub const MIX_VOLUME: ParameterRange = ParameterRange::new(0.0, 1.0);
+pub const INTAKE_NOISE_FACTOR: ParameterRange = ParameterRange::new(0.0, 3.0);
+pub const VALVE_SHIFT: ParameterRange = ParameterRange::new(-0.5, 0.5);
+// lower filter frequencies require more amplitude so its noticable
+pub const CRANKSHAFT_FLUCTUATION: ParameterRange = ParameterRange::new(0.0, 2.5);
+/// reflectivity of the end of a waveguide or of a valve
+pub const REFLECTIVITY: ParameterRange = ParameterRange::new(-1.0, 1.0);
+pub const STRAIGHT_PIPE_LENGTH: ParameterRange = ParameterRange::new(0.1, 3.0);
+/// fraction of the exhaust collector which is fed back into the intake, see `Engine::egr_factor`
+pub const EGR_FACTOR: ParameterRange = ParameterRange::new(0.0, 1.0);
+pub const EGR_PIPE_LENGTH: ParameterRange = ParameterRange::new(0.05, 2.0);
+pub const MUFFLER_OPEN_END_REFL: ParameterRange = ParameterRange::new(-1.0, 0.3);
+pub const MUFFLER_ELEMENT_LENGTH: ParameterRange = ParameterRange::new(0.001, 0.6);
+/// dimensions of the Helmholtz resonator muffler elements in meters and cubic meters,
+/// the resonant frequencies range from about 10 hz to 3.4 khz
+pub const NECK_LENGTH: ParameterRange = ParameterRange::new(0.01, 0.2);
+pub const NECK_RADIUS: ParameterRange = ParameterRange::new(0.005, 0.05);
+pub const CAVITY_VOLUME: ParameterRange = ParameterRange::new(0.0002, 0.01).skew(2.0);
+pub const RESONATOR_REFLECTIVITY: ParameterRange = ParameterRange::new(0.0, 1.0);
+pub const CYLINDER_COUNT: ParameterRange = ParameterRange::new(1.0, MAX_CYLINDERS as f32);
+pub const PISTON_MOTION_FACTOR: ParameterRange = ParameterRange::new(0.0, 20.0);
+pub const IGNITION_FACTOR: ParameterRange = ParameterRange::new(0.0, 20.0);
+pub const IGNITION_TIME: ParameterRange = ParameterRange::new(0.0, 0.3);
+/// the impulse is the opening of the exhaust valve within one sample times this factor, so it is rather small
+pub const PRESSURE_RELEASE_FACTOR: ParameterRange = ParameterRange::new(0.0, 500.0).skew(2.0);
+pub const INTAKE_PIPE_LENGTH: ParameterRange = ParameterRange::new(0.0, 1.0);
+pub const EXHAUST_PIPE_LENGTH: ParameterRange = ParameterRange::new(0.0, 1.7);
+pub const EXTRACTOR_PIPE_LENGTH: ParameterRange = ParameterRange::new(0.0, 10.0);
+
+/// the shared open end reflectivity of the straight pipe muffler elements, `None` if there are only resonators
+pub fn muffler_elements_beta(engine: &Engine) -> Option<f32> {
+    engine
+        .muffler
+        .muffler_elements
+        .iter()
+        .find_map(MufflerElement::waveguide)
+        .map(|waveguide| waveguide.beta)
+}
+
+/// removes cylinders from the end or appends copies of `template` until the engine has `count` cylinders,
+/// the added cylinders are spaced evenly while the offsets of the existing ones are kept
+pub fn set_cylinder_count(engine: &mut Engine, count: usize, template: &Cylinder) {
+    if count <= engine.cylinders.len() {
+        engine.cylinders.truncate(count);
+    } else {
+        for i in engine.cylinders.len()..count {
+            let mut cylinder = template.clone();
+            cylinder.crank_offset = i as f32 / count as f32;
+            cylinder.active = true;
+
+            engine.cylinders.push(cylinder);
+        }
+    }
+}
+
+/// Changes the parameters of an engine which have a slider, either to random values or by moving them a bit
+struct Randomizer {
+    noise: Noise,
+    sample_rate: u32,
+    /// moves values by at most this fraction of the slider's travel, samples new values if `None`
+    amount: Option<f32>,
+}
+
+impl Randomizer {
+    fn sample(&mut self, range: ParameterRange, value: f32) -> f32 {
+        let position = match self.amount {
+            Some(amount) => range.position(value, self.sample_rate) + self.noise.step() * amount,
+            None => (self.noise.step() + 1.0) * 0.5,
+        };
+
+        range.value(position, self.sample_rate)
+    }
+
+    fn lowpass(&mut self, filter: &mut LowPassFilter) {
+        let freq = self.sample(FILTER_FREQ, filter.get_freq());
+
+        if let Some(new) = filter.get_changed(freq, self.sample_rate) {
+            *filter = new;
+        }
+    }
+
+    fn length(&mut self, range: ParameterRange, waveguide: &mut WaveGuide) {
+        let length = self.sample(
+            range,
+            samples_to_distance(waveguide.delay(), self.sample_rate),
+        );
+
+        if let Some(new) = waveguide.get_changed(
+            distance_to_samples(length, self.sample_rate),
+            waveguide.alpha,
+            waveguide.beta,
+            self.sample_rate,
+        ) {
+            *waveguide = new;
+        }
+    }
+
+    fn cylinder(&mut self, cylinder: &mut Cylinder) {
+        cylinder.intake_open_refl = self.sample(REFLECTIVITY, cylinder.intake_open_refl);
+        cylinder.intake_closed_refl = self.sample(REFLECTIVITY, cylinder.intake_closed_refl);
+        cylinder.exhaust_open_refl = self.sample(REFLECTIVITY, cylinder.exhaust_open_refl);
+        cylinder.exhaust_closed_refl = self.sample(REFLECTIVITY, cylinder.exhaust_closed_refl);
+        cylinder.intake_waveguide.beta = self.sample(REFLECTIVITY, cylinder.intake_waveguide.beta);
+        cylinder.extractor_waveguide.beta =
+            self.sample(REFLECTIVITY, cylinder.extractor_waveguide.beta);
+        cylinder.piston_motion_factor =
+            self.sample(PISTON_MOTION_FACTOR, cylinder.piston_motion_factor);
+        cylinder.ignition_factor = self.sample(IGNITION_FACTOR, cylinder.ignition_factor);
+        cylinder.ignition_time = self.sample(IGNITION_TIME, cylinder.ignition_time);
+        cylinder.pressure_release_factor =
+            self.sample(PRESSURE_RELEASE_FACTOR, cylinder.pressure_release_factor);
+        self.length(INTAKE_PIPE_LENGTH, &mut cylinder.intake_waveguide);
+        self.length(EXHAUST_PIPE_LENGTH, &mut cylinder.exhaust_waveguide);
+        self.length(EXTRACTOR_PIPE_LENGTH, &mut cylinder.extractor_waveguide);
+    }
+
+    /// changes everything but the cylinders
+    fn engine(&mut self, engine: &mut Engine) {
+        let volumes = [
+            self.sample(MIX_VOLUME, engine.intake_volume),
+            self.sample(MIX_VOLUME, engine.exhaust_volume),
+            self.sample(MIX_VOLUME, engine.engine_vibrations_volume),
+        ];
+        let sum = volumes.iter().sum::<f32>();
+        // a mix which is silent everywhere keeps the previous volumes
+        if sum > 0.0 {
+            engine.intake_volume = volumes[0] / sum;
+            engine.exhaust_volume = volumes[1] / sum;
+            engine.engine_vibrations_volume = volumes[2] / sum;
+        }
+
+        self.lowpass(&mut engine.engine_vibration_filter);
+        engine.intake_noise_factor = self.sample(INTAKE_NOISE_FACTOR, engine.intake_noise_factor);
+        self.lowpass(&mut engine.intake_noise_lp);
+        engine.intake_valve_shift = self.sample(VALVE_SHIFT, engine.intake_valve_shift);
+        engine.exhaust_valve_shift = self.sample(VALVE_SHIFT, engine.exhaust_valve_shift);
+        engine.crankshaft_fluctuation =
+            self.sample(CRANKSHAFT_FLUCTUATION, engine.crankshaft_fluctuation);
+        self.lowpass(&mut engine.crankshaft_fluctuation_lp);
+
+        let straight_pipe = &mut engine.muffler.straight_pipe;
+        straight_pipe.alpha = self.sample(REFLECTIVITY, straight_pipe.alpha);
+        straight_pipe.beta = self.sample(REFLECTIVITY, straight_pipe.beta);
+        self.length(STRAIGHT_PIPE_LENGTH, straight_pipe);
+
+        // the straight pipe muffler elements share their reflectivity like on the slider
+        let muffler_elements_beta = self.sample(
+            MUFFLER_OPEN_END_REFL,
+            muffler_elements_beta(engine).unwrap_or(0.0),
+        );
+        for muffler_element in engine.muffler.muffler_elements.iter_mut() {
+            match muffler_element {
+                MufflerElement::StraightPipe(waveguide) => {
+                    waveguide.beta = muffler_elements_beta;
+                    self.length(MUFFLER_ELEMENT_LENGTH, waveguide);
+                }
+                MufflerElement::HelmholtzResonator {
+                    neck_length,
+                    neck_radius,
+                    cavity_volume,
+                    alpha,
+                    ..
+                } => {
+                    *neck_length = self.sample(NECK_LENGTH, *neck_length);
+                    *neck_radius = self.sample(NECK_RADIUS, *neck_radius);
+                    *cavity_volume = self.sample(CAVITY_VOLUME, *cavity_volume);
+                    *alpha = self.sample(RESONATOR_REFLECTIVITY, *alpha);
+                }
+            }
+        }
+    }
+}
+
+/// samples new values for all parameters of `engine` which have a slider, a `seed` always results in the same parameters
+///
+/// all cylinders get the same parameters and are spaced evenly, the parameters without a slider
+/// and the rpm, throttle, EGR and effects (turbo, overrun crackle, eq) are kept
+pub fn randomize(engine: &mut Engine, seed: u64, sample_rate: u32) {
+    let mut randomizer = Randomizer {
+        noise: Noise::from_seed(seed),
+        sample_rate,
+        amount: None,
+    };
+
+    randomizer.engine(engine);
+
+    let count = randomizer
+        .sample(CYLINDER_COUNT, engine.cylinders.len() as f32)
+        .round() as usize;
+    let mut template = engine.cylinders[0].clone();
+    randomizer.cylinder(&mut template);
+
+    set_cylinder_count(engine, count, &template);
+    for (i, cylinder) in engine.cylinders.iter_mut().enumerate() {
+        let mut new = template.clone();
+        new.crank_offset = i as f32 / count as f32;
+        new.active = true;
+        *cylinder = new;
+    }
+    engine.firing_preset = None;
+}
+
+/// moves every parameter of `engine` which has a slider by up to `MUTATE_AMOUNT` of the slider's travel,
+/// the cylinders are mutated individually while their count and crank offsets are kept
+pub fn mutate(engine: &mut Engine, seed: u64, sample_rate: u32) {
+    let mut randomizer = Randomizer {
+        noise: Noise::from_seed(seed),
+        sample_rate,
+        amount: Some(MUTATE_AMOUNT),
+    };
+
+    randomizer.engine(engine);
+
+    for cylinder in engine.cylinders.iter_mut() {
+        randomizer.cylinder(cylinder);
+    }
+}
+
+/// a seed for `randomize` or `mutate` which differs on every call
+pub fn new_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enginesound::presets::{load_preset, PRESETS};
+
+    const SAMPLE_RATE: u32 = 48000;
+
+    fn engine() -> Engine {
+        load_preset(PRESETS[0].0, SAMPLE_RATE).unwrap()
+    }
+
+    fn assert_in_range(range: ParameterRange, value: f32) {
+        let (min, max) = range.bounds(SAMPLE_RATE);
+        assert!(value >= min - 1e-3 && value <= max + 1e-3, "{}", value);
+    }
+
+    #[test]
+    fn randomized_engines_are_reproducible_and_stay_in_range() {
+        for seed in 0..20 {
+            let mut a = engine();
+            let mut b = engine();
+            randomize(&mut a, seed, SAMPLE_RATE);
+            randomize(&mut b, seed, SAMPLE_RATE);
+            assert_eq!(ron::to_string(&a).unwrap(), ron::to_string(&b).unwrap());
+
+            let sum = a.intake_volume + a.exhaust_volume + a.engine_vibrations_volume;
+            assert!((sum - 1.0).abs() < 1e-4);
+            assert_in_range(CYLINDER_COUNT, a.cylinders.len() as f32);
+            assert_in_range(FILTER_FREQ, a.intake_noise_lp.get_freq());
+            for cylinder in &a.cylinders {
+                assert_in_range(
+                    EXTRACTOR_PIPE_LENGTH,
+                    samples_to_distance(cylinder.extractor_waveguide.delay(), SAMPLE_RATE),
+                );
+                assert_in_range(IGNITION_TIME, cylinder.ignition_time);
+            }
+        }
+    }
+
+    #[test]
+    fn mutating_moves_parameters_by_a_fraction_of_their_range() {
+        let mut engine = engine();
+        let before = engine.clone();
+        mutate(&mut engine, 1, SAMPLE_RATE);
+
+        assert_eq!(engine.cylinders.len(), before.cylinders.len());
+        let sum = engine.intake_volume + engine.exhaust_volume + engine.engine_vibrations_volume;
+        assert!((sum - 1.0).abs() < 1e-4);
+        for (cylinder, before) in engine.cylinders.iter().zip(&before.cylinders) {
+            let moved = (cylinder.piston_motion_factor - before.piston_motion_factor).abs();
+            assert!(moved <= (PISTON_MOTION_FACTOR.max - PISTON_MOTION_FACTOR.min) * MUTATE_AMOUNT);
+            assert_eq!(cylinder.crank_offset, before.crank_offset);
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! ## Presets module ##
+//!
+//! Engine configs of classic engine archetypes which are embedded into the binary, see `src/presets/`.
+//! Library users can construct them with `Engine::from_preset` or the shortcuts like `Engine::v8`.
+//!
+
+use crate::gen::Engine;
+use crate::utils::fix_engine;
+
+/// names and RON configs of the bundled presets
+pub static PRESETS: &[(&str, &[u8])] = &[
+    ("V8", include_bytes!("presets/v8.esc")),
+    ("I4", include_bytes!("presets/i4.esc")),
+    ("flat-6", include_bytes!("presets/flat6.esc")),
+    ("diesel I6", include_bytes!("presets/diesel_i6.esc")),
+    ("V10", include_bytes!("presets/v10.esc")),
+];
+
+/// loads the preset whose name matches `name` ignoring case and fixes it for `sample_rate`
+pub fn load_preset(name: &str, sample_rate: u32) -> Result<Engine, String> {
+    let (name, config) = PRESETS
+        .iter()
+        .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown preset \"{}\"", name))?;
+
+    let mut engine = ron::de::from_bytes::<Engine>(config)
+        .map_err(|e| format!("Failed to load preset \"{}\": {}", name, e))?;
+    fix_engine(&mut engine, sample_rate)
+        .map_err(|e| format!("Invalid preset \"{}\": {}", name, e))?;
+
+    Ok(engine)
+}
+
+/// Engine archetypes of the bundled presets
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnginePreset {
+    V8,
+    Inline4,
+    Flat6,
+    DieselInline6,
+    V10,
+}
+
+impl EnginePreset {
+    pub const ALL: [EnginePreset; 5] = [
+        EnginePreset::V8,
+        EnginePreset::Inline4,
+        EnginePreset::Flat6,
+        EnginePreset::DieselInline6,
+        EnginePreset::V10,
+    ];
+
+    /// name of the preset in `PRESETS`
+    pub fn name(self) -> &'static str {
+        match self {
+            EnginePreset::V8 => "V8",
+            EnginePreset::Inline4 => "I4",
+            EnginePreset::Flat6 => "flat-6",
+            EnginePreset::DieselInline6 => "diesel I6",
+            EnginePreset::V10 => "V10",
+        }
+    }
+}
+
+impl Engine {
+    /// the bundled preset fixed for `sample_rate`, every preset passes `Engine::validate`
+    pub fn from_preset(preset: EnginePreset, sample_rate: u32) -> Engine {
+        load_preset(preset.name(), sample_rate).expect("bundled presets are valid")
+    }
+
+    pub fn v8(sample_rate: u32) -> Engine {
+        Engine::from_preset(EnginePreset::V8, sample_rate)
+    }
+
+    pub fn inline4(sample_rate: u32) -> Engine {
+        Engine::from_preset(EnginePreset::Inline4, sample_rate)
+    }
+
+    /// boxer six
+    pub fn flat6(sample_rate: u32) -> Engine {
+        Engine::from_preset(EnginePreset::Flat6, sample_rate)
+    }
+
+    pub fn diesel_inline6(sample_rate: u32) -> Engine {
+        Engine::from_preset(EnginePreset::DieselInline6, sample_rate)
+    }
+
+    pub fn v10(sample_rate: u32) -> Engine {
+        Engine::from_preset(EnginePreset::V10, sample_rate)
+    }
+}
@@ -1,9 +1,12 @@
-use hound::{SampleFormat, WavSpec};
+use crate::gen::{Engine, Noise};
+use hound::{SampleFormat, WavSpec, WavWriter};
 use parking_lot::Mutex;
-use std::path::PathBuf;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 use std::{
+    fmt,
     fs::File,
-    io::BufWriter,
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
@@ -11,107 +14,1123 @@ use std::{
     time::Duration,
 };
 
+/// Sample format of the written WAV
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SampleDepth {
+    /// 32-bit float
+    #[default]
+    F32,
+    /// 16-bit PCM
+    I16,
+    /// 24-bit PCM
+    I24,
+}
+
+impl SampleDepth {
+    pub const ALL: [SampleDepth; 3] = [SampleDepth::F32, SampleDepth::I16, SampleDepth::I24];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SampleDepth::F32 => "32-bit float",
+            SampleDepth::I16 => "16-bit PCM",
+            SampleDepth::I24 => "24-bit PCM",
+        }
+    }
+
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            SampleDepth::F32 => 32,
+            SampleDepth::I16 => 16,
+            SampleDepth::I24 => 24,
+        }
+    }
+}
+
+/// File format written by a recorder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// WAV with the given bit depth, integer samples are dithered
+    Wav { bits: u8, float: bool },
+    /// Ogg Vorbis with a VBR quality in -0.2..=1.0
+    Ogg { quality: f32 },
+}
+
+impl Default for OutputFormat {
+    fn default() -> Self {
+        SampleDepth::default().into()
+    }
+}
+
+impl From<SampleDepth> for OutputFormat {
+    fn from(sample_depth: SampleDepth) -> Self {
+        OutputFormat::Wav {
+            bits: sample_depth.bits_per_sample() as u8,
+            float: sample_depth == SampleDepth::F32,
+        }
+    }
+}
+
+impl OutputFormat {
+    pub const DEFAULT_OGG_QUALITY: f32 = 0.6;
+
+    /// picks Ogg Vorbis for `.ogg` files and WAV with the given depth otherwise
+    pub fn from_extension(file: &Path, sample_depth: SampleDepth) -> OutputFormat {
+        match file.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("ogg") => OutputFormat::Ogg {
+                quality: Self::DEFAULT_OGG_QUALITY,
+            },
+            _ => sample_depth.into(),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Wav { .. } => "WAV",
+            OutputFormat::Ogg { .. } => "OGG",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Wav { .. } => "wav",
+            OutputFormat::Ogg { .. } => "ogg",
+        }
+    }
+}
+
+/// Error while creating or writing a recording
+#[derive(Debug)]
+pub enum RecorderError {
+    /// the output file could not be created or written
+    Io(std::io::Error),
+    Wav(hound::Error),
+    #[cfg(feature = "ogg")]
+    Ogg(vorbis_rs::VorbisError),
+    /// the output format is not supported by this build or with these parameters
+    UnsupportedFormat(String),
+}
+
+impl fmt::Display for RecorderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecorderError::Io(e) => write!(f, "I/O error: {}", e),
+            RecorderError::Wav(e) => write!(f, "WAV error: {}", e),
+            #[cfg(feature = "ogg")]
+            RecorderError::Ogg(e) => write!(f, "OGG error: {}", e),
+            RecorderError::UnsupportedFormat(e) => write!(f, "Unsupported format: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RecorderError {}
+
+impl From<std::io::Error> for RecorderError {
+    fn from(e: std::io::Error) -> Self {
+        RecorderError::Io(e)
+    }
+}
+
+impl From<hound::Error> for RecorderError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::IoError(e) => RecorderError::Io(e),
+            e => RecorderError::Wav(e),
+        }
+    }
+}
+
+#[cfg(feature = "ogg")]
+impl From<vorbis_rs::VorbisError> for RecorderError {
+    fn from(e: vorbis_rs::VorbisError) -> Self {
+        match e {
+            vorbis_rs::VorbisError::Io(e) => RecorderError::Io(e),
+            e => RecorderError::Ogg(e),
+        }
+    }
+}
+
+/// Integrated loudness of interleaved samples according to ITU-R BS.1770, with all channels weighted equally
+///
+/// The samples are K-weighted and their energy is measured in overlapping 400 ms blocks, which are gated absolutely at -70 LUFS
+/// and relatively at 10 LU below the loudness of the blocks above the absolute gate.
+pub struct LufsMonitor {
+    /// sum of the squared K-weighted samples of all channels, used if no whole block was measured
+    integrated_energy: f64,
+    /// measured frames
+    num_samples: u64,
+    channels: usize,
+    /// K-weighting (high shelf, then high pass) per channel
+    filters: Vec<[Biquad; 2]>,
+    /// frames per 100 ms, a quarter of a gating block
+    step_len: usize,
+    /// energy of the current step
+    step_energy: f64,
+    step_pos: usize,
+    /// mean square of every completed step
+    steps: Vec<f64>,
+}
+
+/// absolute gate of the blocks in LUFS
+const LUFS_ABSOLUTE_GATE: f64 = -70.0;
+/// relative gate of the blocks below the absolutely gated loudness in LU
+const LUFS_RELATIVE_GATE: f64 = 10.0;
+/// steps of 100 ms per gating block, the blocks overlap by 75%
+const LUFS_STEPS_PER_BLOCK: usize = 4;
+
+impl LufsMonitor {
+    pub fn new(sample_rate: u32, channels: u16) -> LufsMonitor {
+        let channels = (channels as usize).max(1);
+
+        LufsMonitor {
+            integrated_energy: 0.0,
+            num_samples: 0,
+            channels,
+            filters: (0..channels)
+                .map(|_| Biquad::k_weighting(sample_rate as f64))
+                .collect(),
+            step_len: (sample_rate as usize / 10).max(1),
+            step_energy: 0.0,
+            step_pos: 0,
+            steps: Vec::new(),
+        }
+    }
+
+    /// measures interleaved samples
+    pub fn update(&mut self, block: &[f32]) {
+        for frame in block.chunks_exact(self.channels) {
+            let energy = frame
+                .iter()
+                .zip(self.filters.iter_mut())
+                .map(|(sample, [shelf, high_pass])| {
+                    let weighted = high_pass.filter(shelf.filter(*sample as f64));
+                    weighted * weighted
+                })
+                .sum::<f64>();
+
+            self.integrated_energy += energy;
+            self.num_samples += 1;
+
+            self.step_energy += energy;
+            self.step_pos += 1;
+            if self.step_pos == self.step_len {
+                self.steps.push(self.step_energy / self.step_len as f64);
+                self.step_energy = 0.0;
+                self.step_pos = 0;
+            }
+        }
+    }
+
+    /// returns the gated integrated loudness of the measured samples in LUFS, or the ungated loudness if less than one block
+    /// (400 ms) was measured, negative infinity if they are silent
+    pub fn lufs(&self) -> f32 {
+        let blocks = self
+            .steps
+            .windows(LUFS_STEPS_PER_BLOCK)
+            .map(|steps| steps.iter().sum::<f64>() / LUFS_STEPS_PER_BLOCK as f64)
+            .collect::<Vec<f64>>();
+
+        if blocks.is_empty() {
+            return if self.num_samples == 0 {
+                f32::NEG_INFINITY
+            } else {
+                loudness(self.integrated_energy / self.num_samples as f64) as f32
+            };
+        }
+
+        let gated_mean = |gate: f64| {
+            let gated = blocks
+                .iter()
+                .filter(|energy| loudness(**energy) > gate)
+                .collect::<Vec<&f64>>();
+
+            if gated.is_empty() {
+                None
+            } else {
+                Some(gated.iter().copied().sum::<f64>() / gated.len() as f64)
+            }
+        };
+
+        gated_mean(LUFS_ABSOLUTE_GATE)
+            .and_then(|energy| gated_mean(loudness(energy) - LUFS_RELATIVE_GATE))
+            .map_or(f32::NEG_INFINITY, |energy| loudness(energy) as f32)
+    }
+}
+
+/// loudness in LUFS of the mean square of K-weighted samples
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Direct form I biquad filter with normalized coefficients
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    /// the two stages of the K-weighting of ITU-R BS.1770, derived for any sample rate as in libebur128
+    fn k_weighting(sample_rate: f64) -> [Biquad; 2] {
+        // head-related high shelf of about +4 dB
+        let k = (std::f64::consts::PI * 1681.974450955533 / sample_rate).tan();
+        let q = 0.7071752369554196;
+        let vh = 10f64.powf(3.999843853973347 / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        };
+
+        // revised low-frequency B-curve, a high pass at about 38 Hz
+        let k = (std::f64::consts::PI * 38.13547087602444 / sample_rate).tan();
+        let q = 0.5003270373238773;
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        };
+
+        [shelf, high_pass]
+    }
+
+    fn filter(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
+
+/// Level which samples are normalized to, see `normalize`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalizationTarget {
+    /// largest absolute sample, e.g. 0.99
+    Peak(f32),
+    /// integrated loudness in LUFS, e.g. -14.0, the gain is reduced if the samples would peak above 0 dBFS
+    Lufs(f32),
+}
+
+impl NormalizationTarget {
+    /// peak of the `peak` mode of `parse` and of the GUI recordings
+    pub const DEFAULT_PEAK: f32 = 0.99;
+
+    /// parses `peak` or `lufs:<target>`, e.g. `lufs:-14`
+    pub fn parse(s: &str) -> Result<NormalizationTarget, String> {
+        let target = match s.trim().split_once(':') {
+            None if s.trim() == "peak" => NormalizationTarget::Peak(Self::DEFAULT_PEAK),
+            Some(("lufs", lufs)) => match lufs.trim().parse::<f32>() {
+                Ok(lufs) if lufs.is_finite() => NormalizationTarget::Lufs(lufs),
+                _ => return Err(format!("Invalid LUFS target \"{}\"", lufs)),
+            },
+            _ => {
+                return Err(format!(
+                    "Invalid normalization \"{}\", expected \"peak\" or \"lufs:<target>\"",
+                    s
+                ))
+            }
+        };
+
+        Ok(target)
+    }
+}
+
+/// Normalization of a recording, see `Recorder::set_normalization`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Normalization {
+    /// level of the written file
+    pub target: NormalizationTarget,
+    /// longest recording in seconds which is kept in memory to be normalized, longer recordings are written as they are
+    pub max_seconds: f32,
+}
+
+impl Normalization {
+    pub const DEFAULT_MAX_SECONDS: f32 = 600.0;
+
+    pub fn new(target: NormalizationTarget) -> Normalization {
+        Normalization {
+            target,
+            max_seconds: Self::DEFAULT_MAX_SECONDS,
+        }
+    }
+}
+
+/// Description of a finished recording which is written next to it with the `.json` extension, see `Recorder::set_metadata`
+///
+/// The recording can be reproduced by loading `engine_config` with `--config` and passing `seed` to `--seed`
+#[derive(Serialize)]
+pub struct RecordingMetadata {
+    /// RON config of the engine at the start of the recording
+    pub engine_config: String,
+    /// rpm at the start of the recording
+    pub rpm: f32,
+    pub sample_rate: u32,
+    pub duration_sec: f32,
+    /// highest absolute value of the written samples
+    pub peak_amplitude: f32,
+    /// integrated loudness of the written samples, `None` if they are silent
+    pub lufs: Option<f32>,
+    /// seed of the noise sources, `None` if they were seeded randomly
+    pub seed: Option<u64>,
+}
+
+impl RecordingMetadata {
+    fn new(engine: &Engine, sample_rate: u32, written: &Written) -> RecordingMetadata {
+        RecordingMetadata {
+            engine_config: ron::ser::to_string(engine).expect("failed to serialize engine"),
+            rpm: engine.rpm,
+            sample_rate,
+            duration_sec: written.frames as f32 / sample_rate as f32,
+            peak_amplitude: written.peak,
+            lufs: Some(written.lufs).filter(|lufs| lufs.is_finite()),
+            seed: engine.noise_seed,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.flush()
+    }
+}
+
+/// file name of the metadata of a recording, e.g. `recording.json` for `recording.wav`
+pub fn metadata_file_name(recording: &Path) -> PathBuf {
+    recording.with_extension("json")
+}
+
+/// MIDI note written to the `smpl` chunk at which the samples play at their recorded pitch
+const SMPL_UNITY_NOTE: u32 = 60;
+
+/// Appends a `smpl` chunk with a forward loop from the frame `start` to the frame `end` (inclusive) to a finished WAV file
+/// and updates the size of its RIFF chunk, game audio middleware (FMOD, Wwise, Unity) reads it as loop markers
+pub fn write_loop_points(
+    path: &Path,
+    sample_rate: u32,
+    start: u32,
+    end: u32,
+) -> std::io::Result<()> {
+    let fields: [u32; 16] = [
+        // size of the chunk after this field: 9 fields and one loop of 6 fields
+        (9 + 6) * 4,
+        // manufacturer and product
+        0,
+        0,
+        // sample period in nanoseconds
+        1_000_000_000 / sample_rate,
+        SMPL_UNITY_NOTE,
+        // pitch fraction, SMPTE format and offset
+        0,
+        0,
+        0,
+        // number of loops and bytes of sampler specific data
+        1,
+        0,
+        // cue point id, forward loop, start, end, fraction and infinite play count
+        0,
+        0,
+        start,
+        end,
+        0,
+        0,
+    ];
+    let mut chunk = b"smpl".to_vec();
+    fields
+        .iter()
+        .for_each(|field| chunk.extend_from_slice(&field.to_le_bytes()));
+
+    append_chunk(path, &chunk)
+}
+
+/// Name, author and description of an engine, which are written to the `LIST`/`INFO` chunk of WAV recordings
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EngineInfo {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+}
+
+impl EngineInfo {
+    pub fn new(engine: &Engine) -> EngineInfo {
+        EngineInfo {
+            name: engine.name.clone(),
+            author: engine.author.clone(),
+            description: engine.description.clone(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.author.is_none() && self.description.is_none()
+    }
+}
+
+/// Appends a `LIST` chunk with the `INAM` (name), `IART` (author) and `ICMT` (description) sub-chunks of the set fields
+/// of `info` to a finished WAV file and updates the size of its RIFF chunk, nothing is written if `info` is empty
+pub fn write_info(path: &Path, info: &EngineInfo) -> std::io::Result<()> {
+    if info.is_empty() {
+        return Ok(());
+    }
+
+    let mut chunk = b"LIST".to_vec();
+    // the size is filled in once the sub-chunks are written
+    chunk.extend_from_slice(&[0; 4]);
+    chunk.extend_from_slice(b"INFO");
+
+    for (id, text) in [
+        (b"INAM", &info.name),
+        (b"IART", &info.author),
+        (b"ICMT", &info.description),
+    ] {
+        if let Some(text) = text {
+            // zero-terminated, padded to an even size
+            let mut data = text.as_bytes().to_vec();
+            data.push(0);
+            chunk.extend_from_slice(id);
+            chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            if data.len() % 2 == 1 {
+                data.push(0);
+            }
+            chunk.extend_from_slice(&data);
+        }
+    }
+
+    let size = (chunk.len() - 8) as u32;
+    chunk[4..8].copy_from_slice(&size.to_le_bytes());
+
+    append_chunk(path, &chunk)
+}
+
+/// appends `chunk` to the WAV file at `path` and updates the size of its RIFF chunk
+fn append_chunk(path: &Path, chunk: &[u8]) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "not a WAV file",
+        ));
+    }
+
+    let mut len = file.seek(SeekFrom::End(0))?;
+    // chunks start at even offsets
+    if len % 2 == 1 {
+        file.write_all(&[0])?;
+        len += 1;
+    }
+
+    file.write_all(chunk)?;
+
+    let riff_size = (len + chunk.len() as u64 - 8) as u32;
+    file.seek(SeekFrom::Start(4))?;
+    file.write_all(&riff_size.to_le_bytes())?;
+
+    file.flush()
+}
+
+/// properties of the samples written by a `MeasuringWriter`
+struct Written {
+    frames: usize,
+    peak: f32,
+    lufs: f32,
+}
+
+/// Measures the peak and loudness of the samples written by a `Writer` for the `RecordingMetadata`
+struct MeasuringWriter {
+    writer: Writer,
+    peak: f32,
+    loudness: LufsMonitor,
+}
+
+impl MeasuringWriter {
+    fn new(writer: Writer, sample_rate: u32, channels: u16) -> MeasuringWriter {
+        MeasuringWriter {
+            writer,
+            peak: 0.0,
+            loudness: LufsMonitor::new(sample_rate, channels),
+        }
+    }
+
+    fn write(&mut self, samples: &[f32]) -> Result<(), RecorderError> {
+        self.peak = samples
+            .iter()
+            .fold(self.peak, |peak, sample| peak.max(sample.abs()));
+        self.loudness.update(samples);
+
+        self.writer.write(samples)
+    }
+
+    fn finish(self) -> Result<Written, RecorderError> {
+        Ok(Written {
+            frames: self.writer.finish()?,
+            peak: self.peak,
+            lufs: self.loudness.lufs(),
+        })
+    }
+}
+
+enum Writer {
+    Wav {
+        wav_writer: WavWriter<BufWriter<File>>,
+        bits: u8,
+        float: bool,
+        dither: Noise,
+    },
+    #[cfg(feature = "ogg")]
+    Ogg {
+        encoder: Box<vorbis_rs::VorbisEncoder<BufWriter<File>>>,
+        channels: u16,
+        frames: usize,
+    },
+}
+
+impl Writer {
+    fn new(
+        file: &Path,
+        sample_rate: u32,
+        format: OutputFormat,
+        channels: u16,
+    ) -> Result<Writer, RecorderError> {
+        if channels == 0 {
+            return Err(RecorderError::UnsupportedFormat(
+                "at least one channel is required".into(),
+            ));
+        }
+
+        match format {
+            OutputFormat::Wav { bits, float } => {
+                if (float && bits != 32) || (!float && ![8, 16, 24, 32].contains(&bits)) {
+                    return Err(RecorderError::UnsupportedFormat(format!(
+                        "{}-bit {} WAV",
+                        bits,
+                        if float { "float" } else { "PCM" }
+                    )));
+                }
+
+                let wav_writer = hound::WavWriter::new(
+                    BufWriter::new(File::create(file)?),
+                    WavSpec {
+                        channels,
+                        sample_rate,
+                        bits_per_sample: bits as u16,
+                        sample_format: if float {
+                            SampleFormat::Float
+                        } else {
+                            SampleFormat::Int
+                        },
+                    },
+                )?;
+
+                Ok(Writer::Wav {
+                    wav_writer,
+                    bits,
+                    float,
+                    // seeded deterministically so recordings stay reproducible
+                    dither: Noise::from_seed(0),
+                })
+            }
+            #[cfg(feature = "ogg")]
+            OutputFormat::Ogg { quality } => {
+                use std::convert::TryFrom;
+                use std::num::{NonZeroU32, NonZeroU8};
+
+                let unsupported = || {
+                    RecorderError::UnsupportedFormat(format!(
+                        "OGG with {} channels at {} Hz",
+                        channels, sample_rate
+                    ))
+                };
+                let sample_rate = NonZeroU32::new(sample_rate).ok_or_else(unsupported)?;
+                let channels = u8::try_from(channels)
+                    .ok()
+                    .and_then(NonZeroU8::new)
+                    .ok_or_else(unsupported)?;
+
+                // a fixed stream serial keeps recordings reproducible
+                let encoder = vorbis_rs::VorbisEncoderBuilder::new_with_serial(
+                    sample_rate,
+                    channels,
+                    BufWriter::new(File::create(file)?),
+                    0,
+                )
+                .bitrate_management_strategy(
+                    vorbis_rs::VorbisBitrateManagementStrategy::QualityVbr {
+                        target_quality: quality.clamp(-0.2, 1.0),
+                    },
+                )
+                .build()?;
+
+                Ok(Writer::Ogg {
+                    encoder: Box::new(encoder),
+                    channels: channels.get() as u16,
+                    frames: 0,
+                })
+            }
+            #[cfg(not(feature = "ogg"))]
+            OutputFormat::Ogg { .. } => Err(RecorderError::UnsupportedFormat(
+                "writing OGG files requires enginesound to be built with the \"ogg\" feature"
+                    .into(),
+            )),
+        }
+    }
+
+    /// writes interleaved samples
+    fn write(&mut self, samples: &[f32]) -> Result<(), RecorderError> {
+        match self {
+            Writer::Wav {
+                wav_writer,
+                bits,
+                float,
+                dither,
+            } => write_samples(wav_writer, samples, *bits, *float, dither)?,
+            #[cfg(feature = "ogg")]
+            Writer::Ogg {
+                encoder,
+                channels,
+                frames,
+            } => {
+                let channels = *channels as usize;
+                let planar = (0..channels)
+                    .map(|channel| {
+                        samples
+                            .iter()
+                            .skip(channel)
+                            .step_by(channels)
+                            .copied()
+                            .collect::<Vec<f32>>()
+                    })
+                    .collect::<Vec<Vec<f32>>>();
+
+                *frames += samples.len() / channels;
+                encoder.encode_audio_block(&planar)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// flushes all buffered data and returns the amount of written frames
+    fn finish(self) -> Result<usize, RecorderError> {
+        match self {
+            Writer::Wav { wav_writer, .. } => {
+                let frames = wav_writer.duration() as usize;
+                wav_writer.finalize()?;
+                Ok(frames)
+            }
+            #[cfg(feature = "ogg")]
+            Writer::Ogg {
+                encoder, frames, ..
+            } => {
+                encoder.finish()?.flush()?;
+                Ok(frames)
+            }
+        }
+    }
+}
+
 pub struct Recorder {
-    /// recorded samples since creation
+    file: PathBuf,
+    /// recorded frames since creation
     len: usize,
+    channels: u16,
+    /// set if the channels are separate tracks instead of a mono/stereo mix, see `new_multichannel`
+    multichannel: bool,
     sender: crossbeam_channel::Sender<Vec<f32>>,
     running: Arc<AtomicBool>,
     block_lock: Arc<Mutex<()>>,
+    /// set by the writing thread if writing failed, which also stops the recorder
+    error: Arc<Mutex<Option<RecorderError>>>,
+    normalization: Arc<Mutex<Option<Normalization>>>,
+    /// engine whose `RecordingMetadata` is written once the recording is finished
+    metadata: Arc<Mutex<Option<Engine>>>,
+    /// written to the `LIST`/`INFO` chunk of WAV files once the recording is finished
+    info: Arc<Mutex<EngineInfo>>,
 }
 
 impl Recorder {
-    pub fn new(file: PathBuf, sample_rate: u32) -> Recorder {
+    /// Creates a recorder writing a mono file
+    pub fn new(
+        file: PathBuf,
+        sample_rate: u32,
+        format: OutputFormat,
+    ) -> Result<Recorder, RecorderError> {
+        Self::with_channels(file, sample_rate, format, 1)
+    }
+
+    /// Creates a recorder writing a stereo file, recorded samples must be interleaved L/R
+    pub fn new_stereo(
+        file: PathBuf,
+        sample_rate: u32,
+        format: OutputFormat,
+    ) -> Result<Recorder, RecorderError> {
+        Self::with_channels(file, sample_rate, format, 2)
+    }
+
+    /// Creates a recorder writing one track per channel, which are recorded with `record_multichannel`.
+    /// The generator records the intake, engine vibrations and exhaust in this order
+    pub fn new_multichannel(
+        file: PathBuf,
+        sample_rate: u32,
+        format: OutputFormat,
+        channels: u8,
+    ) -> Result<Recorder, RecorderError> {
+        let mut ret = Self::with_channels(file, sample_rate, format, channels as u16)?;
+        ret.multichannel = true;
+        Ok(ret)
+    }
+
+    /// the output file is created before returning, errors while writing are available through `take_error`
+    fn with_channels(
+        file: PathBuf,
+        sample_rate: u32,
+        format: OutputFormat,
+        channels: u16,
+    ) -> Result<Recorder, RecorderError> {
         let (send, recv) = crossbeam_channel::unbounded();
 
         let ret = Recorder {
+            file: file.clone(),
             len: 0,
+            channels,
+            multichannel: false,
             sender: send,
             running: Arc::new(AtomicBool::new(true)),
             block_lock: Arc::new(Mutex::new(())),
+            error: Arc::new(Mutex::new(None)),
+            normalization: Arc::new(Mutex::new(None)),
+            metadata: Arc::new(Mutex::new(None)),
+            info: Arc::new(Mutex::new(EngineInfo::default())),
         };
-        ret.start(recv, file, sample_rate);
-        ret
+        ret.start(recv, file, sample_rate, format)?;
+        Ok(ret)
     }
 
-    fn start(&self, recv: crossbeam_channel::Receiver<Vec<f32>>, file: PathBuf, sample_rate: u32) {
+    fn start(
+        &self,
+        recv: crossbeam_channel::Receiver<Vec<f32>>,
+        file: PathBuf,
+        sample_rate: u32,
+        format: OutputFormat,
+    ) -> Result<(), RecorderError> {
+        // the writer is created on the writing thread, which reports back whether that succeeded
+        let (created_send, created_recv) = crossbeam_channel::bounded(1);
+
         std::thread::spawn({
             let running = self.running.clone();
             let block_lock = self.block_lock.clone();
+            let error = self.error.clone();
+            let normalization = self.normalization.clone();
+            let metadata = self.metadata.clone();
+            let info = self.info.clone();
+            let channels = self.channels;
             move || {
                 let lock = block_lock.lock();
 
-                let mut wav_writer = match hound::WavWriter::new(
-                    BufWriter::new(File::create(&file).unwrap_or_else(|e| {
-                        panic!("Failed to create/open a file for writing the WAV: {}", e)
-                    })),
-                    WavSpec {
-                        channels: 1,
-                        sample_rate,
-                        bits_per_sample: 32,
-                        sample_format: SampleFormat::Float,
-                    },
-                ) {
-                    Ok(wav_writer) => wav_writer,
-                    Err(e) => panic!("Failed to create a WavWriter: {}", e),
+                let writer = match Writer::new(&file, sample_rate, format, channels) {
+                    Ok(writer) => {
+                        let _ = created_send.send(Ok(()));
+                        writer
+                    }
+                    Err(e) => {
+                        running.store(false, Ordering::Relaxed);
+                        let _ = created_send.send(Err(e));
+                        return;
+                    }
                 };
 
-                while running.load(Ordering::Relaxed) {
-                    match recv.recv_timeout(Duration::from_secs(4)) {
-                        Ok(samples) => {
-                            samples
-                                .iter()
-                                .for_each(|sample| wav_writer.write_sample(*sample).unwrap());
-                        }
-                        Err(_) => break,
-                    }
-                }
+                match write_all(
+                    &recv,
+                    &running,
+                    MeasuringWriter::new(writer, sample_rate, channels),
+                    format,
+                    &normalization,
+                    sample_rate,
+                    channels,
+                ) {
+                    Ok(written) => {
+                        println!(
+                            "Done writing {} to File \"{}\" (wrote {:.3} sec)",
+                            format.name(),
+                            file.to_str().unwrap_or("<invalid UTF-8>"),
+                            written.frames as f32 / sample_rate as f32
+                        );
 
-                println!("Stopped recording, finishing writing WAV..");
+                        if let OutputFormat::Wav { .. } = format {
+                            if let Err(e) = write_info(&file, &info.lock()) {
+                                eprintln!(
+                                    "Failed to write the engine info to \"{}\": {}",
+                                    file.display(),
+                                    e
+                                );
+                            }
+                        }
 
-                while let Ok(samples) = recv.try_recv() {
-                    samples
-                        .iter()
-                        .for_each(|sample| wav_writer.write_sample(*sample).unwrap());
+                        if let Some(engine) = metadata.lock().take() {
+                            let path = metadata_file_name(&file);
+                            match RecordingMetadata::new(&engine, sample_rate, &written).save(&path)
+                            {
+                                Ok(()) => println!(
+                                    "Wrote the recording metadata to \"{}\"",
+                                    path.display()
+                                ),
+                                Err(e) => eprintln!(
+                                    "Failed to write the recording metadata \"{}\": {}",
+                                    path.display(),
+                                    e
+                                ),
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Failed to write {} to File \"{}\": {}",
+                            format.name(),
+                            file.to_str().unwrap_or("<invalid UTF-8>"),
+                            e
+                        );
+                        running.store(false, Ordering::Relaxed);
+                        *error.lock() = Some(e);
+                    }
                 }
 
-                wav_writer.flush().unwrap();
-
-                println!(
-                    "Done writing WAV to File \"{}\" (wrote {:.3} sec)",
-                    file.to_str().unwrap_or("<invalid UTF-8>"),
-                    wav_writer.len() as f32 / sample_rate as f32
-                );
-
                 // keeping lock in scope explicitly
                 std::mem::drop(lock);
             }
         });
+
+        created_recv
+            .recv()
+            .expect("recorder thread stopped before creating the writer")
     }
 
     pub fn is_running(&self) -> bool {
         self.running.load(Ordering::Relaxed)
     }
 
+    /// records the given samples, which have to be interleaved if this recorder has more than one channel
     pub fn record(&mut self, samples: Vec<f32>) {
         if self.is_running() {
-            self.len += samples.len();
-            self.sender.send(samples).unwrap();
+            self.len += samples.len() / self.channels as usize;
+            // the writing thread only stops receiving after the recorder stopped running
+            let _ = self.sender.send(samples);
         }
     }
 
+    /// records one buffer of samples per channel, the buffers are interleaved as `[channel0_0, channel1_0, .., channel0_1, ..]`
+    pub fn record_multichannel(&mut self, samples: Vec<Vec<f32>>) {
+        assert_eq!(
+            samples.len(),
+            self.channels as usize,
+            "expected one buffer per recorded channel"
+        );
+
+        let frames = samples.iter().map(Vec::len).min().unwrap_or(0);
+        let interleaved = (0..frames)
+            .flat_map(|frame| samples.iter().map(move |channel| channel[frame]))
+            .collect::<Vec<f32>>();
+
+        self.record(interleaved);
+    }
+
+    /// path of the output file
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// returns the amount of frames recorded
     pub fn get_len(&self) -> usize {
         self.len
     }
 
+    pub fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    pub fn is_multichannel(&self) -> bool {
+        self.multichannel
+    }
+
     pub fn stop(&self) {
         self.running.store(false, Ordering::Relaxed);
     }
 
+    /// stops the recorder and waits until all recorded samples have been written
     pub fn stop_wait(&self) {
         self.running.store(false, Ordering::Relaxed);
 
-        while !self.sender.is_empty() {}
+        // held by the writing thread until it is done
+        std::mem::drop(self.block_lock.lock());
+    }
+
+    /// keeps the recorded samples in memory and writes them normalized to the target loudness once the recorder is stopped,
+    /// has to be set before recording any samples, the gain is reduced if the normalized samples would clip
+    pub fn set_normalization(&mut self, normalization: Option<Normalization>) {
+        *self.normalization.lock() = normalization;
+    }
+
+    /// writes a `RecordingMetadata` of `engine` next to the file once the recorder is stopped,
+    /// has to be set before the recorder is stopped, `engine` should be a copy of the engine at the start of the recording
+    pub fn set_metadata(&mut self, engine: Option<Engine>) {
+        *self.metadata.lock() = engine;
+    }
+
+    /// writes the name, author and description of `engine` to the `LIST`/`INFO` chunk of the file once the recorder is stopped,
+    /// has to be set before the recorder is stopped, only applies to WAV files
+    pub fn set_info(&mut self, engine: &Engine) {
+        *self.info.lock() = EngineInfo::new(engine);
+    }
+
+    /// returns the error which stopped the recorder while writing, if any
+    pub fn take_error(&self) -> Option<RecorderError> {
+        self.error.lock().take()
+    }
+}
+
+/// writes samples until the recorder is stopped and finishes the file
+fn write_all(
+    recv: &crossbeam_channel::Receiver<Vec<f32>>,
+    running: &AtomicBool,
+    mut writer: MeasuringWriter,
+    format: OutputFormat,
+    normalization: &Mutex<Option<Normalization>>,
+    sample_rate: u32,
+    channels: u16,
+) -> Result<Written, RecorderError> {
+    // samples kept in memory while normalizing
+    let mut buffer = Vec::new();
+    let mut normalizing = true;
 
-        let _ = self.block_lock.lock();
+    let mut receive = |samples: Vec<f32>| -> Result<(), RecorderError> {
+        match *normalization.lock() {
+            Some(Normalization { max_seconds, .. }) if normalizing => {
+                buffer.extend_from_slice(&samples);
+
+                if buffer.len() as f32 > max_seconds * sample_rate as f32 * channels as f32 {
+                    println!(
+                        "Recording is longer than {} sec, writing it without normalization",
+                        max_seconds
+                    );
+                    normalizing = false;
+                    writer.write(&buffer)?;
+                    buffer = Vec::new();
+                }
+
+                Ok(())
+            }
+            _ => writer.write(&samples),
+        }
+    };
+
+    while running.load(Ordering::Relaxed) {
+        match recv.recv_timeout(Duration::from_secs(4)) {
+            Ok(samples) => receive(samples)?,
+            Err(_) => break,
+        }
     }
+
+    println!("Stopped recording, finishing writing {}..", format.name());
+
+    while let Ok(samples) = recv.try_recv() {
+        receive(samples)?;
+    }
+
+    if let Some(normalization) = *normalization.lock() {
+        if !buffer.is_empty() {
+            match normalize(&mut buffer, normalization.target, sample_rate, channels) {
+                Some(gain) => {
+                    println!("Normalized the recording by {:+.2} dB", 20.0 * gain.log10())
+                }
+                None => println!("Recording is silent, writing it without normalization"),
+            }
+            writer.write(&buffer)?;
+        }
+    }
+
+    writer.finish()
+}
+
+/// applies the gain which brings the interleaved samples to `target` and returns it, `None` if the samples are silent
+///
+/// a loudness target is reduced with a warning if the samples would peak above 0 dBFS
+pub fn normalize(
+    samples: &mut [f32],
+    target: NormalizationTarget,
+    sample_rate: u32,
+    channels: u16,
+) -> Option<f32> {
+    let peak = samples
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+    if peak == 0.0 {
+        return None;
+    }
+
+    let gain = match target {
+        NormalizationTarget::Peak(target_peak) => target_peak / peak,
+        NormalizationTarget::Lufs(target_lufs) => {
+            let mut monitor = LufsMonitor::new(sample_rate, channels);
+            monitor.update(samples);
+            let lufs = monitor.lufs();
+
+            if !lufs.is_finite() {
+                return None;
+            }
+
+            let gain = 10f32.powf((target_lufs - lufs) / 20.0);
+            if peak * gain > 1.0 {
+                eprintln!(
+                    "Warning: normalizing to {} LUFS would clip (peak at {:.2} dBFS), clamping the peak to 0 dBFS",
+                    target_lufs,
+                    20.0 * (peak * gain).log10()
+                );
+                1.0 / peak
+            } else {
+                gain
+            }
+        }
+    };
+
+    samples.iter_mut().for_each(|sample| *sample *= gain);
+
+    Some(gain)
+}
+
+/// writes float samples with the given depth, integer samples are clipped and TPDF dithered
+fn write_samples<W: Write + Seek>(
+    wav_writer: &mut WavWriter<W>,
+    samples: &[f32],
+    bits: u8,
+    float: bool,
+    dither: &mut Noise,
+) -> Result<(), hound::Error> {
+    if float {
+        return samples
+            .iter()
+            .try_for_each(|sample| wav_writer.write_sample(*sample));
+    }
+
+    let max = ((1i64 << (bits - 1)) - 1) as f32;
+
+    samples.iter().try_for_each(|sample| {
+        // triangular distribution in -1..1 LSB
+        let tpdf = (dither.step() + dither.step()) * 0.5;
+        let quantized = (sample * max + tpdf).round().clamp(-max - 1.0, max) as i32;
+        wav_writer.write_sample(quantized)
+    })
 }
@@ -0,0 +1,82 @@
+use crate::exactstreamer::ExactStreamer;
+
+/// amount of interleaved stereo samples which are read from the source at once
+const INPUT_CHUNK_SIZE: usize = 512;
+
+/// Linearly interpolates interleaved stereo samples from an `ExactStreamer` to another sample rate,
+/// the samples are passed through unchanged if both rates are equal
+pub struct Resampler {
+    source: ExactStreamer<f32>,
+    from_sample_rate: u32,
+    /// input frames per output frame
+    ratio: f64,
+    /// position between `prev` and `next` in input frames, 0.0-1.0
+    pos: f64,
+    prev: [f32; 2],
+    next: [f32; 2],
+    input: Vec<f32>,
+    /// index of the next unread frame in `input`
+    input_pos: usize,
+}
+
+impl Resampler {
+    pub fn new(
+        source: ExactStreamer<f32>,
+        from_sample_rate: u32,
+        to_sample_rate: u32,
+    ) -> Resampler {
+        Resampler {
+            source,
+            from_sample_rate,
+            ratio: from_sample_rate as f64 / to_sample_rate as f64,
+            pos: 0.0,
+            prev: [0.0; 2],
+            next: [0.0; 2],
+            input: vec![0.0; INPUT_CHUNK_SIZE],
+            // the first read fills the input
+            input_pos: INPUT_CHUNK_SIZE / 2,
+        }
+    }
+
+    /// changes the sample rate the samples are resampled to
+    pub fn set_output_sample_rate(&mut self, to_sample_rate: u32) {
+        self.ratio = self.from_sample_rate as f64 / to_sample_rate as f64;
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) -> Result<(), crossbeam_channel::RecvError> {
+        if self.ratio == 1.0 {
+            return self.source.fill(out);
+        }
+
+        for frame in out.chunks_exact_mut(2) {
+            while self.pos >= 1.0 {
+                self.prev = self.next;
+                self.next = self.read_frame()?;
+                self.pos -= 1.0;
+            }
+
+            let pos = self.pos as f32;
+            frame[0] = self.prev[0] + (self.next[0] - self.prev[0]) * pos;
+            frame[1] = self.prev[1] + (self.next[1] - self.prev[1]) * pos;
+
+            self.pos += self.ratio;
+        }
+
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<[f32; 2], crossbeam_channel::RecvError> {
+        if self.input_pos * 2 >= self.input.len() {
+            self.source.fill(&mut self.input)?;
+            self.input_pos = 0;
+        }
+
+        let frame = [
+            self.input[self.input_pos * 2],
+            self.input[self.input_pos * 2 + 1],
+        ];
+        self.input_pos += 1;
+
+        Ok(frame)
+    }
+}
@@ -0,0 +1,138 @@
+//! ## Sample ring module ##
+//!
+//! Lock-free single producer single consumer ring buffer of samples for real-time audio callbacks,
+//! which may neither block nor allocate or free memory.
+//!
+
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+struct Shared {
+    /// bits of the samples, atomic so neither side needs `unsafe` to access a slot the other side might touch
+    samples: Box<[AtomicU32]>,
+    /// samples pushed so far, only written by the producer
+    written: AtomicUsize,
+    /// samples received so far, only written by the consumer
+    read: AtomicUsize,
+}
+
+/// creates a ring buffer which holds up to `capacity` samples
+pub fn sample_ring(capacity: usize) -> (RingProducer, RingConsumer) {
+    let shared = Arc::new(Shared {
+        samples: (0..capacity).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+    });
+
+    (
+        RingProducer {
+            shared: shared.clone(),
+        },
+        RingConsumer { shared },
+    )
+}
+
+pub struct RingProducer {
+    shared: Arc<Shared>,
+}
+
+impl RingProducer {
+    /// pushes as many samples of `samples` as there is room for and returns how many were pushed
+    pub fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let capacity = self.shared.samples.len();
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let free = capacity - (written - self.shared.read.load(Ordering::Acquire));
+        let len = samples.len().min(free);
+
+        for (i, sample) in samples[..len].iter().enumerate() {
+            self.shared.samples[(written + i) % capacity]
+                .store(sample.to_bits(), Ordering::Relaxed);
+        }
+        // publishes the samples to the consumer
+        self.shared.written.store(written + len, Ordering::Release);
+
+        len
+    }
+
+    /// samples which were pushed but not received yet
+    pub fn len(&self) -> usize {
+        self.shared.written.load(Ordering::Relaxed) - self.shared.read.load(Ordering::Acquire)
+    }
+
+    /// true if the consumer was dropped
+    pub fn is_abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+}
+
+pub struct RingConsumer {
+    shared: Arc<Shared>,
+}
+
+impl RingConsumer {
+    /// returns the oldest sample, or `None` if the ring is empty
+    pub fn try_recv(&mut self) -> Option<f32> {
+        let read = self.shared.read.load(Ordering::Relaxed);
+        if read == self.shared.written.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let sample = f32::from_bits(
+            self.shared.samples[read % self.shared.samples.len()].load(Ordering::Relaxed),
+        );
+        // frees the slot for the producer
+        self.shared.read.store(read + 1, Ordering::Release);
+
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_received_in_order_and_pushes_stop_when_full() {
+        let (mut producer, mut consumer) = sample_ring(4);
+
+        assert_eq!(producer.push_slice(&[1.0, 2.0, 3.0]), 3);
+        assert_eq!(consumer.try_recv(), Some(1.0));
+        assert_eq!(consumer.try_recv(), Some(2.0));
+        // wraps around the end of the buffer
+        assert_eq!(producer.push_slice(&[4.0, 5.0, 6.0, 7.0]), 3);
+        assert_eq!(producer.len(), 4);
+
+        let received = std::iter::from_fn(|| consumer.try_recv()).collect::<Vec<_>>();
+        assert_eq!(received, vec![3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(consumer.try_recv(), None);
+
+        assert!(!producer.is_abandoned());
+        drop(consumer);
+        assert!(producer.is_abandoned());
+    }
+
+    #[test]
+    fn samples_arrive_in_order_across_threads() {
+        let (mut producer, mut consumer) = sample_ring(16);
+
+        let producer = std::thread::spawn(move || {
+            let samples = (0..10000).map(|i| i as f32).collect::<Vec<_>>();
+            let mut pushed = 0;
+            while pushed < samples.len() {
+                pushed += producer.push_slice(&samples[pushed..(pushed + 7).min(samples.len())]);
+                std::thread::yield_now();
+            }
+        });
+
+        let mut expected = 0.0;
+        while expected < 10000.0 {
+            if let Some(sample) = consumer.try_recv() {
+                assert_eq!(sample, expected);
+                expected += 1.0;
+            } else {
+                std::thread::yield_now();
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,271 @@
+//! ## Spectrogram module ##
+//!
+//! Renders waterfall lines into a PNG image with a time and a frequency axis burned into its margins
+//!
+
+use crate::gui::{
+    axis_bin_width, display_value, format_freq, waterfall_column_freq, Colormap, WATERFALL_WIDTH,
+};
+use std::path::PathBuf;
+
+/// width of the margin left of the lines which holds the time axis
+const TIME_AXIS_WIDTH: u32 = 40;
+/// height of the margin below the lines which holds the frequency axis
+const FREQ_AXIS_HEIGHT: u32 = 18;
+const AXIS_BACKGROUND: [u8; 3] = [24, 24, 26];
+const AXIS_COLOR: [u8; 3] = [200, 200, 210];
+/// length of the tick marks in pixels
+const TICK_LEN: u32 = 4;
+/// glyphs are scaled up by this factor
+const GLYPH_SCALE: u32 = 2;
+/// the time axis labels are at least this many pixels apart
+const MIN_TIME_LABEL_DISTANCE: f32 = 30.0;
+/// frequencies in hz of the labeled ticks of the frequency axis
+const FREQ_TICKS: [f32; 7] = [50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0];
+/// seconds between two labels of the time axis, the smallest which keeps the labels `MIN_TIME_LABEL_DISTANCE` apart is used
+const TIME_TICK_STEPS: [f32; 6] = [0.5, 1.0, 2.0, 5.0, 10.0, 30.0];
+
+/// Waterfall lines with the display settings they are rendered with
+pub struct Spectrogram {
+    /// lines of `WATERFALL_WIDTH` FFT magnitudes, newest first
+    pub lines: Vec<Vec<f32>>,
+    pub colormap: Colormap,
+    /// linear gain of the magnitudes, see `display_value`
+    pub gain: f32,
+    /// frequency difference of two neighboring FFT bins in hz, the axes are only labeled if it is known
+    pub bin_width: Option<f32>,
+    /// samples of the FFT of one line
+    pub fft_size: usize,
+}
+
+impl Spectrogram {
+    /// image size in pixels
+    pub fn size(&self) -> (u32, u32) {
+        (
+            TIME_AXIS_WIDTH + WATERFALL_WIDTH,
+            self.lines.len() as u32 + FREQ_AXIS_HEIGHT,
+        )
+    }
+
+    /// RGB pixels of the image with the newest line at the top, the oldest line is at 0 seconds of the time axis
+    pub fn render(&self) -> Vec<u8> {
+        let (width, height) = self.size();
+        let mut image = Image {
+            pixels: AXIS_BACKGROUND.repeat((width * height) as usize),
+            width,
+        };
+
+        for (row, line) in self.lines.iter().enumerate() {
+            for (column, &magnitude) in line.iter().enumerate() {
+                let color = self.colormap.color(display_value(magnitude, self.gain));
+                image.set(TIME_AXIS_WIDTH + column as u32, row as u32, color);
+            }
+        }
+
+        if let Some(bin_width) = self.bin_width {
+            self.draw_time_axis(&mut image, bin_width);
+            self.draw_freq_axis(&mut image, axis_bin_width(bin_width, self.fft_size));
+        }
+
+        image.pixels
+    }
+
+    /// one line is the spectrum of `1 / bin_width` seconds
+    fn draw_time_axis(&self, image: &mut Image, bin_width: f32) {
+        let rows = self.lines.len();
+        let lines_per_second = bin_width;
+        let step = TIME_TICK_STEPS
+            .iter()
+            .copied()
+            .find(|step| step * lines_per_second >= MIN_TIME_LABEL_DISTANCE)
+            .unwrap_or(TIME_TICK_STEPS[TIME_TICK_STEPS.len() - 1]);
+
+        let mut time = 0.0;
+        while ((time * lines_per_second) as usize) < rows {
+            let row = (rows - 1 - (time * lines_per_second) as usize) as u32;
+            image.fill(TIME_AXIS_WIDTH - TICK_LEN, row, TICK_LEN, 1, AXIS_COLOR);
+
+            let label = format!("{}s", time);
+            let label_x = (TIME_AXIS_WIDTH - TICK_LEN - 2).saturating_sub(text_width(&label));
+            let label_y = row.saturating_sub(GLYPH_HEIGHT * GLYPH_SCALE);
+            image.text(label_x, label_y, &label);
+
+            time += step;
+        }
+    }
+
+    /// `bin_width` of the frequency axis, see `axis_bin_width`
+    fn draw_freq_axis(&self, image: &mut Image, bin_width: f32) {
+        let top = self.lines.len() as u32;
+        // the low frequencies are close together, labels which would overlap the previous one are left out
+        let mut free_x = 0;
+
+        for &freq in FREQ_TICKS.iter() {
+            let column = match (0..WATERFALL_WIDTH)
+                .find(|&column| waterfall_column_freq(column as f32, bin_width) >= freq)
+            {
+                Some(column) => column,
+                None => continue,
+            };
+            let x = TIME_AXIS_WIDTH + column;
+            image.fill(x, top, 1, TICK_LEN, AXIS_COLOR);
+
+            let label = format_freq(freq);
+            let label_x = x.saturating_sub(text_width(&label) / 2);
+            if label_x >= free_x {
+                image.text(label_x, top + TICK_LEN + 2, &label);
+                free_x = label_x + text_width(&label) + GLYPH_ADVANCE;
+            }
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ::image::ImageError> {
+        let (width, height) = self.size();
+
+        ::image::save_buffer(
+            path,
+            &self.render(),
+            width,
+            height,
+            ::image::ColorType::Rgb8,
+        )
+    }
+
+    /// renders and saves the image on a new thread, the path or the error is sent once it is done
+    pub fn save_in_background(
+        self,
+        path: PathBuf,
+    ) -> crossbeam_channel::Receiver<Result<PathBuf, String>> {
+        let (sender, receiver) = crossbeam_channel::bounded(1);
+
+        std::thread::spawn(move || {
+            let result = self
+                .save(&path.to_string_lossy())
+                .map(|()| path)
+                .map_err(|e| e.to_string());
+            let _ = sender.send(result);
+        });
+
+        receiver
+    }
+}
+
+struct Image {
+    /// RGB pixels row by row
+    pixels: Vec<u8>,
+    width: u32,
+}
+
+impl Image {
+    /// ignores pixels outside of the image
+    fn set(&mut self, x: u32, y: u32, color: [u8; 3]) {
+        let index = (y * self.width + x) as usize * 3;
+        if x < self.width && index < self.pixels.len() {
+            self.pixels[index..index + 3].copy_from_slice(&color);
+        }
+    }
+
+    fn fill(&mut self, x: u32, y: u32, w: u32, h: u32, color: [u8; 3]) {
+        for y in y..(y + h) {
+            for x in x..(x + w) {
+                self.set(x, y, color);
+            }
+        }
+    }
+
+    /// draws `text` with its top left corner at `x`, `y`, characters without a glyph are left blank
+    fn text(&mut self, x: u32, y: u32, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            let left = x + i as u32 * GLYPH_ADVANCE;
+
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        self.fill(
+                            left + column * GLYPH_SCALE,
+                            y + row as u32 * GLYPH_SCALE,
+                            GLYPH_SCALE,
+                            GLYPH_SCALE,
+                            AXIS_COLOR,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+/// horizontal distance of two characters in pixels
+const GLYPH_ADVANCE: u32 = (GLYPH_WIDTH + 1) * GLYPH_SCALE;
+
+/// width of `text` in pixels
+fn text_width(text: &str) -> u32 {
+    (text.chars().count() as u32 * GLYPH_ADVANCE).saturating_sub(GLYPH_SCALE)
+}
+
+/// rows of a 3x5 pixel glyph, the highest of the 3 bits is the leftmost pixel
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        'k' => [0b100, 0b101, 0b110, 0b101, 0b101],
+        's' => [0b000, 0b011, 0b100, 0b001, 0b110],
+        _ => [0; 5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectrogram(rows: usize, bin_width: Option<f32>) -> Spectrogram {
+        Spectrogram {
+            lines: vec![vec![0.0; WATERFALL_WIDTH as usize]; rows],
+            colormap: Colormap::Grayscale,
+            gain: 1.0,
+            bin_width,
+            fft_size: WATERFALL_WIDTH as usize * 2,
+        }
+    }
+
+    /// number of pixels with the axis color in the rectangle
+    fn axis_pixels(spectrogram: &Spectrogram, x: u32, y: u32, w: u32, h: u32) -> usize {
+        let (width, _) = spectrogram.size();
+        let pixels = spectrogram.render();
+
+        (y..(y + h))
+            .flat_map(|y| (x..(x + w)).map(move |x| (y * width + x) as usize * 3))
+            .filter(|&index| pixels[index..index + 3] == AXIS_COLOR)
+            .count()
+    }
+
+    #[test]
+    fn axes_are_drawn_into_the_margins() {
+        // 47 lines per second, about 4 seconds
+        let labeled = spectrogram(200, Some(48000.0 / 1024.0));
+        let (width, height) = labeled.size();
+        assert_eq!((width, height), (TIME_AXIS_WIDTH + WATERFALL_WIDTH, 218));
+
+        let time_axis = axis_pixels(&labeled, 0, 0, TIME_AXIS_WIDTH, 200);
+        let freq_axis = axis_pixels(&labeled, TIME_AXIS_WIDTH, 200, WATERFALL_WIDTH, 18);
+        let lines = axis_pixels(&labeled, TIME_AXIS_WIDTH, 0, WATERFALL_WIDTH, 200);
+        assert!(time_axis > 0);
+        assert!(freq_axis > 0);
+        assert_eq!(lines, 0);
+
+        // the axes can't be labeled without the bin width
+        let unlabeled = spectrogram(200, None);
+        assert_eq!(axis_pixels(&unlabeled, 0, 0, width, height), 0);
+    }
+}
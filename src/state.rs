@@ -0,0 +1,165 @@
+//! Checkpoints of the running state of a `Generator`, see `Generator::get_state` and `Generator::set_state`
+
+use crate::gen::Noise;
+use serde::{Deserialize, Serialize};
+
+/// Complete transient state of a `Generator`: everything which changes while generating and isn't saved with the engine config
+///
+/// The values of the engine are stored in the order in which they are visited, so a state can only be restored into a
+/// generator whose engine has the same layout (cylinder count, waveguide lengths, optional turbo, ..) as the one it was taken from.
+/// A running morph, the stems and the resampler of `Generator::generate_resampled` are not part of the state.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GeneratorState {
+    /// crankshaft position, 0.0-1.0
+    pub crankshaft_pos: f32,
+    /// rpm which the engine moves towards while generating, see `Generator::set_target_rpm`
+    pub target_rpm: Option<f32>,
+    /// samples generated since the rpm and throttle envelopes were set
+    pub envelope_pos: [usize; 2],
+    /// states (z1, z2) of the dc offset filters of the left and right channel
+    pub dc_hp: [[f64; 2]; 2],
+    /// states of the output high pass filters of the left and right channel
+    pub output_hpf: [f32; 2],
+    /// contents of the waveguide chambers and other sample buffers
+    pub buffers: Vec<Vec<f32>>,
+    /// filter states, collectors, oscillator phases and other running values
+    pub values: Vec<f32>,
+    /// read positions of the waveguide chambers and other counters
+    pub counts: Vec<usize>,
+    pub flags: Vec<bool>,
+    /// states of the noise generators
+    pub noise: Vec<Noise>,
+}
+
+impl GeneratorState {
+    /// the RON serialization of this state, compressed with lz4 (`lz4_flex`, which is pure rust and also builds for wasm)
+    pub fn compress(&self) -> Vec<u8> {
+        let ron = ron::ser::to_string(self).expect("failed to serialize generator state");
+
+        lz4_flex::compress_prepend_size(ron.as_bytes())
+    }
+
+    /// reads a state written by `compress`
+    pub fn decompress(data: &[u8]) -> Result<GeneratorState, String> {
+        let ron = lz4_flex::decompress_size_prepended(data)
+            .map_err(|e| format!("Failed to decompress generator state: {}", e))?;
+        let ron = String::from_utf8(ron).map_err(|e| e.to_string())?;
+
+        ron::de::from_str(&ron).map_err(|e| e.to_string())
+    }
+}
+
+/// Visits the running values of the generator in a fixed order, either to collect them into a `GeneratorState`
+/// or to restore them from one
+pub(crate) trait StateVisitor {
+    fn value(&mut self, value: &mut f32);
+    fn count(&mut self, count: &mut usize);
+    fn flag(&mut self, flag: &mut bool);
+    fn noise(&mut self, noise: &mut Noise);
+    /// samples whose length is given by the engine, e.g. the contents of a waveguide chamber
+    fn buffer(&mut self, buffer: &mut [f32]);
+    /// samples whose length changes while generating
+    fn samples(&mut self, samples: &mut Vec<f32>);
+}
+
+/// collects the visited values
+impl StateVisitor for GeneratorState {
+    fn value(&mut self, value: &mut f32) {
+        self.values.push(*value);
+    }
+
+    fn count(&mut self, count: &mut usize) {
+        self.counts.push(*count);
+    }
+
+    fn flag(&mut self, flag: &mut bool) {
+        self.flags.push(*flag);
+    }
+
+    fn noise(&mut self, noise: &mut Noise) {
+        self.noise.push(noise.clone());
+    }
+
+    fn buffer(&mut self, buffer: &mut [f32]) {
+        self.buffers.push(buffer.to_vec());
+    }
+
+    fn samples(&mut self, samples: &mut Vec<f32>) {
+        self.buffers.push(samples.clone());
+    }
+}
+
+/// Overwrites the visited values with the values of a `GeneratorState`
+pub(crate) struct StateRestorer {
+    buffers: std::vec::IntoIter<Vec<f32>>,
+    values: std::vec::IntoIter<f32>,
+    counts: std::vec::IntoIter<usize>,
+    flags: std::vec::IntoIter<bool>,
+    noise: std::vec::IntoIter<Noise>,
+    /// set if the state doesn't fit the visited values
+    mismatch: bool,
+}
+
+impl StateRestorer {
+    pub(crate) fn new(state: GeneratorState) -> StateRestorer {
+        StateRestorer {
+            buffers: state.buffers.into_iter(),
+            values: state.values.into_iter(),
+            counts: state.counts.into_iter(),
+            flags: state.flags.into_iter(),
+            noise: state.noise.into_iter(),
+            mismatch: false,
+        }
+    }
+
+    /// returns an error if the state didn't fit the visited values or wasn't used up
+    pub(crate) fn finish(mut self) -> Result<(), String> {
+        if self.mismatch
+            || self.buffers.next().is_some()
+            || self.values.next().is_some()
+            || self.counts.next().is_some()
+            || self.flags.next().is_some()
+            || self.noise.next().is_some()
+        {
+            Err("The generator state was taken from an engine with a different layout".to_string())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn restore<T>(next: Option<T>, target: &mut T, mismatch: &mut bool) {
+        match next {
+            Some(value) => *target = value,
+            None => *mismatch = true,
+        }
+    }
+}
+
+impl StateVisitor for StateRestorer {
+    fn value(&mut self, value: &mut f32) {
+        Self::restore(self.values.next(), value, &mut self.mismatch);
+    }
+
+    fn count(&mut self, count: &mut usize) {
+        Self::restore(self.counts.next(), count, &mut self.mismatch);
+    }
+
+    fn flag(&mut self, flag: &mut bool) {
+        Self::restore(self.flags.next(), flag, &mut self.mismatch);
+    }
+
+    fn noise(&mut self, noise: &mut Noise) {
+        Self::restore(self.noise.next(), noise, &mut self.mismatch);
+    }
+
+    fn buffer(&mut self, buffer: &mut [f32]) {
+        match self.buffers.next() {
+            Some(samples) if samples.len() == buffer.len() => buffer.copy_from_slice(&samples),
+            _ => self.mismatch = true,
+        }
+    }
+
+    fn samples(&mut self, samples: &mut Vec<f32>) {
+        Self::restore(self.buffers.next(), samples, &mut self.mismatch);
+    }
+}
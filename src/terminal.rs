@@ -0,0 +1,309 @@
+//! ## Terminal module ##
+//!
+//! Keyboard control of the playback without a GUI, e.g. over SSH on a machine without a desktop.
+//! The terminal is switched to raw mode while playing, keys change the engine immediately:
+//!
+//! * up / down arrow: RPM +/- 100
+//! * page up / page down: RPM +/- 1000
+//! * `r`: starts recording to `enginesound_<time>.wav` in the working directory, or stops the recording
+//! * `q` or Ctrl+C: stops the recording and quits
+//!
+//! The RPM, volume and recording state are redrawn on a single status line.
+//!
+
+use enginesound::generator_thread::{GeneratorCommand, GeneratorHandle, GeneratorSnapshot};
+use enginesound::recorder::{OutputFormat, Recorder, SampleDepth};
+use enginesound::utils::recording_name;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// rpm change of the arrow keys
+const RPM_STEP: f32 = 100.0;
+/// rpm change of page up and page down
+const RPM_PAGE_STEP: f32 = 1000.0;
+/// longest time a read waits for a key before the status line is redrawn, in tenths of a second (`VTIME`)
+const READ_TIMEOUT_DECISECONDS: libc::cc_t = 1;
+/// longest time quitting waits for the generator thread to hand over a running recorder
+const RECORDER_HANDOVER_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Char(char),
+}
+
+/// decodes the bytes read from a raw mode terminal, unknown escape sequences are skipped
+pub fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == 0x1b && bytes.get(i + 1) == Some(&b'[') {
+            // CSI sequence, ends with a byte in 0x40..=0x7e
+            let end = match bytes[i + 2..]
+                .iter()
+                .position(|byte| (0x40..=0x7e).contains(byte))
+            {
+                Some(end) => i + 2 + end,
+                None => break,
+            };
+
+            match &bytes[i + 2..=end] {
+                b"A" => keys.push(Key::Up),
+                b"B" => keys.push(Key::Down),
+                b"5~" => keys.push(Key::PageUp),
+                b"6~" => keys.push(Key::PageDown),
+                _ => {}
+            }
+            i = end + 1;
+        } else {
+            keys.push(Key::Char(bytes[i] as char));
+            i += 1;
+        }
+    }
+
+    keys
+}
+
+/// what a key does, see the module documentation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// adds this to the target rpm
+    ChangeRpm(f32),
+    ToggleRecording,
+    Quit,
+}
+
+impl Action {
+    pub fn from_key(key: Key) -> Option<Action> {
+        match key {
+            Key::Up => Some(Action::ChangeRpm(RPM_STEP)),
+            Key::Down => Some(Action::ChangeRpm(-RPM_STEP)),
+            Key::PageUp => Some(Action::ChangeRpm(RPM_PAGE_STEP)),
+            Key::PageDown => Some(Action::ChangeRpm(-RPM_PAGE_STEP)),
+            Key::Char('r') | Key::Char('R') => Some(Action::ToggleRecording),
+            // Ctrl+C doesn't send SIGINT in raw mode
+            Key::Char('q') | Key::Char('Q') | Key::Char('\u{3}') => Some(Action::Quit),
+            Key::Char(_) => None,
+        }
+    }
+}
+
+/// e.g. `RPM 3500 -> 4000 | volume 0.10 | recording 2.5 sec (r: stop, q: quit)`
+pub fn status_line(snapshot: &GeneratorSnapshot) -> String {
+    let rpm = match snapshot.target_rpm {
+        Some(target) if (target - snapshot.engine.rpm).abs() >= 1.0 => {
+            format!("RPM {:.0} -> {:.0}", snapshot.engine.rpm, target)
+        }
+        _ => format!("RPM {:.0}", snapshot.engine.rpm),
+    };
+
+    let recording = match &snapshot.recorder {
+        Some(recorder) if recorder.running => format!(
+            "recording {:.1} sec (r: stop",
+            recorder.len as f32 / snapshot.samples_per_second as f32
+        ),
+        _ => "not recording (r: record".to_string(),
+    };
+
+    format!(
+        "{} | volume {:.2} | {}, arrows/page up/down: RPM, q: quit)",
+        rpm, snapshot.volume, recording
+    )
+}
+
+/// restores the terminal attributes it was created with when dropped
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// disables line buffering, echo and signal keys of stdin, reads return after `READ_TIMEOUT_DECISECONDS` without input
+    fn enable() -> Result<RawMode, String> {
+        let mut original = std::mem::MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr initializes the termios struct if it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+                return Err(format!(
+                    "Keyboard control requires a terminal: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            original.assume_init()
+        };
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = READ_TIMEOUT_DECISECONDS;
+
+        // SAFETY: raw is a valid termios struct
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(format!(
+                "Failed to switch the terminal to raw mode: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: original was returned by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// reads keys from the terminal and applies them to the generator on the current thread until `q` is pressed,
+/// a running recording is finished before returning
+pub fn run(generator: GeneratorHandle, sample_rate: u32) -> Result<(), String> {
+    let raw_mode = RawMode::enable()?;
+    let mut stdin = std::io::stdin();
+    let mut stdout = std::io::stdout();
+    let mut buf = [0u8; 32];
+
+    loop {
+        let len = stdin
+            .read(&mut buf)
+            .map_err(|e| format!("Failed to read from the terminal: {}", e))?;
+
+        let snapshot = match generator.snapshot() {
+            Some(snapshot) => snapshot,
+            None => break,
+        };
+
+        // a stopped recorder has finished writing once it isn't running anymore
+        if let Some(recorder) = &snapshot.recorder {
+            if !recorder.running {
+                generator.apply(|generator| generator.recorder = None);
+                match &recorder.error {
+                    Some(e) => print!("\r\x1b[KRecording failed: {}\r\n", e),
+                    None => print!("\r\x1b[KSaved recording\r\n"),
+                }
+            }
+        }
+
+        // keys pressed in quick succession add up
+        let mut target_rpm = snapshot.target_rpm.unwrap_or(snapshot.engine.rpm);
+
+        for action in parse_keys(&buf[..len])
+            .into_iter()
+            .filter_map(Action::from_key)
+        {
+            match action {
+                Action::ChangeRpm(change) => {
+                    target_rpm = (target_rpm + change).max(0.0);
+                    generator.send(GeneratorCommand::SetRpm(target_rpm));
+                }
+                Action::ToggleRecording => match &snapshot.recorder {
+                    Some(recorder) if recorder.running => generator.apply(|generator| {
+                        if let Some(recorder) = &generator.recorder {
+                            recorder.stop();
+                        }
+                    }),
+                    Some(_) => {}
+                    None => start_recording(&generator, sample_rate),
+                },
+                Action::Quit => {
+                    finish_recording(&generator);
+                    drop(raw_mode);
+                    println!();
+                    return Ok(());
+                }
+            }
+        }
+
+        let _ = write!(
+            stdout,
+            "\r\x1b[K{}",
+            status_line(&generator.snapshot().unwrap_or(snapshot))
+        );
+        let _ = stdout.flush();
+    }
+
+    Ok(())
+}
+
+fn start_recording(generator: &GeneratorHandle, sample_rate: u32) {
+    let path = PathBuf::from(recording_name());
+
+    match Recorder::new(
+        path.clone(),
+        sample_rate,
+        OutputFormat::from(SampleDepth::F32),
+    ) {
+        Ok(mut recorder) => {
+            print!("\r\x1b[KRecording to \"{}\"\r\n", path.display());
+            generator.apply(move |generator| {
+                recorder.set_info(&generator.engine);
+                generator.recorder = Some(recorder);
+            });
+        }
+        Err(e) => print!("\r\x1b[KFailed to start recording: {}\r\n", e),
+    }
+}
+
+/// stops a running recording and waits until it has been written
+fn finish_recording(generator: &GeneratorHandle) {
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    generator.apply(move |generator| {
+        let _ = sender.send(generator.recorder.take());
+    });
+
+    // the writer thread is joined off the generator thread
+    if let Ok(Some(recorder)) = receiver.recv_timeout(RECORDER_HANDOVER_TIMEOUT) {
+        recorder.stop_wait();
+        print!(
+            "\r\x1b[KSaved recording \"{}\"\r\n",
+            recorder.file().display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_sequences_are_decoded() {
+        assert_eq!(
+            parse_keys(b"\x1b[A\x1b[Br\x1b[5~\x1b[6~q"),
+            vec![
+                Key::Up,
+                Key::Down,
+                Key::Char('r'),
+                Key::PageUp,
+                Key::PageDown,
+                Key::Char('q')
+            ]
+        );
+        // unknown sequences (right arrow, F5) are skipped
+        assert_eq!(parse_keys(b"\x1b[C\x1b[15~r"), vec![Key::Char('r')]);
+    }
+
+    #[test]
+    fn keys_change_the_rpm_and_quit() {
+        let actions = parse_keys(b"\x1b[A\x1b[6~\x03x")
+            .into_iter()
+            .map(Action::from_key)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            actions,
+            vec![
+                Some(Action::ChangeRpm(100.0)),
+                Some(Action::ChangeRpm(-1000.0)),
+                Some(Action::Quit),
+                None
+            ]
+        );
+    }
+}
@@ -0,0 +1,100 @@
+//! ## Undo module ##
+//!
+//! Linear undo history of the GUI's engine edits, bound to Ctrl+Z (undo) and Ctrl+Y / Ctrl+Shift+Z (redo)
+//!
+
+/// the oldest states are dropped once the stack holds more than this many
+pub const MAX_UNDO_STEPS: usize = 50;
+
+/// States which can be stepped through with `undo` and `redo`, pushing a state discards the states which were undone
+pub struct UndoStack<T: Clone> {
+    /// oldest first, never empty
+    stack: Vec<T>,
+    /// index of the current state
+    pos: usize,
+}
+
+impl<T: Clone> UndoStack<T> {
+    pub fn new(initial: T) -> Self {
+        UndoStack {
+            stack: vec![initial],
+            pos: 0,
+        }
+    }
+
+    /// makes `state` the current state, the states after the current one can't be redone anymore
+    pub fn push(&mut self, state: T) {
+        self.stack.truncate(self.pos + 1);
+        self.stack.push(state);
+
+        if self.stack.len() > MAX_UNDO_STEPS {
+            self.stack.remove(0);
+        }
+        self.pos = self.stack.len() - 1;
+    }
+
+    /// steps back to the previous state and returns it, `None` if there is none
+    pub fn undo(&mut self) -> Option<&T> {
+        if self.pos == 0 {
+            return None;
+        }
+
+        self.pos -= 1;
+        Some(&self.stack[self.pos])
+    }
+
+    /// steps forward to the state which was undone last and returns it, `None` if there is none
+    pub fn redo(&mut self) -> Option<&T> {
+        if self.pos + 1 == self.stack.len() {
+            return None;
+        }
+
+        self.pos += 1;
+        Some(&self.stack[self.pos])
+    }
+
+    pub fn current(&self) -> &T {
+        &self.stack[self.pos]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_step_through_the_pushed_states() {
+        let mut stack = UndoStack::new(0);
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+
+        assert_eq!(stack.undo(), Some(&2));
+        assert_eq!(stack.undo(), Some(&1));
+        assert_eq!(stack.redo(), Some(&2));
+        assert_eq!(*stack.current(), 2);
+
+        // pushing discards the state which could have been redone
+        stack.push(4);
+        assert_eq!(stack.redo(), None);
+        assert_eq!(stack.undo(), Some(&2));
+        assert_eq!(stack.undo(), Some(&1));
+        assert_eq!(stack.undo(), Some(&0));
+        assert_eq!(stack.undo(), None);
+    }
+
+    #[test]
+    fn oldest_states_are_dropped() {
+        let mut stack = UndoStack::new(0);
+        for i in 1..=100 {
+            stack.push(i);
+        }
+
+        let mut undone = 0;
+        while stack.undo().is_some() {
+            undone += 1;
+        }
+        assert_eq!(undone, MAX_UNDO_STEPS - 1);
+        assert_eq!(*stack.current(), 100 - (MAX_UNDO_STEPS - 1));
+    }
+}
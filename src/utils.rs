@@ -1,5 +1,15 @@
-use crate::gen::{Engine, LoopBuffer, LowPassFilter};
+use crate::gen::{
+    BiquadFilter, Engine, EngineValidationError, Generator, HighPassFilter, LoopBuffer,
+    LowPassFilter, MufflerElement, WaveGuide, DC_OFFSET_LP_FREQ,
+};
+use chrono::{Datelike, Local, Timelike};
+use num_complex::{Complex32, Complex64};
+use num_traits::identities::Zero;
+use rustfft::FFT;
 use std::fs::File;
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 pub const SPEED_OF_SOUND: f32 = 343.0; // m/s
 
@@ -9,9 +19,15 @@ pub fn seconds_to_samples(seconds: f32, sample_rate: u32) -> usize {
     (seconds * sample_rate as f32).max(1.0) as usize
 }
 
-/// converts a given distance into samples via the speed of sound
+/// converts a given distance into (fractional) samples via the speed of sound
 #[inline]
-pub fn distance_to_samples(meters: f32, sample_rate: u32) -> usize {
+pub fn distance_to_samples(meters: f32, sample_rate: u32) -> f32 {
+    (meters / SPEED_OF_SOUND * sample_rate as f32).max(1.0)
+}
+
+/// converts a given distance into whole samples via the speed of sound
+#[inline]
+pub fn distance_to_whole_samples(meters: f32, sample_rate: u32) -> usize {
     seconds_to_samples(meters / SPEED_OF_SOUND, sample_rate)
 }
 
@@ -20,51 +36,455 @@ pub fn samples_to_seconds(samples: usize, sample_rate: u32) -> f32 {
     samples as f32 / sample_rate as f32
 }
 
-/// returns meters
+/// converts a given amount of (fractional) samples into meters
 #[inline]
-pub fn samples_to_distance(samples: usize, sample_rate: u32) -> f32 {
-    samples_to_seconds(samples, sample_rate) * SPEED_OF_SOUND
-}
-
-pub(crate) fn load_engine(path: &str, sample_rate: u32, json: bool) -> Result<Engine, String> {
-    match File::open(path) {
-        Ok(file) => {
-            if json {
-                match serde_json::de::from_reader::<_, Engine>(file) {
-                    Ok(mut engine) => {
-                        fix_engine(&mut engine, sample_rate);
-                        Ok(engine)
-                    }
-                    Err(e) => Err(format!("Failed to load JSON config \"{}\": {}", &path, e)),
+pub fn samples_to_distance(samples: f32, sample_rate: u32) -> f32 {
+    samples / sample_rate as f32 * SPEED_OF_SOUND
+}
+
+/// samples generated by `estimate_generation_time` to measure the speed of the generator
+const GENERATION_BENCHMARK_SAMPLES: usize = 256;
+
+/// estimates how long a copy of `engine` takes to generate `num_samples` samples from the time it takes to generate
+/// a few samples, which is rough because the generator runs slower until the caches are warm
+pub fn estimate_generation_time(engine: &Engine, sample_rate: u32, num_samples: usize) -> Duration {
+    let mut generator = Generator::new(
+        sample_rate,
+        engine.clone(),
+        BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, sample_rate),
+    );
+    let mut buf = [0.0; GENERATION_BENCHMARK_SAMPLES];
+
+    let start = Instant::now();
+    generator.generate(&mut buf);
+
+    start
+        .elapsed()
+        .mul_f64(num_samples as f64 / GENERATION_BENCHMARK_SAMPLES as f64)
+}
+
+/// crossfade in seconds of two engine cycles (a four-stroke cycle is two revolutions, `120 / rpm` seconds)
+/// which makes a recording of whole cycles loop seamlessly, see `--auto-loop`
+pub fn recommended_crossfade(rpm: f32) -> f32 {
+    120.0 / rpm * 2.0
+}
+
+/// Serialization format of an engine config
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Ron,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    pub const ALL: [ConfigFormat; 3] = [ConfigFormat::Ron, ConfigFormat::Json, ConfigFormat::Toml];
+
+    /// `.json` and `.toml` files are JSON and TOML, everything else (`.esc`, `.ron`, ..) is RON
+    pub fn from_path(path: &std::path::Path) -> ConfigFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => ConfigFormat::Json,
+            Some(extension) if extension.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            _ => ConfigFormat::Ron,
+        }
+    }
+
+    /// parses the value of `--config-format`: `ron`, `json` or `toml`
+    pub fn parse(name: &str) -> Result<ConfigFormat, String> {
+        ConfigFormat::ALL
+            .iter()
+            .copied()
+            .find(|format| format.extension() == name.to_ascii_lowercase())
+            .ok_or_else(|| {
+                format!(
+                    "Unknown config format \"{}\", expected \"ron\", \"json\" or \"toml\"",
+                    name
+                )
+            })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigFormat::Ron => "RON",
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Toml => "TOML",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ConfigFormat::Ron => "ron",
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+        }
+    }
+
+    pub fn serialize(self, engine: &Engine) -> Result<String, String> {
+        match self {
+            ConfigFormat::Ron => {
+                let pretty = ron::ser::PrettyConfig::new()
+                    .with_separate_tuple_members(true)
+                    .with_enumerate_arrays(true);
+                ron::ser::to_string_pretty(engine, pretty).map_err(|e| e.to_string())
+            }
+            ConfigFormat::Json => serde_json::to_string_pretty(engine).map_err(|e| e.to_string()),
+            // the toml serializer can't write the enum variants of the muffler elements,
+            // the engine is converted into a toml value by way of a json value instead
+            ConfigFormat::Toml => {
+                let json = serde_json::to_value(engine).map_err(|e| e.to_string())?;
+                let toml =
+                    json_to_toml(json)?.unwrap_or_else(|| toml::Value::Table(Default::default()));
+                toml::to_string_pretty(&toml).map_err(|e| e.to_string())
+            }
+        }
+    }
+
+    pub fn deserialize(self, config: &str) -> Result<Engine, String> {
+        match self {
+            ConfigFormat::Ron => ron::de::from_str(config).map_err(|e| e.to_string()),
+            ConfigFormat::Json => serde_json::from_str(config).map_err(|e| e.to_string()),
+            ConfigFormat::Toml => {
+                let toml = toml::from_str::<toml::Value>(config).map_err(|e| e.to_string())?;
+                serde_json::from_value(toml_to_json(toml)).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+/// `None` for `null`, which toml has no equivalent of, the key is left out instead
+fn json_to_toml(value: serde_json::Value) -> Result<Option<toml::Value>, String> {
+    use serde_json::Value;
+
+    Ok(Some(match value {
+        Value::Null => return Ok(None),
+        Value::Bool(b) => toml::Value::Boolean(b),
+        Value::Number(number) => match (number.as_i64(), number.as_f64()) {
+            (Some(i), _) => toml::Value::Integer(i),
+            (None, Some(f)) if number.is_f64() => toml::Value::Float(f),
+            _ => return Err(format!("{} does not fit into a TOML integer", number)),
+        },
+        Value::String(s) => toml::Value::String(s),
+        Value::Array(array) => {
+            let mut values = Vec::with_capacity(array.len());
+            for value in array {
+                values.push(
+                    json_to_toml(value)?
+                        .ok_or_else(|| "TOML arrays can't contain null".to_owned())?,
+                );
+            }
+            toml::Value::Array(values)
+        }
+        Value::Object(object) => {
+            let mut table = toml::value::Table::new();
+            for (key, value) in object {
+                if let Some(value) = json_to_toml(value)? {
+                    table.insert(key, value);
                 }
-            } else {
-                match ron::de::from_reader::<_, Engine>(file) {
-                    Ok(mut engine) => {
-                        fix_engine(&mut engine, sample_rate);
-                        Ok(engine)
-                    }
-                    Err(e) => Err(format!("Failed to load RON config \"{}\": {}", &path, e)),
+            }
+            toml::Value::Table(table)
+        }
+    }))
+}
+
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Integer(i) => Value::from(i),
+        toml::Value::Float(f) => Value::from(f),
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// loads the config at `path` in `format`, or in the format of its extension (see `ConfigFormat::from_path`) if it is `None`,
+/// prints the name of the engine if it has one
+pub fn load_engine(
+    path: &str,
+    sample_rate: u32,
+    format: Option<ConfigFormat>,
+) -> Result<Engine, String> {
+    let format = format.unwrap_or_else(|| ConfigFormat::from_path(path.as_ref()));
+    let config = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to open file \"{}\": {}", &path, e))?;
+
+    let mut engine = format.deserialize(&config).map_err(|e| {
+        format!(
+            "Failed to load {} config \"{}\": {}",
+            format.name(),
+            &path,
+            e
+        )
+    })?;
+    fix_engine(&mut engine, sample_rate)
+        .map_err(|e| format!("Invalid config \"{}\":\n{}", &path, e))?;
+
+    if let Some(name) = &engine.name {
+        match &engine.author {
+            Some(author) => println!("Loaded engine \"{}\" by {}", name, author),
+            None => println!("Loaded engine \"{}\"", name),
+        }
+    }
+
+    Ok(engine)
+}
+
+/// writes `engine` in the format of the extension of `path`, see `ConfigFormat::from_path`
+pub fn save_engine(path: &std::path::Path, engine: &Engine) -> Result<(), String> {
+    let serialized = ConfigFormat::from_path(path)
+        .serialize(engine)
+        .map_err(|e| format!("Failed to save engine config: {}", e))?;
+
+    File::create(path)
+        .and_then(|mut file| file.write_all(serialized.as_bytes()))
+        .map_err(|e| {
+            format!(
+                "Failed to write engine config \"{}\": {}",
+                path.display(),
+                e
+            )
+        })
+}
+
+/// applies `--set` assignments like `muffler.straight_pipe.alpha=0.12` or `cylinders[0].ignition_time=0.08` in order
+/// and fixes the engine again
+///
+/// Paths name the fields of the config (e.g. `muffler.muffler_elements[1].beta`), and `length` sets the length of a
+/// waveguide in meters.
+/// Setting a crank offset unsets the firing preset, which would override it otherwise.
+pub fn set_parameters(
+    engine: &mut Engine,
+    assignments: &[&str],
+    sample_rate: u32,
+) -> Result<(), String> {
+    if assignments.is_empty() {
+        return Ok(());
+    }
+
+    let mut config = serde_json::to_value(&*engine).map_err(|e| e.to_string())?;
+
+    for assignment in assignments {
+        let error = |e: String| format!("Invalid --set \"{}\": {}", assignment, e);
+
+        let (path, value) = assignment
+            .split_once('=')
+            .ok_or_else(|| error("expected path=value".to_owned()))?;
+        set_parameter(&mut config, path.trim(), value.trim(), sample_rate).map_err(error)?;
+
+        if path.trim().ends_with("crank_offset") {
+            config["firing_preset"] = serde_json::Value::Null;
+        }
+
+        // type errors like a negative cylinder count are reported with the assignment that caused them
+        *engine = serde_json::from_value(config.clone()).map_err(|e| error(e.to_string()))?;
+    }
+
+    fix_engine(engine, sample_rate)
+}
+
+/// sets the field at the dotted `path` of a serialized engine to `value`, which is parsed like the field's current value
+fn set_parameter(
+    config: &mut serde_json::Value,
+    path: &str,
+    value: &str,
+    sample_rate: u32,
+) -> Result<(), String> {
+    use serde_json::Value;
+
+    let mut target = config;
+    let mut segments = path.split('.').peekable();
+    while let Some(segment) = segments.next() {
+        let (key, indices) = match segment.find('[') {
+            Some(bracket) => segment.split_at(bracket),
+            None => (segment, ""),
+        };
+
+        // the virtual `length` field of a waveguide sets the delay of both its chambers
+        if key == "length" && segments.peek().is_none() && indices.is_empty() {
+            if let (Some(_), Some(_)) = (target.get("chamber0"), target.get("chamber1")) {
+                let meters = value
+                    .parse::<f32>()
+                    .map_err(|_| format!("\"{}\" is not a length in meters", value))?;
+                let delay = distance_to_samples(meters, sample_rate) / sample_rate as f32;
+                for chamber in IntoIterator::into_iter(["chamber0", "chamber1"]) {
+                    target[chamber]["samples"]["delay"] = Value::from(delay as f64);
                 }
+                return Ok(());
             }
         }
-        Err(e) => Err(format!("Failed to open file \"{}\": {}", &path, e)),
+
+        target = target
+            .get_mut(key)
+            .ok_or_else(|| format!("unknown field \"{}\"", key))?;
+
+        for index in indices.split_terminator(']') {
+            let index = index
+                .strip_prefix('[')
+                .and_then(|index| index.parse::<usize>().ok())
+                .ok_or_else(|| format!("invalid index in \"{}\"", segment))?;
+            let len = target.as_array().map(Vec::len).unwrap_or(0);
+            target = target
+                .get_mut(index)
+                .ok_or_else(|| format!("index {} of \"{}\" out of range 0..{}", index, key, len))?;
+        }
     }
+
+    let not_a = |kind: &str| format!("\"{}\" is not {}", value, kind);
+    *target = match target {
+        Value::Bool(_) => Value::Bool(value.parse().map_err(|_| not_a("a boolean"))?),
+        Value::Number(number) if number.is_f64() => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| not_a("a number"))?,
+        Value::Number(_) => Value::from(value.parse::<u64>().map_err(|_| not_a("an integer"))?),
+        Value::String(_) => Value::String(value.to_owned()),
+        // unset options and nested values are given as JSON, a bare word is a string (e.g. a firing preset name)
+        Value::Null => {
+            serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_owned()))
+        }
+        Value::Array(_) | Value::Object(_) => {
+            serde_json::from_str(value).map_err(|e| format!("invalid JSON: {}", e))?
+        }
+    };
+
+    Ok(())
+}
+
+/// default file name of a saved config, e.g. `enginesound_31122020-235959.esc`
+pub fn config_name() -> String {
+    format!("enginesound_{}.esc", timestamp())
+}
+
+/// default file name of a recording, e.g. `enginesound_31122020-235959.wav`
+pub fn recording_name() -> String {
+    format!("enginesound_{}.wav", timestamp())
+}
+
+/// current local time as `ddmmyyyy-hhmmss`
+pub fn timestamp() -> String {
+    let time = Local::now();
+
+    format!(
+        "{:02}{:02}{:04}-{:02}{:02}{:02}",
+        time.day(),
+        time.month(),
+        time.year(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    )
+}
+
+/// names of the firing presets which can be applied with `apply_firing_preset`
+pub const FIRING_PRESETS: [&str; 5] = [
+    "even",
+    "V-twin 90°",
+    "crossplane V8",
+    "crossplane V8 bank",
+    "inline-5",
+];
+
+/// returns the crank offsets (in cycles of 720°) of every cylinder of the given firing preset,
+/// "even" keeps the cylinder count and spaces the cylinders evenly
+pub fn firing_preset_crank_offsets(preset: &str, num_cylinders: usize) -> Option<Vec<f32>> {
+    // position of every cylinder in the firing order
+    fn from_firing_order(firing_order: &[usize]) -> Vec<f32> {
+        let mut offsets = vec![0.0; firing_order.len()];
+        for (position, cylinder) in firing_order.iter().enumerate() {
+            offsets[cylinder - 1] = position as f32 / firing_order.len() as f32;
+        }
+        offsets
+    }
+
+    match preset {
+        "even" => Some(
+            (0..num_cylinders.max(1))
+                .map(|i| i as f32 / num_cylinders.max(1) as f32)
+                .collect(),
+        ),
+        // fires at 270° and 450° intervals
+        "V-twin 90°" => Some(vec![0.0, 270.0 / 720.0]),
+        "crossplane V8" => Some(from_firing_order(&[1, 8, 4, 3, 6, 5, 7, 2])),
+        // the whole crossplane V8 fires evenly into the single exhaust collector, its lope comes from each bank's
+        // exhaust, so this is the bank of cylinders 1, 3, 5 and 7 of the firing order 1-8-4-3-6-5-7-2,
+        // which fire at 270°, 180°, 90° and 180° intervals
+        "crossplane V8 bank" => Some(vec![0.0, 270.0 / 720.0, 450.0 / 720.0, 540.0 / 720.0]),
+        "inline-5" => Some(from_firing_order(&[1, 2, 4, 5, 3])),
+        _ => None,
+    }
+}
+
+/// sets the cylinder count and crank offsets of the engine according to the firing preset,
+/// added cylinders are copies of the last cylinder
+pub fn apply_firing_preset(engine: &mut Engine, preset: &str) -> Result<(), String> {
+    let offsets = firing_preset_crank_offsets(preset, engine.cylinders.len())
+        .ok_or_else(|| format!("Unknown firing preset \"{}\"", preset))?;
+
+    let last = engine
+        .cylinders
+        .last()
+        .cloned()
+        .ok_or("Engine has no cylinders")?;
+    engine.cylinders.resize(offsets.len(), last);
+
+    for (cylinder, offset) in engine.cylinders.iter_mut().zip(offsets) {
+        cylinder.crank_offset = offset;
+    }
+
+    engine.firing_preset = Some(preset.to_string());
+
+    Ok(())
 }
 
 /// Deserialization is not fully implemented via serde because we need the sample rate to set up delay buffers
-pub fn fix_engine(engine: &mut Engine, sample_rate: u32) {
+///
+/// the engine is validated first, warnings are printed and errors are returned without fixing the engine,
+/// one `<field>: <error>` line per error
+pub fn fix_engine(engine: &mut Engine, sample_rate: u32) -> Result<(), String> {
+    let (warnings, errors): (Vec<_>, Vec<_>) = engine
+        .validate(sample_rate)
+        .into_iter()
+        .partition(EngineValidationError::is_warning);
+
+    for warning in warnings {
+        eprintln!("Warning: {}: {}", warning.field(), warning);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors
+            .iter()
+            .map(|error| format!("{}: {}", error.field(), error))
+            .collect::<Vec<String>>()
+            .join("\n"));
+    }
+
     fn fix_lpf(lpf: &mut LowPassFilter, sample_rate: u32) {
         *lpf = LowPassFilter::new(1.0 / lpf.delay, sample_rate);
     }
 
     fn fix_loop_buffer(lb: &mut LoopBuffer, sample_rate: u32) {
-        let len = (lb.delay * sample_rate as f32) as usize;
+        // short pipes can be shorter than one sample at low sample rates
+        let len = ((lb.delay * sample_rate as f32) as usize).max(1);
 
-        *lb = LoopBuffer {
-            delay: lb.delay,
-            data: vec![0.0; len],
-            pos: 0,
-        };
+        let delay = lb.delay;
+        *lb = LoopBuffer::new(len, sample_rate);
+        lb.delay = delay;
+    }
+
+    if let Some(preset) = engine.firing_preset.clone() {
+        if let Err(e) = apply_firing_preset(engine, &preset) {
+            eprintln!("{}", e);
+        }
     }
 
     vec![
@@ -75,11 +495,30 @@ pub fn fix_engine(engine: &mut Engine, sample_rate: u32) {
     .into_iter()
     .for_each(|lpf| fix_lpf(lpf, sample_rate));
 
+    // the noise sources are not deserialized
+    engine.seed_noise();
+
+    engine.current_throttle = engine.throttle;
+
+    if let Some(hpf) = &mut engine.exhaust_highpass_filter {
+        *hpf = HighPassFilter::new(1.0 / hpf.delay, sample_rate);
+    }
+
+    if let Some(hpf) = &mut engine.output_highpass_filter {
+        *hpf = HighPassFilter::new(1.0 / hpf.delay, sample_rate);
+    }
+
+    if let Some(turbo) = &mut engine.turbo {
+        fix_lpf(&mut turbo.spool_lp, sample_rate);
+        fix_lpf(&mut turbo.blowoff_lp, sample_rate);
+    }
+
     engine
         .muffler
         .muffler_elements
         .iter_mut()
-        .chain(std::iter::once(&mut engine.muffler.straight_pipe))
+        .filter_map(MufflerElement::waveguide_mut)
+        .chain([&mut engine.muffler.straight_pipe, &mut engine.egr_delay])
         .flat_map(|waveguide| vec![&mut waveguide.chamber0, &mut waveguide.chamber1].into_iter())
         .chain(engine.cylinders.iter_mut().flat_map(|cylinder| {
             vec![
@@ -93,4 +532,388 @@ pub fn fix_engine(engine: &mut Engine, sample_rate: u32) {
             .into_iter()
         }))
         .for_each(|delay_line| fix_loop_buffer(&mut delay_line.samples, sample_rate));
+
+    fn fix_fractional_delay(waveguide: &mut WaveGuide, sample_rate: u32) {
+        waveguide.fractional_delay =
+            (waveguide.chamber0.samples.delay * sample_rate as f32).fract();
+    }
+
+    engine
+        .muffler
+        .muffler_elements
+        .iter_mut()
+        .filter_map(MufflerElement::waveguide_mut)
+        .chain([&mut engine.muffler.straight_pipe, &mut engine.egr_delay])
+        .chain(engine.cylinders.iter_mut().flat_map(|cylinder| {
+            vec![
+                &mut cylinder.exhaust_waveguide,
+                &mut cylinder.extractor_waveguide,
+                &mut cylinder.intake_waveguide,
+            ]
+            .into_iter()
+        }))
+        .for_each(|waveguide| fix_fractional_delay(waveguide, sample_rate));
+
+    Ok(())
+}
+
+/// gain curves of `crossfade`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CrossfadeShape {
+    /// gains add up to 1, which dips in loudness in the middle of the crossfade if the signals are not correlated
+    Linear,
+    /// cosine/sine gains whose squares add up to 1, which keeps the loudness of uncorrelated signals
+    EqualPower,
+}
+
+impl CrossfadeShape {
+    /// gains of the fading out and the fading in signal at `fade` (0.0 - 1.0)
+    #[inline]
+    fn gains(self, fade: f32) -> (f32, f32) {
+        match self {
+            CrossfadeShape::Linear => (1.0 - fade, fade),
+            CrossfadeShape::EqualPower => {
+                let angle = fade * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+        }
+    }
+}
+
+/// cuts the samples in half, swaps the halves and crossfades the middle `crossfade_size` samples,
+/// the returned samples are `crossfade_size / 2` samples shorter
+///
+/// the crossfade is limited to the length of the samples
+pub fn crossfade(samples: &[f32], crossfade_size: usize, shape: CrossfadeShape) -> Vec<f32> {
+    let len = samples.len();
+    let half_len = len / 2;
+    let fade_len = (crossfade_size / 2).min(half_len);
+
+    let shifted = (0..len)
+        .map(|i| samples[(half_len + i) % len])
+        .collect::<Vec<f32>>();
+
+    let mut output = Vec::with_capacity(len - fade_len);
+    output.extend_from_slice(&shifted[..half_len]);
+    output.extend_from_slice(&shifted[(half_len + fade_len)..]);
+
+    let start = half_len - fade_len;
+    for i in start..half_len {
+        let (fade_out, fade_in) = shape.gains((i - start) as f32 / fade_len as f32);
+        output[i] = shifted[i] * fade_out + shifted[i + fade_len] * fade_in;
+    }
+
+    output
+}
+
+/// finds the loop length by comparing the first `window` samples with the window at every offset in `1..=samples.len() / 2`
+/// and returns the offset with the smallest sum of squared differences, or 0 if the samples are too short for the window
+///
+/// short offsets are similar to the start because neighbouring samples are, so the offsets are only searched after the
+/// difference has risen above its average for the first time
+///
+/// the differences of all offsets are computed from a cross-correlation by FFT in O(n log n) instead of comparing
+/// the window at every offset in O(n * window)
+pub fn find_loop_point(samples: &[f32], window: usize) -> usize {
+    let window = window.max(1);
+    let max_offset = (samples.len() / 2).min(samples.len().saturating_sub(window));
+    if max_offset == 0 {
+        return 0;
+    }
+
+    let searched = &samples[..(max_offset + window)];
+    let correlation = cross_correlate(&searched[..window], searched);
+
+    // the squared differences are expanded to (a - b)² = a² + b² - 2ab, the sums of b² are sliding sums
+    let mut energy_prefix = Vec::with_capacity(searched.len() + 1);
+    energy_prefix.push(0.0f64);
+    for sample in searched {
+        energy_prefix.push(energy_prefix.last().unwrap() + f64::from(*sample).powi(2));
+    }
+    let start_energy = energy_prefix[window];
+
+    let differences = (1..=max_offset)
+        .map(|offset| {
+            let energy = energy_prefix[offset + window] - energy_prefix[offset];
+            (start_energy + energy - 2.0 * correlation[offset]).max(0.0)
+        })
+        .collect::<Vec<f64>>();
+
+    let average = differences.iter().sum::<f64>() / differences.len() as f64;
+    let first_rise = differences
+        .iter()
+        .position(|difference| *difference > average)
+        .unwrap_or(0);
+
+    differences[first_rise..]
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| first_rise + i + 1)
+        .unwrap()
+}
+
+/// `result[offset]` is the sum of `pattern[i] * samples[offset + i]` for every offset in `0..=samples.len() - pattern.len()`
+fn cross_correlate(pattern: &[f32], samples: &[f32]) -> Vec<f64> {
+    // zero padded so the circular correlation does not wrap around for the requested offsets
+    let size = samples.len().next_power_of_two();
+    let spectrum = |samples: &[f32]| {
+        let mut input = samples
+            .iter()
+            .map(|sample| Complex64::new(f64::from(*sample), 0.0))
+            .chain(std::iter::repeat(Complex64::zero()))
+            .take(size)
+            .collect::<Vec<_>>();
+        let mut output = vec![Complex64::zero(); size];
+        rustfft::algorithm::Radix4::new(size, false).process(&mut input, &mut output);
+        output
+    };
+
+    let mut product = spectrum(pattern)
+        .into_iter()
+        .zip(spectrum(samples))
+        .map(|(pattern, samples)| pattern.conj() * samples)
+        .collect::<Vec<_>>();
+    let mut correlation = vec![Complex64::zero(); size];
+    rustfft::algorithm::Radix4::new(size, true).process(&mut product, &mut correlation);
+
+    correlation[..=(samples.len() - pattern.len())]
+        .iter()
+        .map(|value| value.re / size as f64)
+        .collect()
+}
+
+/// cuts the samples to a loop of `loop_len` samples (see `find_loop_point`) and crossfades the `crossfade_size` samples
+/// following the loop into its start, so the end of the loop continues seamlessly into its start
+pub fn crossfade_loop(
+    samples: &[f32],
+    loop_len: usize,
+    crossfade_size: usize,
+    shape: CrossfadeShape,
+) -> Vec<f32> {
+    let loop_len = loop_len.min(samples.len());
+    let fade_len = crossfade_size.min(loop_len).min(samples.len() - loop_len);
+
+    let mut output = samples[..loop_len].to_vec();
+
+    for (i, sample) in output.iter_mut().enumerate().take(fade_len) {
+        let (fade_out, fade_in) = shape.gains(i as f32 / fade_len as f32);
+        *sample = samples[loop_len + i] * fade_out + *sample * fade_in;
+    }
+
+    output
+}
+
+/// maximum amount of samples at either side of a loop seam which are compared by `seam_quality`
+const SEAM_FFT_SIZE: usize = 2048;
+/// quieter frequency bins (relative to the loudest bin) are raised to this level so noise does not dominate the spectral difference
+const SEAM_SPECTRUM_FLOOR_DB: f32 = -60.0;
+
+/// how audible the seam between the end and the start of a looped recording is
+#[derive(Copy, Clone, Debug)]
+pub struct SeamQuality {
+    /// sample step across the seam relative to the rms of all sample-to-sample steps of the recording, around 1.0 or below is inaudible
+    pub discontinuity: f32,
+    /// rms difference in dB between the spectra at the end and at the start of the recording
+    pub spectral_difference_db: f32,
+}
+
+/// measures the seam of `samples` when played back in a loop
+pub fn seam_quality(samples: &[f32]) -> SeamQuality {
+    let len = samples.len();
+    if len < 2 {
+        return SeamQuality {
+            discontinuity: 0.0,
+            spectral_difference_db: 0.0,
+        };
+    }
+
+    let rms_step = (samples
+        .windows(2)
+        .map(|pair| (pair[1] - pair[0]).powi(2))
+        .sum::<f32>()
+        / (len - 1) as f32)
+        .sqrt();
+    let seam_step = (samples[0] - samples[len - 1]).abs();
+
+    let discontinuity = if rms_step > 0.0 {
+        seam_step / rms_step
+    } else {
+        0.0
+    };
+
+    // the largest power of two which fits into both halves
+    let fft_size = SEAM_FFT_SIZE.min(len / 2);
+    let fft_size = if fft_size >= 16 {
+        1 << (usize::BITS - 1 - fft_size.leading_zeros())
+    } else {
+        0
+    };
+
+    let spectral_difference_db = if fft_size == 0 {
+        0.0
+    } else {
+        let end = spectrum_db(&samples[(len - fft_size)..]);
+        let start = spectrum_db(&samples[..fft_size]);
+
+        (end.iter()
+            .zip(start.iter())
+            .map(|(end, start)| (end - start).powi(2))
+            .sum::<f32>()
+            / end.len() as f32)
+            .sqrt()
+    };
+
+    SeamQuality {
+        discontinuity,
+        spectral_difference_db,
+    }
+}
+
+/// hann windowed magnitude spectrum in dB, `samples.len()` has to be a power of two
+fn spectrum_db(samples: &[f32]) -> Vec<f32> {
+    let size = samples.len();
+    let window_fac = std::f32::consts::PI * 2.0 / size as f32;
+
+    let mut input = samples
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| {
+            Complex32::new(sample * (0.5 - 0.5 * (i as f32 * window_fac).cos()), 0.0)
+        })
+        .collect::<Vec<_>>();
+    let mut output = vec![Complex32::zero(); size];
+
+    rustfft::algorithm::Radix4::new(size, false).process(&mut input, &mut output);
+
+    let spectrum = output[..(size / 2)]
+        .iter()
+        .map(|bin| 20.0 * bin.norm().max(1e-10).log10())
+        .collect::<Vec<f32>>();
+    let floor = spectrum.iter().copied().fold(f32::MIN, f32::max) + SEAM_SPECTRUM_FLOOR_DB;
+
+    spectrum.into_iter().map(|db| db.max(floor)).collect()
+}
+
+/// width of the bar printed by `ProgressReporter` in characters
+const PROGRESS_BAR_WIDTH: usize = 40;
+
+/// prints the progress of a recording as a terminal progress bar, or as percent-done lines if `NO_COLOR` is set
+pub struct ProgressReporter {
+    total_samples: usize,
+    /// redraws a single line using ANSI escape codes
+    ansi: bool,
+    /// last printed percentage, `usize::MAX` before the first update
+    last_percent: AtomicUsize,
+}
+
+impl ProgressReporter {
+    pub fn new(total_samples: usize) -> ProgressReporter {
+        ProgressReporter {
+            total_samples,
+            ansi: std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty()),
+            last_percent: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    /// prints the progress if the percentage changed since the last update
+    pub fn update(&self, samples_done: usize) {
+        let percent = (samples_done.min(self.total_samples) * 100)
+            .checked_div(self.total_samples)
+            .unwrap_or(100);
+
+        if self.last_percent.swap(percent, Ordering::Relaxed) == percent {
+            return;
+        }
+
+        if self.ansi {
+            let filled = percent * PROGRESS_BAR_WIDTH / 100;
+            // returns to the start of the line and clears it
+            print!(
+                "\r\x1b[2K[{}{}] {:>3}%",
+                "=".repeat(filled),
+                " ".repeat(PROGRESS_BAR_WIDTH - filled),
+                percent
+            );
+            let _ = std::io::stdout().flush();
+        } else {
+            println!("{}% done", percent);
+        }
+    }
+
+    /// prints 100% and ends the progress bar's line
+    pub fn finish(&self) {
+        self.update(self.total_samples);
+
+        if self.ansi {
+            println!();
+        }
+    }
+}
+
+/// linearly interpolates a continuous mono signal, which is passed in chunks, to another sample rate
+#[derive(Clone, Debug)]
+pub struct Resampler {
+    /// input samples per output sample
+    pub ratio: f64,
+    /// position of the next output sample in input samples, 0.0 is the last sample of the previous chunk and 1.0 the first sample of the next chunk.
+    /// it is between -1.0 and 0.0 while upsampling if the next output sample lies between the last two samples of the previous chunk
+    pub phase: f64,
+    /// second to last and last input sample of the previous chunk
+    pub prev: [f32; 2],
+}
+
+impl Resampler {
+    pub fn new(from_sample_rate: u32, to_sample_rate: u32) -> Resampler {
+        Resampler {
+            ratio: from_sample_rate as f64 / to_sample_rate as f64,
+            // the first output sample is the first input sample
+            phase: 1.0,
+            prev: [0.0; 2],
+        }
+    }
+
+    /// amount of input samples which `resample_linear` needs to produce `output_len` samples
+    pub fn input_len(&self, output_len: usize) -> usize {
+        if output_len == 0 {
+            return 0;
+        }
+
+        (self.phase + (output_len - 1) as f64 * self.ratio)
+            .ceil()
+            .max(0.0) as usize
+    }
+
+    /// fills `output` with the resampled `input`, which has to be exactly `input_len(output.len())` samples long
+    pub fn resample_linear(&mut self, input: &[f32], output: &mut [f32]) {
+        debug_assert_eq!(input.len(), self.input_len(output.len()));
+
+        // input sample at the given position relative to the last sample of the previous chunk
+        let sample_at = |pos: isize| match pos {
+            -1 => self.prev[0],
+            0 => self.prev[1],
+            pos => input[pos as usize - 1],
+        };
+
+        let mut phase = self.phase;
+        for sample in output.iter_mut() {
+            let pos = phase.floor();
+            let frac = (phase - pos) as f32;
+            let pos = pos as isize;
+
+            let a = sample_at(pos);
+            // avoids reading past the end of the input if the position is exactly on its last sample
+            let b = if frac > 0.0 { sample_at(pos + 1) } else { a };
+            *sample = a + (b - a) * frac;
+
+            phase += self.ratio;
+        }
+
+        self.phase = phase - input.len() as f64;
+        self.prev = match input {
+            [] => self.prev,
+            [last] => [self.prev[1], *last],
+            [.., second_to_last, last] => [*second_to_last, *last],
+        };
+    }
 }
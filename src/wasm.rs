@@ -0,0 +1,60 @@
+//! ## WebAssembly module ##
+//!
+//! Exposes the generator to JavaScript, e.g. to be driven from a Web Audio `AudioWorkletProcessor` (see `www/`).
+//!
+
+use crate::gen::{BiquadFilter, Engine, Generator, DC_OFFSET_LP_FREQ};
+use crate::utils::fix_engine;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct JsGenerator {
+    generator: Generator,
+}
+
+#[wasm_bindgen]
+impl JsGenerator {
+    /// loads a RON engine config, the noise sources are seeded randomly unless the config sets `noise_seed`
+    #[wasm_bindgen(constructor)]
+    pub fn new(config_ron_str: &str, sample_rate: u32) -> Result<JsGenerator, JsValue> {
+        let mut engine = ron::de::from_str::<Engine>(config_ron_str)
+            .map_err(|e| JsValue::from_str(&format!("Failed to load RON config: {}", e)))?;
+
+        let fixed = if engine.noise_seed.is_none() {
+            engine.noise_seed = Some((js_sys::Math::random() * u64::MAX as f64) as u64);
+            let fixed = fix_engine(&mut engine, sample_rate);
+            engine.noise_seed = None;
+            fixed
+        } else {
+            fix_engine(&mut engine, sample_rate)
+        };
+        fixed.map_err(|e| JsValue::from_str(&format!("Invalid RON config: {}", e)))?;
+
+        Ok(JsGenerator {
+            generator: Generator::new(
+                sample_rate,
+                engine,
+                BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, sample_rate),
+            ),
+        })
+    }
+
+    pub fn set_rpm(&mut self, rpm: f32) {
+        self.generator.engine.rpm = rpm.max(0.0);
+    }
+
+    pub fn set_throttle(&mut self, throttle: f32) {
+        self.generator.set_throttle(throttle);
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.generator.volume = volume;
+    }
+
+    /// generates `num_samples` mono samples, returned as a `Float32Array`
+    pub fn generate(&mut self, num_samples: u32) -> Vec<f32> {
+        let mut buf = vec![0.0; num_samples as usize];
+        self.generator.generate(&mut buf);
+        buf
+    }
+}
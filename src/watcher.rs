@@ -0,0 +1,96 @@
+//! ## Watcher module ##
+//!
+//! Reloads an engine config whenever its file is changed on disk.
+//!
+
+use enginesound::{gen::Engine, utils::load_engine};
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Instant;
+
+pub struct ConfigWatcher {
+    path: PathBuf,
+    sample_rate: u32,
+    /// watches as long as it is alive
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    last_reload: Cell<Option<Instant>>,
+}
+
+impl ConfigWatcher {
+    /// starts watching the config at `path`, reloaded engines are fixed for `sample_rate`
+    pub fn new(path: impl Into<PathBuf>, sample_rate: u32) -> Result<ConfigWatcher, String> {
+        let path = path.into();
+        let (sender, events) = channel();
+
+        let mut watcher = notify::recommended_watcher(sender)
+            .map_err(|e| format!("Failed to create a file watcher: {}", e))?;
+
+        // the directory is watched because many editors replace the file instead of writing to it
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch \"{}\": {}", path.display(), e))?;
+
+        Ok(ConfigWatcher {
+            path,
+            sample_rate,
+            _watcher: watcher,
+            events,
+            last_reload: Cell::new(None),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// time of the last successful reload
+    #[cfg(feature = "gui")]
+    pub fn last_reload(&self) -> Option<Instant> {
+        self.last_reload.get()
+    }
+
+    /// returns the reloaded engine if the config was changed since the last poll
+    pub fn poll(&self) -> Option<Engine> {
+        let file_name = self.path.file_name();
+        let mut changed = false;
+
+        // all pending events are drained so a save which causes several events only reloads once
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Ok(event) => {
+                    changed |= (event.kind.is_modify() || event.kind.is_create())
+                        && event.paths.iter().any(|path| path.file_name() == file_name);
+                }
+                Err(e) => eprintln!(
+                    "Error while watching config \"{}\": {}",
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+
+        if !changed {
+            return None;
+        }
+
+        let path = self.path.to_string_lossy();
+        match load_engine(&path, self.sample_rate, None) {
+            Ok(engine) => {
+                println!("Reloaded engine config \"{}\"", path);
+                self.last_reload.set(Some(Instant::now()));
+                Some(engine)
+            }
+            Err(e) => {
+                eprintln!("Failed to reload engine config \"{}\": {}", path, e);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! Theoretical pipe resonances of `analysis::analyze` and their coincidence with the firing harmonics.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::analysis::{analyze, PipeResonances, REPORTED_RESONANCES};
+use enginesound::{Engine, WaveGuide};
+
+/// i4 at 3000 rpm, which fires at 100 hz
+fn engine() -> Engine {
+    TestEngine::preset("i4").rpm(3000.0).engine()
+}
+
+fn straight_pipe(engine: &Engine) -> PipeResonances {
+    analyze(engine)
+        .pipes
+        .into_iter()
+        .find(|pipe| pipe.name == "muffler.straight_pipe")
+        .unwrap()
+}
+
+fn freqs(pipe: &PipeResonances) -> Vec<f32> {
+    pipe.resonances
+        .iter()
+        .map(|resonance| resonance.freq.round())
+        .collect()
+}
+
+#[test]
+fn firing_frequency_counts_active_cylinders() {
+    let mut engine = engine();
+    assert_eq!(analyze(&engine).firing_frequency, 100.0);
+
+    engine.cylinders[3].active = false;
+    let report = analyze(&engine);
+    assert_eq!(report.cylinders, 3);
+    assert_eq!(report.firing_frequency, 75.0);
+}
+
+#[test]
+fn inverting_ends_only_resonate_at_odd_harmonics() {
+    let mut engine = engine();
+
+    // 10 ms, about 3.4 m
+    engine.muffler.straight_pipe = WaveGuide::new(480.0, 0.5, 0.5, SAMPLE_RATE);
+    let pipe = straight_pipe(&engine);
+    assert!((pipe.length.unwrap() - 3.43).abs() < 0.01);
+    assert_eq!(freqs(&pipe), vec![50.0, 100.0, 150.0, 200.0]);
+
+    engine.muffler.straight_pipe = WaveGuide::new(480.0, 0.5, -0.5, SAMPLE_RATE);
+    assert_eq!(
+        freqs(&straight_pipe(&engine)),
+        vec![25.0, 75.0, 125.0, 175.0]
+    );
+}
+
+#[test]
+fn resonances_on_firing_harmonics_are_flagged() {
+    let mut engine = engine();
+    // 200, 400, 600 and 800 hz, the second to eighth firing harmonic
+    engine.muffler.straight_pipe = WaveGuide::new(120.0, 0.5, 0.5, SAMPLE_RATE);
+    let harmonics = straight_pipe(&engine)
+        .resonances
+        .iter()
+        .map(|resonance| resonance.firing_harmonic)
+        .collect::<Vec<_>>();
+    assert_eq!(harmonics, vec![Some(2), Some(4), Some(6), Some(8)]);
+
+    // 230, 460, 690 and 920 hz are between the firing harmonics, the last one above the eighth
+    engine.muffler.straight_pipe = WaveGuide::new(48000.0 / 460.0, 0.5, 0.5, SAMPLE_RATE);
+    assert!(straight_pipe(&engine)
+        .resonances
+        .iter()
+        .all(|resonance| resonance.firing_harmonic.is_none()));
+}
+
+#[test]
+fn report_lists_every_pipe() {
+    let mut engine = engine();
+    engine.egr_factor = 0.0;
+    let report = analyze(&engine);
+    assert_eq!(
+        report.pipes.len(),
+        4 * 3 + 1 + engine.muffler.muffler_elements.len()
+    );
+    assert!(!report.to_string().contains("egr_delay"));
+
+    let csv = report.to_csv();
+    assert_eq!(
+        csv.lines().count(),
+        1 + report.pipes.len() * REPORTED_RESONANCES
+    );
+    assert!(csv.starts_with("pipe,length_m,resonance,freq_hz,firing_harmonic\n"));
+
+    engine.egr_factor = 0.2;
+    assert!(analyze(&engine).to_string().contains("egr_delay"));
+}
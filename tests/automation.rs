@@ -0,0 +1,138 @@
+//! Parsing and interpolating the keyframes of `--automation` files.
+
+use enginesound::automation::{Automation, AutomationValues, Keyframe};
+
+/// writes `text` to a temporary file ending with `extension` and loads it
+fn load(name: &str, extension: &str, text: &str) -> Result<Automation, String> {
+    let path = std::env::temp_dir().join(format!(
+        "enginesound_automation_{}_{}.{}",
+        name,
+        std::process::id(),
+        extension
+    ));
+    std::fs::write(&path, text).unwrap();
+
+    let automation = Automation::load(path.to_str().unwrap());
+    std::fs::remove_file(path).unwrap();
+    automation
+}
+
+fn keyframe(time: f32, rpm: Option<f32>, throttle: Option<f32>, hold: bool) -> Keyframe {
+    Keyframe {
+        time,
+        rpm,
+        throttle,
+        volume: None,
+        hold,
+    }
+}
+
+#[test]
+fn csv_files_are_parsed_into_sorted_keyframes() {
+    let automation = load(
+        "csv",
+        "csv",
+        "# rev up and let go\ntime, rpm, throttle, hold\n\n2.0,4000,,\n0.0,1000,1.0,true\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        automation.keyframes(),
+        &[
+            keyframe(0.0, Some(1000.0), Some(1.0), true),
+            keyframe(2.0, Some(4000.0), None, false),
+        ]
+    );
+    assert_eq!(automation.duration(), 2.0);
+}
+
+#[test]
+fn invalid_csv_files_are_rejected() {
+    for (text, error) in [
+        ("time,boost\n0.0,1.0\n", "unknown column \"boost\""),
+        ("rpm\n1000\n", "missing \"time\" column"),
+        ("time,rpm\n0.0,fast\n", "line 2: invalid rpm \"fast\""),
+        ("time,rpm\n,1000\n", "line 2: missing time"),
+        (
+            "time,rpm\n0.0,1000,2000\n",
+            "line 2: more values than columns",
+        ),
+        ("time,hold\n0.0,maybe\n", "line 2: invalid hold \"maybe\""),
+        ("time,rpm\n", "no keyframes"),
+    ] {
+        let e = load("invalid", "csv", text).unwrap_err();
+        assert!(
+            e.contains(error),
+            "\"{}\" does not contain \"{}\"",
+            e,
+            error
+        );
+    }
+}
+
+#[test]
+fn ron_files_are_parsed_with_default_fields() {
+    let automation = load(
+        "ron",
+        "ron",
+        "[(time: 1.0, throttle: Some(0.0)), (time: 0.0, rpm: Some(800.0), hold: true)]",
+    )
+    .unwrap();
+
+    assert_eq!(
+        automation.keyframes(),
+        &[
+            keyframe(0.0, Some(800.0), None, true),
+            keyframe(1.0, None, Some(0.0), false),
+        ]
+    );
+
+    assert!(load("ron_nan", "ron", "[(time: NaN)]")
+        .unwrap_err()
+        .contains("invalid keyframe time"));
+}
+
+#[test]
+fn parameters_are_interpolated_between_the_keyframes_setting_them() {
+    let automation = Automation::new(vec![
+        keyframe(1.0, Some(1000.0), Some(1.0), false),
+        keyframe(2.0, None, Some(0.0), false),
+        keyframe(3.0, Some(3000.0), None, false),
+    ])
+    .unwrap();
+
+    // the first and last values are held before and after the keyframes
+    assert_eq!(
+        automation.sample_at(0.0),
+        AutomationValues {
+            rpm: Some(1000.0),
+            throttle: Some(1.0),
+            volume: None,
+        }
+    );
+    // the rpm skips the keyframe without an rpm
+    assert_eq!(automation.sample_at(1.5).rpm, Some(1500.0));
+    assert_eq!(automation.sample_at(1.5).throttle, Some(0.5));
+    assert_eq!(automation.sample_at(2.5).rpm, Some(2500.0));
+    assert_eq!(automation.sample_at(2.5).throttle, Some(0.0));
+    assert_eq!(automation.sample_at(10.0).rpm, Some(3000.0));
+}
+
+#[test]
+fn held_keyframes_step_to_the_next_value() {
+    let automation = Automation::new(vec![
+        keyframe(0.0, Some(1000.0), None, true),
+        keyframe(1.0, Some(2000.0), None, false),
+        keyframe(2.0, Some(4000.0), None, false),
+    ])
+    .unwrap();
+
+    assert_eq!(automation.sample_at(0.99).rpm, Some(1000.0));
+    assert_eq!(automation.sample_at(1.0).rpm, Some(2000.0));
+    assert_eq!(automation.sample_at(1.5).rpm, Some(3000.0));
+}
+
+#[test]
+fn empty_automations_are_none() {
+    assert!(Automation::new(Vec::new()).is_none());
+}
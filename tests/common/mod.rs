@@ -0,0 +1,93 @@
+//! Engines and generators shared by the integration tests, which include this module with `mod common;`
+
+// every test only uses some of the helpers
+#![allow(dead_code)]
+
+use enginesound::presets::PRESETS;
+use enginesound::{fix_engine, BiquadFilter, Engine, Generator, DC_OFFSET_LP_FREQ};
+
+/// sample rate of the tests which don't need a particular one
+pub const SAMPLE_RATE: u32 = 48000;
+
+/// builds the engine of a test from a config, which is fixed for the sample rate once the engine or its generator
+/// is built
+pub struct TestEngine {
+    engine: Engine,
+    sample_rate: u32,
+}
+
+impl TestEngine {
+    /// `src/default.esc`
+    pub fn default_config() -> TestEngine {
+        TestEngine::config(include_str!("../../src/default.esc"))
+    }
+
+    /// a RON config, e.g. `include_str!("../example6.esc")`
+    pub fn config(config: &str) -> TestEngine {
+        TestEngine {
+            engine: ron::de::from_str(config).expect("failed to deserialize config"),
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    /// a bundled preset of `PRESETS`, like `load_preset` the name is case insensitive
+    pub fn preset(name: &str) -> TestEngine {
+        let (_, config) = PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .expect("unknown preset");
+
+        TestEngine {
+            engine: ron::de::from_bytes(config).expect("failed to deserialize preset"),
+            sample_rate: SAMPLE_RATE,
+        }
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> TestEngine {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn rpm(mut self, rpm: f32) -> TestEngine {
+        self.engine.rpm = rpm;
+        self
+    }
+
+    /// seeds the noise sources for deterministic output, like `--seed`
+    pub fn seed(mut self, seed: u64) -> TestEngine {
+        self.engine.noise_seed = Some(seed);
+        self
+    }
+
+    /// changes the engine before it is fixed
+    pub fn with(mut self, change: impl FnOnce(&mut Engine)) -> TestEngine {
+        change(&mut self.engine);
+        self
+    }
+
+    /// the engine, fixed for the sample rate
+    pub fn engine(mut self) -> Engine {
+        fix_engine(&mut self.engine, self.sample_rate).expect("invalid engine");
+        self.engine
+    }
+
+    /// a generator of the engine at the sample rate
+    pub fn generator(self) -> Generator {
+        let sample_rate = self.sample_rate;
+        generator_at(self.engine(), sample_rate)
+    }
+}
+
+/// a generator of an engine which is fixed for `SAMPLE_RATE`
+pub fn generator(engine: Engine) -> Generator {
+    generator_at(engine, SAMPLE_RATE)
+}
+
+/// a generator of an engine which is fixed for `sample_rate`, with the default dc offset filter
+pub fn generator_at(engine: Engine, sample_rate: u32) -> Generator {
+    Generator::new(
+        sample_rate,
+        engine,
+        BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, sample_rate),
+    )
+}
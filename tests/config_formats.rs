@@ -0,0 +1,87 @@
+//! Engine configs in RON, JSON and TOML load into the same engine.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::presets::{load_preset, PRESETS};
+use enginesound::{load_engine, save_engine, ConfigFormat, Engine};
+use std::path::PathBuf;
+
+/// engines don't implement `PartialEq`, they are compared by their RON representation
+fn ron(engine: &Engine) -> String {
+    ConfigFormat::Ron.serialize(engine).unwrap()
+}
+
+fn temp_path(name: &str, format: ConfigFormat) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "enginesound_formats_{}_{}.{}",
+        std::process::id(),
+        name,
+        format.extension()
+    ))
+}
+
+#[test]
+fn every_format_loads_the_same_engine() {
+    let mut engines = PRESETS
+        .iter()
+        .map(|(name, _)| (name.to_string(), load_preset(name, SAMPLE_RATE).unwrap()))
+        .collect::<Vec<_>>();
+    let mut example = load_engine(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/example1.esc"),
+        SAMPLE_RATE,
+        None,
+    )
+    .unwrap();
+    example.name = Some("example".to_string());
+    engines.push(("example1".to_string(), example));
+
+    for (name, engine) in engines {
+        for format in ConfigFormat::ALL.iter().copied() {
+            let path = temp_path(&name, format);
+            save_engine(&path, &engine).unwrap();
+            let loaded = load_engine(path.to_str().unwrap(), SAMPLE_RATE, None);
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(
+                ron(&loaded.unwrap()),
+                ron(&engine),
+                "{} as {:?}",
+                name,
+                format
+            );
+        }
+    }
+}
+
+#[test]
+fn forced_format_overrides_the_extension() {
+    let engine = load_preset("i4", SAMPLE_RATE).unwrap();
+    let path = temp_path("forced", ConfigFormat::Ron).with_extension("txt");
+    std::fs::write(&path, ConfigFormat::Toml.serialize(&engine).unwrap()).unwrap();
+
+    let forced = load_engine(
+        path.to_str().unwrap(),
+        SAMPLE_RATE,
+        Some(ConfigFormat::Toml),
+    );
+    let by_extension = load_engine(path.to_str().unwrap(), SAMPLE_RATE, None);
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(ron(&forced.unwrap()), ron(&engine));
+    assert!(by_extension.err().unwrap().contains("RON"));
+}
+
+#[test]
+fn format_names_and_extensions() {
+    assert_eq!(ConfigFormat::parse("TOML"), Ok(ConfigFormat::Toml));
+    assert!(ConfigFormat::parse("yaml").is_err());
+    assert_eq!(
+        ConfigFormat::from_path("a/b.json".as_ref()),
+        ConfigFormat::Json
+    );
+    assert_eq!(
+        ConfigFormat::from_path("a/b.esc".as_ref()),
+        ConfigFormat::Ron
+    );
+}
@@ -0,0 +1,92 @@
+//! Malformed configs in `tests/fixtures`, edited like by hand, are refused by `load_engine` with the position
+//! of a syntax error or the path of every invalid field.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{load_engine, EngineValidationError};
+
+fn load_fixture(name: &str) -> Result<enginesound::Engine, String> {
+    load_engine(
+        &format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name),
+        SAMPLE_RATE,
+        None,
+    )
+}
+
+#[test]
+fn malformed_configs_are_refused() {
+    for (fixture, field) in [
+        ("negative_rpm.esc", "rpm: invalid rpm -100"),
+        ("no_cylinders.esc", "cylinders: the engine has no cylinders"),
+        ("zero_length_pipe.esc", "cylinders[0].intake_waveguide: "),
+        (
+            "loud_intake.esc",
+            "intake_volume: volume 7 is outside of 0 - 1",
+        ),
+        (
+            "amplifying_reflection.esc",
+            "cylinders[0].exhaust_waveguide.alpha: ",
+        ),
+    ] {
+        let error = load_fixture(fixture).err().unwrap();
+        assert!(error.contains(field), "{}: {}", fixture, error);
+    }
+}
+
+#[test]
+fn syntax_errors_name_their_position() {
+    let error = load_fixture("rpm_not_a_number.esc").err().unwrap();
+
+    assert!(error.starts_with("Failed to load RON config"), "{}", error);
+    assert!(
+        error.ends_with("rpm_not_a_number.esc\": 2:10: Expected float"),
+        "{}",
+        error
+    );
+}
+
+#[test]
+fn every_error_is_listed() {
+    let error = load_fixture("several_errors.esc").err().unwrap();
+    let lines = error.lines().skip(1).collect::<Vec<_>>();
+
+    assert_eq!(
+        lines,
+        [
+            "rpm: invalid rpm -100",
+            "intake_volume: volume 7 is outside of 0 - 1",
+            "cylinders[0].intake_waveguide: the intake pipe of cylinder 1 has no length",
+        ],
+    );
+}
+
+#[test]
+fn warnings_do_not_refuse_the_config() {
+    let mut engine = load_fixture("../../example1.esc").unwrap();
+    engine.exhaust_volume = 0.1;
+    engine.intake_noise_lp = enginesound::LowPassFilter::new(20000.0, SAMPLE_RATE);
+
+    let errors = engine.validate(32000);
+    assert!(errors.iter().all(EngineValidationError::is_warning));
+    assert!(errors
+        .iter()
+        .any(|error| matches!(error, EngineValidationError::VolumeSumUnusual(_))));
+    assert!(errors
+        .iter()
+        .any(|error| error.field() == "intake_noise_lp"));
+}
+
+#[test]
+fn any_number_of_muffler_elements_is_valid() {
+    for count in [1, 4, 8] {
+        let engine = TestEngine::default_config()
+            .with(|engine| {
+                let element = engine.muffler.muffler_elements[0].clone();
+                engine.muffler.muffler_elements.resize(count, element);
+            })
+            .engine();
+
+        assert_eq!(engine.validate(SAMPLE_RATE), vec![], "{} elements", count);
+    }
+}
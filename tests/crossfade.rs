@@ -0,0 +1,97 @@
+//! Crossfading the halves of a recording into a loop with `crossfade`.
+
+use enginesound::{crossfade, CrossfadeShape};
+use std::process::Command;
+
+/// 0.0, 1.0, 2.0, ..
+fn ramp(len: usize) -> Vec<f32> {
+    (0..len).map(|i| i as f32).collect()
+}
+
+#[test]
+fn odd_lengths_swap_the_halves_and_fade_the_middle() {
+    // the halves [0, 1, 2] and [3, 4, 5, 6] are swapped to [3, 4, 5, 6, 0, 1, 2]
+    let output = crossfade(&ramp(7), 4, CrossfadeShape::Linear);
+    assert_eq!(output.len(), 7 - 2);
+
+    // 4, 5 fade into 6, 0, the end of the crossfade continues with the sample after 0
+    assert_eq!(
+        output,
+        vec![3.0, 4.0 * 1.0 + 6.0 * 0.0, 5.0 * 0.5 + 0.0 * 0.5, 1.0, 2.0]
+    );
+}
+
+#[test]
+fn crossfades_longer_than_half_the_samples_are_limited() {
+    let samples = ramp(8);
+    let output = crossfade(&samples, 100, CrossfadeShape::Linear);
+
+    // the whole first half of the swapped samples fades into the second half
+    assert_eq!(output.len(), 4);
+    assert_eq!(output[0], 4.0);
+    assert_eq!(
+        output,
+        vec![
+            4.0,
+            5.0 * 0.75 + 1.0 * 0.25,
+            6.0 * 0.5 + 2.0 * 0.5,
+            7.0 * 0.25 + 3.0 * 0.75
+        ]
+    );
+
+    // gains of a linear crossfade add up to 1
+    let constant = crossfade(&[0.5; 9], 100, CrossfadeShape::Linear);
+    assert!(constant.iter().all(|&sample| (sample - 0.5).abs() < 1e-6));
+}
+
+#[test]
+fn zero_length_crossfades_only_swap_the_halves() {
+    for shape in [CrossfadeShape::Linear, CrossfadeShape::EqualPower] {
+        // a crossfade of one sample is half a sample on each side, which rounds down to none
+        for crossfade_size in [0, 1] {
+            assert_eq!(
+                crossfade(&ramp(5), crossfade_size, shape),
+                vec![2.0, 3.0, 4.0, 0.0, 1.0]
+            );
+        }
+        assert_eq!(crossfade(&[], 4, shape), Vec::<f32>::new());
+    }
+}
+
+#[test]
+fn equal_power_crossfades_keep_the_power_of_uncorrelated_samples() {
+    // the fade out gains are the output of a fade from ones to zeros and the fade in gains the other way around
+    let ones_then_zeros = [[0.0; 8], [1.0; 8]].concat();
+    let zeros_then_ones = [[1.0; 8], [0.0; 8]].concat();
+    let fade_out = crossfade(&ones_then_zeros, 16, CrossfadeShape::EqualPower);
+    let fade_in = crossfade(&zeros_then_ones, 16, CrossfadeShape::EqualPower);
+
+    for (fade_out, fade_in) in fade_out.iter().zip(&fade_in) {
+        assert!((fade_out.powi(2) + fade_in.powi(2) - 1.0).abs() < 1e-6);
+    }
+}
+
+/// stdout of a short headless recording of the i4 with `args`
+fn record(name: &str, args: &[&str]) -> String {
+    let path =
+        std::env::temp_dir().join(format!("enginesound_{}_{}.wav", name, std::process::id()));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_enginesound"))
+        .args(["--headless", "--preset", "i4", "--warmup_time", "0.0"])
+        .args(["--length", "0.25", "--no-progress"])
+        .args(args)
+        .arg("--output")
+        .arg(&path)
+        .output()
+        .expect("failed to run enginesound");
+    assert!(output.status.success());
+
+    std::fs::remove_file(path).unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn seam_is_only_reported_for_loops() {
+    assert!(record("seam_looped", &["--crossfade", "0.01"]).contains("Loop seam:"));
+    assert!(!record("seam_unlooped", &[]).contains("Loop seam:"));
+}
@@ -0,0 +1,58 @@
+//! Deactivated cylinders (`Generator::set_cylinder_active`) don't contribute to the firing frequency.
+
+mod common;
+
+use common::{generator, TestEngine, SAMPLE_RATE};
+use enginesound::{find_loop_point, Engine, Generator};
+
+const RPM: f32 = 3000.0;
+/// one engine cycle (two revolutions) at `RPM`
+const CYCLE_SAMPLES: usize = 1920;
+
+/// i4 without noise whose cylinders only differ by their firing time, so its output repeats after every firing
+fn engine() -> Engine {
+    TestEngine::preset("i4")
+        .rpm(RPM)
+        .with(|engine| {
+            engine.intake_noise_factor = 0.0;
+            engine.crankshaft_fluctuation = 0.0;
+
+            let first = engine.cylinders[0].clone();
+            for cylinder in engine.cylinders.iter_mut().skip(1) {
+                let crank_offset = cylinder.crank_offset;
+                *cylinder = first.clone();
+                cylinder.crank_offset = crank_offset;
+            }
+        })
+        .engine()
+}
+
+/// period of the output in samples after a warmup
+fn period(mut generator: Generator) -> usize {
+    generator.skip(SAMPLE_RATE as usize);
+
+    let mut output = vec![0.0; CYCLE_SAMPLES * 6];
+    generator.generate(&mut output);
+    find_loop_point(&output, CYCLE_SAMPLES / 2)
+}
+
+#[test]
+fn one_active_cylinder_fires_like_a_single_cylinder_engine() {
+    let mut single_cylinder = engine();
+    single_cylinder.cylinders.truncate(1);
+    let single_cylinder_period = period(generator(single_cylinder));
+
+    let mut deactivated = generator(engine());
+    for index in 1..4 {
+        deactivated.set_cylinder_active(index, false);
+    }
+    let deactivated_period = period(deactivated);
+
+    assert_eq!(single_cylinder_period, CYCLE_SAMPLES);
+    assert_eq!(deactivated_period, single_cylinder_period);
+}
+
+#[test]
+fn all_active_cylinders_fire_four_times_per_cycle() {
+    assert_eq!(period(generator(engine())), CYCLE_SAMPLES / 4);
+}
@@ -0,0 +1,39 @@
+//! The second order high pass which removes the dc offset of the generator's output.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::{BiquadFilter, DC_OFFSET_LP_FREQ, PI2F};
+
+#[test]
+fn dc_is_removed_and_100_hz_passes() {
+    let mut filter = BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, SAMPLE_RATE);
+    let dc = 1.0;
+
+    let output = (0..10480)
+        .map(|i| {
+            let sine = 0.5 * (PI2F * 100.0 * i as f32 / SAMPLE_RATE as f32).sin();
+            filter.filter(dc + sine)
+        })
+        .collect::<Vec<f32>>();
+
+    // one period of the sine after 10000 samples
+    let period = &output[10000..];
+    let mean = period.iter().sum::<f32>() / period.len() as f32;
+    assert!(mean.abs() < dc * 0.005, "dc offset {}", mean);
+
+    let peak_to_peak = period.iter().copied().fold(f32::MIN, f32::max)
+        - period.iter().copied().fold(f32::MAX, f32::min);
+    assert!((peak_to_peak - 1.0).abs() < 0.01, "{}", peak_to_peak);
+}
+
+#[test]
+fn reset_clears_the_state() {
+    let mut filter = BiquadFilter::new_highpass(DC_OFFSET_LP_FREQ, SAMPLE_RATE);
+    for _ in 0..100 {
+        filter.filter(1.0);
+    }
+
+    filter.reset();
+    assert_eq!(filter.filter(0.0), 0.0);
+}
@@ -0,0 +1,34 @@
+//! Decaying sample buffers and filter states are flushed to zero instead of becoming subnormal,
+//! like in the `example6_silent` benchmark.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+
+#[test]
+fn silent_engines_have_no_subnormal_samples() {
+    let mut generator = TestEngine::config(include_str!("../example6.esc"))
+        .seed(7)
+        .generator();
+
+    generator.skip(SAMPLE_RATE as usize);
+    generator.engine.intake_noise_factor = 0.0;
+    for cylinder in generator.engine.cylinders.iter_mut() {
+        cylinder.active = false;
+    }
+    generator.skip(SAMPLE_RATE as usize * 10);
+
+    let state = generator.get_state();
+    for (i, buffer) in state.buffers.iter().enumerate() {
+        assert!(
+            !buffer.iter().any(|sample| sample.is_subnormal()),
+            "buffer {} contains subnormal samples",
+            i
+        );
+    }
+    assert!(!state.values.iter().any(|value| value.is_subnormal()));
+
+    let mut output = vec![0.0; 4800];
+    generator.generate(&mut output);
+    assert!(!output.iter().any(|sample| sample.is_subnormal()));
+}
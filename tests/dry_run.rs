@@ -0,0 +1,23 @@
+//! The estimates printed by `--dry-run`.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{estimate_generation_time, recommended_crossfade};
+use std::time::Duration;
+
+#[test]
+fn generation_time_is_estimated() {
+    let engine = TestEngine::preset("i4").engine();
+
+    let estimate = estimate_generation_time(&engine, SAMPLE_RATE, SAMPLE_RATE as usize * 60);
+    assert!(estimate > Duration::ZERO);
+    // the engine which is estimated isn't changed
+    assert_eq!(engine.crankshaft_pos, 0.0);
+}
+
+#[test]
+fn crossfade_covers_two_engine_cycles() {
+    // one cycle at 3000 rpm takes 40 ms
+    assert!((recommended_crossfade(3000.0) - 0.08).abs() < 1e-6);
+}
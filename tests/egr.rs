@@ -0,0 +1,40 @@
+//! The exhaust gas recirculation feedback path of `Engine::egr_factor` and `Engine::egr_delay`.
+
+mod common;
+
+use common::{generator, TestEngine, SAMPLE_RATE};
+use enginesound::{Engine, WaveGuide};
+
+fn engine() -> Engine {
+    TestEngine::preset("i4").seed(7).rpm(3000.0).engine()
+}
+
+fn generate(engine: Engine, len: usize) -> Vec<f32> {
+    let mut generator = generator(engine);
+    let mut buf = vec![0.0; len];
+    generator.generate(&mut buf);
+    buf
+}
+
+#[test]
+fn zero_egr_factor_leaves_the_output_unchanged() {
+    let without = engine();
+    assert_eq!(without.egr_factor, 0.0);
+
+    // the EGR pipe is left out entirely, whatever its parameters
+    let mut with_pipe = engine();
+    with_pipe.egr_delay = WaveGuide::new(200.0, 0.9, -0.9, SAMPLE_RATE);
+
+    assert_eq!(generate(with_pipe, 24000), generate(without, 24000));
+}
+
+#[test]
+fn egr_feedback_changes_the_output_and_stays_stable() {
+    let mut engine = engine();
+    engine.egr_factor = 1.0;
+    engine.egr_delay = WaveGuide::new(100.0, 0.95, 0.95, SAMPLE_RATE);
+
+    let output = generate(engine, SAMPLE_RATE as usize * 2);
+    assert!(output.iter().all(|x| x.is_finite() && x.abs() <= 1.0));
+    assert_ne!(output, generate(self::engine(), SAMPLE_RATE as usize * 2));
+}
@@ -0,0 +1,106 @@
+//! The name, author and description of an engine in saved configs and in the `LIST`/`INFO` chunk of recordings.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{load_engine, save_engine, OutputFormat, Recorder, SampleDepth};
+
+fn described_engine() -> enginesound::Engine {
+    let mut engine = TestEngine::preset("i4").engine();
+    engine.name = Some("Rally \"Ö4\"".to_string());
+    engine.author = Some("someone".to_string());
+    engine.description = Some("two lines,\nthe second one: ends with a backslash \\".to_string());
+    engine
+}
+
+#[test]
+fn info_survives_saving_and_loading() {
+    let engine = described_engine();
+
+    for extension in ["esc", "json"] {
+        let path = std::env::temp_dir().join(format!(
+            "enginesound_info_{}.{}",
+            std::process::id(),
+            extension
+        ));
+        save_engine(&path, &engine).unwrap();
+
+        let loaded = load_engine(path.to_str().unwrap(), SAMPLE_RATE, None).unwrap();
+        assert_eq!(loaded.name, engine.name);
+        assert_eq!(loaded.author, engine.author);
+        assert_eq!(loaded.description, engine.description);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn configs_without_info_still_load() {
+    let path = std::env::temp_dir().join(format!("enginesound_no_info_{}.esc", std::process::id()));
+    let mut engine = described_engine();
+    engine.name = None;
+    engine.author = None;
+    engine.description = None;
+    save_engine(&path, &engine).unwrap();
+
+    // configs saved before the fields existed don't contain them at all
+    let config = std::fs::read_to_string(&path).unwrap();
+    let config = config
+        .lines()
+        .filter(|line| {
+            !["name:", "author:", "description:"]
+                .iter()
+                .any(|field| line.trim_start().starts_with(field))
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    std::fs::write(&path, config).unwrap();
+
+    let loaded = load_engine(path.to_str().unwrap(), SAMPLE_RATE, None).unwrap();
+    assert_eq!(loaded.name, None);
+    assert_eq!(loaded.description, None);
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn recordings_contain_an_info_chunk() {
+    let path = std::env::temp_dir().join(format!("enginesound_info_{}.wav", std::process::id()));
+    let mut engine = described_engine();
+    engine.author = None;
+
+    let mut recorder = Recorder::new(
+        path.clone(),
+        SAMPLE_RATE,
+        OutputFormat::from(SampleDepth::I16),
+    )
+    .unwrap();
+    recorder.set_info(&engine);
+    recorder.record(vec![0.25; 1000]);
+    recorder.stop_wait();
+    assert!(recorder.take_error().is_none());
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(
+        u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize,
+        bytes.len() - 8
+    );
+
+    let list = bytes.windows(4).position(|id| id == b"LIST").unwrap();
+    assert_eq!(&bytes[list + 8..list + 12], b"INFO");
+    let info = &bytes[list + 12..];
+
+    let name = engine.name.unwrap();
+    assert_eq!(&info[0..4], b"INAM");
+    assert_eq!(info[4] as usize, name.len() + 1);
+    assert_eq!(&info[8..8 + name.len()], name.as_bytes());
+    // unset fields are left out
+    assert!(!info.windows(4).any(|id| id == b"IART"));
+    assert!(info.windows(4).any(|id| id == b"ICMT"));
+
+    // the samples can still be read
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.len(), 1000);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,62 @@
+//! Filtering with the bands of the `ParametricEq`.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::gen::{EqBand, EqBandKind, ParametricEq};
+use std::f32::consts::PI;
+
+/// one second of a sine with the amplitude 0.5
+fn sine(freq: f32) -> Vec<f32> {
+    (0..SAMPLE_RATE)
+        .map(|i| (2.0 * PI * freq * i as f32 / SAMPLE_RATE as f32).sin() * 0.5)
+        .collect()
+}
+
+fn peak(samples: &[f32]) -> f32 {
+    samples
+        .iter()
+        .fold(0.0f32, |peak, sample| peak.max(sample.abs()))
+}
+
+fn filter(eq: &mut ParametricEq, input: &[f32]) -> Vec<f32> {
+    eq.update(SAMPLE_RATE);
+    input.iter().map(|&sample| eq.filter(sample, 0)).collect()
+}
+
+#[test]
+fn peak_band_with_0_db_passes_samples_through_unchanged() {
+    let mut eq = ParametricEq {
+        bands: vec![EqBand::new(EqBandKind::Peak, 1000.0, 0.0, 1.0)],
+    };
+    let input = sine(440.0)
+        .into_iter()
+        .zip(sine(3520.0))
+        .map(|(a, b)| a + b)
+        .collect::<Vec<f32>>();
+
+    let output = filter(&mut eq, &input);
+    assert!(input
+        .iter()
+        .zip(&output)
+        .all(|(input, output)| input.to_bits() == output.to_bits()));
+}
+
+#[test]
+fn peak_band_with_6_db_doubles_the_amplitude_at_its_frequency() {
+    let mut eq = ParametricEq {
+        bands: vec![EqBand::new(EqBandKind::Peak, 1000.0, 6.0, 1.0)],
+    };
+    let input = sine(1000.0);
+
+    let output = filter(&mut eq, &input);
+    // after the filter has settled
+    let gain = peak(&output[SAMPLE_RATE as usize / 2..]) / peak(&input);
+    // +6 dB is a factor of 1.995
+    assert!((gain - 2.0).abs() < 0.02, "gain {}", gain);
+
+    // frequencies far away from the band are left alone
+    let low = sine(50.0);
+    let gain = peak(&filter(&mut eq, &low)[SAMPLE_RATE as usize / 2..]) / peak(&low);
+    assert!((gain - 1.0).abs() < 0.05, "gain {}", gain);
+}
@@ -0,0 +1,61 @@
+//! Compiles the C test in `tests/ffi_test.c` against the generated header and the `cdylib` of this crate and runs it.
+//! Needs a C compiler, `cc` or the one set in `CC`.
+
+#![cfg(all(feature = "ffi", unix))]
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// the header generated by the build script
+const GENERATED_HEADER: &str = include_str!(concat!(env!("OUT_DIR"), "/enginesound.h"));
+const CHECKED_IN_HEADER: &str = include_str!("../include/enginesound.h");
+
+/// the directory with `libenginesound.so` (or `.dylib`), which cargo builds next to this test
+fn library_dir() -> PathBuf {
+    std::env::current_exe()
+        .unwrap()
+        .parent()
+        .expect("test executable without directory")
+        .to_path_buf()
+}
+
+#[test]
+fn checked_in_header_is_up_to_date() {
+    assert!(
+        GENERATED_HEADER == CHECKED_IN_HEADER,
+        "include/enginesound.h differs from the header generated from src/ffi.rs, copy {}/enginesound.h over it",
+        env!("OUT_DIR")
+    );
+}
+
+#[test]
+fn c_api_generates_samples() {
+    let crate_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let library_dir = library_dir();
+    let executable =
+        std::env::temp_dir().join(format!("enginesound_ffi_test_{}", std::process::id()));
+
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(&compiler)
+        .arg(crate_dir.join("tests").join("ffi_test.c"))
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .arg("-L")
+        .arg(&library_dir)
+        .arg(format!("-Wl,-rpath,{}", library_dir.display()))
+        .args(["-Wall", "-Werror", "-lenginesound", "-lm", "-o"])
+        .arg(&executable)
+        .status()
+        .unwrap_or_else(|e| panic!("failed to run the C compiler \"{}\": {}", compiler, e));
+    assert!(status.success(), "failed to compile tests/ffi_test.c");
+
+    // cargo's library path may contain a library of a build without the "ffi" feature, the rpath points to this one
+    let status = Command::new(&executable)
+        .env_remove("LD_LIBRARY_PATH")
+        .env_remove("DYLD_LIBRARY_PATH")
+        .arg(crate_dir.join("src").join("default.esc"))
+        .status()
+        .unwrap();
+    std::fs::remove_file(&executable).unwrap();
+    assert_eq!(status.code(), Some(0));
+}
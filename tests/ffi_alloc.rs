@@ -0,0 +1,55 @@
+//! Checks that `enginesound_generate` can be called from an audio callback without allocating.
+
+#![cfg(feature = "ffi")]
+
+use enginesound::ffi::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::CString;
+
+/// counts the allocations of the current thread, the test harness allocates on other threads
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[test]
+fn generate_does_not_allocate() {
+    let config = CString::new(&include_bytes!("../src/default.esc")[..]).unwrap();
+    let mut buf = vec![0.0f32; 4800];
+
+    unsafe {
+        let gen = enginesound_create(config.as_ptr(), 48000);
+        assert!(!gen.is_null());
+        assert_eq!(enginesound_set_rpm(gen, 4000.0), EnginesoundStatus::Ok);
+        assert_eq!(enginesound_set_throttle(gen, 1.0), EnginesoundStatus::Ok);
+
+        let before = ALLOCATIONS.with(Cell::get);
+        for _ in 0..10 {
+            assert_eq!(
+                enginesound_generate(gen, buf.as_mut_ptr(), buf.len()),
+                EnginesoundStatus::Ok
+            );
+        }
+        assert_eq!(ALLOCATIONS.with(Cell::get), before);
+
+        enginesound_destroy(gen);
+    }
+
+    assert!(buf.iter().any(|&sample| sample != 0.0));
+}
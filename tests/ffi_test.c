@@ -0,0 +1,110 @@
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "enginesound.h"
+
+#define NUM_SAMPLES 3000
+
+/* returns 0 if a generator can be created from `config_ron` and generates finite samples */
+static int enginesound_ffi_test(const char *config_ron) {
+    float buf[NUM_SAMPLES];
+    OpaqueGenerator *gen = enginesound_create(config_ron, 48000);
+
+    if (gen == NULL) {
+        fprintf(stderr, "enginesound_create returned NULL\n");
+        return 1;
+    }
+
+    /* more samples than the generator produces at once */
+    if (enginesound_set_rpm(gen, 3000.0f) != ENGINESOUND_STATUS_OK ||
+        enginesound_set_throttle(gen, 0.5f) != ENGINESOUND_STATUS_OK ||
+        enginesound_generate(gen, buf, NUM_SAMPLES) != ENGINESOUND_STATUS_OK) {
+        fprintf(stderr, "generating samples failed\n");
+        enginesound_destroy(gen);
+        return 2;
+    }
+
+    for (int i = 0; i < NUM_SAMPLES; i++) {
+        if (!isfinite(buf[i])) {
+            fprintf(stderr, "sample %d is not finite\n", i);
+            enginesound_destroy(gen);
+            return 3;
+        }
+    }
+
+    if (enginesound_reset(gen) != ENGINESOUND_STATUS_OK ||
+        enginesound_generate(gen, buf, 1) != ENGINESOUND_STATUS_OK ||
+        !isfinite(buf[0])) {
+        fprintf(stderr, "generating after enginesound_reset failed\n");
+        enginesound_destroy(gen);
+        return 4;
+    }
+
+    if (enginesound_generate(gen, NULL, NUM_SAMPLES) != ENGINESOUND_STATUS_NULL_POINTER) {
+        fprintf(stderr, "enginesound_generate accepted a NULL buffer\n");
+        enginesound_destroy(gen);
+        return 5;
+    }
+
+    enginesound_destroy(gen);
+
+    /* invalid configs and handles are reported instead of crashing */
+    if (enginesound_create("(", 48000) != NULL || enginesound_create(NULL, 48000) != NULL) {
+        fprintf(stderr, "enginesound_create accepted an invalid config\n");
+        return 6;
+    }
+
+    if (enginesound_set_rpm(NULL, 3000.0f) != ENGINESOUND_STATUS_NULL_POINTER ||
+        enginesound_set_throttle(NULL, 1.0f) != ENGINESOUND_STATUS_NULL_POINTER ||
+        enginesound_reset(NULL) != ENGINESOUND_STATUS_NULL_POINTER ||
+        enginesound_generate(NULL, buf, NUM_SAMPLES) != ENGINESOUND_STATUS_NULL_POINTER) {
+        fprintf(stderr, "a NULL generator was accepted\n");
+        return 7;
+    }
+    enginesound_destroy(NULL);
+
+    return 0;
+}
+
+static char *read_file(const char *path) {
+    FILE *file = fopen(path, "rb");
+    char *data = NULL;
+    long len;
+
+    if (file == NULL) {
+        return NULL;
+    }
+    if (fseek(file, 0, SEEK_END) == 0 && (len = ftell(file)) >= 0 && fseek(file, 0, SEEK_SET) == 0) {
+        data = malloc((size_t)len + 1);
+        if (data != NULL && fread(data, 1, (size_t)len, file) == (size_t)len) {
+            data[len] = '\0';
+        } else {
+            free(data);
+            data = NULL;
+        }
+    }
+    fclose(file);
+    return data;
+}
+
+/* compiled and run by tests/ffi.rs with the path of a config */
+int main(int argc, char **argv) {
+    char *config;
+    int status;
+
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <config.esc>\n", argv[0]);
+        return 100;
+    }
+
+    config = read_file(argv[1]);
+    if (config == NULL) {
+        fprintf(stderr, "failed to read \"%s\"\n", argv[1]);
+        return 101;
+    }
+
+    status = enginesound_ffi_test(config);
+    free(config);
+    return status;
+}
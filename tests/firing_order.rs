@@ -0,0 +1,86 @@
+//! Firing order of the cylinders by their crank offsets and its validation.
+
+mod common;
+
+use common::TestEngine;
+use enginesound::{apply_firing_preset, firing_preset_crank_offsets, Engine, FiringOrderWarning};
+
+/// `preset` engine with the given crank offsets, cylinders are added or removed to match
+fn engine_with_offsets(preset: &str, offsets: &[f32]) -> Engine {
+    let mut engine = TestEngine::preset(preset).engine();
+    let last = engine.cylinders[0].clone();
+    engine.cylinders.resize(offsets.len(), last);
+    for (cylinder, &offset) in engine.cylinders.iter_mut().zip(offsets) {
+        cylinder.crank_offset = offset;
+    }
+    engine
+}
+
+#[test]
+fn inline_four_fires_1_3_4_2() {
+    let engine = engine_with_offsets("i4", &[0.0, 0.75, 0.25, 0.5]);
+
+    assert_eq!(engine.cylinder_firing_order(), vec![0, 2, 3, 1]);
+    assert_eq!(engine.validate_firing_order(), vec![]);
+}
+
+#[test]
+fn v8_preset_fires_1_8_4_3_6_5_7_2() {
+    let engine = TestEngine::preset("v8").engine();
+
+    assert_eq!(engine.cylinder_firing_order(), vec![0, 7, 3, 2, 5, 4, 6, 1]);
+    assert_eq!(engine.validate_firing_order(), vec![]);
+}
+
+#[test]
+fn crossplane_v8_has_eight_cylinders_firing_1_8_4_3_6_5_7_2() {
+    let mut engine = TestEngine::preset("i4").engine();
+    apply_firing_preset(&mut engine, "crossplane V8").unwrap();
+
+    assert_eq!(engine.cylinders.len(), 8);
+    assert_eq!(engine.cylinder_firing_order(), vec![0, 7, 3, 2, 5, 4, 6, 1]);
+    assert_eq!(engine.validate_firing_order(), vec![]);
+}
+
+#[test]
+fn crossplane_v8_bank_fires_unevenly() {
+    let offsets = firing_preset_crank_offsets("crossplane V8 bank", 8).unwrap();
+    let engine = engine_with_offsets("v8", &offsets);
+    assert_eq!(engine.cylinder_firing_order(), vec![0, 1, 2, 3]);
+
+    // intervals in degrees of the 720° cycle, the last cylinder is followed by the first one of the next cycle
+    let intervals = (0..offsets.len())
+        .map(|i| {
+            let next = offsets.get(i + 1).copied().unwrap_or(1.0);
+            ((next - offsets[i]) * 720.0).round()
+        })
+        .collect::<Vec<f32>>();
+    assert_eq!(intervals, vec![270.0, 180.0, 90.0, 180.0]);
+}
+
+#[test]
+fn irregular_firing_orders_are_warned_about() {
+    // the second and the third cylinder fire together, leaving a gap of half a cycle
+    let engine = engine_with_offsets("i4", &[0.0, 0.25, 0.25, 0.5]);
+    assert_eq!(
+        engine.validate_firing_order(),
+        vec![
+            FiringOrderWarning::SimultaneousFiring {
+                first: 1,
+                second: 2
+            },
+            FiringOrderWarning::UnevenFiring { after: 3, gap: 0.5 },
+        ]
+    );
+
+    let offsets = firing_preset_crank_offsets("V-twin 90°", 2).unwrap();
+    let v_twin = engine_with_offsets("i4", &offsets);
+    assert_eq!(v_twin.cylinder_firing_order(), vec![0, 1]);
+    assert_eq!(
+        v_twin.validate_firing_order(),
+        vec![FiringOrderWarning::OffsetOffGrid {
+            cylinder: 1,
+            offset: 0.375
+        }]
+    );
+}
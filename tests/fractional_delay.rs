@@ -0,0 +1,115 @@
+//! Sweeping the length of a `WaveGuide` through its fractional delay (`LoopBuffer::pop_interpolated`) in place.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::WaveGuide;
+
+const FREQUENCY: f32 = 440.0;
+/// delay of the waveguide at the start and end of the sweep in samples
+const SWEEP: (f32, f32) = (100.0, 124.0);
+const SWEEP_SAMPLES: usize = SAMPLE_RATE as usize;
+
+/// sends a 440 hz sine through a waveguide without reflections while sweeping its delay from `from` to `to` samples
+fn swept_sine(from: f32, to: f32) -> Vec<f32> {
+    let mut waveguide = WaveGuide::new(from, 0.0, 0.0, SAMPLE_RATE);
+    let step = std::f32::consts::PI * 2.0 * FREQUENCY / SAMPLE_RATE as f32;
+
+    let mut output = Vec::with_capacity(SWEEP_SAMPLES);
+    for i in 0..(SWEEP_SAMPLES + to.max(from) as usize) {
+        let delay = from + (to - from) * (i.min(SWEEP_SAMPLES) as f32 / SWEEP_SAMPLES as f32);
+        if let Some(new) = waveguide.get_changed(delay, 0.0, 0.0, SAMPLE_RATE) {
+            waveguide = new;
+        }
+
+        let (_, c0_out, _) = waveguide.pop(false);
+        waveguide.push((i as f32 * step).sin(), 0.0);
+        output.push(c0_out);
+    }
+
+    // skips the silence before the sine has passed the waveguide and the end of the sweep
+    output.drain(..(to.max(from) as usize + 1));
+    output.truncate(SWEEP_SAMPLES - to.max(from) as usize * 2);
+    output
+}
+
+/// positions of the rising zero crossings in samples, interpolated between the samples
+fn rising_zero_crossings(samples: &[f32]) -> Vec<f32> {
+    samples
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+        .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+        .collect()
+}
+
+fn assert_without_pitch_jumps(from: f32, to: f32) {
+    let output = swept_sine(from, to);
+
+    // a whole sample jump of the delay would step by up to twice the largest step of the sine
+    let max_step = std::f32::consts::PI * 2.0 * FREQUENCY / SAMPLE_RATE as f32 * 1.05;
+    for (i, pair) in output.windows(2).enumerate() {
+        assert!(
+            (pair[1] - pair[0]).abs() < max_step,
+            "step of {} at sample {}",
+            pair[1] - pair[0],
+            i
+        );
+    }
+
+    // the sweep shifts the frequency evenly (doppler effect), so every period has the same length
+    let crossings = rising_zero_crossings(&output);
+    let periods = crossings
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .collect::<Vec<_>>();
+    let expected = SAMPLE_RATE as f32 / FREQUENCY / (1.0 - (to - from) / SWEEP_SAMPLES as f32);
+    for period in periods {
+        assert!(
+            (period - expected).abs() < 0.05,
+            "period of {} samples instead of {}",
+            period,
+            expected
+        );
+    }
+}
+
+#[test]
+fn lengthening_sweeps_shift_the_pitch_smoothly() {
+    assert_without_pitch_jumps(SWEEP.0, SWEEP.1);
+}
+
+#[test]
+fn shortening_sweeps_shift_the_pitch_smoothly() {
+    assert_without_pitch_jumps(SWEEP.1, SWEEP.0);
+}
+
+#[test]
+fn delay_changes_keep_the_buffers() {
+    let mut waveguide = WaveGuide::new(100.0, 0.5, 0.5, SAMPLE_RATE);
+    let buffers = (
+        waveguide.chamber0.samples.data.as_ptr(),
+        waveguide.chamber1.samples.data.as_ptr(),
+    );
+
+    // fractional, fractional with new reflection factors and whole sample changes
+    assert!(waveguide
+        .get_changed(100.5, 0.5, 0.5, SAMPLE_RATE)
+        .is_none());
+    assert!(waveguide
+        .get_changed(100.25, 0.4, -0.4, SAMPLE_RATE)
+        .is_none());
+    assert!(waveguide
+        .get_changed(110.75, 0.4, -0.4, SAMPLE_RATE)
+        .is_none());
+
+    assert_eq!(waveguide.delay(), 110.75);
+    assert_eq!((waveguide.alpha, waveguide.beta), (0.4, -0.4));
+    assert_eq!(
+        (
+            waveguide.chamber0.samples.data.as_ptr(),
+            waveguide.chamber1.samples.data.as_ptr()
+        ),
+        buffers
+    );
+}
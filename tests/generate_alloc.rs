@@ -0,0 +1,75 @@
+//! Checks that `Generator::generate` and `generate_resampled` can be called from an audio callback without allocating.
+
+mod common;
+
+use common::TestEngine;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+/// counts the allocations of the current thread, the test harness allocates on other threads
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// allocations made by `f`
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+#[test]
+fn generate_allocates_only_for_longer_buffers() {
+    let mut generator = TestEngine::default_config().generator();
+    let mut buf = vec![0.0; 1024];
+
+    generator.generate(&mut buf);
+    assert_eq!(
+        allocations(|| {
+            for _ in 0..10 {
+                generator.generate(&mut buf);
+                generator.generate(&mut buf[..100]);
+            }
+        }),
+        0
+    );
+    assert!(buf.iter().any(|&sample| sample != 0.0));
+
+    let mut longer = vec![0.0; 2048];
+    assert!(allocations(|| generator.generate(&mut longer)) > 0);
+}
+
+#[test]
+fn generate_resampled_does_not_allocate_after_the_first_buffers() {
+    let mut generator = TestEngine::default_config().generator();
+    let mut buf = vec![0.0; 1024];
+
+    // the resampler and the scratch buffers are created by the first calls, the input length varies by a sample
+    for _ in 0..10 {
+        generator.generate_resampled(&mut buf, 44100);
+    }
+    assert_eq!(
+        allocations(|| {
+            for _ in 0..10 {
+                generator.generate_resampled(&mut buf, 44100);
+            }
+        }),
+        0
+    );
+}
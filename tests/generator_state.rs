@@ -0,0 +1,70 @@
+//! Checkpointing the running state of a `Generator` with `get_state` and `set_state`.
+
+mod common;
+
+use common::TestEngine;
+use enginesound::{Generator, GeneratorState};
+
+fn generator(preset: &str) -> Generator {
+    TestEngine::preset(preset)
+        .with(|engine| engine.crankshaft_fluctuation = 0.3)
+        .generator()
+}
+
+fn generate(generator: &mut Generator, len: usize) -> Vec<f32> {
+    let mut buf = vec![0.0; len];
+    generator.generate(&mut buf);
+    buf
+}
+
+#[test]
+fn restored_state_generates_the_same_samples() {
+    let mut generator = generator("i4");
+    generate(&mut generator, 10000);
+
+    let state = generator.get_state();
+    let original = generate(&mut generator, 10000);
+
+    generator.set_state(state).unwrap();
+    assert_eq!(generate(&mut generator, 10000), original);
+}
+
+#[test]
+fn state_can_be_moved_to_another_generator() {
+    let mut generator_a = generator("i4");
+    generator_a.set_target_rpm(4000.0);
+    generate(&mut generator_a, 10000);
+
+    // the noise sources of this generator are seeded differently
+    let mut generator_b = generator("i4");
+    generator_b.set_state(generator_a.get_state()).unwrap();
+
+    assert_eq!(
+        generate(&mut generator_b, 10000),
+        generate(&mut generator_a, 10000)
+    );
+}
+
+#[test]
+fn state_survives_serialization_and_compression() {
+    let mut generator = generator("i4");
+    generate(&mut generator, 10000);
+    let state = generator.get_state();
+
+    let ron = ron::ser::to_string(&state).unwrap();
+    assert!(ron::de::from_str::<GeneratorState>(&ron).unwrap() == state);
+
+    let compressed = state.compress();
+    assert!(compressed.len() < ron.len());
+    assert!(GeneratorState::decompress(&compressed).unwrap() == state);
+}
+
+#[test]
+fn state_of_a_different_engine_is_rejected() {
+    let state = generator("i4").get_state();
+
+    let mut v8 = generator("v8");
+    let original = v8.get_state();
+    assert!(v8.set_state(state).is_err());
+    assert!(v8.get_state() == original);
+}
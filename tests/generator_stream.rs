@@ -0,0 +1,19 @@
+//! Polling a `GeneratorStream` on the tokio runtime.
+#![cfg(feature = "tokio")]
+
+mod common;
+
+use common::TestEngine;
+use enginesound::GeneratorStream;
+use futures_util::StreamExt;
+
+#[tokio::test]
+async fn stream_yields_chunks_of_audio() {
+    let mut stream = GeneratorStream::new(TestEngine::preset("i4").generator(), 4800);
+
+    for _ in 0..4 {
+        let chunk = stream.next().await.unwrap();
+        assert_eq!(chunk.len(), 4800);
+        assert!(chunk.iter().any(|&sample| sample != 0.0));
+    }
+}
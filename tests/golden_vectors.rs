@@ -0,0 +1,65 @@
+//! Compares seeded output of the generator with reference vectors in `tests/golden`, so changes of the
+//! generated sound are noticed. Run with `ENGINESOUND_UPDATE_GOLDEN=1` to rewrite the vectors after an intended change.
+
+mod common;
+
+use common::TestEngine;
+use std::path::PathBuf;
+
+const SEED: u64 = 12345;
+const NUM_SAMPLES: usize = 1024;
+/// allows for differences of the floating point math libraries of other platforms
+const TOLERANCE: f32 = 1e-4;
+
+/// `NUM_SAMPLES` samples of `default.esc` at `rpm` with the noise seeded with `SEED`, like `--seed`
+fn generate(rpm: f32) -> Vec<f32> {
+    let mut generator = TestEngine::default_config().rpm(rpm).seed(SEED).generator();
+    let mut buf = vec![0.0; NUM_SAMPLES];
+    generator.generate(&mut buf);
+    buf
+}
+
+/// compares the samples with the little endian f32 reference vector `tests/golden/<name>`
+fn assert_matches_golden(name: &str, samples: &[f32]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(name);
+
+    if std::env::var_os("ENGINESOUND_UPDATE_GOLDEN").is_some() {
+        let bytes = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<u8>>();
+        std::fs::write(&path, bytes).unwrap();
+        return;
+    }
+
+    let golden = std::fs::read(&path)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", path.display(), e))
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect::<Vec<f32>>();
+    assert_eq!(golden.len(), samples.len());
+
+    for (i, (&sample, &expected)) in samples.iter().zip(&golden).enumerate() {
+        assert!(
+            (sample - expected).abs() <= TOLERANCE,
+            "sample {} of {} is {}, expected {}",
+            i,
+            name,
+            sample,
+            expected
+        );
+    }
+}
+
+#[test]
+fn seeded_output_at_800_rpm_matches_the_reference() {
+    assert_matches_golden("seed12345_800rpm.f32", &generate(800.0));
+}
+
+#[test]
+fn seeded_output_at_3000_rpm_matches_the_reference() {
+    assert_matches_golden("seed12345_3000rpm.f32", &generate(3000.0));
+}
@@ -0,0 +1,52 @@
+//! Helmholtz resonator muffler elements.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::gen::{helmholtz_frequency, MufflerElement};
+
+#[test]
+fn resonant_frequency_follows_the_geometry() {
+    // f = 343 / 2pi * sqrt(pi * 0.02^2 / (0.05 * 0.002))
+    let freq = helmholtz_frequency(0.05, 0.02, 0.002);
+    assert!((freq - 193.5).abs() < 0.1, "{}", freq);
+
+    // four times the cavity volume or neck length halves the frequency, twice the neck radius doubles it
+    assert!((helmholtz_frequency(0.05, 0.02, 0.008) - freq * 0.5).abs() < 1e-3);
+    assert!((helmholtz_frequency(0.2, 0.02, 0.002) - freq * 0.5).abs() < 1e-3);
+    assert!((helmholtz_frequency(0.05, 0.04, 0.002) - freq * 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn resonator_notches_the_muffler_response() {
+    let resonator = MufflerElement::helmholtz_resonator(0.05, 0.02, 0.002, 0.5);
+    let freq = resonator.resonant_frequency().unwrap();
+
+    let num_bins = 4801;
+    let bin_width = SAMPLE_RATE as f32 * 0.5 / (num_bins - 1) as f32;
+    let response = resonator.frequency_response(SAMPLE_RATE, num_bins);
+
+    // the deepest bin is the one closest to the resonant frequency
+    let deepest = (0..num_bins)
+        .min_by(|&a, &b| response[a].partial_cmp(&response[b]).unwrap())
+        .unwrap();
+    assert_eq!(deepest, (freq / bin_width).round() as usize);
+    assert!(response[deepest] < 0.1);
+    assert!((response[(4000.0 / bin_width) as usize] - 1.0).abs() < 0.05);
+}
+
+#[test]
+fn resonators_are_saved_with_the_engine() {
+    let mut engine = TestEngine::preset("i4").engine();
+    engine.muffler.muffler_elements[1] =
+        MufflerElement::helmholtz_resonator(0.05, 0.02, 0.002, 0.5);
+
+    let ron = ron::ser::to_string(&engine).unwrap();
+    let loaded: enginesound::gen::Engine = ron::de::from_str(&ron).unwrap();
+
+    assert!(loaded.muffler.muffler_elements[0].waveguide().is_some());
+    assert_eq!(
+        loaded.muffler.muffler_elements[1].resonant_frequency(),
+        engine.muffler.muffler_elements[1].resonant_frequency()
+    );
+}
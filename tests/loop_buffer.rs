@@ -0,0 +1,96 @@
+//! Resizing a `LoopBuffer` in place with `LoopBuffer::resize` and processing it in blocks with `pop_bulk` and `push_bulk`.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::LoopBuffer;
+
+/// a buffer of `len` samples which pushed 1.0, 2.0, .. `len` so far
+fn filled(len: usize) -> LoopBuffer {
+    let mut lb = LoopBuffer::new(len, SAMPLE_RATE);
+    for value in 1..=len {
+        lb.push(value as f32);
+        lb.advance();
+    }
+    lb
+}
+
+/// pops the next `n` samples like a delay line, pushing silence
+fn pop_n(lb: &mut LoopBuffer, n: usize) -> Vec<f32> {
+    (0..n)
+        .map(|_| {
+            let value = lb.pop();
+            lb.push(0.0);
+            lb.advance();
+            value
+        })
+        .collect()
+}
+
+#[test]
+fn shrinking_keeps_the_most_recent_samples() {
+    let mut lb = filled(8);
+    lb.resize(4, SAMPLE_RATE);
+
+    assert_eq!(lb.len(), 4);
+    assert_eq!(lb.pop_interpolated(1.0), 5.0);
+    assert_eq!(pop_n(&mut lb, 3), vec![6.0, 7.0, 8.0]);
+    // the samples pushed after resizing follow
+    assert_eq!(pop_n(&mut lb, 4), vec![0.0; 4]);
+}
+
+#[test]
+fn growing_keeps_the_samples_in_order() {
+    let mut expected = filled(8);
+    let expected = pop_n(&mut expected, 7);
+
+    // within and beyond the capacity of the buffer
+    for new_len in [12, 40] {
+        let mut lb = filled(8);
+        lb.resize(new_len, SAMPLE_RATE);
+
+        assert_eq!(lb.len(), new_len);
+        assert_eq!(lb.pop_interpolated(1.0), 1.0);
+        assert_eq!(pop_n(&mut lb, 7), expected);
+    }
+}
+
+#[test]
+fn bulk_operations_match_single_samples() {
+    // wraps around the 128 sample buffer several times with blocks of varying lengths
+    let mut single = LoopBuffer::new(100, SAMPLE_RATE);
+    let mut bulk = LoopBuffer::new(100, SAMPLE_RATE);
+    let mut input = (1..).map(|value| value as f32);
+
+    for block_len in [1, 7, 64, 99, 3, 33, 99, 0, 50]
+        .iter()
+        .copied()
+        .cycle()
+        .take(50)
+    {
+        let block = input.by_ref().take(block_len).collect::<Vec<f32>>();
+
+        let expected = block
+            .iter()
+            .map(|&value| {
+                let popped = single.pop();
+                single.push(value);
+                single.advance();
+                popped
+            })
+            .collect::<Vec<f32>>();
+
+        let mut popped = vec![0.0; block_len];
+        bulk.pop_bulk(&mut popped);
+        bulk.push_bulk(&block);
+
+        assert_eq!(popped, expected);
+        assert_eq!(bulk.pop(), single.pop());
+    }
+}
+
+#[test]
+#[should_panic]
+fn bulk_pops_are_shorter_than_the_delay() {
+    LoopBuffer::new(8, SAMPLE_RATE).pop_bulk(&mut [0.0; 8]);
+}
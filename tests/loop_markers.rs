@@ -0,0 +1,51 @@
+//! The `smpl` chunk with the loop markers appended by `write_loop_points`.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::recorder::write_loop_points;
+use enginesound::{OutputFormat, Recorder, SampleDepth};
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+#[test]
+fn smpl_chunk_marks_the_loop() {
+    let path = std::env::temp_dir().join(format!("enginesound_loop_{}.wav", std::process::id()));
+
+    let mut recorder = Recorder::new(
+        path.clone(),
+        SAMPLE_RATE,
+        OutputFormat::from(SampleDepth::I16),
+    )
+    .unwrap();
+    recorder.record((0..1000).map(|i| (i as f32 * 0.1).sin() * 0.5).collect());
+    recorder.stop_wait();
+    assert!(recorder.take_error().is_none());
+
+    write_loop_points(&path, SAMPLE_RATE, 0, 999).unwrap();
+
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(u32_at(&bytes, 4) as usize, bytes.len() - 8);
+
+    let smpl = bytes.len() - 68;
+    assert_eq!(&bytes[smpl..smpl + 4], b"smpl");
+    assert_eq!(u32_at(&bytes, smpl + 4), 60);
+    // one forward loop over the whole file
+    assert_eq!(u32_at(&bytes, smpl + 36), 1);
+    assert_eq!(u32_at(&bytes, smpl + 48), 0);
+    assert_eq!(u32_at(&bytes, smpl + 52), 0);
+    assert_eq!(u32_at(&bytes, smpl + 56), 999);
+
+    // the samples can still be read
+    let reader = hound::WavReader::open(&path).unwrap();
+    assert_eq!(reader.len(), 1000);
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,37 @@
+//! Loop lengths found by `find_loop_point` for `--autocorrelation-loop`.
+
+use enginesound::find_loop_point;
+
+fn sine(period: usize, len: usize) -> Vec<f32> {
+    (0..len)
+        .map(|i| (i as f32 * std::f32::consts::PI * 2.0 / period as f32).sin())
+        .collect()
+}
+
+#[test]
+fn sines_loop_after_exactly_one_period() {
+    for period in [37, 100, 441] {
+        assert_eq!(
+            find_loop_point(&sine(period, period * 10), period / 2),
+            period
+        );
+    }
+}
+
+#[test]
+fn mixed_sines_loop_after_their_common_period() {
+    // periods of 80 and 120 samples repeat together after 240 samples
+    let samples = sine(80, 2000)
+        .into_iter()
+        .zip(sine(120, 2000))
+        .map(|(a, b)| a + 0.5 * b)
+        .collect::<Vec<_>>();
+
+    assert_eq!(find_loop_point(&samples, 300), 240);
+}
+
+#[test]
+fn samples_shorter_than_the_window_have_no_loop_point() {
+    assert_eq!(find_loop_point(&sine(100, 150), 200), 0);
+    assert_eq!(find_loop_point(&[], 10), 0);
+}
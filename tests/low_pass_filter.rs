@@ -0,0 +1,128 @@
+//! Changing the cutoff frequency of a `LowPassFilter` (and the `BandPassFilter` built from it) in place and clearing its state.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::{BandPassFilter, LowPassFilter};
+
+fn run(filter: &mut LowPassFilter, input: &[f32]) -> Vec<f32> {
+    input.iter().map(|&sample| filter.filter(sample)).collect()
+}
+
+fn signal() -> Vec<f32> {
+    (0..256).map(|i| (i as f32 * 0.05).sin()).collect()
+}
+
+#[test]
+fn set_freq_matches_a_new_filter_after_silence() {
+    let mut filter = LowPassFilter::new(200.0, SAMPLE_RATE);
+    run(&mut filter, &signal());
+    run(&mut filter, &[0.0; 48000]);
+
+    filter.set_freq(1000.0, SAMPLE_RATE);
+    assert_eq!(
+        run(&mut filter, &signal()),
+        run(&mut LowPassFilter::new(1000.0, SAMPLE_RATE), &signal())
+    );
+}
+
+#[test]
+fn set_freq_keeps_the_state_mid_signal() {
+    let mut filter = LowPassFilter::new(200.0, SAMPLE_RATE);
+    run(&mut filter, &signal());
+    let last = filter.last;
+    assert_ne!(last, 0.0);
+
+    filter.set_freq(1000.0, SAMPLE_RATE);
+    assert_eq!(filter.last, last);
+    assert!((filter.get_freq() - 1000.0).abs() < 1e-3);
+    assert_ne!(
+        run(&mut filter, &signal()),
+        run(&mut LowPassFilter::new(1000.0, SAMPLE_RATE), &signal())
+    );
+
+    // clearing the state makes it behave like a new filter again
+    filter.reset();
+    assert_eq!(
+        run(&mut filter, &signal()),
+        run(&mut LowPassFilter::new(1000.0, SAMPLE_RATE), &signal())
+    );
+}
+
+#[test]
+fn get_changed_ignores_the_state() {
+    let mut filter = LowPassFilter::new(200.0, SAMPLE_RATE);
+    run(&mut filter, &signal());
+
+    assert!(filter.get_changed(200.0, SAMPLE_RATE).is_none());
+    let changed = filter.get_changed(400.0, SAMPLE_RATE).unwrap();
+    assert_eq!(changed.last, filter.last);
+}
+
+#[test]
+fn band_pass_get_changed_keeps_the_state() {
+    let mut filter = BandPassFilter::new(100.0, 2000.0, SAMPLE_RATE);
+    for &sample in signal().iter() {
+        filter.filter(sample);
+    }
+
+    assert!(filter.get_changed(100.0, 2000.0, SAMPLE_RATE).is_none());
+
+    for (low_freq, high_freq) in [(150.0, 2000.0), (100.0, 3000.0), (150.0, 3000.0)] {
+        let changed = filter
+            .get_changed(low_freq, high_freq, SAMPLE_RATE)
+            .unwrap();
+        assert_eq!(changed.low.last, filter.low.last);
+        assert_eq!(changed.high.last, filter.high.last);
+        assert!((changed.get_freqs().0 - low_freq).abs() < 1e-3);
+        assert!((changed.get_freqs().1 - high_freq).abs() < 1e-3);
+    }
+}
+
+/// filters `input` while ramping the cutoff frequency from `from` to `to` hz with `get_changed` at every sample
+fn run_ramped(from: f32, to: f32, input: &[f32]) -> Vec<f32> {
+    let mut filter = LowPassFilter::new(from, SAMPLE_RATE);
+
+    input
+        .iter()
+        .enumerate()
+        .map(|(i, &sample)| {
+            let freq = from + (to - from) * i as f32 / input.len() as f32;
+            if let Some(new) = filter.get_changed(freq, SAMPLE_RATE) {
+                filter = new;
+            }
+            filter.filter(sample)
+        })
+        .collect()
+}
+
+#[test]
+fn ramping_the_cutoff_keeps_the_output_continuous() {
+    // a low pass can't change faster than its input, which changes by up to `max_slope` per sample
+    let step = std::f32::consts::PI * 2.0 * 100.0 / SAMPLE_RATE as f32;
+    let max_slope = step * 1.01;
+    let input = (0..SAMPLE_RATE)
+        .map(|i| (i as f32 * step).sin())
+        .collect::<Vec<_>>();
+
+    for (from, to) in [(200.0, 5000.0), (5000.0, 200.0)] {
+        let output = run_ramped(from, to, &input);
+
+        for (i, pair) in output.windows(2).enumerate() {
+            assert!(
+                (pair[1] - pair[0]).abs() <= max_slope,
+                "jump of {} at sample {} while ramping from {} to {} hz",
+                pair[1] - pair[0],
+                i,
+                from,
+                to
+            );
+        }
+    }
+
+    // a settled constant input stays constant
+    let output = run_ramped(5000.0, 200.0, &[1.0; SAMPLE_RATE as usize]);
+    assert!(output[100..]
+        .iter()
+        .all(|&sample| (sample - 1.0).abs() < 1e-6));
+}
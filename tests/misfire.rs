@@ -0,0 +1,103 @@
+//! Misfiring cylinders (`Cylinder::misfire_probability`) skip the ignition of whole cycles.
+
+mod common;
+
+use common::TestEngine;
+use enginesound::{Generator, Noise};
+
+const SAMPLE_RATE: u32 = 8000;
+/// 80 samples per cycle at `SAMPLE_RATE`
+const RPM: f32 = 12000.0;
+const SAMPLES_PER_CYCLE: usize = 80;
+const CYCLES: usize = 10000;
+/// ignition lasts from 0.5 to 0.75 cycles
+const IGNITION_TIME: f32 = 0.5;
+/// the combustion pressure peaks in the middle of the ignition at 0.625 cycles
+const PEAK_SAMPLE: usize = 50;
+
+/// generator of a single cylinder without piston motion, so its `cyl_sound` is only the combustion pressure
+fn generator(misfire_probability: f32) -> Generator {
+    TestEngine::default_config()
+        .sample_rate(SAMPLE_RATE)
+        .rpm(RPM)
+        .seed(1)
+        .with(|engine| {
+            engine.cylinders.truncate(1);
+            let cylinder = &mut engine.cylinders[0];
+            cylinder.crank_offset = 0.0;
+            cylinder.piston_motion_factor = 0.0;
+            cylinder.ignition_time = IGNITION_TIME;
+            cylinder.misfire_probability = misfire_probability;
+            engine.throttle = 1.0;
+            engine.crankshaft_fluctuation = 0.0;
+        })
+        .generator()
+}
+
+/// average combustion pressure at its peak over `CYCLES` cycles
+fn average_peak_pressure(generator: &mut Generator) -> f32 {
+    let mut buf = vec![0.0; SAMPLES_PER_CYCLE];
+    generator.generate(&mut buf[..PEAK_SAMPLE]);
+
+    let mut sum = 0.0;
+    for _ in 0..CYCLES {
+        sum += generator.engine.cylinders[0].cyl_sound;
+        generator.generate(&mut buf);
+    }
+
+    sum / CYCLES as f32
+}
+
+#[test]
+fn combustion_pressure_falls_in_proportion_to_the_misfire_probability() {
+    let full = average_peak_pressure(&mut generator(0.0));
+    assert!(full > 0.5, "peak pressure without misfires {}", full);
+
+    for misfire_probability in [0.25, 0.5, 0.75, 1.0] {
+        let average = average_peak_pressure(&mut generator(misfire_probability));
+        let expected = full * (1.0 - misfire_probability);
+
+        assert!(
+            (average - expected).abs() < full * 0.02,
+            "{} misfire probability: {} instead of {}",
+            misfire_probability,
+            average,
+            expected
+        );
+    }
+}
+
+#[test]
+fn misfires_are_decided_once_per_cycle() {
+    let mut generator = generator(0.5);
+    let mut noise: Noise = generator.engine.misfire_noise.clone();
+
+    // every sample of the ignition of a cycle misfires or none does
+    let mut sample = [0.0];
+    for _ in 0..100 {
+        let mut pressures = Vec::with_capacity(SAMPLES_PER_CYCLE);
+        for _ in 0..SAMPLES_PER_CYCLE {
+            generator.generate(&mut sample);
+            pressures.push(generator.engine.cylinders[0].cyl_sound);
+        }
+
+        let ignition = &pressures[41..60];
+        assert!(
+            ignition.iter().all(|&pressure| pressure > 0.0)
+                || ignition.iter().all(|&pressure| pressure == 0.0),
+            "{:?}",
+            ignition
+        );
+    }
+
+    // the misfire noise has been stepped once for each new cycle, the first cycle may start with
+    // the first sample or a cycle later
+    let steps = (0..=101)
+        .position(|_| {
+            let stepped = noise == generator.engine.misfire_noise;
+            noise.step();
+            stepped
+        })
+        .expect("the misfire noise was stepped more than once per cycle");
+    assert!((99..=100).contains(&steps), "{} steps", steps);
+}
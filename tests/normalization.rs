@@ -0,0 +1,96 @@
+//! Loudness measured by `LufsMonitor` and peak and loudness normalization of generated audio with `normalize`.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::recorder::normalize;
+use enginesound::{LufsMonitor, NormalizationTarget};
+
+/// one second of a quiet 1 khz sine
+fn sine() -> Vec<f32> {
+    (0..SAMPLE_RATE)
+        .map(|i| (i as f32 * 1000.0 / SAMPLE_RATE as f32 * std::f32::consts::TAU).sin() * 0.1)
+        .collect()
+}
+
+#[test]
+fn targets_are_parsed() {
+    assert_eq!(
+        NormalizationTarget::parse("peak"),
+        Ok(NormalizationTarget::Peak(NormalizationTarget::DEFAULT_PEAK))
+    );
+    assert_eq!(
+        NormalizationTarget::parse("lufs:-14"),
+        Ok(NormalizationTarget::Lufs(-14.0))
+    );
+    assert!(NormalizationTarget::parse("lufs:loud").is_err());
+    assert!(NormalizationTarget::parse("rms").is_err());
+}
+
+#[test]
+fn peak_is_normalized() {
+    let mut samples = sine();
+    let gain = normalize(
+        &mut samples,
+        NormalizationTarget::Peak(0.99),
+        SAMPLE_RATE,
+        1,
+    )
+    .unwrap();
+
+    let peak = samples.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+    assert!((peak - 0.99).abs() < 1e-5);
+    assert!((gain - 9.9).abs() < 0.01);
+}
+
+#[test]
+fn full_scale_sine_reads_the_reference_loudness() {
+    // ITU-R BS.1770: a 997 hz sine at 0 dBFS in one channel reads -3.01 LUFS, 20 dB quieter reads 20 LU less
+    for (amplitude, expected) in [(1.0, -3.01), (0.1, -23.01)] {
+        let samples = (0..SAMPLE_RATE * 5)
+            .map(|i| {
+                (i as f64 * 997.0 / SAMPLE_RATE as f64 * std::f64::consts::TAU).sin() as f32
+                    * amplitude
+            })
+            .collect::<Vec<f32>>();
+
+        let mut monitor = LufsMonitor::new(SAMPLE_RATE, 1);
+        monitor.update(&samples);
+        assert!(
+            (monitor.lufs() - expected).abs() < 0.1,
+            "{} LUFS instead of {}",
+            monitor.lufs(),
+            expected
+        );
+    }
+}
+
+#[test]
+fn loudness_is_normalized() {
+    let mut samples = sine();
+    normalize(
+        &mut samples,
+        NormalizationTarget::Lufs(-14.0),
+        SAMPLE_RATE,
+        1,
+    )
+    .unwrap();
+
+    let mut monitor = LufsMonitor::new(SAMPLE_RATE, 1);
+    monitor.update(&samples);
+    assert!((monitor.lufs() + 14.0).abs() < 0.1);
+}
+
+#[test]
+fn silence_is_left_unchanged() {
+    let mut samples = vec![0.0; 1000];
+    assert_eq!(
+        normalize(
+            &mut samples,
+            NormalizationTarget::Peak(0.99),
+            SAMPLE_RATE,
+            1
+        ),
+        None
+    );
+}
@@ -0,0 +1,41 @@
+//! Recording Ogg Vorbis files with `OutputFormat::Ogg`.
+#![cfg(feature = "ogg")]
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{OutputFormat, Recorder};
+
+#[test]
+fn recordings_are_ogg_files() {
+    let mut generator = TestEngine::preset("i4").generator();
+
+    let path = std::env::temp_dir().join(format!("enginesound_ogg_{}.ogg", std::process::id()));
+    generator.recorder = Some(
+        Recorder::new(
+            path.clone(),
+            SAMPLE_RATE,
+            OutputFormat::Ogg {
+                quality: OutputFormat::DEFAULT_OGG_QUALITY,
+            },
+        )
+        .unwrap(),
+    );
+
+    generator.generate(&mut [0.0; 4096]);
+
+    let recorder = generator.recorder.take().unwrap();
+    assert_eq!(recorder.get_len(), 4096);
+    recorder.stop_wait();
+    assert!(recorder.take_error().is_none());
+
+    let file = std::fs::read(&path).unwrap();
+    assert!(
+        file.starts_with(b"OggS"),
+        "{:?}",
+        &file[..file.len().min(4)]
+    );
+    assert!(file.len() > 4);
+
+    std::fs::remove_file(path).unwrap();
+}
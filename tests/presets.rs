@@ -0,0 +1,43 @@
+//! Constructing the bundled presets with `Engine::from_preset`.
+
+mod common;
+
+use common::{generator, SAMPLE_RATE};
+use enginesound::{Engine, EnginePreset};
+
+const BUFFER_SIZE: usize = 256;
+
+#[test]
+fn presets_are_valid() {
+    for preset in EnginePreset::ALL.iter().copied() {
+        let errors = Engine::from_preset(preset, SAMPLE_RATE).validate(SAMPLE_RATE);
+        assert!(errors.is_empty(), "{:?}: {:?}", preset, errors);
+    }
+
+    assert_eq!(
+        Engine::v8(SAMPLE_RATE).cylinders.len(),
+        Engine::from_preset(EnginePreset::V8, SAMPLE_RATE)
+            .cylinders
+            .len()
+    );
+    assert_eq!(Engine::inline4(SAMPLE_RATE).cylinders.len(), 4);
+    assert_eq!(Engine::flat6(SAMPLE_RATE).cylinders.len(), 6);
+    assert_eq!(Engine::diesel_inline6(SAMPLE_RATE).cylinders.len(), 6);
+    assert_eq!(Engine::v10(SAMPLE_RATE).cylinders.len(), 10);
+}
+
+#[test]
+fn presets_generate_a_second_without_damping() {
+    for preset in EnginePreset::ALL.iter().copied() {
+        let mut engine = Engine::from_preset(preset, SAMPLE_RATE);
+        engine.waveguide_adaptive_damping = true;
+        let mut generator = generator(engine);
+
+        let mut buf = vec![0.0; BUFFER_SIZE];
+        for _ in 0..SAMPLE_RATE as usize / BUFFER_SIZE {
+            generator.generate(&mut buf);
+            assert_eq!(generator.dampened_waveguides, 0, "{:?}", preset);
+            assert!(buf.iter().all(|sample| sample.is_finite()));
+        }
+    }
+}
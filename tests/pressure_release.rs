@@ -0,0 +1,63 @@
+//! The pressure release impulse of `Cylinder::pressure_release_factor` when the exhaust valve opens.
+
+mod common;
+
+use common::TestEngine;
+use enginesound::Generator;
+
+/// a single cylinder engine at a steady rpm
+fn generator(pressure_release_factor: f32) -> Generator {
+    TestEngine::preset("i4")
+        .rpm(3000.0)
+        .with(|engine| {
+            engine.cylinders.truncate(1);
+            engine.cylinders[0].pressure_release_factor = pressure_release_factor;
+            engine.cylinders[0].misfire_probability = 0.0;
+            engine.crankshaft_fluctuation = 0.0;
+        })
+        .generator()
+}
+
+/// generates one sample at a time and returns the pressure release impulses of every crankshaft cycle
+/// which was completed
+fn impulses_per_cycle(generator: &mut Generator, cycles: usize) -> Vec<Vec<f32>> {
+    let mut completed = Vec::new();
+    let mut current = Vec::new();
+    let mut last_pos = generator.engine.crankshaft_pos;
+
+    while completed.len() < cycles {
+        generator.generate(&mut [0.0]);
+
+        let impulse = generator.engine.cylinders[0].pressure_release;
+        if impulse != 0.0 {
+            current.push(impulse);
+        }
+
+        let pos = generator.engine.crankshaft_pos;
+        if pos < last_pos {
+            completed.push(std::mem::take(&mut current));
+        }
+        last_pos = pos;
+    }
+
+    // the first cycle may have started after the valve opened
+    completed.remove(0);
+    completed
+}
+
+#[test]
+fn impulse_fires_once_per_cycle() {
+    let cycles = impulses_per_cycle(&mut generator(100.0), 20);
+
+    for impulses in cycles {
+        assert_eq!(impulses.len(), 1);
+        assert!(impulses[0] > 0.0);
+    }
+}
+
+#[test]
+fn no_impulse_without_pressure_release_factor() {
+    let cycles = impulses_per_cycle(&mut generator(0.0), 5);
+
+    assert!(cycles.iter().all(Vec::is_empty));
+}
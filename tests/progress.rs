@@ -0,0 +1,38 @@
+//! Reporting the progress of a recording with `Generator::set_progress_callback`.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn progress_reaches_the_total_after_generating() {
+    // not a multiple of the sample rate, so the last call isn't one of the calls every second
+    let total = SAMPLE_RATE as usize * 5 / 2;
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let mut generator = TestEngine::preset("i4").generator();
+    generator.set_progress_callback(
+        Some(Box::new({
+            let calls = calls.clone();
+            move |done, total| calls.lock().unwrap().push((done, total))
+        })),
+        total,
+    );
+
+    let mut buf = vec![0.0; 1000];
+    for _ in 0..(total / buf.len() + 1) {
+        generator.generate(&mut buf);
+    }
+    // generating past the total doesn't report any more progress
+    generator.generate(&mut buf);
+
+    assert_eq!(
+        *calls.lock().unwrap(),
+        vec![
+            (SAMPLE_RATE as usize, total),
+            (SAMPLE_RATE as usize * 2, total),
+            (total, total)
+        ]
+    );
+}
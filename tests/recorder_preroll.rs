@@ -0,0 +1,36 @@
+//! `Generator::recorder_preroll`, the frames generated before the recorder receives samples.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{OutputFormat, Recorder, SampleDepth};
+
+#[test]
+fn recorder_starts_after_the_preroll() {
+    let mut generator = TestEngine::preset("i4").generator();
+
+    let path = std::env::temp_dir().join(format!("enginesound_preroll_{}.wav", std::process::id()));
+    generator.recorder = Some(
+        Recorder::new(
+            path.clone(),
+            SAMPLE_RATE,
+            OutputFormat::from(SampleDepth::F32),
+        )
+        .unwrap(),
+    );
+    // ends in the middle of the third buffer
+    generator.recorder_preroll = 2500;
+
+    let mut buf = vec![0.0; 1000];
+    for _ in 0..5 {
+        generator.generate(&mut buf);
+    }
+    assert_eq!(generator.recorder_preroll, 0);
+
+    let recorder = generator.recorder.take().unwrap();
+    assert_eq!(recorder.get_len(), 5000 - 2500);
+
+    recorder.stop_wait();
+    assert!(recorder.take_error().is_none());
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,65 @@
+//! The JSON file written next to a recording by `Recorder::set_metadata`.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::recorder::metadata_file_name;
+use enginesound::{Engine, OutputFormat, Recorder, SampleDepth};
+
+fn record(name: &str, engine: Option<Engine>) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("{}_{}.wav", name, std::process::id()));
+
+    let mut recorder = Recorder::new(
+        path.clone(),
+        SAMPLE_RATE,
+        OutputFormat::from(SampleDepth::F32),
+    )
+    .unwrap();
+    recorder.set_metadata(engine);
+    // one second of a 0.5 peak sine
+    recorder.record(
+        (0..SAMPLE_RATE)
+            .map(|i| (i as f32 * 0.05).sin() * 0.5)
+            .collect(),
+    );
+    recorder.stop_wait();
+    assert!(recorder.take_error().is_none());
+
+    path
+}
+
+#[test]
+fn metadata_is_written_next_to_the_recording() {
+    let mut engine = TestEngine::preset("i4").engine();
+    engine.rpm = 3000.0;
+    engine.noise_seed = Some(42);
+
+    let path = record("enginesound_metadata", Some(engine.clone()));
+    let metadata_path = metadata_file_name(&path);
+    assert_eq!(metadata_path.extension().unwrap(), "json");
+
+    let metadata: serde_json::Value =
+        serde_json::from_reader(std::fs::File::open(&metadata_path).unwrap()).unwrap();
+    assert_eq!(metadata["rpm"], 3000.0);
+    assert_eq!(metadata["sample_rate"], SAMPLE_RATE);
+    assert_eq!(metadata["seed"], 42);
+    assert!((metadata["duration_sec"].as_f64().unwrap() - 1.0).abs() < 1e-6);
+    assert!((metadata["peak_amplitude"].as_f64().unwrap() - 0.5).abs() < 1e-3);
+    assert!(metadata["lufs"].as_f64().unwrap() < 0.0);
+
+    // the config can be loaded again
+    let config: Engine = ron::de::from_str(metadata["engine_config"].as_str().unwrap()).unwrap();
+    assert_eq!(config.cylinders.len(), engine.cylinders.len());
+    assert_eq!(config.noise_seed, Some(42));
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(metadata_path).unwrap();
+}
+
+#[test]
+fn metadata_is_only_written_if_set() {
+    let path = record("enginesound_no_metadata", None);
+    assert!(!metadata_file_name(&path).exists());
+
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,71 @@
+//! Resampling of the generator output with `Generator::generate_resampled` and `Resampler`.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{Generator, Resampler};
+
+fn generator() -> Generator {
+    TestEngine::default_config().seed(7).generator()
+}
+
+fn mean_square(samples: &[f32]) -> f64 {
+    samples.iter().map(|&x| (x as f64).powi(2)).sum::<f64>() / samples.len() as f64
+}
+
+#[test]
+fn resampling_one_second_preserves_length_and_energy() {
+    let mut original = vec![0.0; SAMPLE_RATE as usize];
+    generator().generate(&mut original);
+
+    let mut resampled = vec![0.0; 44100];
+    generator().generate_resampled(&mut resampled, 44100);
+
+    assert_eq!(resampled.len(), 44100);
+
+    let (original, resampled) = (mean_square(&original), mean_square(&resampled));
+    assert!(original > 0.0);
+    assert!(
+        (resampled / original - 1.0).abs() < 0.01,
+        "energy changed from {} to {}",
+        original,
+        resampled
+    );
+}
+
+#[test]
+fn resampling_in_chunks_is_continuous() {
+    let input = (0..1000)
+        .map(|i| (i as f32 * 0.05).sin())
+        .collect::<Vec<f32>>();
+
+    for &(from, to) in &[(48000, 44100), (44100, 48000), (22050, 48000)] {
+        let mut whole = Resampler::new(from, to);
+        let mut expected = vec![0.0; 700];
+        let input_len = whole.input_len(expected.len());
+        whole.resample_linear(&input[..input_len], &mut expected);
+
+        let mut chunked = Resampler::new(from, to);
+        let mut output = Vec::new();
+        let mut consumed = 0;
+        for chunk_len in [1, 7, 64, 3, 200, 425].iter().copied() {
+            let mut chunk = vec![0.0; chunk_len];
+            let len = chunked.input_len(chunk_len);
+            chunked.resample_linear(&input[consumed..(consumed + len)], &mut chunk);
+            consumed += len;
+            output.extend_from_slice(&chunk);
+        }
+
+        assert_eq!(consumed, input_len);
+        for (a, b) in output.iter().zip(expected.iter()) {
+            assert!(
+                (a - b).abs() < 1e-5,
+                "{} != {} ({} Hz -> {} Hz)",
+                a,
+                b,
+                from,
+                to
+            );
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Records `--rpm-envelope`s with the headless CLI, whose recordings last `--length` whatever the envelope duration.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("enginesound_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// records 0.25 seconds following `envelope` and returns the length of the recording and the RPMs of its `--rpm-log`
+fn record(name: &str, envelope: &str) -> (u32, Vec<f32>) {
+    let dir = output_dir(name);
+    let output = dir.join("envelope.wav");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_enginesound"))
+        .args(["--headless", "--preset", "i4", "--warmup_time", "0.0"])
+        .args(["--rpm-envelope", envelope, "--length", "0.25"])
+        .args(["--rpm-log", "--no-progress"])
+        .arg("--output")
+        .arg(&output)
+        .status()
+        .expect("failed to run enginesound");
+    assert!(status.success());
+
+    let duration = hound::WavReader::open(&output).unwrap().duration();
+    let rpms = std::fs::read_to_string(dir.join("envelope_rpm.csv"))
+        .unwrap()
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(1).unwrap().parse::<f32>().unwrap())
+        .collect();
+
+    std::fs::remove_dir_all(dir).unwrap();
+    (duration, rpms)
+}
+
+#[test]
+fn shorter_envelopes_hold_their_last_rpm_until_the_end() {
+    let (duration, rpms) = record("rpm_envelope_short", "0.0:1000,0.1:3000");
+
+    assert_eq!(duration, 12000);
+    assert_eq!(rpms.last(), Some(&3000.0));
+}
+
+#[test]
+fn longer_envelopes_are_cut_off() {
+    let (duration, rpms) = record("rpm_envelope_long", "0.0:1000,1.0:5000");
+
+    assert_eq!(duration, 12000);
+    // a quarter of the envelope has passed
+    let last_rpm = *rpms.last().unwrap();
+    assert!((last_rpm - 2000.0).abs() < 10.0, "last RPM {}", last_rpm);
+}
+
+#[test]
+fn envelopes_ending_with_the_recording_fill_it() {
+    let (duration, rpms) = record("rpm_envelope_exact", "0.0:1000,0.25:2000");
+
+    assert_eq!(duration, 12000);
+    assert!(rpms.windows(2).all(|pair| pair[0] <= pair[1]));
+}
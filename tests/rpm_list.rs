@@ -0,0 +1,50 @@
+//! Records several RPMs with `--rpm-list` into separate files.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SAMPLE_RATE: u32 = 8000;
+
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("enginesound_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn record(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_enginesound"))
+        .args(["--headless", "--preset", "i4", "--warmup_time", "0.1"])
+        .args(["--samplerate", &SAMPLE_RATE.to_string(), "--length", "0.5"])
+        .args(["--seed", "1", "--no-progress", "--output-base"])
+        .arg(dir.join("engine"))
+        .arg("--output")
+        .arg(dir.join("single.wav"))
+        .args(args)
+        .status()
+        .expect("failed to run enginesound");
+    assert!(status.success());
+}
+
+fn samples(path: PathBuf) -> Vec<f32> {
+    let mut wav = hound::WavReader::open(path).unwrap();
+    assert_eq!(wav.spec().sample_rate, SAMPLE_RATE);
+    wav.samples::<f32>().map(Result::unwrap).collect()
+}
+
+#[test]
+fn every_rpm_is_recorded_like_a_single_recording() {
+    let dir = output_dir("rpm_list");
+
+    record(&dir, &["--rpm-list", "1000,3000"]);
+    let low = samples(dir.join("engine_1000.wav"));
+    let high = samples(dir.join("engine_3000.wav"));
+    assert_eq!(low.len(), SAMPLE_RATE as usize / 2);
+    assert_eq!(high.len(), SAMPLE_RATE as usize / 2);
+    assert_ne!(low, high);
+
+    // the second recording starts from a fresh engine instead of the state the first one left behind
+    record(&dir, &["--rpm", "3000"]);
+    assert_eq!(samples(dir.join("single.wav")), high);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
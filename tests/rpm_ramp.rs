@@ -0,0 +1,97 @@
+//! Records short `--rpm-ramp`s, sweeps and keyframes, with the headless CLI and checks their `--rpm-log`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn output_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("enginesound_{}_{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// (sample index, rpm) rows of an `--rpm-log`
+fn rpm_log(path: &Path) -> Vec<(usize, f32)> {
+    let log = std::fs::read_to_string(path).unwrap();
+    let mut lines = log.lines();
+    assert_eq!(lines.next(), Some("sample_index,rpm"));
+    lines
+        .map(|line| {
+            let mut values = line.split(',');
+            let sample_index = values.next().unwrap().parse::<usize>().unwrap();
+            let rpm = values.next().unwrap().parse::<f32>().unwrap();
+            (sample_index, rpm)
+        })
+        .collect()
+}
+
+#[test]
+fn rpm_ramp_log_goes_from_start_to_end() {
+    let dir = output_dir("rpm_ramp");
+    let output = dir.join("ramp.wav");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_enginesound"))
+        .args(["--headless", "--preset", "i4", "--warmup_time", "0.1"])
+        .args(["--rpm-ramp", "1000:3000:0.5", "--rpm-log", "--no-progress"])
+        .arg("--output")
+        .arg(&output)
+        .status()
+        .expect("failed to run enginesound");
+    assert!(status.success());
+
+    let wav = hound::WavReader::open(&output).unwrap();
+    assert_eq!(wav.duration(), 24000);
+
+    let rows = rpm_log(&dir.join("ramp_rpm.csv"));
+
+    let (first_index, first_rpm) = rows[0];
+    let (last_index, last_rpm) = rows[rows.len() - 1];
+    assert_eq!(first_index, 0);
+    assert!((first_rpm - 1000.0).abs() < 1.0, "first RPM {}", first_rpm);
+    assert!(last_index < 24000);
+    assert!((last_rpm - 3000.0).abs() < 10.0, "last RPM {}", last_rpm);
+    assert!(rows.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rpm_ramp_rejects_crossfade() {
+    let dir = output_dir("rpm_ramp_crossfade");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_enginesound"))
+        .args(["--headless", "--preset", "i4", "--warmup_time", "0.0"])
+        .args(["--rpm-ramp", "1000:3000:0.5", "--crossfade", "0.01"])
+        .arg("--output")
+        .arg(dir.join("ramp.wav"))
+        .status()
+        .expect("failed to run enginesound");
+    assert_eq!(status.code(), Some(4));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rpm_ramp_follows_keyframes() {
+    let dir = output_dir("rpm_ramp_keyframes");
+    let output = dir.join("ramp.wav");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_enginesound"))
+        .args(["--headless", "--preset", "i4", "--warmup_time", "0.0"])
+        .args(["--rpm-ramp", "0.0:3000,0.25:1000", "--length", "0.5"])
+        .args(["--rpm-log", "--no-progress"])
+        .arg("--output")
+        .arg(&output)
+        .status()
+        .expect("failed to run enginesound");
+    assert!(status.success());
+
+    // keyframes don't set the length of the recording
+    assert_eq!(hound::WavReader::open(&output).unwrap().duration(), 24000);
+
+    let rows = rpm_log(&dir.join("ramp_rpm.csv"));
+    assert!((rows[0].1 - 3000.0).abs() < 1.0, "first RPM {}", rows[0].1);
+    // the last keyframe is held
+    assert_eq!(rows[rows.len() - 1].1, 1000.0);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
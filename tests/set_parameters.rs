@@ -0,0 +1,92 @@
+//! Overriding config fields by their path with `set_parameters`, see `--set`.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::{set_parameters, Engine, MufflerElement, SPEED_OF_SOUND};
+
+fn engine() -> Engine {
+    TestEngine::preset("i4").engine()
+}
+
+#[test]
+fn fields_are_set_in_order() {
+    let mut engine = engine();
+    set_parameters(
+        &mut engine,
+        &[
+            "muffler.straight_pipe.alpha=0.12",
+            "cylinders[0].ignition_time=0.08",
+            "cylinders[1].active=false",
+            "muffler.muffler_elements[0].beta=0.2",
+            "rpm=3000",
+            "rpm=4000",
+            "noise_seed=7",
+        ],
+        SAMPLE_RATE,
+    )
+    .unwrap();
+
+    assert_eq!(engine.muffler.straight_pipe.alpha, 0.12);
+    assert_eq!(engine.cylinders[0].ignition_time, 0.08);
+    assert!(!engine.cylinders[1].active);
+    match &engine.muffler.muffler_elements[0] {
+        MufflerElement::StraightPipe(waveguide) => assert_eq!(waveguide.beta, 0.2),
+        _ => panic!("the first muffler element of the preset is a straight pipe"),
+    }
+    assert_eq!(engine.rpm, 4000.0);
+    assert_eq!(engine.noise_seed, Some(7));
+}
+
+#[test]
+fn waveguide_lengths_are_set_in_meters() {
+    let mut engine = engine();
+    set_parameters(
+        &mut engine,
+        &["muffler.straight_pipe.length=1.5"],
+        SAMPLE_RATE,
+    )
+    .unwrap();
+
+    let samples = 1.5 / SPEED_OF_SOUND * SAMPLE_RATE as f32;
+    let waveguide = &engine.muffler.straight_pipe;
+    assert!((waveguide.delay() - samples).abs() < 0.01);
+    assert_eq!(waveguide.chamber0.samples.len(), samples as usize);
+    assert_eq!(waveguide.chamber1.samples.len(), samples as usize);
+}
+
+#[test]
+fn crank_offsets_unset_the_firing_preset() {
+    let mut engine = engine();
+    engine.firing_preset = Some("even".to_string());
+    set_parameters(&mut engine, &["cylinders[1].crank_offset=0.1"], SAMPLE_RATE).unwrap();
+
+    assert_eq!(engine.firing_preset, None);
+    assert_eq!(engine.cylinders[1].crank_offset, 0.1);
+}
+
+#[test]
+fn invalid_assignments_are_named() {
+    for (assignment, reason) in [
+        (
+            "muffler.straight_pip.alpha=0.1",
+            "unknown field \"straight_pip\"",
+        ),
+        ("cylinders[99].active=false", "out of range"),
+        ("cylinders[0].active=maybe", "not a boolean"),
+        ("rpm=fast", "not a number"),
+        ("muffler.straight_pipe.length=long", "not a length"),
+        ("rpm", "expected path=value"),
+    ] {
+        let error = set_parameters(&mut engine(), &[assignment], SAMPLE_RATE).unwrap_err();
+        assert!(
+            error.contains(assignment) && error.contains(reason),
+            "{}",
+            error
+        );
+    }
+
+    // assignments which make the engine invalid are reported by the validation
+    let error = set_parameters(&mut engine(), &["rpm=-5"], SAMPLE_RATE).unwrap_err();
+    assert!(error.starts_with("rpm: "), "{}", error);
+}
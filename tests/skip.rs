@@ -0,0 +1,29 @@
+//! Fast-forwarding a `Generator` with `skip` instead of generating and discarding a warmup.
+
+mod common;
+
+use common::{TestEngine, SAMPLE_RATE};
+use enginesound::Generator;
+
+fn generator() -> Generator {
+    TestEngine::default_config().seed(3).generator()
+}
+
+#[test]
+fn skipping_leaves_the_same_state_as_generating() {
+    // not a multiple of the chunks `skip` generates in
+    let warmup = SAMPLE_RATE as usize + 123;
+
+    let mut skipped = generator();
+    skipped.skip(warmup);
+    let mut generated = generator();
+    generated.generate(&mut vec![0.0; warmup]);
+
+    let mut skipped_output = vec![0.0; 4800];
+    skipped.generate(&mut skipped_output);
+    let mut generated_output = vec![0.0; 4800];
+    generated.generate(&mut generated_output);
+
+    assert!(skipped_output.iter().any(|&sample| sample != 0.0));
+    assert_eq!(skipped_output, generated_output);
+}
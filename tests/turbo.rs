@@ -0,0 +1,47 @@
+//! Pitch of the `Turbo` whistle, which follows the turbo shaft rpm.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::{Turbo, TURBO_REFERENCE_RPM};
+
+const RPM_SCALE: f32 = 20.0;
+const WHISTLE_FREQ: f32 = 4000.0;
+
+/// frequency of the whistle once the turbo has spooled up at `engine_rpm` and wide open throttle
+fn whistle_freq(engine_rpm: f32) -> f32 {
+    let mut turbo = Turbo::new(RPM_SCALE, WHISTLE_FREQ, 1.0, 20.0, 0.0, SAMPLE_RATE);
+    for _ in 0..SAMPLE_RATE {
+        turbo.step(engine_rpm, 1.0, SAMPLE_RATE);
+    }
+
+    // counts the rising zero crossings of one second, interpolated between the samples
+    let samples = (0..SAMPLE_RATE)
+        .map(|_| turbo.step(engine_rpm, 1.0, SAMPLE_RATE))
+        .collect::<Vec<_>>();
+    let crossings = samples
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair[0] < 0.0 && pair[1] >= 0.0)
+        .map(|(i, pair)| i as f32 + pair[0] / (pair[0] - pair[1]))
+        .collect::<Vec<_>>();
+
+    (crossings.len() - 1) as f32 * SAMPLE_RATE as f32
+        / (crossings[crossings.len() - 1] - crossings[0])
+}
+
+#[test]
+fn whistle_frequency_is_proportional_to_the_rpm() {
+    for engine_rpm in [1000.0, 2500.0, 4000.0, 7000.0] {
+        let expected = WHISTLE_FREQ * engine_rpm * RPM_SCALE / TURBO_REFERENCE_RPM;
+        let measured = whistle_freq(engine_rpm);
+
+        assert!(
+            (measured - expected).abs() < expected * 0.001,
+            "{} hz instead of {} hz at {} rpm",
+            measured,
+            expected,
+            engine_rpm
+        );
+    }
+}
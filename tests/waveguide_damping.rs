@@ -0,0 +1,60 @@
+//! Adaptive damping of resonating waveguides with `Engine::waveguide_adaptive_damping`.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::{WaveGuide, MIN_STABLE_SAMPLES, WAVEGUIDE_DAMPEN_THRESHOLD};
+
+/// a lossless waveguide with a pulse of `amp` circulating in its chambers
+fn resonating(amp: f32) -> WaveGuide {
+    let mut waveguide = WaveGuide::new(32.0, 1.0, 1.0, SAMPLE_RATE);
+    for _ in 0..16 {
+        waveguide.pop(false);
+        waveguide.push(amp, amp);
+    }
+    waveguide
+}
+
+/// runs the waveguide without input and returns the maximum amplitude of its outputs
+fn run(waveguide: &mut WaveGuide, samples: usize, adaptive_damping: bool) -> f32 {
+    (0..samples)
+        .map(|_| {
+            let (c1_out, c0_out, _) = waveguide.pop(adaptive_damping);
+            waveguide.push(0.0, 0.0);
+            c1_out.abs().max(c0_out.abs())
+        })
+        .fold(0.0, f32::max)
+}
+
+#[test]
+fn loud_resonances_are_dampened_and_recover() {
+    let mut waveguide = resonating(WAVEGUIDE_DAMPEN_THRESHOLD * 1.5);
+
+    // single peaks are not dampened
+    run(&mut waveguide, MIN_STABLE_SAMPLES / 2, true);
+    assert_eq!(waveguide.gain, 1.0);
+    assert!(!waveguide.dampened);
+
+    run(&mut waveguide, MIN_STABLE_SAMPLES * 4, true);
+    assert!(waveguide.gain < 1.0);
+    assert!(waveguide.dampened);
+
+    run(&mut waveguide, SAMPLE_RATE as usize / 2, true);
+    assert!(waveguide.running_max < WAVEGUIDE_DAMPEN_THRESHOLD);
+
+    // the gain is restored once the waveguide is quiet
+    run(&mut waveguide, SAMPLE_RATE as usize * 3, true);
+    assert_eq!(waveguide.gain, 1.0);
+}
+
+#[test]
+fn quiet_and_undampened_waveguides_keep_their_gain() {
+    let mut quiet = resonating(WAVEGUIDE_DAMPEN_THRESHOLD * 0.5);
+    run(&mut quiet, SAMPLE_RATE as usize, true);
+    assert_eq!(quiet.gain, 1.0);
+    assert!(!quiet.dampened);
+
+    let mut loud = resonating(WAVEGUIDE_DAMPEN_THRESHOLD * 1.5);
+    run(&mut loud, SAMPLE_RATE as usize, false);
+    assert_eq!(loud.gain, 1.0);
+}
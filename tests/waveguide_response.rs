@@ -0,0 +1,69 @@
+//! Resonances of `WaveGuide::frequency_response`, which are spaced like those of a pipe.
+
+mod common;
+
+use common::SAMPLE_RATE;
+use enginesound::{distance_to_samples, WaveGuide, SPEED_OF_SOUND};
+
+/// 1 hz per bin
+const NUM_BINS: usize = SAMPLE_RATE as usize / 2 + 1;
+const PIPE_LENGTH: f32 = 0.5;
+
+/// frequencies of the local maxima of the response of a pipe with the given reflection factors
+fn peaks(alpha: f32, beta: f32) -> Vec<f32> {
+    let waveguide = WaveGuide::new(
+        distance_to_samples(PIPE_LENGTH, SAMPLE_RATE),
+        alpha,
+        beta,
+        SAMPLE_RATE,
+    );
+    let response = waveguide.frequency_response(SAMPLE_RATE, NUM_BINS);
+
+    response
+        .windows(3)
+        .enumerate()
+        .filter(|(_, bins)| bins[0] < bins[1] && bins[1] >= bins[2])
+        .map(|(i, _)| (i + 1) as f32)
+        .collect()
+}
+
+#[test]
+fn peaks_are_multiples_of_the_pipe_resonance() {
+    // both ends reflect in phase, like a pipe which is open or closed at both ends
+    let resonance = SPEED_OF_SOUND / (2.0 * PIPE_LENGTH);
+    let peaks = peaks(0.9, 0.9);
+
+    assert_eq!(peaks.len(), (SAMPLE_RATE as f32 / 2.0 / resonance) as usize);
+    for (i, peak) in peaks.into_iter().enumerate() {
+        let expected = resonance * (i + 1) as f32;
+        assert!(
+            (peak - expected).abs() <= 1.0,
+            "peak {} at {} hz instead of {} hz",
+            i,
+            peak,
+            expected
+        );
+    }
+}
+
+#[test]
+fn inverted_reflections_shift_the_peaks_by_half_the_resonance() {
+    // one end inverts the reflections, like a pipe which is open at one end and closed at the other
+    let resonance = SPEED_OF_SOUND / (2.0 * PIPE_LENGTH);
+    let peaks = peaks(-0.9, 0.9);
+
+    assert_eq!(
+        peaks.len(),
+        (SAMPLE_RATE as f32 / 2.0 / resonance + 0.5) as usize
+    );
+    for (i, peak) in peaks.into_iter().enumerate() {
+        let expected = resonance * (i as f32 + 0.5);
+        assert!(
+            (peak - expected).abs() <= 1.0,
+            "peak {} at {} hz instead of {} hz",
+            i,
+            peak,
+            expected
+        );
+    }
+}
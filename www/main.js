@@ -0,0 +1,38 @@
+// Serve the repository root (e.g. `python3 -m http.server`) after running
+// `wasm-pack build --target web --no-default-features --features wasm`
+// and open http://localhost:8000/www/
+
+const CONFIG_URL = "../example1.esc";
+const WASM_URL = "../pkg/enginesound_bg.wasm";
+
+const start = document.getElementById("start");
+const rpm = document.getElementById("rpm");
+const rpmValue = document.getElementById("rpm-value");
+
+start.addEventListener("click", async () => {
+    start.disabled = true;
+
+    const context = new AudioContext();
+    const [config, module] = await Promise.all([
+        fetch(CONFIG_URL).then((response) => response.text()),
+        WebAssembly.compileStreaming(fetch(WASM_URL)),
+    ]);
+
+    await context.audioWorklet.addModule("worklet.js");
+
+    // the compiled module is passed to the worklet since it can't fetch by itself
+    const node = new AudioWorkletNode(context, "enginesound", {
+        numberOfOutputs: 1,
+        outputChannelCount: [1],
+        processorOptions: { config, module, sampleRate: context.sampleRate },
+    });
+    node.port.onmessage = (event) => console.error(event.data);
+    node.connect(context.destination);
+
+    rpm.disabled = false;
+    rpm.addEventListener("input", () => {
+        rpmValue.textContent = rpm.value;
+        node.port.postMessage({ rpm: parseFloat(rpm.value) });
+    });
+    node.port.postMessage({ rpm: parseFloat(rpm.value) });
+});
@@ -0,0 +1,24 @@
+// AudioWorkletGlobalScope has no TextDecoder/TextEncoder, which the wasm-bindgen glue code needs.
+// Only ASCII is needed for config errors and identifiers, so this is kept minimal.
+
+if (typeof globalThis.TextDecoder === "undefined") {
+    globalThis.TextDecoder = class {
+        decode(bytes) {
+            return bytes === undefined ? "" : String.fromCharCode(...new Uint8Array(bytes));
+        }
+    };
+}
+
+if (typeof globalThis.TextEncoder === "undefined") {
+    globalThis.TextEncoder = class {
+        encode(text = "") {
+            return Uint8Array.from(text, (c) => c.charCodeAt(0) & 0xff);
+        }
+
+        encodeInto(text, bytes) {
+            const encoded = this.encode(text);
+            bytes.set(encoded.subarray(0, bytes.length));
+            return { read: Math.min(text.length, bytes.length), written: Math.min(encoded.length, bytes.length) };
+        }
+    };
+}
@@ -0,0 +1,35 @@
+import "./polyfill.js";
+import { initSync, JsGenerator } from "../pkg/enginesound.js";
+
+class EngineSoundProcessor extends AudioWorkletProcessor {
+    constructor(options) {
+        super();
+
+        const { config, module, sampleRate } = options.processorOptions;
+        initSync({ module });
+
+        try {
+            this.generator = new JsGenerator(config, sampleRate);
+        } catch (e) {
+            this.port.postMessage(String(e));
+        }
+
+        this.port.onmessage = (event) => {
+            if (this.generator && event.data.rpm !== undefined) {
+                this.generator.set_rpm(event.data.rpm);
+            }
+        };
+    }
+
+    process(inputs, outputs) {
+        if (!this.generator) {
+            return false;
+        }
+
+        const output = outputs[0][0];
+        output.set(this.generator.generate(output.length));
+        return true;
+    }
+}
+
+registerProcessor("enginesound", EngineSoundProcessor);